@@ -0,0 +1,193 @@
+//! This module checks the built pages for a skip link and ARIA landmarks.
+//!
+//! Pages lacking them are reported and optionally fixed.
+
+use std::path::Path;
+
+use log::{debug, warn};
+
+use crate::{
+    config::AccessibilityConfig,
+    error::{Error, Result},
+    html::{start_tags, StartTag},
+    list_files,
+};
+
+/// Class of an inserted skip link, allowing themes to style it.
+const SKIP_LINK_CLASS: &str = "skip-link";
+
+/// A simple selector for an element.
+#[derive(Debug, PartialEq, Eq)]
+enum Selector<'a> {
+    /// Matches the element name, e.g. `main`.
+    Element(&'a str),
+    /// Matches the id attribute, e.g. `#content`.
+    Id(&'a str),
+    /// Matches one of the classes, e.g. `.content`.
+    Class(&'a str),
+    /// Matches an attribute value, e.g. `[role=main]`.
+    Attribute(&'a str, &'a str),
+}
+
+impl<'a> Selector<'a> {
+    /// Parse a selector, returns `None` if it is not supported.
+    fn parse(selector: &'a str) -> Option<Self> {
+        let selector = selector.trim();
+        if let Some(id) = selector.strip_prefix('#') {
+            Some(Selector::Id(id))
+        } else if let Some(class) = selector.strip_prefix('.') {
+            Some(Selector::Class(class))
+        } else if let Some(attr) = selector.strip_prefix('[') {
+            let (name, value) = attr.strip_suffix(']')?.split_once('=')?;
+            Some(Selector::Attribute(
+                name.trim(),
+                value.trim().trim_matches(|c| c == '"' || c == '\''),
+            ))
+        } else if !selector.is_empty() && selector.chars().all(char::is_alphanumeric) {
+            Some(Selector::Element(selector))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the start tag matches the selector.
+    fn matches(&self, tag: &StartTag) -> bool {
+        match self {
+            Selector::Element(name) => tag.name.eq_ignore_ascii_case(name),
+            Selector::Id(id) => tag.attr("id") == Some(id),
+            Selector::Class(class) => tag
+                .attr("class")
+                .is_some_and(|classes| classes.split_whitespace().any(|c| c == *class)),
+            Selector::Attribute(name, value) => tag.attr(name) == Some(value),
+        }
+    }
+}
+
+/// Check a single page and fix it if enabled.
+///
+/// Returns the descriptions of all issues which remain and the fixed HTML if it
+/// was changed.
+fn check_page(config: &AccessibilityConfig, html: &str) -> (Vec<String>, Option<String>) {
+    let tags = start_tags(html);
+    let mut issues = Vec::new();
+    // Insertions into the HTML as (byte index, text), applied back to front
+    let mut fixes: Vec<(usize, String)> = Vec::new();
+
+    for landmark in &config.landmarks {
+        match Selector::parse(landmark) {
+            Some(selector) if tags.iter().any(|tag| selector.matches(tag)) => {}
+            Some(_) => issues.push(format!("missing landmark '{}'", landmark)),
+            None => issues.push(format!("unsupported selector '{}'", landmark)),
+        }
+    }
+
+    if let Some(target) = &config.skip_target {
+        let has_target = tags.iter().any(|tag| tag.attr("id") == Some(target));
+        let main = tags
+            .iter()
+            .find(|tag| tag.name.eq_ignore_ascii_case("main"));
+        match (has_target, main) {
+            (true, _) => {}
+            (false, Some(main)) if config.fix && main.attr("id").is_none() => {
+                fixes.push((main.end, format!(" id=\"{}\"", target)));
+            }
+            (false, _) => issues.push(format!("missing skip link target '#{}'", target)),
+        }
+
+        let href = format!("#{}", target);
+        let has_link = tags
+            .iter()
+            .any(|tag| tag.name.eq_ignore_ascii_case("a") && tag.attr("href") == Some(&href));
+        let body = tags
+            .iter()
+            .find(|tag| tag.name.eq_ignore_ascii_case("body"));
+        match (has_link, body) {
+            (true, _) => {}
+            (false, Some(body)) if config.fix => fixes.push((
+                body.end + 1,
+                format!(
+                    "<a class=\"{}\" href=\"{}\">{}</a>",
+                    SKIP_LINK_CLASS, href, config.skip_link_text
+                ),
+            )),
+            (false, _) => issues.push(format!("missing skip link to '{}'", href)),
+        }
+    }
+
+    if fixes.is_empty() {
+        return (issues, None);
+    }
+    let mut fixed = html.to_string();
+    fixes.sort_by_key(|(i, _)| *i);
+    for (i, text) in fixes.into_iter().rev() {
+        fixed.insert_str(i, &text);
+    }
+    (issues, Some(fixed))
+}
+
+/// Check all HTML pages in the output directory.
+///
+/// Returns the number of pages which still have issues.
+pub async fn check_output(config: &AccessibilityConfig, output_path: &Path) -> Result<usize> {
+    let mut pages_with_issues = 0;
+
+    for file in list_files(output_path).await? {
+        if file.extension().and_then(|ext| ext.to_str()) != Some("html") {
+            continue;
+        }
+        let path = output_path.join(&file);
+        let html = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::ReadInput(path.clone(), e))?;
+
+        let (issues, fixed) = check_page(config, &html);
+        if let Some(fixed) = fixed {
+            debug!("Fixing accessibility of {}", file.display());
+            tokio::fs::write(&path, fixed)
+                .await
+                .map_err(|e| Error::WriteFile(path.clone(), e))?;
+        }
+        for issue in &issues {
+            warn!("Page {}: {}", file.display(), issue);
+        }
+        if !issues.is_empty() {
+            pages_with_issues += 1;
+        }
+    }
+
+    Ok(pages_with_issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(fix: bool) -> AccessibilityConfig {
+        AccessibilityConfig {
+            skip_target: Some("content".to_string()),
+            skip_link_text: "Skip".to_string(),
+            landmarks: vec!["nav".to_string(), "[role=contentinfo]".to_string()],
+            fix,
+        }
+    }
+
+    #[test]
+    fn test_report_issues() {
+        let html = "<body><nav></nav><main></main></body>";
+        let (issues, fixed) = check_page(&config(false), html);
+        assert_eq!(3, issues.len());
+        assert!(fixed.is_none());
+    }
+
+    #[test]
+    fn test_fix_skip_link() {
+        let html = "<body><nav></nav><main>x</main><footer role=\"contentinfo\"></footer></body>";
+        let (issues, fixed) = check_page(&config(true), html);
+        assert!(issues.is_empty());
+        assert_eq!(
+            "<body><a class=\"skip-link\" href=\"#content\">Skip</a><nav></nav>\
+             <main id=\"content\">x</main><footer role=\"contentinfo\"></footer></body>",
+            fixed.unwrap()
+        );
+    }
+}
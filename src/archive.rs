@@ -0,0 +1,162 @@
+//! This module generates listing pages of the dated pages by year and month,
+//! e.g. `/archive/2024/` and `/archive/2024/05/`.
+//!
+//! Pages are assigned to the year and month of their date in UTC.
+
+use std::collections::{BTreeMap, HashMap};
+
+use log::debug;
+use time::{Month, UtcOffset};
+
+use crate::{
+    config::{ArchiveConfig, Config},
+    error::Result,
+    template::{Context, Value},
+    write_page, Cli, Index, Page,
+};
+
+/// Published pages by year and month, newest first within a month.
+type Archive<'a> = BTreeMap<i32, BTreeMap<u8, Vec<&'a Page>>>;
+
+/// Collect all published pages with a date by their year and month.
+fn collect_pages<'a>(indices: &'a [Index], opts: &Cli) -> Archive<'a> {
+    let mut archive: Archive = BTreeMap::new();
+    let pages = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts));
+    for page in pages {
+        if let Some(date) = page.metadata.date {
+            let date = date.to_offset(UtcOffset::UTC);
+            archive
+                .entry(date.year())
+                .or_default()
+                .entry(date.month() as u8)
+                .or_default()
+                .push(page);
+        }
+    }
+    for pages in archive.values_mut().flat_map(|months| months.values_mut()) {
+        pages.sort_by_key(|page| std::cmp::Reverse(page.metadata.date));
+    }
+    archive
+}
+
+/// Absolute URL path of a year or a month of the archive.
+fn archive_url(archive: &ArchiveConfig, year: i32, month: Option<u8>) -> String {
+    match month {
+        Some(month) => format!("/{}/{}/{:02}/", archive.path.display(), year, month),
+        None => format!("/{}/{}/", archive.path.display(), year),
+    }
+}
+
+/// English name of the month, e.g. `May`.
+fn month_name(month: u8) -> String {
+    Month::try_from(month)
+        .map(|month| month.to_string())
+        .unwrap_or_default()
+}
+
+/// Convert a month with its pages into a map for templating.
+fn month_map(
+    config: &Config,
+    archive: &ArchiveConfig,
+    year: i32,
+    month: u8,
+    pages: &[&Page],
+) -> HashMap<String, Value> {
+    HashMap::from([
+        ("year".to_string(), i64::from(year).into()),
+        ("month".to_string(), format!("{:02}", month).into()),
+        ("name".to_string(), month_name(month).into()),
+        (
+            "url".to_string(),
+            config
+                .url_path(&archive_url(archive, year, Some(month)))
+                .into(),
+        ),
+        (
+            "year_url".to_string(),
+            config.url_path(&archive_url(archive, year, None)).into(),
+        ),
+        (
+            "pages".to_string(),
+            pages
+                .iter()
+                .map(|page| page.to_value(config))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+    ])
+}
+
+/// Write the listing pages of every year and month with dated pages.
+///
+/// The listing of a year contains its months, newest first, and all of its
+/// pages.
+pub async fn export_archive(
+    config: &Config,
+    archive: &ArchiveConfig,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+) -> Result<()> {
+    for (year, months) in collect_pages(indices, opts) {
+        debug!("Building archive of {}", year);
+
+        let year_dir = config
+            .output_path
+            .join(&archive.path)
+            .join(year.to_string());
+        let mut month_values = Vec::with_capacity(months.len());
+        for (month, pages) in months.iter().rev() {
+            let value = month_map(config, archive, year, *month, pages);
+            let mut month_ctx = ctx.clone();
+            month_ctx.insert("title", format!("{} {}", month_name(*month), year));
+            month_ctx.insert("archive", Value::Map(value.clone()));
+            let dir = year_dir.join(format!("{:02}", month));
+            write_page(config, &month_ctx, &archive.month_template, &dir, false).await?;
+            month_values.push(Value::Map(value));
+        }
+
+        let pages: Vec<_> = months
+            .values()
+            .rev()
+            .flatten()
+            .map(|page| page.to_value(config))
+            .collect();
+        let mut year_ctx = ctx.clone();
+        year_ctx.insert("title", year.to_string());
+        year_ctx.insert(
+            "archive",
+            Value::from_iter([
+                ("year", Value::from(i64::from(year))),
+                (
+                    "url",
+                    config.url_path(&archive_url(archive, year, None)).into(),
+                ),
+                ("months", month_values.into()),
+                ("pages", pages.into()),
+            ]),
+        );
+        write_page(config, &year_ctx, &archive.year_template, &year_dir, false).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_url() {
+        let archive = ArchiveConfig {
+            path: "archive".into(),
+            year_template: "archive_year.html".into(),
+            month_template: "archive_month.html".into(),
+        };
+        assert_eq!("/archive/2024/", archive_url(&archive, 2024, None));
+        assert_eq!("/archive/2024/05/", archive_url(&archive, 2024, Some(5)));
+    }
+}
@@ -0,0 +1,272 @@
+//! This module adds attributes given after images and links in the markdown
+//! to their HTML, e.g. `![A cat](cat.jpg){ .wide width=600 }`.
+//!
+//! Attributes are given as `name=value` with an optional quoted value, `.class`
+//! or `#id`. The parsed attributes are kept in a comment after the element,
+//! so they survive the other passes rewriting images and links, and are moved
+//! into the start tag of the element once the HTML is written.
+
+use pulldown_cmark::{escape::escape_html, CowStr, Event, Tag};
+
+/// Start of the comment holding the attributes of the preceding element.
+const MARKER_START: &str = "<!--fweb:attrs ";
+
+/// End of the comment holding the attributes.
+const MARKER_END: &str = "-->";
+
+/// Parse the attributes at the start of the text.
+///
+/// Returns the attributes with the length of the text they were given in.
+/// Typographic quotes of smart punctuation are accepted as well.
+fn parse(text: &str) -> Option<(Vec<(String, String)>, usize)> {
+    let inner = text.strip_prefix('{')?;
+    let end = inner.find('}')?;
+    let inner = &inner[..end];
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let mut rest = inner.trim_start();
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':');
+    while !rest.is_empty() {
+        let (name, value, remaining) = if let Some(class) = rest.strip_prefix('.') {
+            let len = class.find(char::is_whitespace).unwrap_or(class.len());
+            ("class", &class[..len], &class[len..])
+        } else if let Some(id) = rest.strip_prefix('#') {
+            let len = id.find(char::is_whitespace).unwrap_or(id.len());
+            ("id", &id[..len], &id[len..])
+        } else {
+            let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let (name, after) = rest.split_at(len);
+            let after = after.strip_prefix('=')?;
+            let (value, remaining) = match after.chars().next()? {
+                quote @ ('"' | '\'' | '“' | '‘') => {
+                    let closing: &[char] = match quote {
+                        '“' => &['”', '"'],
+                        '‘' => &['’', '\''],
+                        _ => &[quote],
+                    };
+                    let value = &after[quote.len_utf8()..];
+                    let len = value.find(closing)?;
+                    let quote_len = value[len..].chars().next()?.len_utf8();
+                    (&value[..len], &value[len + quote_len..])
+                }
+                _ => {
+                    let len = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..len], &after[len..])
+                }
+            };
+            (name, value, remaining)
+        };
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return None;
+        }
+        match attributes
+            .iter_mut()
+            .find(|(other, _)| other == "class" && name == "class")
+        {
+            Some((_, classes)) => {
+                classes.push(' ');
+                classes.push_str(value);
+            }
+            None => attributes.push((name.to_string(), value.to_string())),
+        }
+        if !remaining.is_empty() && !remaining.starts_with(char::is_whitespace) {
+            return None;
+        }
+        rest = remaining.trim_start();
+    }
+    (!attributes.is_empty()).then_some((attributes, end + 2))
+}
+
+/// Format the attributes as they are written into a tag.
+fn format_attributes(attributes: &[(String, String)]) -> String {
+    let mut formatted = String::new();
+    for (name, value) in attributes {
+        formatted.push(' ');
+        formatted.push_str(name);
+        formatted.push_str("=\"");
+        escape_html(&mut formatted, value).expect("writing to a string never fails");
+        formatted.push('"');
+    }
+    formatted
+}
+
+/// Replace the attributes following images and links in the events by
+/// comments holding them.
+pub fn mark(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let kind = match &event {
+            Event::End(Tag::Image(..)) => "img",
+            Event::End(Tag::Link(..)) => "a",
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+        output.push(event);
+
+        // The parser splits text at special characters
+        let mut texts = Vec::new();
+        while let Some(Event::Text(_)) = events.peek() {
+            match events.next() {
+                Some(Event::Text(text)) => texts.push(text),
+                _ => unreachable!("peeked at text"),
+            }
+        }
+        let text: String = texts.iter().map(|text| text.as_ref()).collect();
+        match parse(&text) {
+            Some((attributes, len)) => {
+                output.push(Event::Html(
+                    format!(
+                        "{}{}{}{}",
+                        MARKER_START,
+                        kind,
+                        format_attributes(&attributes),
+                        MARKER_END
+                    )
+                    .into(),
+                ));
+                if len < text.len() {
+                    output.push(Event::Text(CowStr::from(text[len..].to_string())));
+                }
+            }
+            None => output.extend(texts.into_iter().map(Event::Text)),
+        }
+    }
+    output
+}
+
+/// Move the attributes of the comments into the start tags of the elements
+/// before them.
+///
+/// Classes are added to the ones of the element, other attributes are added
+/// before the ones of the element, which take precedence in browsers.
+pub fn apply(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(MARKER_START) {
+        output.push_str(&rest[..start]);
+        let marker = &rest[start + MARKER_START.len()..];
+        let Some(end) = marker.find(MARKER_END) else {
+            break;
+        };
+        rest = &marker[end + MARKER_END.len()..];
+        let (kind, attributes) = marker[..end]
+            .split_once(' ')
+            .unwrap_or((&marker[..end], ""));
+
+        let tag_start = match output.rfind(&format!("<{} ", kind)) {
+            Some(tag_start) => tag_start,
+            None => continue,
+        };
+        let Some(tag_len) = output[tag_start..].find('>') else {
+            continue;
+        };
+        let tag_end = tag_start + tag_len;
+        let mut tag = output[tag_start..tag_end]
+            .trim_end_matches('/')
+            .trim_end()
+            .to_string();
+        let self_closing = output[tag_start..tag_end].ends_with('/');
+        let mut additional = String::new();
+        for attribute in split_attributes(attributes) {
+            let (name, value) = attribute.split_once('=').unwrap_or((attribute, "\"\""));
+            let existing = format!(" {}=\"", name);
+            match tag.find(&existing) {
+                Some(position) if name == "class" => {
+                    let value_start = position + existing.len();
+                    let value_end = value_start + tag[value_start..].find('"').unwrap_or(0);
+                    tag.insert_str(value_end, &format!(" {}", value.trim_matches('"')));
+                }
+                _ => {
+                    additional.push(' ');
+                    additional.push_str(attribute);
+                }
+            }
+        }
+        // Added right after the tag name
+        tag.insert_str(1 + kind.len(), &additional);
+        if self_closing {
+            tag.push_str(" /");
+        }
+        output.replace_range(tag_start..tag_end, &tag);
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Split the formatted attributes at the spaces between them.
+fn split_attributes(attributes: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in attributes.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if i > start {
+                    split.push(&attributes[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < attributes.len() {
+        split.push(&attributes[start..]);
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some((
+                attributes(&[("class", "wide full"), ("width", "600"), ("id", "cat")]),
+                35
+            )),
+            parse("{ .wide width=600 #cat class=full } and more")
+        );
+        assert_eq!(
+            Some((attributes(&[("title", "A cat")]), 19)),
+            parse("{title=“A cat”}")
+        );
+        assert_eq!(None, parse("{ not an attribute }"));
+        assert_eq!(None, parse("{ width=600"));
+        assert_eq!(None, parse("{}"));
+    }
+
+    #[test]
+    fn test_mark_and_apply() {
+        let markdown = "![A cat](cat.jpg){ .wide width=600 } and [a link](/x/ \"X\"){.more}.";
+        let events = pulldown_cmark::Parser::new(markdown).collect();
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, mark(events).into_iter());
+        assert_eq!(
+            "<p><img class=\"wide\" width=\"600\" src=\"cat.jpg\" alt=\"A cat\" /> and \
+             <a class=\"more\" href=\"/x/\" title=\"X\">a link</a>.</p>\n",
+            apply(&html)
+        );
+    }
+
+    #[test]
+    fn test_apply_adds_classes() {
+        let html = "<a href=\"https://x.org\" class=\"external\">x</a>\
+                    <!--fweb:attrs a class=\"wide\" data-x=\"a b\"-->";
+        assert_eq!(
+            "<a data-x=\"a b\" href=\"https://x.org\" class=\"external wide\">x</a>",
+            apply(html)
+        );
+    }
+}
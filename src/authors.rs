@@ -0,0 +1,151 @@
+//! This module resolves the authors of pages by the config and generates a
+//! listing page of the pages of every author if a template is configured.
+
+use std::collections::{BTreeMap, HashMap};
+
+use log::debug;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    slug::slugify,
+    template::{Context, Value},
+    write_page, Cli, Index, Page,
+};
+
+/// Absolute URL path of the listing of an author with a trailing slash.
+pub fn author_url(config: &Config, id: &str) -> String {
+    format!("/authors/{}/", slugify(&config.slugs, id))
+}
+
+/// Convert an author into a value for templating.
+///
+/// Authors missing in the config are named by their id. The URL of the
+/// listing is only given if listings are generated.
+pub fn author_value(config: &Config, id: &str) -> Value {
+    let author = config.authors.get(id);
+    let mut value = HashMap::from([
+        ("id".to_string(), Value::from(id)),
+        (
+            "name".to_string(),
+            author.map_or(id, |author| author.name.as_str()).into(),
+        ),
+        (
+            "bio".to_string(),
+            author
+                .map(|author| author.bio.clone())
+                .unwrap_or_default()
+                .into(),
+        ),
+    ]);
+    if config.author_template.is_some() {
+        value.insert(
+            "url".to_string(),
+            config.url_path(&author_url(config, id)).into(),
+        );
+    }
+    Value::Map(value)
+}
+
+/// Collect all authors with their published pages, ordered by id.
+fn collect_authors<'a>(indices: &'a [Index], opts: &Cli) -> BTreeMap<&'a str, Vec<&'a Page>> {
+    let mut authors: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let pages = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts));
+    for page in pages {
+        for author in &page.metadata.authors {
+            authors.entry(author.as_str()).or_default().push(page);
+        }
+    }
+    authors
+}
+
+/// Check that the listings of all authors of published pages have distinct
+/// URLs.
+fn check_slugs(config: &Config, authors: &BTreeMap<&str, Vec<&Page>>) -> Result<()> {
+    let mut slugs: HashMap<String, &str> = HashMap::new();
+    for id in authors.keys() {
+        let slug = slugify(&config.slugs, id);
+        if slug.is_empty() {
+            return Err(Error::EmptyAuthorSlug(id.to_string()));
+        }
+        if let Some(other) = slugs.insert(slug.clone(), id) {
+            return Err(Error::SlugCollision(
+                slug,
+                other.to_string(),
+                id.to_string(),
+                "authors".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Write the listing pages of all authors of published pages.
+pub async fn export_authors(
+    config: &Config,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+) -> Result<()> {
+    let Some(template) = &config.author_template else {
+        return Ok(());
+    };
+    let authors = collect_authors(indices, opts);
+    check_slugs(config, &authors)?;
+    for (id, pages) in authors {
+        debug!("Building author '{}'", id);
+
+        let mut value = HashMap::from([(
+            "pages".to_string(),
+            pages
+                .iter()
+                .map(|page| page.to_value(config))
+                .collect::<Vec<_>>()
+                .into(),
+        )]);
+        if let Value::Map(map) = author_value(config, id) {
+            value.extend(map);
+        }
+        let mut author_ctx = ctx.clone();
+        author_ctx.insert(
+            "title",
+            config
+                .authors
+                .get(id)
+                .map_or(id, |author| author.name.as_str()),
+        );
+        author_ctx.insert("author", Value::Map(value));
+        let dir = config
+            .output_path
+            .join("authors")
+            .join(slugify(&config.slugs, id));
+        write_page(config, &author_ctx, template, &dir, false).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_check_slugs() {
+        let config = dummy_config();
+        let authors = |ids: &[&'static str]| ids.iter().map(|id| (*id, Vec::new())).collect();
+
+        assert!(check_slugs(&config, &authors(&["alice", "bob"])).is_ok());
+        assert!(matches!(
+            check_slugs(&config, &authors(&["Alice", "alice"])),
+            Err(Error::SlugCollision(slug, ..)) if slug == "alice"
+        ));
+        assert!(matches!(
+            check_slugs(&config, &authors(&["alice", "!!"])),
+            Err(Error::EmptyAuthorSlug(id)) if id == "!!"
+        ));
+    }
+}
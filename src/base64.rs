@@ -0,0 +1,70 @@
+//! This module encodes and decodes base64 with the standard alphabet and
+//! padding, as used by data URLs, the data of protected pages and basic auth.
+
+/// Characters of the standard alphabet by their value.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode the bytes.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * i)) as usize & 63]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode the input, `None` if it is not valid base64.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| ALPHABET.iter().position(|a| *a == c).map(|i| i as u32);
+    let input = input.trim().as_bytes();
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    for chunk in input.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for c in &chunk[..4 - padding] {
+            bits = (bits << 6) | value(*c)?;
+        }
+        bits <<= 6 * padding as u32;
+        output.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!("", encode(b""));
+        assert_eq!("Zg==", encode(b"f"));
+        assert_eq!("Zm8=", encode(b"fo"));
+        assert_eq!("Zm9vYmFy", encode(b"foobar"));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Some(b"alice:secret".to_vec()), decode("YWxpY2U6c2VjcmV0"));
+        assert_eq!(Some(b"ab".to_vec()), decode("YWI="));
+        assert_eq!(Some(b"a".to_vec()), decode("YQ=="));
+        assert_eq!(None, decode("YQ="));
+        assert_eq!(None, decode("Y!=="));
+        assert_eq!(Some(b"foobar".to_vec()), decode(&encode(b"foobar")));
+    }
+}
@@ -0,0 +1,375 @@
+//! This module keeps the pages rendered by previous builds in the build store
+//! of the state directory, so incremental builds only render the templates of
+//! pages whose inputs changed.
+//!
+//! A rendering is identified by a key hashing everything it depends on: the
+//! values of its context, its template along with the templates it includes
+//! and the assets they reference, and the inputs shared by all pages, e.g. the
+//! config and the translations. Values which change with every build, like
+//! `build.timestamp` or the result of `now()`, are the ones of the build which
+//! rendered the page.
+//!
+//! Only the rendering of templates is cached. The content is still read,
+//! parsed and converted on every build, since the values of all pages, e.g.
+//! listings and taxonomies, are derived from it. Templates which pass a
+//! variable to an asset function reference assets which are only known while
+//! rendering, so they are always rendered, as are protected pages, whose
+//! plain HTML must not be kept in the state directory.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use log::debug;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    list_files,
+    state::{StateDir, Store},
+    template::{self, Context, Dependencies, Value},
+    AssetManifest,
+};
+
+/// Paths of the values which differ on every build and are left out of the
+/// keys.
+const VOLATILE: &[&str] = &["build.timestamp"];
+
+/// Function whose results are written to the output while rendering, so
+/// templates calling it are always rendered.
+const SIDE_EFFECT_FUNCTION: &str = "resize_image";
+
+/// Hashed in place of the content of templates which do not exist.
+const MISSING_TEMPLATE: &str = "\0absent";
+
+/// Extension of the files storing rendered pages.
+const EXTENSION: &str = "json";
+
+/// The 64 bit FNV-1a hash, which unlike the default hasher is stable between
+/// builds.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Hash the value at the path independent of the order of maps.
+fn hash_value(value: &Value, path: &str, state: &mut impl Hasher) {
+    if VOLATILE.contains(&path) {
+        return;
+    }
+    match value {
+        Value::String(s) => {
+            state.write_u8(0);
+            s.hash(state);
+        }
+        Value::Integer(n) => {
+            state.write_u8(1);
+            n.hash(state);
+        }
+        Value::Bool(b) => {
+            state.write_u8(2);
+            b.hash(state);
+        }
+        Value::List(list) => {
+            state.write_u8(3);
+            list.len().hash(state);
+            for value in list {
+                hash_value(value, path, state);
+            }
+        }
+        Value::Map(map) => {
+            state.write_u8(4);
+            map.len().hash(state);
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            for (key, value) in entries {
+                key.hash(state);
+                hash_value(value, &format!("{}.{}", path, key), state);
+            }
+        }
+    }
+}
+
+/// Hash the values by their name independent of their order.
+fn hash_values<'a>(values: impl Iterator<Item = (&'a str, &'a Value)>, state: &mut impl Hasher) {
+    let mut values: Vec<_> = values.collect();
+    values.sort_unstable_by_key(|(name, _)| *name);
+    for (name, value) in values {
+        name.hash(state);
+        hash_value(value, name, state);
+    }
+}
+
+/// Hash the content of the file, which is not required to exist.
+async fn hash_file(path: &Path, state: &mut impl Hasher) {
+    match tokio::fs::read(path).await {
+        Ok(content) => {
+            state.write_u8(1);
+            content.hash(state);
+        }
+        Err(_) => state.write_u8(0),
+    }
+}
+
+/// Pages rendered by previous builds.
+#[derive(Debug)]
+pub struct RenderCache {
+    /// Directory of the store.
+    dir: PathBuf,
+    /// Hash of the inputs shared by all pages.
+    shared: u64,
+    /// Hashes of the templates with their dependencies by their path
+    /// relative to `templates/`, `None` if they must always be rendered.
+    templates: Mutex<HashMap<PathBuf, Option<u64>>>,
+    /// Keys of the pages used by this build.
+    used: Mutex<HashSet<String>>,
+    /// Number of pages reused by this build.
+    reused: AtomicUsize,
+}
+
+impl RenderCache {
+    /// Open the cache of the site, of which the context holds the values
+    /// shared by all pages.
+    pub async fn open(
+        config: &Config,
+        ctx: &Context,
+        manifest: Option<&AssetManifest>,
+    ) -> Result<Self> {
+        let dir = StateDir::new(config).store(Store::Build).await?;
+
+        let mut state = Fnv::default();
+        env!("CARGO_PKG_VERSION").hash(&mut state);
+        hash_file(&config.config_file, &mut state).await;
+        let i18n_dir = config.content_path.join("i18n");
+        if i18n_dir.is_dir() {
+            for file in list_files(&i18n_dir).await? {
+                file.hash(&mut state);
+                hash_file(&i18n_dir.join(file), &mut state).await;
+            }
+        }
+        manifest.hash(&mut state);
+        hash_values(ctx.values(), &mut state);
+
+        Ok(RenderCache {
+            dir,
+            shared: state.finish(),
+            templates: Default::default(),
+            used: Default::default(),
+            reused: AtomicUsize::new(0),
+        })
+    }
+
+    /// Hash of the template with the templates it includes and the assets
+    /// they reference, `None` if it must always be rendered.
+    async fn template_hash(&self, config: &Config, path: &Path) -> Option<u64> {
+        if let Some(hash) = self
+            .templates
+            .lock()
+            .expect("lock is never poisoned")
+            .get(path)
+        {
+            return *hash;
+        }
+
+        let mut dependencies = Dependencies::default();
+        dependencies.add_template(config, path).await;
+        let mut state = Fnv::default();
+        let mut cacheable = true;
+        for template in &dependencies.templates {
+            let Some(file) = template::template_path(config, template) else {
+                continue;
+            };
+            let content = tokio::fs::read_to_string(&file).await.unwrap_or_default();
+            if content.contains(SIDE_EFFECT_FUNCTION) {
+                cacheable = false;
+                break;
+            }
+            template.hash(&mut state);
+            content.hash(&mut state);
+        }
+        // Creating a missing template changes the page, e.g. by an
+        // `include_if_exists`
+        for template in &dependencies.missing {
+            template.hash(&mut state);
+            MISSING_TEMPLATE.hash(&mut state);
+        }
+        cacheable &= !dependencies.dynamic_assets;
+        for asset in &dependencies.assets {
+            asset.hash(&mut state);
+            hash_file(&config.content_path.join("assets").join(asset), &mut state).await;
+        }
+
+        let hash = cacheable.then(|| state.finish());
+        self.templates
+            .lock()
+            .expect("lock is never poisoned")
+            .insert(path.to_path_buf(), hash);
+        hash
+    }
+
+    /// Key of the rendering of the template at the URL, `None` if it must
+    /// always be rendered.
+    async fn key(&self, config: &Config, ctx: &Context, path: &Path, url: &str) -> Option<String> {
+        let mut state = Fnv::default();
+        state.write_u64(self.shared);
+        state.write_u64(self.template_hash(config, path).await?);
+        path.hash(&mut state);
+        url.hash(&mut state);
+        hash_values(ctx.local_values(), &mut state);
+        Some(format!("{:016x}", state.finish()))
+    }
+
+    /// File of the rendering with the key.
+    fn file(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(EXTENSION)
+    }
+
+    /// Pages of a previous rendering with the key, if any.
+    async fn get(&self, key: &str) -> Option<Vec<String>> {
+        let content = tokio::fs::read_to_string(self.file(key)).await.ok()?;
+        // Unreadable entries are rendered again
+        let pages = serde_json::from_str(&content).ok()?;
+        self.used
+            .lock()
+            .expect("lock is never poisoned")
+            .insert(key.to_string());
+        self.reused.fetch_add(1, Ordering::Relaxed);
+        Some(pages)
+    }
+
+    /// Store the pages of the rendering with the key.
+    async fn insert(&self, key: &str, pages: &[String]) -> Result<()> {
+        let path = self.file(key);
+        let content = serde_json::to_string(pages).expect("pages are always serializable");
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|e| Error::WriteFile(path, e))?;
+        self.used
+            .lock()
+            .expect("lock is never poisoned")
+            .insert(key.to_string());
+        Ok(())
+    }
+
+    /// Remove the pages which were not used by this build.
+    pub async fn prune(&self) -> Result<()> {
+        let used = std::mem::take(&mut *self.used.lock().expect("lock is never poisoned"));
+        debug!(
+            "Reused {} of {} cached pages",
+            self.reused.load(Ordering::Relaxed),
+            used.len()
+        );
+        for file in list_files(&self.dir).await? {
+            let stale = file.extension().is_some_and(|ext| ext == EXTENSION)
+                && file
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_none_or(|key| !used.contains(key));
+            if stale {
+                let path = self.dir.join(file);
+                tokio::fs::remove_file(&path)
+                    .await
+                    .map_err(|e| Error::WriteFile(path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render the template once for every page of its pagination like
+/// [`template::template_pages`], reusing the pages of a previous build if the
+/// context has a render cache and none of their inputs changed.
+pub async fn template_pages(
+    config: &Config,
+    ctx: &Context,
+    path: &Path,
+    url: &str,
+) -> Result<Vec<String>> {
+    let Some(cache) = ctx.render_cache() else {
+        return template::template_pages(config, ctx, path, url).await;
+    };
+    let key = cache.key(config, ctx, path, url).await;
+    if let Some(key) = &key {
+        if let Some(pages) = cache.get(key).await {
+            return Ok(pages);
+        }
+    }
+
+    let pages = template::template_pages(config, ctx, path, url).await?;
+    if let Some(key) = &key {
+        cache.insert(key, &pages).await?;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(value: &Value) -> u64 {
+        let mut state = Fnv::default();
+        hash_value(value, "", &mut state);
+        state.finish()
+    }
+
+    #[test]
+    fn test_hash_value() {
+        let map = |entries: &[(&str, i64)]| {
+            Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, n)| (key.to_string(), Value::Integer(*n)))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            hash(&map(&[("a", 1), ("b", 2), ("c", 3)])),
+            hash(&map(&[("c", 3), ("a", 1), ("b", 2)]))
+        );
+        assert_ne!(
+            hash(&map(&[("a", 1), ("b", 2)])),
+            hash(&map(&[("a", 2), ("b", 1)]))
+        );
+        assert_ne!(hash(&Value::from("1")), hash(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_hash_values_volatile() {
+        let build = |timestamp: &str| {
+            Context::from_iter([(
+                "build",
+                Value::from_iter([("profile", "production"), ("timestamp", timestamp)]),
+            )])
+        };
+        let hash = |ctx: &Context| {
+            let mut state = Fnv::default();
+            hash_values(ctx.values(), &mut state);
+            state.finish()
+        };
+        assert_eq!(
+            hash(&build("2024-01-01T00:00:00Z")),
+            hash(&build("2024-01-02T00:00:00Z"))
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! This module rewrites references to assets in the built pages so they are
+//! loaded from a CDN while the pages stay on the main domain.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    error::{Error, Result},
+    html::start_tags,
+    list_files,
+};
+
+/// Attributes which reference assets.
+const ASSET_ATTRIBUTES: &[&str] = &["src", "href", "poster"];
+
+/// Prefix an absolute URL path with the CDN origin.
+pub fn cdn_url(cdn_url: &str, path: &str) -> String {
+    format!("{}{}", cdn_url.trim_end_matches('/'), path)
+}
+
+/// Whether the URL references one of the assets.
+///
+/// Only absolute URL paths are considered, a query or fragment is ignored.
+fn is_asset(assets: &BTreeSet<PathBuf>, url: &str) -> bool {
+    if !url.starts_with('/') || url.starts_with("//") {
+        return false;
+    }
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    assets.contains(Path::new(path.trim_start_matches('/')))
+}
+
+/// Rewrite all asset references of a single page.
+///
+/// Returns the rewritten HTML if it was changed.
+fn rewrite_page(cdn: &str, assets: &BTreeSet<PathBuf>, html: &str) -> Option<String> {
+    let starts: Vec<_> = start_tags(html)
+        .iter()
+        .flat_map(|tag| &tag.attrs)
+        .filter(|attr| {
+            ASSET_ATTRIBUTES
+                .iter()
+                .any(|name| attr.name.eq_ignore_ascii_case(name))
+                && is_asset(assets, attr.value)
+        })
+        .map(|attr| attr.value_start)
+        .collect();
+    if starts.is_empty() {
+        return None;
+    }
+
+    let mut rewritten = html.to_string();
+    for start in starts.into_iter().rev() {
+        rewritten.insert_str(start, cdn.trim_end_matches('/'));
+    }
+    Some(rewritten)
+}
+
+/// Rewrite the references to the assets, given by their path relative to the
+/// output directory, of all HTML pages in the output directory.
+///
+/// Returns the number of rewritten pages.
+pub async fn rewrite_output(
+    cdn: &str,
+    assets: &BTreeSet<PathBuf>,
+    output_path: &Path,
+) -> Result<usize> {
+    let mut rewritten_pages = 0;
+
+    for file in list_files(output_path).await? {
+        if file.extension().and_then(|ext| ext.to_str()) != Some("html") {
+            continue;
+        }
+        let path = output_path.join(&file);
+        let html = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::ReadInput(path.clone(), e))?;
+
+        if let Some(rewritten) = rewrite_page(cdn, assets, &html) {
+            debug!("Rewriting asset URLs of {}", file.display());
+            tokio::fs::write(&path, rewritten)
+                .await
+                .map_err(|e| Error::WriteFile(path, e))?;
+            rewritten_pages += 1;
+        }
+    }
+
+    Ok(rewritten_pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_page() {
+        let assets = BTreeSet::from([PathBuf::from("style.css"), PathBuf::from("img/a.png")]);
+        let html = r#"<link href="/style.css?v=1"><a href="/blog/"><img src=/img/a.png>"#;
+        assert_eq!(
+            r#"<link href="https://cdn.example.com/style.css?v=1"><a href="/blog/"><img src=https://cdn.example.com/img/a.png>"#,
+            rewrite_page("https://cdn.example.com/", &assets, html).unwrap()
+        );
+        assert_eq!(
+            None,
+            rewrite_page("https://cdn", &assets, "<a href=//style.css>")
+        );
+    }
+}
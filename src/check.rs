@@ -0,0 +1,329 @@
+//! This module checks the links of a built site and the lengths of the
+//! titles and descriptions of its pages.
+//!
+//! Links to the site itself must resolve to a file in the output directory.
+//! Links to other sites are optionally checked with a `HEAD` request. Links to
+//! pages and sections must follow the trailing slash policy of the site.
+//! Titles, descriptions and excerpts must fit the length budgets, if given,
+//! so listings and social previews do not truncate them.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    config::{BudgetsConfig, Config, TrailingSlash},
+    error::{Error, Result},
+    html::start_tags,
+    links::percent_decode,
+    list_files, Cli, Index,
+};
+
+/// File discarding everything written to it.
+const NULL_DEVICE: &str = if cfg!(windows) { "NUL" } else { "/dev/null" };
+
+/// Attributes which reference other files.
+const LINK_ATTRIBUTES: &[&str] = &["href", "src"];
+
+/// Schemes of links which are never checked.
+const IGNORED_SCHEMES: &[&str] = &["mailto:", "tel:", "data:", "javascript:"];
+
+/// A link which does not resolve.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// Page containing the link, relative to the output directory.
+    pub page: PathBuf,
+    /// Target of the link as given in the page.
+    pub target: String,
+    /// Why the link is broken.
+    pub reason: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} links to {}: {}",
+            self.page.display(),
+            self.target,
+            self.reason
+        )
+    }
+}
+
+/// Check all links of the pages in the output directory of the site.
+///
+/// Links to other sites are only checked if enabled. Returns the broken links
+/// ordered by their page.
+pub async fn check_links(config: &Config, external: bool) -> Result<Vec<BrokenLink>> {
+    let own_prefixes: Vec<_> = [&config.base_url, &config.cdn_url]
+        .into_iter()
+        .flatten()
+        .map(|prefix| prefix.trim_end_matches('/'))
+        .collect();
+    let output = &config.output_path;
+    let pages = list_files(output)
+        .await?
+        .into_iter()
+        .filter(|file| file.extension().is_some_and(|ext| ext == "html"));
+
+    let mut broken = Vec::new();
+    // Pages by the external links they contain
+    let mut external_links: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for page in pages {
+        let path = output.join(&page);
+        let html = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::ReadInput(path, e))?;
+        let targets = start_tags(&html)
+            .iter()
+            .flat_map(|tag| &tag.attrs)
+            .filter(|attr| {
+                LINK_ATTRIBUTES
+                    .iter()
+                    .any(|name| attr.name.eq_ignore_ascii_case(name))
+            })
+            .map(|attr| attr.value.to_string())
+            .collect::<Vec<_>>();
+
+        for target in targets {
+            if target.is_empty()
+                || target.starts_with('#')
+                || IGNORED_SCHEMES
+                    .iter()
+                    .any(|scheme| target.starts_with(scheme))
+            {
+                continue;
+            }
+            let own = own_prefixes
+                .iter()
+                .find_map(|prefix| target.strip_prefix(prefix))
+                .filter(|rest| rest.is_empty() || rest.starts_with('/'));
+            let url = match own {
+                Some("") => "/",
+                Some(rest) => rest,
+                None if target.contains("://") || target.starts_with("//") => {
+                    external_links.entry(target).or_default().push(page.clone());
+                    continue;
+                }
+                None => target.as_str(),
+            };
+            let file = output.join(resolve(&page, url));
+            let exists = file.is_file() || file.join("index.html").is_file();
+            if !exists {
+                broken.push(BrokenLink {
+                    page: page.clone(),
+                    target: target.clone(),
+                    reason: "file does not exist".to_string(),
+                });
+            } else if !file.is_file() && !conforms(config.trailing_slash, url) {
+                broken.push(BrokenLink {
+                    page: page.clone(),
+                    target: target.clone(),
+                    reason: "does not follow the trailing slash policy".to_string(),
+                });
+            }
+        }
+    }
+
+    if external {
+        check_curl().await?;
+        for (url, pages) in external_links {
+            debug!("Checking external link {}", url);
+            if let Some(reason) = head_request(&url).await? {
+                broken.extend(pages.into_iter().map(|page| BrokenLink {
+                    page,
+                    target: url.clone(),
+                    reason: reason.clone(),
+                }));
+            }
+        }
+    }
+
+    broken.sort_by(|a, b| a.page.cmp(&b.page));
+    Ok(broken)
+}
+
+/// A title, description or excerpt of a page not fitting its length budget.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExceededBudget {
+    /// Content file of the page.
+    pub file: PathBuf,
+    /// What does not fit, e.g. `title`.
+    pub field: &'static str,
+    /// Why it does not fit.
+    pub reason: String,
+}
+
+impl fmt::Display for ExceededBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.file.display(), self.field, self.reason)
+    }
+}
+
+/// Why the text does not fit the range of lengths in characters, if it does
+/// not.
+fn length_violation(text: &str, min: usize, max: usize) -> Option<String> {
+    let length = text.chars().count();
+    if length > max {
+        Some(format!("has {} characters, more than {}", length, max))
+    } else if length < min {
+        Some(format!("has {} characters, less than {}", length, min))
+    } else {
+        None
+    }
+}
+
+/// Check the titles, descriptions and excerpts of all pages against the
+/// length budgets of the site and the overrides of the pages.
+///
+/// Drafts are only checked if they are built.
+pub fn check_budgets(
+    budgets: &BudgetsConfig,
+    indices: &[Index],
+    opts: &Cli,
+) -> Vec<ExceededBudget> {
+    let mut exceeded = Vec::new();
+    let pages = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts));
+    for page in pages {
+        let budgets = budgets.with_overrides(&page.metadata.budgets);
+        let file = &page.metadata.filepath;
+        let mut check = |field, text: &str, min, max| {
+            if let Some(reason) = length_violation(text, min, max) {
+                exceeded.push(ExceededBudget {
+                    file: file.clone(),
+                    field,
+                    reason,
+                });
+            }
+        };
+
+        check("title", &page.metadata.title, 0, budgets.title_max);
+        let description = page.description();
+        let (min, max) = (budgets.description_min, budgets.description_max);
+        check("description", &description, min, max);
+        // The description is commonly the excerpt
+        if let Some(excerpt) = page.excerpt.as_ref().filter(|e| **e != description) {
+            check("excerpt", excerpt, min, max);
+        }
+    }
+    exceeded.sort_by(|a, b| a.file.cmp(&b.file));
+    exceeded
+}
+
+/// Whether the path of the URL of a page follows the trailing slash policy.
+fn conforms(policy: TrailingSlash, url: &str) -> bool {
+    policy.conforms(url.split(['?', '#']).next().unwrap_or_default())
+}
+
+/// Resolve the URL of a link in the page to a path relative to the output
+/// directory.
+fn resolve(page: &Path, url: &str) -> PathBuf {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path).unwrap_or_else(|| path.to_string());
+    let joined = match path.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page.parent().unwrap_or(Path::new("")).join(path),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => resolved.push(name),
+            _ => {}
+        }
+    }
+    resolved
+}
+
+/// Make sure `curl` can be run, which sends the requests for external links.
+async fn check_curl() -> Result<()> {
+    let output = tokio::task::spawn_blocking(|| {
+        std::process::Command::new("curl").arg("--version").output()
+    })
+    .await
+    .map_err(Error::Join)?;
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(Error::Curl(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))),
+        Err(e) => Err(Error::Curl(e)),
+    }
+}
+
+/// Send a `HEAD` request to the URL with `curl`.
+///
+/// Returns why the request failed if it did.
+async fn head_request(url: &str) -> Result<Option<String>> {
+    let url = url.to_string();
+    let output = tokio::task::spawn_blocking(move || {
+        std::process::Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location", "--head"])
+            .args(["--max-time", "10", "--output", NULL_DEVICE, "--", &url])
+            .output()
+    })
+    .await
+    .map_err(Error::Join)?;
+
+    Ok(match output {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(e) => Some(format!("running curl failed: {}", e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let page = Path::new("blog/first/index.html");
+        assert_eq!(PathBuf::from("tags/rust"), resolve(page, "/tags/rust/#top"));
+        assert_eq!(
+            PathBuf::from("blog/first/cover.jpg"),
+            resolve(page, "cover.jpg")
+        );
+        assert_eq!(
+            PathBuf::from("blog/second"),
+            resolve(page, "../second/?v=1")
+        );
+        assert_eq!(
+            PathBuf::from("blog/crème"),
+            resolve(page, "/blog/cr%C3%A8me")
+        );
+    }
+
+    #[test]
+    fn test_length_violation() {
+        assert_eq!(None, length_violation("Crème brûlée", 0, 12));
+        assert_eq!(
+            Some("has 12 characters, more than 11".to_string()),
+            length_violation("Crème brûlée", 0, 11)
+        );
+        assert_eq!(
+            Some("has 5 characters, less than 50".to_string()),
+            length_violation("Short", 50, 160)
+        );
+    }
+
+    #[test]
+    fn test_conforms() {
+        assert!(conforms(TrailingSlash::Always, "/blog/first/#top"));
+        assert!(!conforms(TrailingSlash::Always, "/blog/first?v=1"));
+        assert!(conforms(TrailingSlash::Never, "/blog/first#top"));
+        assert!(conforms(TrailingSlash::Never, "/"));
+        assert!(!conforms(TrailingSlash::Never, "../second/"));
+    }
+}
@@ -1,9 +1,36 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::Error;
 
+/// Direction in which text is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    /// Left to right.
+    #[default]
+    Ltr,
+    /// Right to left.
+    Rtl,
+    /// Determined by the browser based on the content.
+    Auto,
+}
+
+impl TextDirection {
+    /// Value of the HTML `dir` attribute.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+            TextDirection::Auto => "auto",
+        }
+    }
+}
+
 /// Information concerning the site.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteInfo {
@@ -11,6 +38,517 @@ pub struct SiteInfo {
     pub title: String,
     /// Short site description.
     pub description: String,
+    /// Default language of all pages, as used by the HTML `lang` attribute.
+    #[serde(default = "default_lang")]
+    pub lang: String,
+    /// Default text direction of all pages.
+    #[serde(default)]
+    pub dir: TextDirection,
+}
+
+fn default_lang() -> String {
+    "en".to_string()
+}
+
+/// A taxonomy to classify pages with, e.g. tags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Taxonomy {
+    /// Name of the taxonomy.
+    ///
+    /// This is also the frontmatter field listing the terms of a page and the
+    /// URL path the taxonomy is generated at.
+    pub name: String,
+
+    /// Template listing all terms of the taxonomy.
+    /// Relative to `templates/`.
+    #[serde(default = "default_taxonomy_template")]
+    pub template: PathBuf,
+
+    /// Template listing all pages of a single term.
+    /// Relative to `templates/`.
+    #[serde(default = "default_term_template")]
+    pub term_template: PathBuf,
+
+    /// Terms replaced by another term when collecting the terms of pages,
+    /// e.g. `"rust-lang" = "rust"`.
+    ///
+    /// Aliases are matched regardless of their case.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// An author of pages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Author {
+    /// Name of the author as displayed.
+    pub name: String,
+
+    /// Short description of the author.
+    #[serde(default)]
+    pub bio: String,
+}
+
+fn default_taxonomy_template() -> PathBuf {
+    "taxonomy.html".into()
+}
+
+fn default_term_template() -> PathBuf {
+    "taxonomy_term.html".into()
+}
+
+/// An entry of the navigation which is not a page, e.g. a link to another
+/// site.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NavLink {
+    /// Text of the link.
+    pub title: String,
+
+    /// URL the link points to.
+    pub url: String,
+
+    /// Position in the navigation among the entries of the content, as given
+    /// by their `display_in_nav`.
+    pub position: usize,
+}
+
+/// An entry of a menu besides the navigation, e.g. in the footer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItem {
+    /// Text of the link.
+    pub title: String,
+
+    /// URL the link points to, either an absolute URL path of the site or
+    /// the URL of another site.
+    pub url: String,
+
+    /// Entries are ordered by their weight in ascending order.
+    #[serde(default)]
+    pub weight: i64,
+
+    /// Entries nested below the entry.
+    #[serde(default)]
+    pub children: Vec<MenuItem>,
+}
+
+/// Checks of the built pages for accessibility.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessibilityConfig {
+    /// Id of the element a skip link at the start of every page points to.
+    #[serde(default)]
+    pub skip_target: Option<String>,
+
+    /// Text of inserted skip links.
+    #[serde(default = "default_skip_link_text")]
+    pub skip_link_text: String,
+
+    /// Selectors of landmarks every page must contain.
+    ///
+    /// Supported are element names (`main`), ids (`#content`), classes
+    /// (`.content`) and attributes (`[role=main]`).
+    #[serde(default)]
+    pub landmarks: Vec<String>,
+
+    /// Insert missing skip links and their target instead of only reporting
+    /// them.
+    ///
+    /// The target id is added to the `<main>` element.
+    #[serde(default)]
+    pub fix: bool,
+}
+
+fn default_skip_link_text() -> String {
+    "Skip to content".to_string()
+}
+
+/// Whether URLs of pages and sections end with a slash, e.g. `/blog/first/`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// URLs end with a slash.
+    #[default]
+    Always,
+    /// URLs end without a slash, except the one of the root.
+    Never,
+}
+
+impl TrailingSlash {
+    /// Whether the URL path of a page or section follows the policy.
+    pub fn conforms(self, path: &str) -> bool {
+        match self {
+            TrailingSlash::Always => path.ends_with('/'),
+            TrailingSlash::Never => path == "/" || !path.ends_with('/'),
+        }
+    }
+}
+
+/// An Atom feed of the pages of a section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedConfig {
+    /// Section whose pages are listed including its subsections.
+    /// Relative to `content/`, all pages by default.
+    #[serde(default)]
+    pub section: PathBuf,
+
+    /// Path of the feed.
+    /// Relative to the output.
+    #[serde(default = "default_feed_path")]
+    pub path: PathBuf,
+
+    /// Title of the feed, defaults to the title of the site.
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// What entries contain of the pages.
+    #[serde(default)]
+    pub content: FeedContent,
+
+    /// Maximum number of entries, only the newest pages are listed.
+    #[serde(default = "default_feed_limit")]
+    pub limit: usize,
+
+    /// Whether entries carry the `updated` date of pages, so feed readers show
+    /// changed pages again.
+    #[serde(default)]
+    pub include_updated: bool,
+
+    /// Date by which the pages are ordered.
+    #[serde(default)]
+    pub order: FeedOrder,
+
+    /// Whether older pages beyond the limit are kept in archive documents
+    /// linked from the feed (RFC 5005).
+    #[serde(default)]
+    pub archive: bool,
+}
+
+fn default_feed_path() -> PathBuf {
+    "atom.xml".into()
+}
+
+fn default_feed_limit() -> usize {
+    20
+}
+
+/// What entries of a feed contain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedContent {
+    /// The whole content of the page.
+    #[default]
+    Full,
+    /// Only the description of the page.
+    Summary,
+}
+
+/// Date by which the pages of a feed are ordered, newest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedOrder {
+    /// The date the page was published.
+    #[default]
+    Date,
+    /// The date the page was last updated, or published if never updated.
+    Updated,
+}
+
+/// A human-readable page listing all sections and pages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SitemapConfig {
+    /// Template of the sitemap.
+    /// Relative to `templates/`.
+    #[serde(default = "default_sitemap_template")]
+    pub template: PathBuf,
+
+    /// URL path the sitemap is generated at.
+    #[serde(default = "default_sitemap_path")]
+    pub path: PathBuf,
+
+    /// Title of the sitemap page.
+    #[serde(default = "default_sitemap_title")]
+    pub title: String,
+}
+
+fn default_sitemap_template() -> PathBuf {
+    "sitemap.html".into()
+}
+
+fn default_sitemap_path() -> PathBuf {
+    "sitemap".into()
+}
+
+fn default_sitemap_title() -> String {
+    "Sitemap".to_string()
+}
+
+/// Listing pages of the dated pages by year and month.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    /// URL path below which the years are generated, e.g. `archive/2024/`.
+    #[serde(default = "default_archive_path")]
+    pub path: PathBuf,
+
+    /// Template listing the pages of a year.
+    /// Relative to `templates/`.
+    #[serde(default = "default_archive_year_template")]
+    pub year_template: PathBuf,
+
+    /// Template listing the pages of a month.
+    /// Relative to `templates/`.
+    #[serde(default = "default_archive_month_template")]
+    pub month_template: PathBuf,
+}
+
+fn default_archive_path() -> PathBuf {
+    "archive".into()
+}
+
+fn default_archive_year_template() -> PathBuf {
+    "archive_year.html".into()
+}
+
+fn default_archive_month_template() -> PathBuf {
+    "archive_month.html".into()
+}
+
+/// Short links redirecting to pages, which stay valid if pages are moved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortLinksConfig {
+    /// URL path the short links are generated below.
+    #[serde(default = "default_short_links_path")]
+    pub path: PathBuf,
+}
+
+fn default_short_links_path() -> PathBuf {
+    "s".into()
+}
+
+/// Encryption of pages marked as protected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtectionConfig {
+    /// Environment variable containing the password of protected pages.
+    ///
+    /// The password is not part of the config, so it is not committed along
+    /// with the site.
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+
+    /// Iterations of PBKDF2 deriving the key from the password, by default
+    /// the 600,000 recommended by OWASP for PBKDF2-HMAC-SHA-256.
+    #[serde(default = "default_protection_iterations")]
+    pub iterations: u32,
+}
+
+fn default_password_env() -> String {
+    "FWEB_PASSWORD".to_string()
+}
+
+fn default_protection_iterations() -> u32 {
+    600_000
+}
+
+/// Limits of the lengths of titles and descriptions in characters, so they
+/// are not truncated in listings and previews.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetsConfig {
+    /// Maximum length of titles.
+    #[serde(default = "default_title_max")]
+    pub title_max: usize,
+
+    /// Minimum length of descriptions and excerpts.
+    #[serde(default = "default_description_min")]
+    pub description_min: usize,
+
+    /// Maximum length of descriptions and excerpts.
+    #[serde(default = "default_description_max")]
+    pub description_max: usize,
+}
+
+fn default_title_max() -> usize {
+    60
+}
+
+fn default_description_min() -> usize {
+    50
+}
+
+fn default_description_max() -> usize {
+    160
+}
+
+/// Limits of a page overriding the ones of the site.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetOverrides {
+    /// Maximum length of the title.
+    #[serde(default)]
+    pub title_max: Option<usize>,
+
+    /// Minimum length of the description and excerpt.
+    #[serde(default)]
+    pub description_min: Option<usize>,
+
+    /// Maximum length of the description and excerpt.
+    #[serde(default)]
+    pub description_max: Option<usize>,
+}
+
+impl BudgetsConfig {
+    /// Limits with the overrides of a page applied.
+    pub fn with_overrides(&self, overrides: &BudgetOverrides) -> Self {
+        BudgetsConfig {
+            title_max: overrides.title_max.unwrap_or(self.title_max),
+            description_min: overrides.description_min.unwrap_or(self.description_min),
+            description_max: overrides.description_max.unwrap_or(self.description_max),
+        }
+    }
+}
+
+/// Open Graph and Twitter Card meta tags of pages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocialConfig {
+    /// Image of pages which do not give their own, as an absolute URL path or
+    /// URL.
+    #[serde(default)]
+    pub image: Option<String>,
+
+    /// Twitter handle of the site, e.g. `@example`.
+    #[serde(default)]
+    pub twitter_site: Option<String>,
+}
+
+/// Compilation of Sass and SCSS stylesheets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SassConfig {
+    /// Output compressed CSS.
+    #[serde(default)]
+    pub minify: bool,
+}
+
+/// How derived ids, directories and taxonomy terms and thereby URLs are
+/// normalized. Ids given in the frontmatter are used as is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlugConfig {
+    /// Keep letters outside of ASCII instead of transliterating them.
+    #[serde(default)]
+    pub unicode: bool,
+
+    /// Keep upper case letters instead of converting them to lower case.
+    #[serde(default)]
+    pub preserve_case: bool,
+
+    /// Truncate slugs to at most this many characters if given.
+    #[serde(default)]
+    pub max_length: Option<usize>,
+}
+
+/// Options of the markdown conversion.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarkdownConfig {
+    /// Render footnotes with this markup instead of the default if given.
+    #[serde(default)]
+    pub footnotes: Option<FootnoteConfig>,
+
+    /// Replace quotes, dashes and ellipses by their typographic form.
+    ///
+    /// Pages can override this in their frontmatter.
+    #[serde(default)]
+    pub smart_punctuation: bool,
+
+    /// Open links to other sites in a new tab if given.
+    #[serde(default)]
+    pub external_links: Option<ExternalLinksConfig>,
+
+    /// Offer images of the assets in further widths if given.
+    #[serde(default)]
+    pub responsive_images: Option<ResponsiveImagesConfig>,
+
+    /// Render LaTeX math written as `$...$` and `$$...$$` if given.
+    #[serde(default)]
+    pub math: Option<MathConfig>,
+}
+
+/// How math in the content is rendered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MathConfig {
+    /// Whether formulas are rendered in the browser or at build time.
+    #[serde(default)]
+    pub mode: MathMode,
+}
+
+/// Where formulas are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathMode {
+    /// Wrap formulas in elements with delimiters for KaTeX.
+    #[default]
+    Katex,
+    /// Convert formulas to MathML at build time.
+    Mathml,
+}
+
+/// Markup of links to other sites.
+///
+/// These are opened in a new tab with `rel="noopener noreferrer"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalLinksConfig {
+    /// Class added to the links if given, e.g. to show an icon.
+    #[serde(default)]
+    pub class: Option<String>,
+}
+
+/// Widths images in the content are offered in with a `srcset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsiveImagesConfig {
+    /// Widths in pixels of the resized images.
+    ///
+    /// Widths which are not smaller than the image itself are skipped.
+    pub widths: Vec<u32>,
+
+    /// Value of the `sizes` attribute if given.
+    #[serde(default)]
+    pub sizes: Option<String>,
+}
+
+/// Markup of footnotes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FootnoteConfig {
+    /// Text of the link from a footnote back to its reference.
+    #[serde(default)]
+    pub backlink: Option<String>,
+
+    /// Heading of the section listing all footnotes.
+    #[serde(default)]
+    pub heading: Option<String>,
+
+    /// Place footnotes in an `<aside>` right after the block of their
+    /// reference instead of a section at the end.
+    #[serde(default)]
+    pub sidenotes: bool,
+}
+
+/// Typographic improvements of the content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypographyConfig {
+    /// Join the last two words of paragraphs, headings and list items with a
+    /// non-breaking space.
+    #[serde(default)]
+    pub widows: bool,
+
+    /// Typography by language, e.g. `fr` or `de-CH`.
+    #[serde(default)]
+    pub languages: BTreeMap<String, LanguageTypography>,
+}
+
+/// Typographic conventions of a language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageTypography {
+    /// Opening and closing double quotes followed by opening and closing
+    /// single quotes, defaults to English quotes.
+    #[serde(default)]
+    pub quotes: Option<[String; 4]>,
+
+    /// Put a non-breaking space before `;`, `:`, `!`, `?` and `»`.
+    #[serde(default)]
+    pub space_before_punctuation: bool,
 }
 
 /// Generation configuration and global information.
@@ -19,6 +557,10 @@ pub struct Config {
     /// Information about the website
     pub site_info: SiteInfo,
 
+    /// Path of the config file the config was read from.
+    #[serde(skip)]
+    pub config_file: PathBuf,
+
     /// Directory with the website its source files.
     /// Relative to `config.toml`.
     #[serde(default = "default_content_path")]
@@ -28,6 +570,202 @@ pub struct Config {
     /// Relative to `config.toml`.
     #[serde(default = "default_output_path")]
     pub output_path: PathBuf,
+
+    /// Directory where state is kept between builds, e.g. caches.
+    /// Relative to `config.toml`.
+    #[serde(default = "default_state_path")]
+    pub state_path: PathBuf,
+
+    /// Absolute URL the site is hosted at, e.g. `https://example.com`.
+    ///
+    /// Used for permalinks and absolute links.
+    #[serde(default)]
+    pub base_url: Option<String>,
+
+    /// Use absolute URLs based on `base_url` in the navigation and article
+    /// lists instead of URL paths.
+    #[serde(default)]
+    pub absolute_links: bool,
+
+    /// Origin of a CDN hosting the assets, e.g. `https://cdn.example.com`.
+    ///
+    /// Used by the `bust` function and the `cdn_rewrite` pass.
+    #[serde(default)]
+    pub cdn_url: Option<String>,
+
+    /// Rewrite references to assets in the built pages to the `cdn_url`.
+    #[serde(default)]
+    pub cdn_rewrite: bool,
+
+    /// Directory of a theme whose templates are used if the site has no
+    /// template of the same name.
+    /// Relative to `config.toml`.
+    #[serde(default)]
+    pub theme_path: Option<PathBuf>,
+
+    /// Add the content hash to the file names of assets, e.g.
+    /// `style.50a6bae2.css`.
+    ///
+    /// Templates refer to assets with the `asset` function.
+    #[serde(default)]
+    pub fingerprint_assets: bool,
+
+    /// Minify CSS and JavaScript assets in production builds, i.e. builds
+    /// without drafts and future pages.
+    #[serde(default)]
+    pub minify_assets: bool,
+
+    /// Taxonomies pages can be classified with.
+    #[serde(default)]
+    pub taxonomies: Vec<Taxonomy>,
+
+    /// Authors of the pages by the id given in their `authors` field.
+    ///
+    /// Authors which are not listed are shown by their id.
+    #[serde(default)]
+    pub authors: BTreeMap<String, Author>,
+
+    /// Template of the listing pages of all pages of an author, no listing
+    /// pages are generated if not given.
+    /// Relative to `templates/`.
+    #[serde(default)]
+    pub author_template: Option<PathBuf>,
+
+    /// Template of the landing pages of series listing all their parts, no
+    /// landing pages are generated if not given.
+    /// Relative to `templates/`.
+    #[serde(default)]
+    pub series_template: Option<PathBuf>,
+
+    /// How ids and taxonomy terms are normalized for URLs.
+    #[serde(default)]
+    pub slugs: SlugConfig,
+
+    /// Options of the markdown conversion.
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+
+    /// Further entries of the navigation, e.g. links to other sites.
+    #[serde(default)]
+    pub nav: Vec<NavLink>,
+
+    /// Nest the entries of sections in the navigation below them instead of
+    /// listing all entries in order.
+    #[serde(default)]
+    pub nav_groups: bool,
+
+    /// Menus besides the navigation by their name, e.g. `footer`, given to
+    /// templates as `menus.<name>`.
+    #[serde(default)]
+    pub menus: BTreeMap<String, Vec<MenuItem>>,
+
+    /// Maximum number of characters of descriptions derived from the text of
+    /// a page.
+    #[serde(default = "default_description_length")]
+    pub description_length: usize,
+
+    /// Format of the dates of pages and of the `date` filter if none is
+    /// given, e.g. `%B %d, %Y`.
+    ///
+    /// Names of months and weekdays are always in English.
+    ///
+    /// Filters and comparisons read dates in this format, so they only see
+    /// the parts it keeps. `date_iso8601` always keeps the full time.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+
+    /// Improve the typography of the content if given.
+    #[serde(default)]
+    pub typography: Option<TypographyConfig>,
+
+    /// How often shortcodes within values inserted by tags are expanded.
+    ///
+    /// By default values are inserted as is.
+    #[serde(default)]
+    pub tag_expansion_depth: usize,
+
+    /// Compile stylesheets in `assets/` and `sass/` to CSS if given.
+    #[serde(default)]
+    pub sass: Option<SassConfig>,
+
+    /// Generate `search_index.json` with the text of all pages for a client
+    /// side search.
+    #[serde(default)]
+    pub search_index: bool,
+
+    /// Generate short links to all pages if given.
+    #[serde(default)]
+    pub short_links: Option<ShortLinksConfig>,
+
+    /// Whether URLs of pages and sections end with a slash.
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+
+    /// Atom feeds of the pages.
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+
+    /// Generate a sitemap page if given.
+    #[serde(default)]
+    pub sitemap: Option<SitemapConfig>,
+
+    /// Generate archive pages by year and month if given.
+    #[serde(default)]
+    pub archive: Option<ArchiveConfig>,
+
+    /// Check the built pages for a skip link and landmarks if given.
+    #[serde(default)]
+    pub accessibility: Option<AccessibilityConfig>,
+
+    /// Encrypt pages marked as protected with a password if given.
+    #[serde(default)]
+    pub protection: Option<ProtectionConfig>,
+
+    /// Generate Open Graph and Twitter Card meta tags of pages as
+    /// `social_meta` if given.
+    #[serde(default)]
+    pub social: Option<SocialConfig>,
+
+    /// Generate schema.org structured data as JSON-LD, an `Article` of pages
+    /// and a `WebSite` of the homepage, as `structured_data`.
+    #[serde(default)]
+    pub structured_data: bool,
+
+    /// Check the lengths of titles and descriptions with `fweb check` if
+    /// given.
+    #[serde(default)]
+    pub budgets: Option<BudgetsConfig>,
+
+    /// Keep the rendered pages in the state directory and only render the
+    /// pages again whose content, templates or shared values changed.
+    #[serde(default)]
+    pub incremental: bool,
+
+    /// Number of outputs of the last builds kept in the state directory for
+    /// `fweb rollback`, including the published one, none by default.
+    /// A single snapshot leaves nothing to roll back to and is rejected.
+    #[serde(default)]
+    pub snapshots: usize,
+}
+
+/// Several sites which are built together in one invocation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    /// Configs of the sites.
+    /// Relative to the workspace config.
+    pub sites: Vec<PathBuf>,
+
+    /// Theme used by all sites which do not set their own.
+    /// Relative to the workspace config.
+    #[serde(default)]
+    pub theme_path: Option<PathBuf>,
+}
+
+/// A config file which may declare a workspace.
+#[derive(Debug, Deserialize)]
+struct WorkspaceFile {
+    #[serde(default)]
+    workspace: Option<Workspace>,
 }
 
 fn default_content_path() -> PathBuf {
@@ -38,24 +776,213 @@ fn default_output_path() -> PathBuf {
     "_site".into()
 }
 
+fn default_state_path() -> PathBuf {
+    ".fweb".into()
+}
+
+fn default_description_length() -> usize {
+    160
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".into()
+}
+
 impl Config {
+    /// Convert an absolute URL path into an absolute URL based on `base_url`.
+    ///
+    /// The path is returned unchanged if no base URL is configured.
+    pub fn absolute_url(&self, path: &str) -> String {
+        let path = self.url_path(path);
+        match &self.base_url {
+            Some(base_url) => format!("{}{}", base_url.trim_end_matches('/'), path),
+            None => path,
+        }
+    }
+
+    /// URL of a link to the given absolute URL path as configured by
+    /// `absolute_links`.
+    pub fn link_url(&self, path: &str) -> String {
+        if self.absolute_links {
+            self.absolute_url(path)
+        } else {
+            self.url_path(path)
+        }
+    }
+
+    /// Apply the `trailing_slash` policy to an absolute URL path, e.g.
+    /// `/blog/first/#top` becomes `/blog/first#top` if slashes are not wanted.
+    pub fn url_path(&self, path: &str) -> String {
+        let (dir, rest) = path.split_at(path.find(['?', '#']).unwrap_or(path.len()));
+        match (self.trailing_slash, dir.strip_suffix('/')) {
+            (TrailingSlash::Never, Some(stripped)) if !stripped.is_empty() => {
+                format!("{}{}", stripped, rest)
+            }
+            _ => path.to_string(),
+        }
+    }
+
     /// Read and parse site config
     pub async fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
-        // Read and parse config.
         let path = path.as_ref();
         let content = tokio::fs::read_to_string(path)
             .await
             .map_err(|e| Error::ConfigRead(path.into(), e))?;
-        let mut config: Config =
+        Self::parse(path, &content)
+    }
+
+    /// Read and parse either a site config or a workspace config listing the
+    /// configs of several sites.
+    pub async fn load_all(path: impl AsRef<Path>) -> Result<Vec<Self>, Error> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| Error::ConfigRead(path.into(), e))?;
+        let file: WorkspaceFile =
             toml::from_str(&content).map_err(|e| Error::ConfigParse(path.into(), e))?;
+        let Some(workspace) = file.workspace else {
+            return Ok(vec![Self::parse(path, &content)?]);
+        };
+
+        let basedir = path
+            .parent()
+            .expect("file does exist and must have a parent");
+        let mut configs = Vec::with_capacity(workspace.sites.len());
+        for site in &workspace.sites {
+            let mut config = Self::from_file(basedir.join(site)).await?;
+            if config.theme_path.is_none() {
+                config.theme_path = workspace.theme_path.as_ref().map(|p| basedir.join(p));
+            }
+            configs.push(config);
+        }
+        Ok(configs)
+    }
+
+    /// Parse the site config read from the file at `path`.
+    fn parse(path: &Path, content: &str) -> Result<Self, Error> {
+        let mut config: Config =
+            toml::from_str(content).map_err(|e| Error::ConfigParse(path.into(), e))?;
 
         // Make config paths relative to the configuration file.
         let basedir = path
             .parent()
             .expect("file does exist and must have a parent");
+        config.config_file = path.to_path_buf();
         config.content_path = basedir.join(&config.content_path);
         config.output_path = basedir.join(&config.output_path);
+        config.state_path = basedir.join(&config.state_path);
+        config.theme_path = config.theme_path.map(|p| basedir.join(p));
+        config.check_features()?;
+        crate::template::format_date(&time::OffsetDateTime::UNIX_EPOCH, &config.date_format)
+            .map_err(|e| Error::InvalidDateFormat(config.date_format.clone(), e))?;
+        if config.snapshots == 1 {
+            return Err(Error::SingleSnapshot);
+        }
 
         Ok(config)
     }
+
+    /// Fail if the config uses a cargo feature the binary was built without.
+    fn check_features(&self) -> Result<(), Error> {
+        let features = [
+            (
+                "images",
+                self.markdown.responsive_images.is_some(),
+                cfg!(feature = "images"),
+            ),
+            ("math", self.markdown.math.is_some(), cfg!(feature = "math")),
+            ("sass", self.sass.is_some(), cfg!(feature = "sass")),
+        ];
+        match features.iter().find(|(_, used, built)| *used && !built) {
+            Some((feature, ..)) => Err(Error::MissingFeature(feature)),
+            None => Ok(()),
+        }
+    }
+
+    /// Directories searched for templates in order.
+    pub fn template_dirs(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        std::iter::once(self.content_path.join("templates")).chain(
+            self.theme_path
+                .iter()
+                .map(|theme_path| theme_path.join("templates")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_config(options: &str) -> Config {
+        let content = format!(
+            "{}\n[site_info]\ntitle = \"Test\"\ndescription = \"\"\n",
+            options
+        );
+        Config::parse(Path::new("config.toml"), &content).unwrap()
+    }
+
+    #[test]
+    fn test_absolute_url() {
+        let config = parse_config("base_url = \"https://example.com/\"");
+        assert_eq!(
+            "https://example.com/blog/first/",
+            config.absolute_url("/blog/first/")
+        );
+        assert_eq!("/blog/first/", config.link_url("/blog/first/"));
+
+        let config = parse_config("base_url = \"https://example.com/docs\"\nabsolute_links = true");
+        assert_eq!(
+            "https://example.com/docs/blog/first/",
+            config.link_url("/blog/first/")
+        );
+
+        let config = parse_config("");
+        assert_eq!("/blog/first/", config.absolute_url("/blog/first/"));
+    }
+
+    #[test]
+    fn test_url_path() {
+        let config = parse_config("trailing_slash = \"never\"");
+        assert_eq!("/", config.url_path("/"));
+        assert_eq!("/blog/first#top", config.url_path("/blog/first/#top"));
+        assert_eq!("/feed.xml?v=1", config.url_path("/feed.xml?v=1"));
+        assert_eq!("/blog/first/", parse_config("").url_path("/blog/first/"));
+    }
+
+    #[tokio::test]
+    async fn test_load_workspace() {
+        let dir = std::env::temp_dir().join(format!("fweb-workspace-{}", std::process::id()));
+        let site_info = "[site_info]\ntitle = \"Test\"\ndescription = \"\"\n";
+        for (file, content) in [
+            (
+                "fweb.toml",
+                "[workspace]\nsites = [\"a/config.toml\", \"b/config.toml\"]\ntheme_path = \"theme\"\n",
+            ),
+            ("a/config.toml", site_info),
+            ("b/config.toml", &format!("theme_path = \"own\"\n{}", site_info)),
+        ] {
+            let path = dir.join(file);
+            tokio::fs::create_dir_all(path.parent().unwrap())
+                .await
+                .unwrap();
+            tokio::fs::write(path, content).await.unwrap();
+        }
+
+        let configs = Config::load_all(dir.join("fweb.toml")).await.unwrap();
+        let single = Config::load_all(dir.join("a/config.toml")).await.unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        let paths: Vec<_> = configs
+            .iter()
+            .map(|config| (config.content_path.clone(), config.theme_path.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (dir.join("a/."), Some(dir.join("theme"))),
+                (dir.join("b/."), Some(dir.join("b/own"))),
+            ],
+            paths
+        );
+        assert_eq!(1, single.len());
+        assert_eq!(None, single[0].theme_path);
+    }
 }
@@ -0,0 +1,316 @@
+//! This module rebuilds sites periodically, e.g. to publish pages once their
+//! date is reached.
+//!
+//! Every build is written to a staging directory next to the output, which
+//! replaces the output once the build succeeded. A failed build keeps the
+//! previous output published. Between moving the previous output aside and
+//! moving the build in its place, the output is missing for a moment, so a
+//! web server serving it directly may answer a few requests with not found.
+//!
+//! Changes of the config files are picked up between builds. The configs are
+//! read again and all sites are rebuilt right away.
+//!
+//! With the error overlay, the errors of a failed build are written to the
+//! build store, from where `fweb serve` shows them in a banner on its pages.
+//! The published output is never touched, and the next successful build
+//! removes the diagnostics again.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, error, info, warn};
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    snapshot,
+    state::{StateDir, Store},
+    verify::{self, Difference},
+    Cli, Website,
+};
+
+/// Time between two checks of the config files for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// File name of the diagnostics of the last failed build in the build store.
+const DIAGNOSTICS_FILE: &str = "diagnostics.txt";
+
+/// Longest interval between two builds.
+const MAX_INTERVAL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Parse an interval like `90s`, `30m`, `1h` or `1d`, at most a year.
+pub fn parse_interval(input: &str) -> std::result::Result<Duration, String> {
+    let input = input.trim();
+    let unit_start = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(unit_start);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' does not start with a number", input))?;
+    let seconds = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{}', expected s, m, h or d", unit)),
+    };
+    match number.checked_mul(seconds).map(Duration::from_secs) {
+        Some(Duration::ZERO) => Err("the interval must not be zero".to_string()),
+        Some(interval) if interval <= MAX_INTERVAL => Ok(interval),
+        _ => Err("the interval must not be longer than a year".to_string()),
+    }
+}
+
+/// Path next to the output with the suffix appended to its name.
+pub fn sibling_path(output_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = output_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    name.push(suffix);
+    output_path.with_file_name(name)
+}
+
+/// Config files of the sites, including the workspace config if any.
+fn config_files(opts: &Cli, configs: &[Config]) -> Vec<PathBuf> {
+    let mut files = vec![opts.config_path.clone()];
+    for config in configs {
+        if !files.contains(&config.config_file) {
+            files.push(config.config_file.clone());
+        }
+    }
+    files
+}
+
+/// Modification times of the files, `None` for files which cannot be read.
+async fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    let mut times = Vec::with_capacity(files.len());
+    for file in files {
+        let metadata = tokio::fs::metadata(file).await.ok();
+        times.push(metadata.and_then(|metadata| metadata.modified().ok()));
+    }
+    times
+}
+
+/// Build the sites again and again with the interval between the builds.
+///
+/// Failed builds are logged and retried with the next build. If a config
+/// file changes, the caches of previous builds are removed and the sites are
+/// rebuilt with the new configs. Configs which fail to load keep the
+/// previous ones in use.
+pub async fn run(
+    mut configs: Vec<Config>,
+    opts: &Cli,
+    interval: Duration,
+    error_overlay: bool,
+) -> Result<()> {
+    let mut files = config_files(opts, &configs);
+    let mut modified = modification_times(&files).await;
+    loop {
+        for config in &configs {
+            info!("Rebuilding {}", config.site_info.title);
+            let diagnostics = match rebuild(config, opts).await {
+                Ok(()) => None,
+                Err(e) => {
+                    error!("Rebuilding {} failed: {}", config.site_info.title, e);
+                    error_overlay.then(|| e.to_string())
+                }
+            };
+            if let Err(e) = write_diagnostics(config, diagnostics.as_deref()).await {
+                warn!(
+                    "Updating the error overlay of {} failed: {}",
+                    config.site_info.title, e
+                );
+            }
+        }
+        debug!("Next build in {:?}", interval);
+
+        let now = Instant::now();
+        let next_build = now.checked_add(interval.min(MAX_INTERVAL)).unwrap_or(now);
+        while let Some(remaining) = next_build.checked_duration_since(Instant::now()) {
+            tokio::time::sleep(remaining.min(CONFIG_POLL_INTERVAL)).await;
+            let current = modification_times(&files).await;
+            if current == modified {
+                continue;
+            }
+            modified = current;
+            match Config::load_all(&opts.config_path).await {
+                Ok(reloaded) => {
+                    info!("Config changed, rebuilding");
+                    for config in &reloaded {
+                        if let Err(e) = StateDir::new(config).clean_store(Store::Build).await {
+                            warn!(
+                                "Clearing the build cache of {} failed: {}",
+                                config.site_info.title, e
+                            );
+                        }
+                    }
+                    configs = reloaded;
+                    files = config_files(opts, &configs);
+                    modified = modification_times(&files).await;
+                    break;
+                }
+                Err(e) => error!("Reloading the config failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Build the site to the staging directory and publish it if anything
+/// changed.
+async fn rebuild(config: &Config, opts: &Cli) -> Result<()> {
+    let output_path = &config.output_path;
+    let staging_path = sibling_path(output_path, ".staging");
+    let staging_config = Config {
+        output_path: staging_path.clone(),
+        ..config.clone()
+    };
+    Website::new(staging_config).build(opts).await?;
+
+    let published = output_path.is_dir();
+    let differences = match published {
+        true => verify::compare_dirs(&staging_path, output_path).await?,
+        false => Vec::new(),
+    };
+    let (mut added, mut changed, mut removed) = (0, 0, 0);
+    for difference in &differences {
+        match difference {
+            Difference::Unexpected(path) => {
+                added += 1;
+                info!("Added {}", path.display());
+            }
+            Difference::Changed(path) => {
+                changed += 1;
+                info!("Changed {}", path.display());
+            }
+            Difference::Missing(path) => {
+                removed += 1;
+                info!("Removed {}", path.display());
+            }
+        }
+    }
+
+    if published && differences.is_empty() {
+        info!("Nothing changed");
+        return tokio::fs::remove_dir_all(&staging_path)
+            .await
+            .map_err(|e| Error::OutputPathClean(staging_path, e));
+    }
+    publish(&staging_path, output_path).await?;
+    if !opts.is_preview() {
+        snapshot::take_snapshot(config).await?;
+    }
+    match published {
+        true => info!(
+            "Published {} added, {} changed and {} removed files",
+            added, changed, removed
+        ),
+        false => info!("Published the first build"),
+    }
+    Ok(())
+}
+
+/// Replace the output by the staging directory.
+///
+/// Both are renamed, so the output is never partially written. The renames
+/// are two steps though, in between the output does not exist.
+pub async fn publish(staging_path: &Path, output_path: &Path) -> Result<()> {
+    let previous_path = sibling_path(output_path, ".previous");
+    let clean = |path: PathBuf| async move {
+        match tokio::fs::remove_dir_all(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Error::OutputPathClean(path, e))
+            }
+            _ => Ok(()),
+        }
+    };
+
+    clean(previous_path.clone()).await?;
+    if output_path.is_dir() {
+        tokio::fs::rename(output_path, &previous_path)
+            .await
+            .map_err(|e| Error::Publish(output_path.to_path_buf(), e))?;
+    }
+    tokio::fs::rename(staging_path, output_path)
+        .await
+        .map_err(|e| Error::Publish(output_path.to_path_buf(), e))?;
+    clean(previous_path).await
+}
+
+/// Path of the diagnostics of the last failed build of the site, which are
+/// shown in the error overlay of the preview server.
+pub fn diagnostics_path(config: &Config) -> PathBuf {
+    StateDir::new(config)
+        .path()
+        .join(Store::Build.dir_name())
+        .join(DIAGNOSTICS_FILE)
+}
+
+/// Write the diagnostics of a failed build, or remove them if there are
+/// none.
+async fn write_diagnostics(config: &Config, diagnostics: Option<&str>) -> Result<()> {
+    let path = diagnostics_path(config);
+    match diagnostics {
+        Some(diagnostics) => {
+            StateDir::new(config).store(Store::Build).await?;
+            tokio::fs::write(&path, diagnostics)
+                .await
+                .map_err(|e| Error::WriteFile(path, e))
+        }
+        None => match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::WriteFile(path, e)),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(Ok(Duration::from_secs(90)), parse_interval("90s"));
+        assert_eq!(Ok(Duration::from_secs(1800)), parse_interval("30m"));
+        assert_eq!(Ok(Duration::from_secs(3600)), parse_interval("1h"));
+        assert_eq!(Ok(Duration::from_secs(86400)), parse_interval("1d"));
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("1w").is_err());
+        assert_eq!(Ok(MAX_INTERVAL), parse_interval("365d"));
+        assert!(parse_interval("366d").is_err());
+        assert!(parse_interval("18446744073709551615d").is_err());
+    }
+
+    #[test]
+    fn test_sibling_path() {
+        assert_eq!(
+            PathBuf::from("site/_site.staging"),
+            sibling_path(Path::new("site/_site"), ".staging")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_diagnostics() {
+        let config = Config {
+            state_path: std::env::temp_dir()
+                .join(format!("fweb-diagnostics-{}", std::process::id())),
+            ..dummy_config()
+        };
+        let path = diagnostics_path(&config);
+
+        write_diagnostics(&config, Some("a < b")).await.unwrap();
+        assert_eq!("a < b", tokio::fs::read_to_string(&path).await.unwrap());
+        write_diagnostics(&config, None).await.unwrap();
+        assert!(!path.exists());
+        // Removing diagnostics which do not exist is fine
+        write_diagnostics(&config, None).await.unwrap();
+
+        tokio::fs::remove_dir_all(&config.state_path).await.unwrap();
+    }
+}
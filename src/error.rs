@@ -7,6 +7,23 @@ pub enum Error {
     #[error("Cleaning output directory {0} failed: {1}")]
     OutputPathClean(PathBuf, std::io::Error),
 
+    #[error("Publishing the output directory {0} failed: {1}")]
+    Publish(PathBuf, std::io::Error),
+
+    #[error("Site {0} has no snapshot of a previous build to roll back to")]
+    NoSnapshot(String),
+
+    #[error("Snapshot {0} does not match its manifest")]
+    DamagedSnapshot(PathBuf),
+
+    #[error(
+        "Keeping a single snapshot leaves none to roll back to, set snapshots to 0 or at least 2"
+    )]
+    SingleSnapshot,
+
+    #[error("Cleaning state directory {0} failed: {1}")]
+    StateClean(PathBuf, std::io::Error),
+
     #[error("Parsing config file {0} failed: {1}")]
     ConfigParse(PathBuf, toml::de::Error),
 
@@ -23,7 +40,96 @@ pub enum Error {
     WriteFile(PathBuf, std::io::Error),
 
     #[error("Parsing metadata from frontmatter failed for {0}: {1}")]
-    ParseMetadata(PathBuf, toml::de::Error),
+    ParseMetadata(PathBuf, String),
+
+    #[error("Page {0} has no id and none can be derived from its title")]
+    EmptyId(PathBuf),
+
+    #[error("Directory {0} has no characters left for its URL")]
+    EmptyDirSlug(PathBuf),
+
+    #[error("Author '{0}' has no characters left for its URL")]
+    EmptyAuthorSlug(String),
+
+    #[error("Term '{0}' of {1} has no characters left for its URL")]
+    EmptyTermSlug(String, String),
+
+    #[error("{1} and {2} both have the URL {0}")]
+    DuplicateUrl(String, PathBuf, PathBuf),
+
+    #[error("Only a single site can be verified, not a workspace")]
+    VerifyWorkspace,
+
+    #[error("Only a single site can render a page, not a workspace")]
+    RenderWorkspace,
+
+    #[error("Only a single site can serve editors, not a workspace")]
+    RpcWorkspace,
+
+    #[error("Only a single site can explain an output file, not a workspace")]
+    ExplainWorkspace,
+
+    #[error("Only a single site can be refactored, not a workspace")]
+    RefactorWorkspace,
+
+    #[error("No section is located at {0}")]
+    UnknownSection(String),
+
+    #[error("Cannot move the section to {0}, which exists or is within the section")]
+    RefactorTarget(String),
+
+    #[error("Only a single site can be served, not a workspace")]
+    ServeWorkspace,
+
+    #[error("Serving on {0} failed: {1}")]
+    Serve(std::net::SocketAddr, std::io::Error),
+
+    #[error("Checking external links requires curl, which cannot be run: {0}")]
+    Curl(std::io::Error),
+
+    #[error("Alias {0} is not a URL path within the site")]
+    InvalidAlias(String),
+
+    #[error("{0} is not built from a page, section or asset")]
+    UnknownOutput(PathBuf),
+
+    #[error("{0} links to {1} which does not exist")]
+    InternalLink(PathBuf, String),
+
+    #[error("Pages {1} and {2} have the same id '{0}'")]
+    DuplicateId(String, PathBuf, PathBuf),
+
+    #[error("Terms '{1}' and '{2}' of {3} both have the slug '{0}'")]
+    SlugCollision(String, String, String, String),
+
+    #[error("Parsing translations {0} failed: {1}")]
+    ParseTranslations(PathBuf, toml::de::Error),
+
+    #[error("Parsing registry {0} failed: {1}")]
+    ParseRegistry(PathBuf, toml::de::Error),
+
+    #[error("Serializing registry failed: {0}")]
+    SerializeRegistry(toml::ser::Error),
+
+    #[error("The `{0}` feature is used by the config but was not built")]
+    MissingFeature(&'static str),
+
+    #[error("Date format '{0}' is invalid: {1}")]
+    InvalidDateFormat(String, String),
+
+    #[cfg(feature = "sass")]
+    #[error("Compiling stylesheet {0} failed: {1}")]
+    Sass(PathBuf, String),
+
+    #[cfg(feature = "images")]
+    #[error("Processing image {0} failed: {1}")]
+    Image(PathBuf, String),
+
+    #[error("Minifying {0} failed: {1}")]
+    Minify(PathBuf, String),
+
+    #[error("Serializing JSON failed: {0}")]
+    Json(serde_json::Error),
 
     #[error("Reading directory {0} failed: {1}")]
     ReadDirectory(PathBuf, std::io::Error),
@@ -43,8 +149,89 @@ pub enum Error {
     #[error("Could not include file {0}: {1}")]
     IncludeShortcode(PathBuf, std::io::Error),
 
+    #[error("In template {0}: {1}")]
+    InTemplate(PathBuf, Box<Error>),
+
+    #[error("In content {0}: {1}")]
+    InContent(PathBuf, Box<Error>),
+
+    #[error("Template {0} requires `{1}`")]
+    MissingRequired(PathBuf, String),
+
+    #[error("In value of tag '{0}': {1}")]
+    InTag(String, Box<Error>),
+
+    #[error("Including {0} exceeds the maximum include depth")]
+    IncludeDepth(PathBuf),
+
+    #[error("Shortcode '{0}' exceeds the maximum nesting depth")]
+    ShortcodeDepth(String),
+
+    #[error("{0} is outside of the {1} directory")]
+    OutsideDirectory(PathBuf, &'static str),
+
     #[error("Tag '{0}' does not exist")]
     TagNotFound(String),
+
+    #[error("Could not parse literal '{0}'")]
+    ParseLiteral(String),
+
+    #[error("Filter '{0}' does not exist")]
+    UnknownFilter(String),
+
+    #[error("Filter '{0}' failed: {1}")]
+    Filter(String, String),
+
+    #[error("Function '{0}' does not exist")]
+    UnknownFunction(String),
+
+    #[error("Function '{0}' failed: {1}")]
+    Function(String, String),
+
+    #[error("Tag '{0}' is not text and cannot be inserted")]
+    TagNotText(String),
+
+    #[error("Arguments of shortcode '{0}' must be named")]
+    ShortcodeArgument(String),
+
+    #[error("Header '{1}' in {0} is not a valid HTTP header")]
+    InvalidHeader(PathBuf, String),
+
+    #[error("Value '{0}' is not a list")]
+    NotAList(String),
+
+    #[error("Cannot compare the values in condition '{0}'")]
+    Compare(String),
+
+    #[error("Terms of taxonomy '{1}' in {0} must be a list of strings")]
+    InvalidTerms(PathBuf, String),
+
+    #[error("Built output differs from the expected output in {0} files")]
+    Verify(usize),
+
+    #[error("Found {0} broken links")]
+    BrokenLinks(usize),
+
+    #[error("Found {0} titles and descriptions exceeding their length budgets")]
+    ExceededBudgets(usize),
+
+    #[error("Found {0} content files whose template does not exist")]
+    MissingTemplates(usize),
+
+    #[error("Pages are protected but no protection is configured")]
+    ProtectionNotConfigured,
+
+    #[error("Protected pages require a password in the environment variable {0}")]
+    MissingPassword(String),
+
+    #[error("Generating random numbers failed: {0}")]
+    Random(String),
+
+    #[error("Block '{0}' is never closed")]
+    UnclosedBlock(String),
+
+    #[error("Unexpected block shortcode '{0}'")]
+    UnexpectedBlock(String),
 }
 
 /// Wrapper around the [Error]
@@ -0,0 +1,218 @@
+//! This module explains which files an output file of the site is built from,
+//! e.g. to find out why a page changed.
+//!
+//! The files are found from the content and the templates without rendering,
+//! so templates included within conditions are listed even if the output file
+//! does not use them. Pages, sections, the assets of page bundles and assets
+//! can be explained.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    load_and_parse_content,
+    not_found::{NOT_FOUND_FILE, NOT_FOUND_PAGE, NOT_FOUND_TEMPLATE},
+    parse_file,
+    template::{self, Context, Dependencies},
+    Cli,
+};
+
+/// Files an output file is built from.
+#[derive(Debug)]
+pub struct Explanation {
+    /// Output file relative to the output directory.
+    output: PathBuf,
+    /// Config file of the site.
+    config: PathBuf,
+    /// Content file or asset the output file is built from, `None` for
+    /// sections without an `_index.md`.
+    source: Option<PathBuf>,
+    /// Shortcodes used in the content.
+    shortcodes: BTreeSet<String>,
+    /// Files of the templates used to render the content.
+    templates: Vec<PathBuf>,
+    /// Assets referenced by the templates.
+    assets: Vec<PathBuf>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.output.display())?;
+        writeln!(f, "  config: {}", self.config.display())?;
+        match &self.source {
+            Some(source) => writeln!(f, "  source: {}", source.display())?,
+            None => writeln!(f, "  source: none, implicit section")?,
+        }
+        for shortcode in &self.shortcodes {
+            writeln!(f, "  shortcode: {}", shortcode)?;
+        }
+        for template in &self.templates {
+            writeln!(f, "  template: {}", template.display())?;
+        }
+        for asset in &self.assets {
+            writeln!(f, "  asset: {}", asset.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the output file relative to the output directory.
+///
+/// The file is given relative to the current directory or the output
+/// directory. Directories stand for their `index.html`.
+fn output_relpath(config: &Config, file: &Path) -> PathBuf {
+    let relpath = file
+        .strip_prefix(&config.output_path)
+        .ok()
+        .map(Path::to_path_buf)
+        .or_else(|| {
+            let file = std::fs::canonicalize(file).ok()?;
+            let output = std::fs::canonicalize(&config.output_path).ok()?;
+            file.strip_prefix(output).ok().map(Path::to_path_buf)
+        })
+        .unwrap_or_else(|| file.to_path_buf());
+    let relpath: PathBuf = relpath
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    match relpath.extension() {
+        Some(_) => relpath,
+        None => relpath.join("index.html"),
+    }
+}
+
+/// Whether the output file is the HTML of the page written to the
+/// directory, including further pages of a pagination and parts.
+fn is_page_output(relpath: &Path, dir: &Path) -> bool {
+    let Ok(rest) = relpath.strip_prefix(dir) else {
+        return false;
+    };
+    let components: Vec<_> = rest.iter().filter_map(|c| c.to_str()).collect();
+    match components.as_slice() {
+        ["index.html"] => true,
+        ["page" | "part", number, "index.html"] => number.parse::<usize>().is_ok(),
+        _ => false,
+    }
+}
+
+/// Leave out the `./` components of the path for display.
+fn without_current_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Path relative to the current directory of a file of the site.
+fn site_path(config: &Config, path: &Path) -> PathBuf {
+    without_current_dir(&config.content_path.join(path))
+}
+
+/// Find the files the output file at the path is built from.
+pub async fn explain(config: &Config, opts: &Cli, file: &Path) -> Result<Explanation> {
+    let output = output_relpath(config, file);
+    let mut explanation = Explanation {
+        output: output.clone(),
+        config: config.config_file.clone(),
+        source: None,
+        shortcodes: BTreeSet::new(),
+        templates: Vec::new(),
+        assets: Vec::new(),
+    };
+
+    // Content of the page or section and its template
+    let mut content = None;
+    if output == Path::new(NOT_FOUND_FILE) {
+        content = Some((Path::new(NOT_FOUND_PAGE), Path::new(NOT_FOUND_TEMPLATE)));
+    }
+    let indices = load_and_parse_content(config, &Context::new(), opts).await?;
+    for index in &indices {
+        if is_page_output(&output, &index.url_dir) {
+            content = Some((&index.metadata.filepath, &index.metadata.template));
+        }
+        for page in &index.pages {
+            let dir = page.output_dir();
+            if is_page_output(&output, &dir) {
+                content = Some((&page.metadata.filepath, &page.metadata.template));
+            } else if let Some(bundle) = page
+                .bundle
+                .as_ref()
+                .filter(|_| output.parent() == Some(&dir))
+            {
+                let asset = bundle.join(output.file_name().expect("output is a file"));
+                explanation.source = Some(site_path(config, &Path::new("content").join(asset)));
+                return Ok(explanation);
+            }
+        }
+    }
+
+    let Some((relpath, template)) = content else {
+        // Stylesheets are compiled from sass
+        let mut candidates = vec![output.clone()];
+        if output.extension().is_some_and(|ext| ext == "css") {
+            candidates.push(output.with_extension("scss"));
+            candidates.push(output.with_extension("sass"));
+        }
+        let asset = candidates
+            .into_iter()
+            .map(|candidate| site_path(config, &Path::new("assets").join(candidate)))
+            .find(|asset| asset.is_file())
+            .ok_or_else(|| Error::UnknownOutput(output.clone()))?;
+        explanation.source = Some(asset);
+        return Ok(explanation);
+    };
+
+    let mut dependencies = Dependencies::default();
+    let source = site_path(config, &Path::new("content").join(relpath));
+    if source.is_file() {
+        let input = tokio::fs::read_to_string(&source)
+            .await
+            .map_err(|e| Error::ReadInput(source.clone(), e))?;
+        let (_, markdown) = parse_file(&input, &source)?;
+        explanation.shortcodes = template::shortcode_names(config, markdown);
+        explanation.source = Some(source);
+    }
+    for shortcode in &explanation.shortcodes {
+        dependencies.add_shortcode(config, shortcode).await;
+    }
+    dependencies.add_template(config, template).await;
+
+    explanation.templates = dependencies
+        .templates
+        .iter()
+        .filter_map(|template| template::template_path(config, template))
+        .map(|file| without_current_dir(&file))
+        .collect();
+    explanation.assets = dependencies
+        .assets
+        .iter()
+        .map(|asset| site_path(config, &Path::new("assets").join(asset)))
+        .collect();
+    Ok(explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_page_output() {
+        let dir = Path::new("blog/first");
+        assert!(is_page_output(Path::new("blog/first/index.html"), dir));
+        assert!(is_page_output(
+            Path::new("blog/first/part/2/index.html"),
+            dir
+        ));
+        assert!(is_page_output(
+            Path::new("blog/first/page/3/index.html"),
+            dir
+        ));
+        assert!(!is_page_output(Path::new("blog/first/dot.svg"), dir));
+        assert!(!is_page_output(Path::new("blog/first/x/index.html"), dir));
+        assert!(!is_page_output(Path::new("blog/index.html"), dir));
+    }
+}
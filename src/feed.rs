@@ -0,0 +1,360 @@
+//! This module generates Atom feeds of the dated pages of a section.
+//!
+//! Feeds with archives keep all pages beyond the newest ones of the feed in
+//! archive documents of the size of the feed, which are linked from the feed
+//! as of RFC 5005. The archives are ordered by the publication date and
+//! filled from the oldest page on, so earlier archives stay the same as new
+//! pages are published, even if the feed is ordered by updates.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use pulldown_cmark::escape::escape_html;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{Config, FeedConfig, FeedContent, FeedOrder},
+    error::{Error, Result},
+    format_date_iso8601,
+    links::ContentUrls,
+    template::Context,
+    Cli, Index, Page,
+};
+
+/// Escape text for the content of an XML element or attribute.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    escape_html(&mut escaped, text).expect("writing to a string never fails");
+    escaped
+}
+
+/// Namespace of the feed history elements of RFC 5005.
+const HISTORY_NAMESPACE: &str = "http://purl.org/syndication/history/1.0";
+
+/// All pages of the feed, newest first by the order of the feed.
+///
+/// Drafts are only listed if enabled, pages without a date never.
+fn entries<'a>(feed: &FeedConfig, opts: &Cli, indices: &'a [Index]) -> Vec<&'a Page> {
+    let mut pages: Vec<_> = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.dir().starts_with(&feed.section))
+        .filter(|page| page.metadata.date.is_some())
+        .filter(|page| page.is_published(opts))
+        .collect();
+    pages.sort_by_key(|page| {
+        std::cmp::Reverse(match feed.order {
+            FeedOrder::Date => page.metadata.date,
+            FeedOrder::Updated => page.metadata.updated.or(page.metadata.date),
+        })
+    });
+    pages
+}
+
+/// Split the entries, newest first, into the ones of the feed and its
+/// archives, oldest archive first.
+///
+/// The feed holds the newest entries up to the limit. The remaining ones are
+/// ordered by the key, the publication date, and split into archives of the
+/// limit from the oldest one on, so only the newest archive may be partial.
+fn split_archives<T: Copy, K: Ord>(
+    entries: &[T],
+    limit: usize,
+    key: impl Fn(T) -> K,
+) -> (Vec<T>, Vec<Vec<T>>) {
+    let limit = limit.max(1);
+    let current = entries.iter().take(limit).copied().collect();
+    let mut oldest_first: Vec<_> = entries.iter().skip(limit).copied().collect();
+    oldest_first.sort_by_key(|entry| key(*entry));
+    let archives = oldest_first
+        .chunks(limit)
+        .map(|chunk| chunk.iter().rev().copied().collect())
+        .collect();
+    (current, archives)
+}
+
+/// Path of the archive document with the number, counted from 1 for the
+/// oldest one, e.g. `blog/atom-archive-1.xml`.
+fn archive_path(feed: &FeedConfig, number: usize) -> PathBuf {
+    let stem = feed
+        .path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("atom");
+    feed.path
+        .with_file_name(format!("{}-archive-{}.xml", stem, number))
+}
+
+/// Links of a document to the other documents of the feed history.
+#[derive(Debug, Default)]
+struct History {
+    /// Whether the document is an archive instead of the feed.
+    archived: bool,
+    /// Paths by the relation, e.g. `prev-archive`.
+    links: Vec<(&'static str, PathBuf)>,
+}
+
+/// Name of the author with the id, the id itself if the author is missing in
+/// the config.
+fn author_name<'a>(config: &'a Config, id: &'a str) -> &'a str {
+    config
+        .authors
+        .get(id)
+        .map_or(id, |author| author.name.as_str())
+}
+
+/// Render the feed or the archive at the path as Atom XML.
+///
+/// The pages are given along with their rendered content. The site is the
+/// author of the feed and thus of all entries without authors. A document
+/// without entries is updated at the time of the build.
+fn atom(
+    config: &Config,
+    feed: &FeedConfig,
+    path: &Path,
+    pages: &[(&Page, Cow<str>)],
+    history: &History,
+) -> String {
+    let title = feed.title.as_deref().unwrap_or(&config.site_info.title);
+    let section_url = match feed.section.to_str() {
+        Some("") => config.absolute_url("/"),
+        _ => config.absolute_url(&format!("/{}/", feed.section.display())),
+    };
+    let feed_url = config.absolute_url(&format!("/{}", path.display()));
+    let updated = |page: &Page| match feed.include_updated {
+        true => page.metadata.updated.or(page.metadata.date),
+        false => page.metadata.date,
+    };
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    match feed.archive {
+        true => xml.push_str(&format!(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:fh=\"{}\">\n",
+            HISTORY_NAMESPACE
+        )),
+        false => xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n"),
+    }
+    if history.archived {
+        xml.push_str("  <fh:archive/>\n");
+    }
+    xml.push_str(&format!("  <title>{}</title>\n", escape(title)));
+    xml.push_str(&format!(
+        "  <link href=\"{}\" rel=\"self\"/>\n",
+        escape(&feed_url)
+    ));
+    xml.push_str(&format!("  <link href=\"{}\"/>\n", escape(&section_url)));
+    for (rel, path) in &history.links {
+        let url = config.absolute_url(&format!("/{}", path.display()));
+        xml.push_str(&format!(
+            "  <link href=\"{}\" rel=\"{}\"/>\n",
+            escape(&url),
+            rel
+        ));
+    }
+    xml.push_str(&format!("  <id>{}</id>\n", escape(&section_url)));
+    let latest = pages
+        .iter()
+        .filter_map(|(page, _)| updated(page))
+        .max()
+        .unwrap_or_else(OffsetDateTime::now_utc);
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        format_date_iso8601(&latest)
+    ));
+    xml.push_str(&format!(
+        "  <author><name>{}</name></author>\n",
+        escape(&config.site_info.title)
+    ));
+    for (page, html) in pages {
+        let permalink = escape(&config.absolute_url(&page.url()));
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape(&page.metadata.title)
+        ));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", permalink));
+        xml.push_str(&format!("    <id>{}</id>\n", permalink));
+        for author in &page.metadata.authors {
+            xml.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                escape(author_name(config, author))
+            ));
+        }
+        if let Some(date) = &page.metadata.date {
+            xml.push_str(&format!(
+                "    <published>{}</published>\n",
+                format_date_iso8601(date)
+            ));
+        }
+        if let Some(date) = updated(page) {
+            xml.push_str(&format!(
+                "    <updated>{}</updated>\n",
+                format_date_iso8601(&date)
+            ));
+        }
+        // The text of protected pages is only part of the encrypted page.
+        // Relative URLs of the content resolve against the page.
+        match feed.content {
+            FeedContent::Full if !page.metadata.protected => {
+                xml.push_str(&format!(
+                    "    <content type=\"html\" xml:base=\"{}\">{}</content>\n",
+                    permalink,
+                    escape(html)
+                ));
+            }
+            _ => {
+                xml.push_str(&format!(
+                    "    <summary>{}</summary>\n",
+                    escape(&page.description())
+                ));
+            }
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Write all configured feeds.
+///
+/// The content of pages deferred in low-memory mode is rendered for full
+/// content feeds, with the templates of the context for shortcodes.
+pub async fn export_feeds(
+    config: &Config,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+    urls: &ContentUrls,
+) -> Result<()> {
+    for feed in &config.feeds {
+        debug!("Building feed at /{}", feed.path.display());
+        let mut entries = entries(feed, opts, indices);
+        let (current, archives) = match feed.archive {
+            true => split_archives(&entries, feed.limit, |page| page.metadata.date),
+            false => {
+                entries.truncate(feed.limit);
+                (entries, Vec::new())
+            }
+        };
+
+        let mut history = History::default();
+        if !archives.is_empty() {
+            history
+                .links
+                .push(("prev-archive", archive_path(feed, archives.len())));
+        }
+        let entries = rendered_entries(config, opts, ctx, feed, urls, &current).await?;
+        write_document(config, feed, &feed.path, &entries, &history).await?;
+        for (i, pages) in archives.iter().enumerate() {
+            let number = i + 1;
+            let mut history = History {
+                archived: true,
+                links: vec![("current", feed.path.clone())],
+            };
+            if number > 1 {
+                history
+                    .links
+                    .push(("prev-archive", archive_path(feed, number - 1)));
+            }
+            if number < archives.len() {
+                history
+                    .links
+                    .push(("next-archive", archive_path(feed, number + 1)));
+            }
+            let path = archive_path(feed, number);
+            let entries = rendered_entries(config, opts, ctx, feed, urls, pages).await?;
+            write_document(config, feed, &path, &entries, &history).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The pages along with their rendered content.
+async fn rendered_entries<'a>(
+    config: &Config,
+    opts: &Cli,
+    ctx: &Context,
+    feed: &FeedConfig,
+    urls: &ContentUrls,
+    pages: &[&'a Page],
+) -> Result<Vec<(&'a Page, Cow<'a, str>)>> {
+    let mut entries = Vec::with_capacity(pages.len());
+    for page in pages {
+        let html = match feed.content {
+            FeedContent::Full if opts.low_memory && !page.metadata.protected => {
+                Cow::Owned(page.render_deferred(config, ctx, urls).await?)
+            }
+            _ => Cow::Borrowed(page.html.as_str()),
+        };
+        entries.push((*page, html));
+    }
+    Ok(entries)
+}
+
+/// Write the feed or one of its archives with the entries to the path
+/// relative to the output.
+async fn write_document(
+    config: &Config,
+    feed: &FeedConfig,
+    path: &Path,
+    entries: &[(&Page, Cow<'_, str>)],
+    history: &History,
+) -> Result<()> {
+    let xml = atom(config, feed, path, entries, history);
+    let path = config.output_path.join(path);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.to_path_buf(), e))?;
+    }
+    tokio::fs::write(&path, xml)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_split_archives() {
+        let id = |entry: i32| entry;
+        assert_eq!(
+            (vec![5, 4], vec![vec![2, 1], vec![3]]),
+            split_archives(&[5, 4, 3, 2, 1], 2, id)
+        );
+        assert_eq!(
+            (vec![4, 3], vec![vec![2, 1]]),
+            split_archives(&[4, 3, 2, 1], 2, id)
+        );
+        assert_eq!((Vec::<i32>::new(), Vec::new()), split_archives(&[], 2, id));
+        // Ordered by updates, archives still follow the publication date
+        assert_eq!(
+            (
+                vec![(1, 9), (5, 5)],
+                vec![vec![(3, 3), (2, 2)], vec![(4, 4)]]
+            ),
+            split_archives(&[(1, 9), (5, 5), (4, 4), (3, 3), (2, 2)], 2, |(date, _)| {
+                date
+            })
+        );
+    }
+
+    #[test]
+    fn test_atom_without_entries() {
+        let config = Config {
+            base_url: Some("https://example.com/".to_string()),
+            ..dummy_config()
+        };
+        let feed: FeedConfig = toml::from_str("").unwrap();
+        let xml = atom(&config, &feed, &feed.path, &[], &History::default());
+        // Atom requires the time of the last update and an author
+        let year = OffsetDateTime::now_utc().year();
+        assert!(xml.contains(&format!("  <updated>{}-", year)));
+        assert!(xml.contains("  <author><name>"));
+    }
+}
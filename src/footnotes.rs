@@ -0,0 +1,188 @@
+//! This module renders footnotes of the markdown content with configurable
+//! markup instead of the default of pulldown-cmark.
+//!
+//! Footnotes are numbered in the order they are first referenced and either
+//! collected in a section at the end of the content, or placed as sidenotes
+//! in `<aside>` elements right after the block of their reference, since an
+//! `<aside>` may not be part of e.g. a paragraph.
+
+use std::collections::HashMap;
+
+use pulldown_cmark::{escape::escape_html, html, CowStr, Event, Tag};
+
+use crate::config::FootnoteConfig;
+
+/// Escape text for use in HTML attributes and content.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    escape_html(&mut escaped, text).expect("writing to a string never fails");
+    escaped
+}
+
+/// Render the content of a footnote definition.
+///
+/// A single paragraph is unwrapped so it can be placed inline.
+fn render_definition(events: Vec<Event<'_>>, unwrap: bool) -> String {
+    let paragraphs = events
+        .iter()
+        .filter(|event| matches!(event, Event::Start(Tag::Paragraph)))
+        .count();
+    let events = events.into_iter().filter(|event| {
+        !(unwrap
+            && paragraphs == 1
+            && matches!(
+                event,
+                Event::Start(Tag::Paragraph) | Event::End(Tag::Paragraph)
+            ))
+    });
+    let mut content = String::new();
+    html::push_html(&mut content, events);
+    content.trim_end().to_string()
+}
+
+/// Render the markdown events to HTML with the configured footnotes.
+pub fn push_html<'a>(
+    config: &FootnoteConfig,
+    html: &mut String,
+    events: impl Iterator<Item = Event<'a>>,
+) {
+    // Split the definitions from the remaining content
+    let mut content = Vec::new();
+    let mut definitions: HashMap<CowStr<'a>, Vec<Event<'a>>> = HashMap::new();
+    let mut current: Option<(CowStr<'a>, Vec<Event<'a>>)> = None;
+    for event in events {
+        match (event, &mut current) {
+            (Event::Start(Tag::FootnoteDefinition(name)), _) => current = Some((name, Vec::new())),
+            (Event::End(Tag::FootnoteDefinition(_)), _) => {
+                if let Some((name, events)) = current.take() {
+                    definitions.insert(name, events);
+                }
+            }
+            (event, Some((_, events))) => events.push(event),
+            (event, None) => content.push(event),
+        }
+    }
+
+    // Number the footnotes in the order of their first reference
+    let mut numbers: HashMap<CowStr<'a>, usize> = HashMap::new();
+    let mut order = Vec::new();
+    let mut output = Vec::with_capacity(content.len());
+    // Sidenotes wait for the end of the top-level block of their reference
+    let mut depth = 0usize;
+    let mut sidenotes = String::new();
+    for event in content {
+        match &event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let Event::FootnoteReference(name) = event else {
+            output.push(event);
+            if depth == 0 && !sidenotes.is_empty() {
+                output.push(Event::Html(std::mem::take(&mut sidenotes).into()));
+            }
+            continue;
+        };
+        let first = !numbers.contains_key(&name);
+        let number = *numbers.entry(name.clone()).or_insert_with(|| {
+            order.push(name.clone());
+            order.len()
+        });
+        let id = escape(&name);
+        let reference = if first {
+            format!(
+                "<sup class=\"footnote-reference\" id=\"fnref-{id}\"><a href=\"#fn-{id}\">{number}</a></sup>"
+            )
+        } else {
+            format!("<sup class=\"footnote-reference\"><a href=\"#fn-{id}\">{number}</a></sup>")
+        };
+        if config.sidenotes && first {
+            if let Some(events) = definitions.remove(&name) {
+                sidenotes.push_str(&format!(
+                    "<aside class=\"sidenote\" id=\"fn-{id}\"><span class=\"sidenote-number\">{number}</span> {}</aside>\n",
+                    render_definition(events, true)
+                ));
+            }
+        }
+        output.push(Event::Html(reference.into()));
+    }
+    if !sidenotes.is_empty() {
+        output.push(Event::Html(sidenotes.into()));
+    }
+    html::push_html(html, output.into_iter());
+
+    if config.sidenotes || order.is_empty() {
+        return;
+    }
+    html.push_str("<section class=\"footnotes\">\n");
+    if let Some(heading) = &config.heading {
+        html.push_str(&format!("<h2>{}</h2>\n", escape(heading)));
+    }
+    html.push_str("<ol>\n");
+    for name in order {
+        let id = escape(&name);
+        let content = render_definition(definitions.remove(&name).unwrap_or_default(), true);
+        html.push_str(&format!("<li id=\"fn-{id}\">{content}"));
+        if let Some(backlink) = &config.backlink {
+            html.push_str(&format!(
+                " <a class=\"footnote-backref\" href=\"#fnref-{id}\">{}</a>",
+                escape(backlink)
+            ));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n</section>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(config: &FootnoteConfig, markdown: &str) -> String {
+        let parser =
+            pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::ENABLE_FOOTNOTES);
+        let mut html = String::new();
+        push_html(config, &mut html, parser);
+        html
+    }
+
+    #[test]
+    fn test_footnote_section() {
+        let config = FootnoteConfig {
+            backlink: Some("↩".to_string()),
+            heading: Some("Notes".to_string()),
+            sidenotes: false,
+        };
+        let html = render(&config, "A[^b] and c[^a].\n\n[^a]: First.\n[^b]: *Second*.");
+        assert_eq!(
+            "<p>A<sup class=\"footnote-reference\" id=\"fnref-b\"><a href=\"#fn-b\">1</a></sup> \
+             and c<sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#fn-a\">2</a></sup>.</p>\n\
+             <section class=\"footnotes\">\n<h2>Notes</h2>\n<ol>\n\
+             <li id=\"fn-b\"><em>Second</em>. <a class=\"footnote-backref\" href=\"#fnref-b\">↩</a></li>\n\
+             <li id=\"fn-a\">First. <a class=\"footnote-backref\" href=\"#fnref-a\">↩</a></li>\n\
+             </ol>\n</section>\n",
+            html
+        );
+    }
+
+    #[test]
+    fn test_sidenotes() {
+        let config = FootnoteConfig {
+            backlink: None,
+            heading: None,
+            sidenotes: true,
+        };
+        let html = render(
+            &config,
+            "A[^n] and *b[^m]*.\n\nC.\n\n[^n]: Note.\n[^m]: <b>.",
+        );
+        assert_eq!(
+            "<p>A<sup class=\"footnote-reference\" id=\"fnref-n\"><a href=\"#fn-n\">1</a></sup> \
+             and <em>b<sup class=\"footnote-reference\" id=\"fnref-m\"><a href=\"#fn-m\">2</a></sup></em>.</p>\n\
+             <aside class=\"sidenote\" id=\"fn-n\"><span class=\"sidenote-number\">1</span> Note.</aside>\n\
+             <aside class=\"sidenote\" id=\"fn-m\"><span class=\"sidenote-number\">2</span> <b>.</aside>\n\
+             <p>C.</p>\n",
+            html
+        );
+    }
+}
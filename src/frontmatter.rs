@@ -0,0 +1,243 @@
+//! This module splits content files into their frontmatter and markdown.
+//!
+//! The format of the frontmatter is detected by its delimiter: TOML between
+//! `+++`, YAML between `---` or a JSON object at the start of the file.
+
+use serde::de::DeserializeOwned;
+use time::{
+    format_description::{well_known::Iso8601, FormatItem},
+    macros::format_description,
+    Date, OffsetDateTime, PrimitiveDateTime,
+};
+
+/// Delimiter around TOML frontmatter.
+const TOML_DELIMITER: &str = "+++";
+
+/// Delimiter around YAML frontmatter.
+const YAML_DELIMITER: &str = "---";
+
+/// Dates as written by Jekyll, e.g. `2023-01-02 10:00:00 +0100`.
+const JEKYLL_DATE_FORMAT: &[FormatItem<'static>] = format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+);
+
+/// Frontmatter in one of the supported formats.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frontmatter<'a> {
+    Toml(&'a str),
+    Yaml(&'a str),
+    Json(&'a str),
+}
+
+impl Frontmatter<'_> {
+    /// Deserialize the metadata from the frontmatter.
+    ///
+    /// Keys set to null in YAML or JSON are left out as if they were not
+    /// given, since TOML has no null.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, String> {
+        let value = match self {
+            Frontmatter::Toml(s) => return toml::from_str(s).map_err(|e| e.to_string()),
+            Frontmatter::Yaml(s) => serde_yaml::from_str(s).map_err(|e| e.to_string())?,
+            Frontmatter::Json(s) => serde_json::from_str(s).map_err(|e| e.to_string())?,
+        };
+        T::deserialize(without_nulls(value)).map_err(|e| e.to_string())
+    }
+
+    /// Deserialize the metadata with the defaults for all fields which are
+    /// not given in the frontmatter.
+    pub fn parse_with_defaults<T: DeserializeOwned>(
+        &self,
+        defaults: &toml::Table,
+    ) -> Result<T, String> {
+        if defaults.is_empty() {
+            return self.parse();
+        }
+        let mut table: toml::Table = self.parse()?;
+        merge_defaults(&mut table, defaults);
+        table.try_into().map_err(|e: toml::de::Error| e.to_string())
+    }
+}
+
+/// Remove all null values from the objects and arrays of the value.
+fn without_nulls(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => object
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect(),
+        serde_json::Value::Array(array) => array
+            .into_iter()
+            .filter(|value| !value.is_null())
+            .map(without_nulls)
+            .collect(),
+        value => value,
+    }
+}
+
+/// Add the defaults to the table where they are not given, merging nested
+/// tables.
+pub fn merge_defaults(table: &mut toml::Table, defaults: &toml::Table) {
+    for (key, default) in defaults {
+        match (table.get_mut(key), default) {
+            (Some(toml::Value::Table(nested)), toml::Value::Table(default)) => {
+                merge_defaults(nested, default)
+            }
+            (Some(_), _) => {}
+            (None, _) => {
+                table.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+/// Split the input into its frontmatter and the trimmed markdown after it.
+pub fn split(input: &str) -> Option<(Frontmatter<'_>, &str)> {
+    let trimmed = input.trim_start();
+    if let Some(rest) = trimmed.strip_prefix(YAML_DELIMITER) {
+        // The closing delimiter is on a line of its own
+        let end = rest
+            .match_indices(&format!("\n{}", YAML_DELIMITER))
+            .map(|(i, _)| i)
+            .find(|i| {
+                let after = &rest[i + 1 + YAML_DELIMITER.len()..];
+                after.is_empty() || after.starts_with(['\n', '\r'])
+            })?;
+        let markdown = &rest[end + 1 + YAML_DELIMITER.len()..];
+        return Some((Frontmatter::Yaml(&rest[..end]), markdown.trim()));
+    }
+    if trimmed.starts_with('{') {
+        // The object ends where the first JSON value ends
+        let mut values =
+            serde_json::Deserializer::from_str(trimmed).into_iter::<serde::de::IgnoredAny>();
+        values.next()?.ok()?;
+        let end = values.byte_offset();
+        return Some((Frontmatter::Json(&trimmed[..end]), trimmed[end..].trim()));
+    }
+
+    let mut split = input.splitn(3, TOML_DELIMITER);
+    // Empty before frontmatter
+    split.next();
+    let frontmatter = split.next()?;
+    let markdown = split.next()?.trim();
+    Some((Frontmatter::Toml(frontmatter), markdown))
+}
+
+/// Parse a date of the frontmatter.
+///
+/// Besides ISO 8601, dates as written by Jekyll and dates without a time,
+/// which are taken as midnight UTC, are accepted.
+pub fn parse_date(s: &str) -> Option<OffsetDateTime> {
+    let s = s.trim();
+    OffsetDateTime::parse(s, &Iso8601::DEFAULT)
+        .or_else(|_| OffsetDateTime::parse(s, JEKYLL_DATE_FORMAT))
+        .ok()
+        .or_else(|| {
+            let date = Date::parse(s, format_description!("[year]-[month]-[day]")).ok()?;
+            Some(PrimitiveDateTime::new(date, time::Time::MIDNIGHT).assume_utc())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_frontmatter() {
+        assert_eq!(
+            Some((Frontmatter::Toml("\ntitle = \"A\"\n"), "Text")),
+            split("+++\ntitle = \"A\"\n+++\n\nText\n")
+        );
+        assert_eq!(
+            Some((
+                Frontmatter::Yaml("\ntitle: A\nrule: ---x"),
+                "Text\n---\nMore"
+            )),
+            split("---\ntitle: A\nrule: ---x\n---\nText\n---\nMore")
+        );
+        assert_eq!(
+            Some((Frontmatter::Json("{\"title\": \"}\"}"), "Text")),
+            split("{\"title\": \"}\"}\nText")
+        );
+        assert_eq!(None, split("---\ntitle: A\n"));
+    }
+
+    #[test]
+    fn test_parse_frontmatter() {
+        #[derive(serde::Deserialize)]
+        struct Metadata {
+            title: String,
+            tags: Vec<String>,
+        }
+        for frontmatter in [
+            Frontmatter::Toml("title = \"A\"\ntags = [\"x\"]"),
+            Frontmatter::Yaml("title: A\ntags:\n  - x"),
+            Frontmatter::Json("{\"title\": \"A\", \"tags\": [\"x\"]}"),
+        ] {
+            let metadata: Metadata = frontmatter.parse().unwrap();
+            assert_eq!(
+                ("A", vec!["x".to_string()]),
+                (metadata.title.as_str(), metadata.tags)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_with_defaults() {
+        #[derive(serde::Deserialize)]
+        struct Metadata {
+            template: String,
+            weight: i64,
+            extra: toml::Table,
+        }
+        let defaults: toml::Table =
+            toml::from_str("template = \"post.html\"\nweight = 1\n[extra]\na = 1\nb = 2").unwrap();
+        let metadata: Metadata = Frontmatter::Toml("weight = 5\n[extra]\nb = 3")
+            .parse_with_defaults(&defaults)
+            .unwrap();
+        assert_eq!("post.html", metadata.template);
+        assert_eq!(5, metadata.weight);
+        assert_eq!(
+            toml::from_str::<toml::Table>("a = 1\nb = 3").unwrap(),
+            metadata.extra
+        );
+    }
+
+    #[test]
+    fn test_parse_nulls() {
+        #[derive(serde::Deserialize)]
+        struct Metadata {
+            title: String,
+            #[serde(default)]
+            template: String,
+            description: Option<String>,
+        }
+        let defaults: toml::Table = toml::from_str("template = \"post.html\"").unwrap();
+        for frontmatter in [
+            Frontmatter::Yaml("title: A\ntemplate: ~\ndescription: null"),
+            Frontmatter::Json("{\"title\": \"A\", \"template\": null, \"description\": null}"),
+        ] {
+            let metadata: Metadata = frontmatter.parse().unwrap();
+            assert_eq!(
+                ("", None),
+                (metadata.template.as_str(), metadata.description)
+            );
+            let metadata: Metadata = frontmatter.parse_with_defaults(&defaults).unwrap();
+            assert_eq!("A", metadata.title);
+            assert_eq!("post.html", metadata.template);
+            assert_eq!(None, metadata.description);
+        }
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let expected = time::macros::datetime!(2023-01-02 09:00 UTC);
+        assert_eq!(Some(expected), parse_date("2023-01-02T09:00:00Z"));
+        assert_eq!(Some(expected), parse_date("2023-01-02 10:00:00 +0100"));
+        assert_eq!(
+            Some(time::macros::datetime!(2023-01-02 00:00 UTC)),
+            parse_date("2023-01-02")
+        );
+        assert_eq!(None, parse_date("yesterday"));
+    }
+}
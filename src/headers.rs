@@ -0,0 +1,93 @@
+//! This module generates the `_headers` file of hosts like Netlify and
+//! Cloudflare Pages from the `[headers]` of the pages, e.g. to opt sensitive
+//! pages out of caching:
+//!
+//! ```toml
+//! [headers]
+//! cache_control = "no-store"
+//! ```
+//!
+//! Rules of a `_headers` file in the assets are kept.
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    Cli, Index,
+};
+
+/// File name of the header rules in the output directory.
+const HEADERS_FILE: &str = "_headers";
+
+/// Name of the HTTP header given by a frontmatter key, e.g. `Cache-Control`
+/// for `cache_control`.
+fn header_name(key: &str) -> String {
+    key.split(['_', '-'])
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Collect the header rules of all published pages with headers.
+pub fn page_headers(opts: &Cli, indices: &[Index]) -> Result<String> {
+    let mut rules = String::new();
+    let pages = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts))
+        .filter(|page| !page.metadata.headers.is_empty());
+    for page in pages {
+        rules.push_str(&page.url());
+        rules.push('\n');
+        for (key, value) in &page.metadata.headers {
+            let valid_key = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid_key || value.contains(['\r', '\n']) {
+                return Err(Error::InvalidHeader(
+                    page.metadata.filepath.clone(),
+                    key.clone(),
+                ));
+            }
+            rules.push_str(&format!("  {}: {}\n", header_name(key), value));
+        }
+    }
+    Ok(rules)
+}
+
+/// Append the header rules to the `_headers` file in the output.
+pub async fn write_headers(config: &Config, rules: &str) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let path = config.output_path.join(HEADERS_FILE);
+    let mut content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::ReadInput(path, e)),
+    };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(rules);
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_name() {
+        assert_eq!("Cache-Control", header_name("cache_control"));
+        assert_eq!("X-Robots-Tag", header_name("x-robots-tag"));
+    }
+}
@@ -0,0 +1,123 @@
+//! A lenient scanner for the HTML of the built pages, used by the passes
+//! post-processing the output.
+
+/// An attribute of a start tag.
+#[derive(Debug)]
+pub struct Attribute<'a> {
+    /// Name of the attribute.
+    pub name: &'a str,
+    /// Value of the attribute, empty if not given.
+    pub value: &'a str,
+    /// Byte index where the value starts.
+    pub value_start: usize,
+}
+
+/// A start tag of an HTML element.
+#[derive(Debug)]
+pub struct StartTag<'a> {
+    /// Name of the element.
+    pub name: &'a str,
+    /// Attributes in the order they are given.
+    pub attrs: Vec<Attribute<'a>>,
+    /// Byte index of the `>` closing the tag.
+    pub end: usize,
+}
+
+impl StartTag<'_> {
+    /// Get the value of an attribute.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|attr| attr.name.eq_ignore_ascii_case(name))
+            .map(|attr| attr.value)
+    }
+}
+
+/// Find all start tags in the HTML.
+///
+/// This is a lenient scanner which does not validate the HTML.
+pub fn start_tags(html: &str) -> Vec<StartTag<'_>> {
+    let mut tags = Vec::new();
+    let mut rest_start = 0;
+
+    while let Some(i) = html[rest_start..].find('<') {
+        let start = rest_start + i + 1;
+        rest_start = start;
+        let name_len = html[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(html.len() - start);
+        if name_len == 0 {
+            // Closing tags, comments and doctypes
+            continue;
+        }
+        let name = &html[start..start + name_len];
+
+        // Parse the attributes up to the closing '>'
+        let mut attrs = Vec::new();
+        let mut pos = start + name_len;
+        let end = loop {
+            let rest = &html[pos..];
+            let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            pos += rest.len() - trimmed.len();
+            if trimmed.is_empty() {
+                break None;
+            }
+            if trimmed.starts_with('>') {
+                break Some(pos);
+            }
+            let attr_len = trimmed
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>')
+                .unwrap_or(trimmed.len());
+            let attr = &trimmed[..attr_len];
+            pos += attr_len;
+            let mut value = "";
+            let mut value_start = pos;
+            if html[pos..].starts_with('=') {
+                pos += 1;
+                value_start = pos;
+                let rest = &html[pos..];
+                if let Some(quote @ ('"' | '\'')) = rest.chars().next() {
+                    let value_len = rest[1..].find(quote).unwrap_or(rest.len() - 1);
+                    value = &rest[1..1 + value_len];
+                    value_start += 1;
+                    pos += (value_len + 2).min(rest.len());
+                } else {
+                    let value_len = rest
+                        .find(|c: char| c.is_whitespace() || c == '>')
+                        .unwrap_or(rest.len());
+                    value = &rest[..value_len];
+                    pos += value_len;
+                }
+            }
+            attrs.push(Attribute {
+                name: attr,
+                value,
+                value_start,
+            });
+        };
+
+        if let Some(end) = end {
+            rest_start = end;
+            tags.push(StartTag { name, attrs, end });
+        }
+    }
+
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_tags() {
+        let tags = start_tags(r#"<!doctype html><a href="/x" class=a hidden>b</a><br/>"#);
+        let names: Vec<_> = tags.iter().map(|tag| tag.name).collect();
+        assert_eq!(vec!["a", "br"], names);
+        assert_eq!(Some("/x"), tags[0].attr("href"));
+        assert_eq!(Some("a"), tags[0].attr("class"));
+        assert_eq!(Some(""), tags[0].attr("hidden"));
+        assert_eq!(24, tags[0].attrs[0].value_start);
+        assert_eq!(34, tags[0].attrs[1].value_start);
+    }
+}
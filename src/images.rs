@@ -0,0 +1,282 @@
+//! This module resizes images of the assets for use in templates.
+//!
+//! Resized images are written to `processed_images/` in the output and kept
+//! in the image store of the state directory, so they are only computed again
+//! if the source image or the requested size changes.
+//!
+//! Images in the content are optionally offered in further widths with a
+//! `srcset`. Tiny placeholders of images can be inlined as `data:` URLs to be
+//! shown blurred while the image loads.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use pulldown_cmark::{
+    escape::{escape_href, escape_html},
+    Event, Tag,
+};
+
+use crate::{
+    base64,
+    config::{Config, ResponsiveImagesConfig},
+    error::{Error, Result},
+    state::{StateDir, Store},
+    template::{content_hash, Context},
+};
+
+/// Directory in the output containing the resized images.
+pub const OUTPUT_DIR: &str = "processed_images";
+
+/// Resize an image of the assets and return the URL path of the result.
+///
+/// If only one of the dimensions is given, the aspect ratio is kept. If both
+/// are given, the image is scaled to fit within them.
+pub async fn resize(
+    config: &Config,
+    path: &Path,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<String> {
+    let source = config.content_path.join("assets").join(path);
+    let content = tokio::fs::read(&source)
+        .await
+        .map_err(|e| Error::ReadInput(source.clone(), e))?;
+
+    // The name depends on the content and the size only
+    let mut key = content.clone();
+    key.extend(format!("{:?}x{:?}", width, height).into_bytes());
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or("image");
+    let ext = path.extension().and_then(OsStr::to_str).unwrap_or("png");
+    let name = format!("{}.{}.{}", stem, content_hash(&key), ext);
+
+    let cache = StateDir::new(config)
+        .store(Store::Images)
+        .await?
+        .join(&name);
+    if !cache.is_file() {
+        debug!("Resizing {} to {}", path.display(), name);
+        let target = cache.clone();
+        tokio::task::spawn_blocking(move || resize_file(&content, &target, width, height))
+            .await
+            .map_err(Error::Join)?
+            .map_err(|e| Error::Image(source, e))?;
+    }
+
+    let dir = config.output_path.join(OUTPUT_DIR);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| Error::CreateDirectory(dir.clone(), e))?;
+    let output = dir.join(&name);
+    tokio::fs::copy(&cache, &output)
+        .await
+        .map_err(|e| Error::Copy(cache, output, e))?;
+
+    Ok(format!("/{}/{}", OUTPUT_DIR, name))
+}
+
+/// Width of the placeholders of images in pixels.
+const PLACEHOLDER_WIDTH: u32 = 16;
+
+/// Compute a tiny placeholder of an image of the assets as a `data:` URL.
+///
+/// Placeholders are kept in the image store like resized images.
+pub async fn placeholder(config: &Config, path: &Path) -> Result<String> {
+    let source = config.content_path.join("assets").join(path);
+    let content = tokio::fs::read(&source)
+        .await
+        .map_err(|e| Error::ReadInput(source.clone(), e))?;
+
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or("image");
+    let name = format!("{}.{}.placeholder", stem, content_hash(&content));
+    let cache = StateDir::new(config)
+        .store(Store::Images)
+        .await?
+        .join(&name);
+    if let Ok(url) = tokio::fs::read_to_string(&cache).await {
+        return Ok(url);
+    }
+
+    debug!("Computing the placeholder of {}", path.display());
+    let url = tokio::task::spawn_blocking(move || placeholder_url(&content))
+        .await
+        .map_err(Error::Join)?
+        .map_err(|e| Error::Image(source, e))?;
+    tokio::fs::write(&cache, &url)
+        .await
+        .map_err(|e| Error::WriteFile(cache, e))?;
+    Ok(url)
+}
+
+/// Scale the image down to the width of placeholders and encode it as PNG in a
+/// `data:` URL.
+fn placeholder_url(content: &[u8]) -> std::result::Result<String, String> {
+    let image = image::load_from_memory(content).map_err(|e| e.to_string())?;
+    let mut png = Vec::new();
+    image
+        .thumbnail(PLACEHOLDER_WIDTH, u32::MAX)
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(format!("data:image/png;base64,{}", base64::encode(&png)))
+}
+
+/// Extensions of the images which can be resized.
+const EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Replace images of the assets in the markdown events by an `<img>` with a
+/// `srcset` offering the configured widths.
+///
+/// The image itself is linked by its output path in the context, which is
+/// fingerprinted if assets are. External images and images which are not
+/// found in `assets/` are kept as is.
+pub async fn responsive_images<'a>(
+    config: &Config,
+    ctx: &Context,
+    responsive: &ResponsiveImagesConfig,
+    mut events: impl Iterator<Item = Event<'a>>,
+) -> Result<Vec<Event<'a>>> {
+    let mut output = Vec::new();
+    while let Some(event) = events.next() {
+        let (dest, title) = match &event {
+            Event::Start(Tag::Image(_, dest, title)) => (dest.clone(), title.clone()),
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+        let path = Path::new(dest.trim_start_matches('/'));
+        let source = config.content_path.join("assets").join(path);
+        let resizable = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+        let external = dest.contains(':') || dest.starts_with("//");
+        let output_path = ctx
+            .asset_output(path)
+            .filter(|_| !external && resizable && source.is_file());
+        let Some(output_path) = output_path else {
+            output.push(event);
+            continue;
+        };
+        let src = format!("/{}", output_path.display());
+
+        // The alternative text is given by the events up to the end of the
+        // image, which may contain further images
+        let mut alt = String::new();
+        let mut depth = 1;
+        for event in events.by_ref() {
+            match event {
+                Event::Start(Tag::Image(..)) => depth += 1,
+                Event::End(Tag::Image(..)) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                _ => {}
+            }
+        }
+
+        let srcset = srcset(config, path, &src, &responsive.widths).await?;
+        let mut html = String::from("<img src=\"");
+        escape_href(&mut html, &src).expect("writing to a string never fails");
+        html.push_str("\" srcset=\"");
+        escape_html(&mut html, &srcset).expect("writing to a string never fails");
+        if let Some(sizes) = &responsive.sizes {
+            html.push_str("\" sizes=\"");
+            escape_html(&mut html, sizes).expect("writing to a string never fails");
+        }
+        html.push_str("\" alt=\"");
+        escape_html(&mut html, &alt).expect("writing to a string never fails");
+        if !title.is_empty() {
+            html.push_str("\" title=\"");
+            escape_html(&mut html, &title).expect("writing to a string never fails");
+        }
+        html.push_str("\" />");
+        output.push(Event::Html(html.into()));
+    }
+    Ok(output)
+}
+
+/// Resize the image to all widths below its own width and list them together
+/// with the image itself at the URL as `srcset`.
+async fn srcset(config: &Config, path: &Path, url: &str, widths: &[u32]) -> Result<String> {
+    let source = config.content_path.join("assets").join(path);
+    let dimensions = {
+        let source = source.clone();
+        tokio::task::spawn_blocking(move || image::image_dimensions(source))
+            .await
+            .map_err(Error::Join)?
+    };
+    let (width, _) = dimensions.map_err(|e| Error::Image(source, e.to_string()))?;
+
+    let mut candidates = Vec::new();
+    for resized in widths.iter().filter(|resized| **resized < width) {
+        let url = resize(config, path, Some(*resized), None).await?;
+        candidates.push(format!("{} {}w", url, resized));
+    }
+    candidates.push(format!("{} {}w", url, width));
+    Ok(candidates.join(", "))
+}
+
+/// Decode the image, resize it and save it in the format of its extension.
+fn resize_file(
+    content: &[u8],
+    target: &PathBuf,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> std::result::Result<(), String> {
+    let image = image::load_from_memory(content).map_err(|e| e.to_string())?;
+    let resized = match (width, height) {
+        (Some(width), Some(height)) => image.resize(width, height, image::imageops::Lanczos3),
+        (Some(width), None) => image.resize(width, u32::MAX, image::imageops::Lanczos3),
+        (None, Some(height)) => image.resize(u32::MAX, height, image::imageops::Lanczos3),
+        (None, None) => image,
+    };
+    resized.save(target).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_file() {
+        let dir = std::env::temp_dir().join(format!("fweb-images-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut source = Vec::new();
+        image::DynamicImage::new_rgb8(40, 20)
+            .write_to(
+                &mut std::io::Cursor::new(&mut source),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+
+        let target = dir.join("a.png");
+        resize_file(&source, &target, Some(10), None).unwrap();
+        let resized = image::open(&target).unwrap();
+        assert_eq!((10, 5), (resized.width(), resized.height()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_placeholder_url() {
+        let mut source = Vec::new();
+        image::DynamicImage::new_rgb8(160, 80)
+            .write_to(
+                &mut std::io::Cursor::new(&mut source),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+
+        let url = placeholder_url(&source).unwrap();
+        assert!(url.starts_with("data:image/png;base64,"));
+        assert!(url.len() < source.len());
+    }
+}
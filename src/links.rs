@@ -0,0 +1,253 @@
+//! This module rewrites the links of the markdown content.
+//!
+//! Internal links to content files, e.g. `[text](@/blog/post.md)`, are
+//! resolved to the URL of the page once all content is known.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{
+    escape::{escape_href, escape_html},
+    Event, LinkType, Tag,
+};
+
+use crate::{
+    config::{Config, ExternalLinksConfig},
+    error::{Error, Result},
+    html::start_tags,
+    Cli, Index,
+};
+
+/// Prefix of links to content files relative to `content/`.
+pub const INTERNAL_PREFIX: &str = "@/";
+
+/// Attributes which may contain internal links.
+const LINK_ATTRIBUTES: &[&str] = &["href", "src"];
+
+/// Whether the URL starts with a scheme, e.g. `https:` or `mailto:`.
+///
+/// A colon later in the path, e.g. in `img/a:b.png`, does not make a URL
+/// absolute.
+pub fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Decode the percent-encoded bytes of a URL path.
+///
+/// Returns `None` if an escape is malformed or the bytes are not UTF-8.
+pub fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether the link points to another site.
+///
+/// Absolute links to the base URL of the site are internal, but not links
+/// to other hosts starting with it, e.g. `https://example.com.evil.org`.
+fn is_external(config: &Config, dest: &str) -> bool {
+    let absolute =
+        dest.starts_with("http://") || dest.starts_with("https://") || dest.starts_with("//");
+    let own = config.base_url.as_deref().is_some_and(|base_url| {
+        dest.strip_prefix(base_url.trim_end_matches('/'))
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+    });
+    absolute && !own
+}
+
+/// Open external links in a new tab without giving it access to the page and
+/// add the configured class to them.
+pub fn rewrite_external<'a>(
+    config: &Config,
+    external: &ExternalLinksConfig,
+    events: Vec<Event<'a>>,
+) -> Vec<Event<'a>> {
+    let mut in_external = false;
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link(link_type, dest, title))
+                if link_type != LinkType::Email && is_external(config, &dest) =>
+            {
+                in_external = true;
+                let mut html = String::from("<a href=\"");
+                escape_href(&mut html, &dest).expect("writing to a string never fails");
+                if !title.is_empty() {
+                    html.push_str("\" title=\"");
+                    escape_html(&mut html, &title).expect("writing to a string never fails");
+                }
+                if let Some(class) = &external.class {
+                    html.push_str("\" class=\"");
+                    escape_html(&mut html, class).expect("writing to a string never fails");
+                }
+                html.push_str("\" target=\"_blank\" rel=\"noopener noreferrer\">");
+                Event::Html(html.into())
+            }
+            Event::End(Tag::Link(..)) if in_external => {
+                in_external = false;
+                Event::Html("</a>".into())
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// URLs of the content files by their path relative to `content/`.
+pub type ContentUrls = HashMap<PathBuf, String>;
+
+/// Collect the URLs of all sections and pages which can be linked to.
+///
+/// Drafts can only be linked to when building drafts.
+pub fn content_urls(config: &Config, opts: &Cli, indices: &[Index]) -> ContentUrls {
+    let mut urls = HashMap::new();
+    for index in indices {
+        urls.insert(
+            index.metadata.filepath.clone(),
+            config.link_url(&index.url()),
+        );
+        let pages = index.pages.iter().filter(|page| page.is_published(opts));
+        for page in pages {
+            urls.insert(page.metadata.filepath.clone(), config.link_url(&page.url()));
+        }
+    }
+    urls
+}
+
+/// Replace the internal links in the HTML of all sections and pages by the
+/// URLs of the content files they point to.
+pub fn resolve_internal(urls: &ContentUrls, indices: &mut [Index]) -> Result<()> {
+    for index in indices.iter_mut() {
+        index.html = resolve_content(&index.metadata.filepath, &index.html, urls)?;
+        for page in &mut index.pages {
+            page.html = resolve_content(&page.metadata.filepath, &page.html, urls)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the internal links in the HTML of the content file.
+pub fn resolve_content(file: &Path, html: &str, urls: &ContentUrls) -> Result<String> {
+    resolve_html(html, urls).map_err(|target| Error::InternalLink(file.to_path_buf(), target))
+}
+
+/// Replace the internal links of the HTML by the URLs of their content files.
+///
+/// Returns the first link whose content file does not exist as error.
+fn resolve_html(html: &str, urls: &ContentUrls) -> std::result::Result<String, String> {
+    let mut links = Vec::new();
+    for attr in start_tags(html).iter().flat_map(|tag| &tag.attrs) {
+        let is_link = LINK_ATTRIBUTES
+            .iter()
+            .any(|name| attr.name.eq_ignore_ascii_case(name));
+        let Some(target) = attr.value.strip_prefix(INTERNAL_PREFIX).filter(|_| is_link) else {
+            continue;
+        };
+        let (path, fragment) = match target.find(['#', '?']) {
+            Some(i) => target.split_at(i),
+            None => (target, ""),
+        };
+        let url = urls
+            .get(&PathBuf::from(path))
+            .ok_or_else(|| attr.value.to_string())?;
+        links.push((
+            attr.value_start,
+            attr.value.len(),
+            format!("{}{}", url, fragment),
+        ));
+    }
+
+    let mut resolved = html.to_string();
+    for (start, len, url) in links.into_iter().rev() {
+        resolved.replace_range(start..start + len, &url);
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_resolve_html() {
+        let urls = HashMap::from([
+            (PathBuf::from("blog/post.md"), "/blog/post/".to_string()),
+            (PathBuf::from("_index.md"), "/".to_string()),
+        ]);
+        let html = r#"<a href="@/blog/post.md#intro">Post</a> <a href="@/_index.md">Home</a>"#;
+        assert_eq!(
+            r#"<a href="/blog/post/#intro">Post</a> <a href="/">Home</a>"#,
+            resolve_html(html, &urls).unwrap()
+        );
+        assert_eq!(
+            Err("@/missing.md".to_string()),
+            resolve_html(r#"<a href="@/missing.md">"#, &urls)
+        );
+    }
+
+    #[test]
+    fn test_has_scheme() {
+        assert!(has_scheme("https://example.com"));
+        assert!(has_scheme("mailto:a@example.com"));
+        assert!(has_scheme("git+ssh://example.com"));
+        assert!(!has_scheme("img/a:b.png"));
+        assert!(!has_scheme("/a:b"));
+        assert!(!has_scheme(":x"));
+        assert!(!has_scheme("1a:b"));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(Some("/crème".to_string()), percent_decode("/cr%C3%A8me"));
+        assert_eq!(Some("/a b".to_string()), percent_decode("/a%20b"));
+        assert_eq!(None, percent_decode("/100%"));
+        assert_eq!(None, percent_decode("/%zz"));
+        assert_eq!(None, percent_decode("/%ff"));
+    }
+
+    #[test]
+    fn test_is_external() {
+        let config = Config {
+            base_url: Some("https://example.com/".to_string()),
+            ..dummy_config()
+        };
+        for dest in [
+            "https://example.com",
+            "https://example.com/blog/",
+            "https://example.com?page=2",
+            "https://example.com#top",
+            "/blog/",
+        ] {
+            assert!(!is_external(&config, dest), "{}", dest);
+        }
+        for dest in [
+            "https://example.com.evil.org/",
+            "https://example.community/",
+            "https://example.org/",
+            "//example.org/",
+        ] {
+            assert!(is_external(&config, dest), "{}", dest);
+        }
+    }
+}
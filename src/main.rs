@@ -1,39 +1,78 @@
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
     ffi::OsStr,
-    fmt::Write,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use clap::Parser;
-use log::{debug, error, info};
-use pulldown_cmark::Options;
+use log::{debug, error, info, warn};
+use pulldown_cmark::{Event, Options, Tag};
 use serde::Deserialize;
-use template::Context;
+use template::{Context, Usage, Value};
 use time::{
-    format_description::{
-        well_known::{
-            iso8601::{self, EncodedConfig, TimePrecision},
-            Iso8601,
-        },
-        FormatItem,
+    format_description::well_known::{
+        iso8601::{self, EncodedConfig, TimePrecision},
+        Iso8601,
     },
-    macros::format_description,
-    OffsetDateTime,
+    Date, OffsetDateTime,
 };
-
+use tokio::sync::Semaphore;
+
+mod accessibility;
+mod archive;
+mod attributes;
+mod authors;
+mod base64;
+mod cache;
+mod cdn;
+mod check;
 mod config;
+mod daemon;
 mod error;
+mod explain;
+mod feed;
+mod footnotes;
+mod frontmatter;
+mod headers;
+mod html;
+#[cfg(feature = "images")]
+mod images;
+mod links;
+#[cfg(feature = "math")]
+mod math;
+mod minify;
+mod nav;
+mod not_found;
+mod protect;
+mod redirects;
+mod refactor;
+mod rpc;
+mod sass;
+mod search;
+mod series;
+mod serve;
+mod shortlink;
+mod sitemap;
+mod slug;
+mod snapshot;
+mod social;
+mod split;
+mod state;
+mod structured_data;
+mod taxonomy;
 mod template;
+mod typography;
+mod verify;
 
 use crate::{
-    config::Config,
+    cache::RenderCache,
+    config::{BudgetOverrides, Config, SassConfig, TextDirection, TypographyConfig},
     error::{Error, Result},
 };
 
-/// Date format used to display dates.
-const DATE_FORMAT: &[FormatItem<'static>] =
-    format_description!("[year]-[month]-[day] [hour]:[minute]Z");
-
 /// Export configuration to export a date and time compatible with the datetime
 /// attribute used in the HTML `<time>` element.
 const DATE_ISO_CONFIG: EncodedConfig = iso8601::Config::DEFAULT
@@ -46,12 +85,132 @@ const DATE_ISO_CONFIG: EncodedConfig = iso8601::Config::DEFAULT
 #[derive(Debug, clap::Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Path to the site config.
+    /// Path to the site config or a workspace config listing several sites.
     #[arg(default_value = "config.toml", value_hint = clap::ValueHint::FilePath)]
     pub config_path: PathBuf,
     /// Build draft pages.
     #[arg(long, default_value_t = false)]
     pub drafts: bool,
+    /// Build pages which are dated in the future.
+    #[arg(long, default_value_t = false)]
+    pub future: bool,
+    /// Report templates and context values which were never used.
+    #[arg(long, default_value_t = false)]
+    pub report_unused: bool,
+    /// Render the content of pages only when they are written, one page at
+    /// a time, to bound the memory needed for large sites.
+    #[arg(long, default_value_t = false)]
+    pub low_memory: bool,
+    /// Maximum number of pages parsed or rendered at the same time, the
+    /// number of CPUs by default.
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Command to run instead of a plain build.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Whether unpublished content is built, as opposed to a production
+    /// build.
+    pub fn is_preview(&self) -> bool {
+        self.drafts || self.future
+    }
+
+    /// Limit of the pages processed at the same time.
+    pub fn job_limit(&self) -> Arc<Semaphore> {
+        let jobs = self
+            .jobs
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get);
+        Arc::new(Semaphore::new(jobs))
+    }
+}
+
+/// Commands besides building the site.
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Build the site and compare the output with an expected output tree.
+    Verify {
+        /// Directory containing the expected output.
+        #[arg(value_hint = clap::ValueHint::DirPath)]
+        expected_dir: PathBuf,
+    },
+    /// Build the site and check that all links resolve and titles and
+    /// descriptions fit their length budgets.
+    Check {
+        /// Also check links to other sites with a `HEAD` request, which
+        /// requires `curl` to be installed.
+        #[arg(long, default_value_t = false)]
+        external: bool,
+    },
+    /// Rebuild the site periodically, e.g. to publish pages dated in the
+    /// future once their date is reached.
+    Daemon {
+        /// Time between two builds, e.g. `30m`, `1h` or `1d`.
+        #[arg(long, default_value = "1h", value_parser = daemon::parse_interval)]
+        interval: std::time::Duration,
+        /// Keep the errors of failed builds for `fweb serve`, which shows
+        /// them in a banner on all pages until a build succeeds again.
+        #[arg(long)]
+        error_overlay: bool,
+    },
+    /// Render a single markdown file with the templates of the site and
+    /// print the HTML instead of building the site.
+    Render {
+        /// Markdown file to render, read from stdin if not given.
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        file: Option<PathBuf>,
+    },
+    /// Build the site and serve it over HTTP for previews.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        addr: std::net::SocketAddr,
+        /// Require these credentials with basic auth, given as `user:pass`.
+        #[arg(long, value_parser = serve::parse_credentials)]
+        auth: Option<serve::Credentials>,
+    },
+    /// Print the content file, templates, shortcodes and assets an output
+    /// file is built from.
+    Explain {
+        /// Output file, relative to the current or the output directory.
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    /// Move a section to another URL, rewriting the links to it in the
+    /// content and adding redirects from its former URLs.
+    Refactor {
+        /// URL path of the section, e.g. `/old-section/`.
+        #[arg(long)]
+        from: String,
+        /// URL path the section is moved to, e.g. `/new-section/`.
+        #[arg(long)]
+        to: String,
+        /// Print the changes as a diff instead of applying them.
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Answer requests of editor integrations, e.g. to render or validate
+    /// a page, as JSON-RPC over stdin and stdout.
+    Rpc,
+    /// Publish the output of the previous build again from its snapshot
+    /// without building the site.
+    Rollback,
+    /// Manage the state directory of the site without building it.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+/// Commands managing the state directory.
+#[derive(Debug, clap::Subcommand)]
+pub enum CacheCommand {
+    /// Remove all caches from the state directory.
+    Clean,
+    /// Show the number and size of the files in every store.
+    Stats,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -70,6 +229,9 @@ enum SortOrder {
 #[derive(Debug, Clone, Deserialize)]
 struct PageMetadata {
     /// ID used for URLs.
+    ///
+    /// Derived from the title if not given, given ids are used as is.
+    #[serde(default)]
     id: String,
 
     /// Post title.
@@ -93,11 +255,27 @@ struct PageMetadata {
     #[serde(default)]
     excerpt: Option<String>,
 
+    /// Whether to enable smart punctuation, defaults to the markdown options
+    /// of the site.
+    #[serde(default)]
+    smart_punctuation: Option<bool>,
+
+    /// Summary of the page for e.g. meta tags.
+    ///
+    /// Defaults to the excerpt or the start of the text.
+    #[serde(default)]
+    description: Option<String>,
+
     /// Date when the page was written
     #[serde(default)]
     #[serde(deserialize_with = "optional_datetime")]
     date: Option<OffsetDateTime>,
 
+    /// Date when the page was last changed after it was written.
+    #[serde(default)]
+    #[serde(deserialize_with = "optional_datetime")]
+    updated: Option<OffsetDateTime>,
+
     /// The path to the markdown input file.
     ///
     /// This path is relative to the `content/`
@@ -110,11 +288,82 @@ struct PageMetadata {
     #[serde(default = "default_page_template")]
     template: PathBuf,
 
+    /// Whether the page is encrypted with the password of the site.
+    #[serde(default)]
+    protected: bool,
+
     /// Whether the page is a draft.
     ///
     /// If this is set, only a site build with the draft option enabled will output this page.
     #[serde(default)]
     draft: bool,
+
+    /// Whether the page is featured.
+    ///
+    /// Featured pages are listed in the `featured_pages` context and are
+    /// candidates for the `page_of_the_day`.
+    #[serde(default)]
+    featured: bool,
+
+    /// URL of the original if the page is cross-posted from elsewhere.
+    ///
+    /// Cross-posted pages are left out of the sitemap.
+    #[serde(default)]
+    canonical: Option<String>,
+
+    /// Whether search engines should not index the page.
+    ///
+    /// Such pages are left out of the sitemap.
+    #[serde(default)]
+    noindex: bool,
+
+    /// Former URL paths of the page redirecting to it, e.g. after the page
+    /// was moved.
+    #[serde(default)]
+    aliases: Vec<String>,
+
+    /// HTTP headers of the page written to `_headers` for the host, e.g.
+    /// `cache_control = "no-store"`.
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+
+    /// Arbitrary values given in the `[extra]` table.
+    ///
+    /// These are available to templates as `extra.<key>`.
+    #[serde(default)]
+    extra: toml::Table,
+
+    /// Language of the content, defaults to the language of the site.
+    #[serde(default)]
+    lang: Option<String>,
+
+    /// Text direction of the content, defaults to the direction of the site.
+    #[serde(default)]
+    dir: Option<TextDirection>,
+
+    /// Ids of the authors of the page.
+    #[serde(default)]
+    authors: Vec<String>,
+
+    /// Name of the series the page is a part of.
+    #[serde(default)]
+    series: Option<String>,
+
+    /// Heading level up to which the page is split into parts, e.g. `2` to
+    /// start a new part at every heading of the first and second level.
+    #[serde(default)]
+    split: Option<u8>,
+
+    /// Length budgets of the page overriding the ones of the site, e.g. for a
+    /// title which is long on purpose.
+    #[serde(default)]
+    budgets: BudgetOverrides,
+
+    /// All other fields of the frontmatter.
+    ///
+    /// Used to look up the terms of the taxonomies by their name.
+    #[serde(flatten)]
+    fields: toml::Table,
 }
 
 fn default_page_template() -> PathBuf {
@@ -125,11 +374,12 @@ fn optional_datetime<'de, D>(d: D) -> std::result::Result<Option<OffsetDateTime>
 where
     D: serde::de::Deserializer<'de>,
 {
-    #[derive(Deserialize)]
-    struct Wrapper(#[serde(with = "time::serde::iso8601")] OffsetDateTime);
-
-    let wrapper = Option::deserialize(d)?;
-    Ok(wrapper.map(|Wrapper(external)| external))
+    let Some(date) = Option::<String>::deserialize(d)? else {
+        return Ok(None);
+    };
+    frontmatter::parse_date(&date)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid date '{}'", date)))
 }
 
 /// A page is an HTML file within a folder.
@@ -137,25 +387,424 @@ where
 struct Page {
     metadata: PageMetadata,
     html: String,
+    /// Markdown of the content without the frontmatter, `None` if it is
+    /// read from the file again when needed to save memory.
+    #[serde(skip)]
+    raw_markdown: Option<String>,
+    /// Text of the content without any markup, `None` if it is derived from
+    /// the file again when needed to save memory.
+    #[serde(skip)]
+    plain_text: Option<String>,
+    /// Start of the text, which describes the page if neither a description
+    /// nor an excerpt is given.
+    #[serde(skip)]
+    summary: String,
+    /// Number of words of the text.
+    #[serde(skip)]
+    word_count: usize,
+    /// Excerpt given in the frontmatter, otherwise derived from the text up
+    /// to a `<!-- more -->` marker or the first paragraph.
+    #[serde(skip)]
+    excerpt: Option<String>,
+    /// URL path of the short link to the page if assigned.
+    #[serde(skip)]
+    short_url: Option<String>,
+    /// Terms of the page by the name of their taxonomy.
+    #[serde(skip)]
+    taxonomies: BTreeMap<String, Vec<String>>,
+    /// Position of the page in its series with the other parts.
+    #[serde(skip)]
+    series: Option<Value>,
+    /// Titles of the parts the page is split into, empty if not split.
+    #[serde(skip)]
+    parts: Vec<String>,
+    /// Directory relative to the content of a page bundled with its assets.
+    #[serde(skip)]
+    bundle: Option<PathBuf>,
+    /// Directory of the URL of the page, the one of its file normalized by
+    /// the slug policy.
+    #[serde(skip)]
+    url_dir: PathBuf,
 }
 
+/// File name of the page in the directory of a page bundle.
+const BUNDLE_PAGE: &str = "index.md";
+
 impl Page {
-    async fn parse_md(content_dir: impl AsRef<Path>, relpath: impl AsRef<Path>) -> Result<Self> {
+    /// Reads and parses an input markdown file.
+    ///
+    /// Fields missing in the frontmatter are taken from the cascade of the
+    /// sections above the page. If `defer_html` is set, the content is only
+    /// rendered when the page is written, and its markdown and text are not
+    /// kept.
+    async fn parse_md(
+        content_dir: impl AsRef<Path>,
+        relpath: impl AsRef<Path>,
+        config: &Config,
+        ctx: &Context,
+        cascade: &toml::Table,
+        defer_html: bool,
+    ) -> Result<Self> {
         let file = content_dir.as_ref().join(&relpath);
         let content = tokio::fs::read_to_string(&file)
             .await
             .map_err(|e| Error::ReadInput(relpath.as_ref().to_path_buf(), e))?;
+        Self::parse(&content, relpath.as_ref(), config, ctx, cascade, defer_html).await
+    }
 
-        let (frontmatter, markdown) = parse_file(&content, file)?;
-        let mut metadata: PageMetadata = toml::from_str(frontmatter)
-            .map_err(|e| Error::ParseMetadata(relpath.as_ref().to_path_buf(), e))?;
-        metadata.filepath = relpath.as_ref().to_path_buf();
+    /// Parses the content of a markdown file located at the path relative to
+    /// `content/`.
+    ///
+    /// Shortcodes are rendered with the templates of the context.
+    async fn parse(
+        content: &str,
+        relpath: &Path,
+        config: &Config,
+        ctx: &Context,
+        cascade: &toml::Table,
+        defer_html: bool,
+    ) -> Result<Self> {
+        let (frontmatter, markdown) = parse_file(content, relpath)?;
+        let mut metadata: PageMetadata = frontmatter
+            .parse_with_defaults(cascade)
+            .map_err(|e| Error::ParseMetadata(relpath.to_path_buf(), e))?;
+        metadata.filepath = relpath.to_path_buf();
+        // The authors may also be the terms of a taxonomy of the same name
+        if !metadata.authors.is_empty() {
+            let authors = metadata.authors.iter().cloned().map(toml::Value::from);
+            metadata
+                .fields
+                .insert("authors".to_string(), toml::Value::Array(authors.collect()));
+        }
+        let bundle = metadata
+            .filepath
+            .parent()
+            .filter(|dir| metadata.filepath.ends_with(BUNDLE_PAGE) && *dir != Path::new(""))
+            .map(Path::to_path_buf);
+        // Ids are derived from the directory name of bundles or the title if
+        // not given. Given ids are kept, so the URLs of existing pages never
+        // change with the slug policy.
+        let derived_from = match &bundle {
+            _ if !metadata.id.is_empty() => None,
+            Some(bundle) => bundle.file_name().and_then(OsStr::to_str),
+            None => Some(metadata.title.as_str()),
+        };
+        match derived_from {
+            Some(text) => metadata.id = slug::slugify(&config.slugs, text),
+            None => {
+                let slug = slug::slugify(&config.slugs, &metadata.id);
+                if slug != metadata.id {
+                    warn!(
+                        "Id '{}' of {} does not follow the slug policy, which would make it '{}'",
+                        metadata.id,
+                        relpath.display(),
+                        slug
+                    );
+                }
+            }
+        }
+        if metadata.id.is_empty() {
+            return Err(Error::EmptyId(metadata.filepath));
+        }
 
-        Ok(Self {
+        let smart_punctuation = metadata
+            .smart_punctuation
+            .unwrap_or(config.markdown.smart_punctuation);
+        let options = markdown_options(smart_punctuation);
+        let text = markdown_to_plain_text(config, markdown, options);
+        let word_count = text.split_whitespace().count();
+        // The text of protected pages must not appear in e.g. listings or the
+        // search index, only in the encrypted page
+        let (raw_markdown, plain_text) = match (metadata.protected, defer_html) {
+            (true, _) => (Some(String::new()), Some(String::new())),
+            (false, true) => (None, None),
+            (false, false) => (Some(markdown.to_string()), Some(text.clone())),
+        };
+        let summary = match metadata.protected {
+            true => String::new(),
+            false => truncate_text(&text, config.description_length),
+        };
+        let excerpt = match &metadata.excerpt {
+            Some(excerpt) => Some(excerpt.clone()),
+            None if !metadata.protected => derive_excerpt(config, markdown, options),
+            None => None,
+        };
+        let parts = match metadata.split {
+            Some(level) => split::part_titles(markdown, level, options, &metadata.title),
+            None => Vec::new(),
+        };
+        let mut page = Self {
             metadata,
-            html: convert_markdown(markdown),
+            html: String::new(),
+            raw_markdown,
+            plain_text,
+            summary,
+            word_count,
+            excerpt,
+            short_url: None,
+            taxonomies: BTreeMap::new(),
+            series: None,
+            parts,
+            bundle,
+            url_dir: PathBuf::new(),
+        };
+        page.url_dir = slug::slugify_dir(&config.slugs, page.dir())
+            .ok_or_else(|| Error::EmptyDirSlug(page.dir().to_path_buf()))?;
+        if !defer_html {
+            page.html = page.content_html(markdown, config, ctx).await?;
+        }
+        Ok(page)
+    }
+
+    /// Options of the markdown parser for the page.
+    fn markdown_options(&self, config: &Config) -> Options {
+        markdown_options(
+            self.metadata
+                .smart_punctuation
+                .unwrap_or(config.markdown.smart_punctuation),
+        )
+    }
+
+    /// Convert the markdown of the page to HTML.
+    async fn content_html(&self, markdown: &str, config: &Config, ctx: &Context) -> Result<String> {
+        // Relative images of bundles refer to their assets next to the page
+        let bundle_url = self.bundle.as_ref().map(|_| self.url());
+        let options = self.markdown_options(config);
+        let markdown = match self.metadata.split {
+            Some(level) => Cow::Owned(split::mark_parts(markdown, level, options)),
+            None => Cow::Borrowed(markdown),
+        };
+        convert_markdown(&markdown, options, config, ctx, bundle_url.as_deref()).await
+    }
+
+    /// Render the HTML of a page whose content was deferred, reading its
+    /// file again.
+    ///
+    /// Internal links and the typography pass are applied as for all other
+    /// pages.
+    async fn render_deferred(
+        &self,
+        config: &Config,
+        ctx: &Context,
+        urls: &links::ContentUrls,
+    ) -> Result<String> {
+        let markdown = self.read_markdown(config).await?;
+        self.render_markdown(&markdown, config, ctx, urls).await
+    }
+
+    /// Render the HTML of a page whose content was deferred and keep its
+    /// markdown and text while the page is written.
+    async fn restore_deferred(
+        &mut self,
+        config: &Config,
+        ctx: &Context,
+        urls: &links::ContentUrls,
+    ) -> Result<()> {
+        let markdown = self.read_markdown(config).await?;
+        self.html = self.render_markdown(&markdown, config, ctx, urls).await?;
+        if !self.metadata.protected {
+            let options = self.markdown_options(config);
+            self.plain_text = Some(markdown_to_plain_text(config, &markdown, options));
+            self.raw_markdown = Some(markdown);
+        }
+        Ok(())
+    }
+
+    /// Render the markdown of the page with its internal links resolved and
+    /// the typography applied.
+    async fn render_markdown(
+        &self,
+        markdown: &str,
+        config: &Config,
+        ctx: &Context,
+        urls: &links::ContentUrls,
+    ) -> Result<String> {
+        let html = self.content_html(markdown, config, ctx).await?;
+        let html = links::resolve_content(&self.metadata.filepath, &html, urls)?;
+        Ok(match &config.typography {
+            Some(typography) => {
+                let lang = self.metadata.lang.as_deref().unwrap_or_default();
+                typography::apply(typography, lang, &html)
+            }
+            None => html,
         })
     }
+
+    /// Read the markdown of the page without the frontmatter from its file.
+    async fn read_markdown(&self, config: &Config) -> Result<String> {
+        let relpath = &self.metadata.filepath;
+        let file = config.content_path.join("content").join(relpath);
+        let content = tokio::fs::read_to_string(&file)
+            .await
+            .map_err(|e| Error::ReadInput(relpath.clone(), e))?;
+        let (_, markdown) = parse_file(&content, file)?;
+        Ok(markdown.to_string())
+    }
+
+    /// Text of the content, derived from the file again if it was not kept.
+    async fn plain_text(&self, config: &Config) -> Result<Cow<'_, str>> {
+        match &self.plain_text {
+            Some(text) => Ok(Cow::Borrowed(text)),
+            None => {
+                let markdown = self.read_markdown(config).await?;
+                let options = self.markdown_options(config);
+                Ok(Cow::Owned(markdown_to_plain_text(
+                    config, &markdown, options,
+                )))
+            }
+        }
+    }
+
+    /// Whether the page is built, which drafts only are if requested.
+    fn is_published(&self, opts: &Cli) -> bool {
+        !self.metadata.draft || opts.drafts
+    }
+
+    /// Summary of the page given in the frontmatter, by the excerpt or the
+    /// start of the text.
+    fn description(&self) -> String {
+        match self.metadata.description.as_ref().or(self.excerpt.as_ref()) {
+            Some(description) => description.clone(),
+            None => self.summary.clone(),
+        }
+    }
+
+    /// Directory of the page relative to the content.
+    ///
+    /// Bundled pages are located in the parent of their bundle.
+    fn dir(&self) -> &Path {
+        let dir = self.metadata.filepath.parent().expect("page is a file");
+        match &self.bundle {
+            Some(_) => dir.parent().expect("bundle is a directory"),
+            None => dir,
+        }
+    }
+
+    /// Absolute URL path of the page without a trailing slash.
+    fn url_path(&self) -> PathBuf {
+        PathBuf::from("/")
+            .join(&self.url_dir)
+            .join(&self.metadata.id)
+    }
+
+    /// Directory of the page in the output relative to its root.
+    fn output_dir(&self) -> PathBuf {
+        self.url_dir.join(&self.metadata.id)
+    }
+
+    /// Absolute URL path of the page with a trailing slash.
+    fn url(&self) -> String {
+        format!("{}/", self.url_path().display())
+    }
+
+    /// Canonical URL of the page, the original one of cross-posted pages.
+    fn canonical(&self, config: &Config) -> String {
+        self.metadata
+            .canonical
+            .clone()
+            .unwrap_or_else(|| config.absolute_url(&self.url()))
+    }
+
+    /// Convert the page metadata into named values for templating.
+    ///
+    /// The rendered content, markdown and plain text are not included since
+    /// pages are commonly listed, they are only given for the current page.
+    fn to_map(&self, config: &Config) -> HashMap<String, Value> {
+        let mut page = HashMap::from([
+            ("id".to_string(), Value::from(self.metadata.id.as_str())),
+            ("word_count".to_string(), (self.word_count as i64).into()),
+            (
+                "reading_time".to_string(),
+                (reading_time(self.word_count) as i64).into(),
+            ),
+            ("title".to_string(), self.metadata.title.as_str().into()),
+            ("url".to_string(), config.url_path(&self.url()).into()),
+            (
+                "permalink".to_string(),
+                config.absolute_url(&self.url()).into(),
+            ),
+            ("canonical".to_string(), self.canonical(config).into()),
+            (
+                "cross_posted".to_string(),
+                self.metadata.canonical.is_some().into(),
+            ),
+            ("noindex".to_string(), self.metadata.noindex.into()),
+            ("draft".to_string(), self.metadata.draft.into()),
+            ("featured".to_string(), self.metadata.featured.into()),
+            (
+                "lang".to_string(),
+                self.metadata.lang.clone().unwrap_or_default().into(),
+            ),
+            (
+                "dir".to_string(),
+                self.metadata.dir.unwrap_or_default().as_str().into(),
+            ),
+            ("extra".to_string(), self.extra()),
+            (
+                "taxonomies".to_string(),
+                self.taxonomies
+                    .iter()
+                    .map(|(taxonomy, terms)| {
+                        let terms = terms
+                            .iter()
+                            .map(|term| taxonomy::term_value(config, taxonomy, term))
+                            .collect::<Vec<_>>();
+                        (taxonomy.as_str(), terms)
+                    })
+                    .collect(),
+            ),
+            (
+                "authors".to_string(),
+                self.metadata
+                    .authors
+                    .iter()
+                    .map(|author| authors::author_value(config, author))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ]);
+        if let Some(weight) = self.metadata.weight {
+            page.insert("weight".to_string(), i64::from(weight).into());
+        }
+        page.insert("description".to_string(), self.description().into());
+        if let Some(excerpt) = &self.excerpt {
+            page.insert("excerpt".to_string(), excerpt.as_str().into());
+        }
+        if let Some(series) = &self.series {
+            page.insert("series".to_string(), series.clone());
+        }
+        if let Some(short_url) = &self.short_url {
+            page.insert(
+                "short_url".to_string(),
+                config.absolute_url(short_url).into(),
+            );
+        }
+        if let Some(date) = &self.metadata.date {
+            page.insert("date".to_string(), format_date_utc(config, date).into());
+            page.insert("date_iso8601".to_string(), format_date_iso8601(date).into());
+        }
+        if let Some(updated) = &self.metadata.updated {
+            page.insert(
+                "updated".to_string(),
+                format_date_utc(config, updated).into(),
+            );
+            page.insert(
+                "updated_iso8601".to_string(),
+                format_date_iso8601(updated).into(),
+            );
+        }
+        page
+    }
+
+    /// Values from the `[extra]` table.
+    fn extra(&self) -> Value {
+        toml::Value::Table(self.metadata.extra.clone()).into()
+    }
+
+    /// Convert the page metadata into a value for templating.
+    fn to_value(&self, config: &Config) -> Value {
+        Value::Map(self.to_map(config))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -171,6 +820,11 @@ struct IndexMetadata {
     #[serde(default)]
     display_in_nav: Option<usize>,
 
+    /// How many levels of pages and subsections are shown below the index in
+    /// the navigation, all by default.
+    #[serde(default)]
+    nav_depth: Option<usize>,
+
     /// Sort pages by the specified order
     sort_by: SortOrder,
 
@@ -185,6 +839,31 @@ struct IndexMetadata {
     /// This path is relative to `content/`
     #[serde(skip_deserializing)]
     filepath: PathBuf,
+
+    /// Arbitrary values given in the `[extra]` table.
+    ///
+    /// These are available to templates as `extra.<key>`.
+    #[serde(default)]
+    extra: toml::Table,
+
+    /// Defaults of the frontmatter of all pages below the section, including
+    /// the ones of nested sections, e.g. `template` or `tags`.
+    ///
+    /// The cascades of nested sections take precedence.
+    #[serde(default)]
+    cascade: toml::Table,
+
+    /// Former URL paths of the section redirecting to it.
+    #[serde(default)]
+    aliases: Vec<String>,
+
+    /// Language of the content, defaults to the language of the site.
+    #[serde(default)]
+    lang: Option<String>,
+
+    /// Text direction of the content, defaults to the direction of the site.
+    #[serde(default)]
+    dir: Option<TextDirection>,
 }
 
 fn default_index_template() -> PathBuf {
@@ -192,34 +871,171 @@ fn default_index_template() -> PathBuf {
 }
 
 /// An index is the `_index.html` within a folder in the content.
+///
+/// Indices form a tree of sections, pages in directories without an index
+/// belong to the section of the nearest ancestor directory.
 #[derive(Debug, Clone)]
 struct Index {
     metadata: IndexMetadata,
     html: String,
     pages: Vec<Page>,
+    /// Directory of the parent section relative to `content/`.
+    parent: Option<PathBuf>,
+    /// Directories of the direct subsections relative to `content/`.
+    subsections: Vec<PathBuf>,
+    /// Directory of the URL of the index, the one of its file normalized by
+    /// the slug policy.
+    url_dir: PathBuf,
 }
 
 impl Index {
     /// Reads and parses an input markdown file.
     ///
     /// Note: This does not read in any pages
-    async fn parse_md(content_dir: impl AsRef<Path>, relpath: impl AsRef<Path>) -> Result<Self> {
+    async fn parse_md(
+        content_dir: impl AsRef<Path>,
+        relpath: impl AsRef<Path>,
+        config: &Config,
+        ctx: &Context,
+    ) -> Result<Self> {
         let file = content_dir.as_ref().join(&relpath);
         let content = tokio::fs::read_to_string(&file)
             .await
             .map_err(|e| Error::ReadInput(relpath.as_ref().to_path_buf(), e))?;
 
         let (frontmatter, markdown) = parse_file(&content, file)?;
-        let mut metadata: IndexMetadata = toml::from_str(frontmatter)
+        let mut metadata: IndexMetadata = frontmatter
+            .parse()
             .map_err(|e| Error::ParseMetadata(relpath.as_ref().to_path_buf(), e))?;
         metadata.filepath = relpath.as_ref().to_path_buf();
+        let dir = relpath.as_ref().parent().expect("index is a file");
+        let url_dir = slug::slugify_dir(&config.slugs, dir)
+            .ok_or_else(|| Error::EmptyDirSlug(dir.to_path_buf()))?;
 
         Ok(Self {
             metadata,
-            html: convert_markdown(markdown),
+            html: convert_markdown(
+                markdown,
+                markdown_options(config.markdown.smart_punctuation),
+                config,
+                ctx,
+                None,
+            )
+            .await?,
             pages: Vec::new(),
+            parent: None,
+            subsections: Vec::new(),
+            url_dir,
         })
     }
+
+    /// Sort the pages by the order of the section.
+    fn sort_pages(&mut self) {
+        // We use unstable here since _I suppose_ pages are already in arbitrary order
+        // coming from the async tasks.
+        let sort_by = &self.metadata.sort_by;
+        self.pages.sort_unstable_by(|p1, p2| {
+            match sort_by {
+                SortOrder::Title => p1.metadata.title.cmp(&p2.metadata.title),
+                SortOrder::Date => {
+                    // Sort pages based on their date descending.
+                    p2.metadata.date.cmp(&p1.metadata.date)
+                }
+                SortOrder::Weight => p1.metadata.weight.cmp(&p2.metadata.weight),
+            }
+        });
+    }
+
+    /// Create a section for a directory without an `_index.md`.
+    ///
+    /// The directory name is used as the title.
+    fn implicit(config: &Config, dir: &Path) -> Result<Self> {
+        let url_dir = slug::slugify_dir(&config.slugs, dir)
+            .ok_or_else(|| Error::EmptyDirSlug(dir.to_path_buf()))?;
+        let title = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self {
+            metadata: IndexMetadata {
+                title,
+                display_in_nav: None,
+                nav_depth: None,
+                sort_by: SortOrder::Title,
+                template: default_index_template(),
+                filepath: dir.join("_index.md"),
+                extra: toml::Table::new(),
+                cascade: toml::Table::new(),
+                aliases: Vec::new(),
+                lang: None,
+                dir: None,
+            },
+            html: String::new(),
+            pages: Vec::new(),
+            parent: None,
+            subsections: Vec::new(),
+            url_dir,
+        })
+    }
+
+    /// Directory of the index relative to `content/`.
+    fn dir(&self) -> &Path {
+        self.metadata.filepath.parent().unwrap()
+    }
+
+    /// Values from the `[extra]` table.
+    fn extra(&self) -> Value {
+        toml::Value::Table(self.metadata.extra.clone()).into()
+    }
+
+    /// Convert the section into a value for templating.
+    ///
+    /// Subsections are included recursively with their published pages, the
+    /// parent only with its title and URL.
+    fn section_value(&self, config: &Config, opts: &Cli, indices: &[Index]) -> Value {
+        let find = |dir: &Path| indices.iter().find(|index| index.dir() == dir);
+        let pages: Vec<_> = self
+            .pages
+            .iter()
+            .filter(|page| page.is_published(opts))
+            .map(|page| page.to_value(config))
+            .collect();
+        let subsections: Vec<_> = self
+            .subsections
+            .iter()
+            .filter_map(|dir| find(dir))
+            .map(|index| index.section_value(config, opts, indices))
+            .collect();
+        let mut section = HashMap::from([
+            (
+                "title".to_string(),
+                Value::from(self.metadata.title.as_str()),
+            ),
+            ("url".to_string(), config.url_path(&self.url()).into()),
+            ("pages".to_string(), pages.into()),
+            ("subsections".to_string(), subsections.into()),
+        ]);
+        if let Some(parent) = self.parent.as_deref().and_then(find) {
+            section.insert(
+                "parent".to_string(),
+                Value::from_iter([
+                    ("title", parent.metadata.title.clone()),
+                    ("url", config.url_path(&parent.url())),
+                ]),
+            );
+        }
+        Value::Map(section)
+    }
+
+    /// Absolute URL path of the index with a trailing slash.
+    fn url(&self) -> String {
+        let path = PathBuf::from("/").join(&self.url_dir).display().to_string();
+        if path.len() > 1 {
+            format!("{}/", path)
+        } else {
+            path
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -234,6 +1050,79 @@ impl Website {
         Website { config }
     }
 
+    /// Render a single markdown file, or the markdown read from stdin, to the
+    /// HTML of its page.
+    ///
+    /// The page takes the place of the page of the same file within the
+    /// content of the site, if any, so it is rendered with the context of the
+    /// whole site. Nothing is written to the output.
+    async fn render(&self, opts: &Cli, file: Option<&Path>) -> Result<String> {
+        let (content, relpath) = match file {
+            Some(file) => {
+                let content = tokio::fs::read_to_string(file)
+                    .await
+                    .map_err(|e| Error::ReadInput(file.to_path_buf(), e))?;
+                (content, content_relpath(&self.config, file))
+            }
+            None => {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut content)
+                    .map_err(|e| Error::ReadInput("stdin".into(), e))?;
+                (content, PathBuf::from("stdin.md"))
+            }
+        };
+        let indices = load_and_parse_content(&self.config, &template::Context::new(), opts).await?;
+        self.render_content(opts, indices, &content, relpath).await
+    }
+
+    /// Render the markdown of the file at the path relative to `content/` to
+    /// the HTML of its page within the parsed content of the site.
+    async fn render_content(
+        &self,
+        opts: &Cli,
+        mut indices: Vec<Index>,
+        content: &str,
+        relpath: PathBuf,
+    ) -> Result<String> {
+        let mut ctx = template::Context::new();
+        let cascade = cascade_of(&indices, &relpath);
+        let position = section_of(&indices, &relpath).unwrap_or_else(|| {
+            indices.push(Index::implicit(&self.config, Path::new("")).expect("root has no name"));
+            indices.len() - 1
+        });
+        let index = &mut indices[position];
+        let page = Page::parse(content, &relpath, &self.config, &ctx, &cascade, false).await?;
+        index
+            .pages
+            .retain(|other| other.metadata.filepath != relpath);
+        index.pages.push(page);
+
+        taxonomy::assign_terms(&self.config.taxonomies, &mut indices)?;
+        let urls = links::content_urls(&self.config, opts, &indices);
+        links::resolve_internal(&urls, &mut indices)?;
+        apply_site_defaults(&self.config, &mut indices);
+        if let Some(typography) = &self.config.typography {
+            apply_typography(typography, &mut indices);
+        }
+        if let Some(short_links) = &self.config.short_links {
+            shortlink::assign_short_links(&self.config, short_links, opts, &mut indices).await?;
+        }
+        series::assign_series(&self.config, opts, &mut indices);
+
+        fill_site_context(&self.config, opts, &mut ctx, &indices).await;
+        let page = indices[position]
+            .pages
+            .iter()
+            .find(|page| page.metadata.filepath == relpath)
+            .expect("page was added");
+        fill_page_context(&self.config, &mut ctx, page);
+        let url = self.config.url_path(&page.url());
+        let html = template::template_pages(&self.config, &ctx, &page.metadata.template, &url)
+            .await
+            .map_err(|e| Error::InContent(relpath.clone(), Box::new(e)))?;
+        Ok(html.into_iter().next().unwrap_or_default())
+    }
+
     /// Build the website to HTML content.
     async fn build(self, opts: &Cli) -> Result<()> {
         // Copy all assets
@@ -249,42 +1138,176 @@ impl Website {
             })?;
 
         // Copy all assets
-        let mirror_assets_handle = tokio::spawn(async move { mirror_assets(from, to).await });
+        let sass = self.config.sass.clone();
+        let sass_dir = self.config.content_path.join("sass");
+        let fingerprint = self.config.fingerprint_assets;
+        let minify = self.config.minify_assets && !opts.is_preview();
+        let mirror_assets_handle = tokio::spawn(async move {
+            let mut manifest =
+                mirror_assets(from.clone(), to.clone(), sass.as_ref(), fingerprint, minify).await?;
+            if let Some(sass) = sass.filter(|_| sass_dir.is_dir()) {
+                let load_paths = [sass_dir.clone(), from];
+                for relpath in list_files(&sass_dir).await? {
+                    let Some(css) =
+                        sass::compile(&sass, &sass_dir.join(&relpath), &load_paths).await?
+                    else {
+                        continue;
+                    };
+                    let relpath = relpath.with_extension("css");
+                    let dir = to.join(relpath.parent().expect("is a file"));
+                    tokio::fs::create_dir_all(&dir)
+                        .await
+                        .map_err(|e| Error::CreateDirectory(dir, e))?;
+                    let output = write_asset(&to, &relpath, css.as_bytes(), fingerprint).await?;
+                    manifest.insert(relpath, output);
+                }
+            }
+            Result::Ok(manifest)
+        });
+        // Fingerprinted names are needed to render templates
+        let (manifest, mirror_assets_handle) = if fingerprint {
+            (
+                Some(mirror_assets_handle.await.map_err(Error::Join)??),
+                None,
+            )
+        } else {
+            (None, Some(mirror_assets_handle))
+        };
+
+        // The templates parsed for shortcodes are kept for rendering
+        let mut ctx = template::Context::new();
+        if let Some(manifest) = &manifest {
+            ctx.set_asset_manifest(manifest.clone());
+        }
 
         // Read and parse content
-        let content_dir = self.config.content_path.join("content");
-        let indices = load_and_parse_content(content_dir).await?;
+        let mut indices = load_and_parse_content(&self.config, &ctx, opts).await?;
+        check_templates(&self.config, opts, &indices)?;
+        taxonomy::assign_terms(&self.config.taxonomies, &mut indices)?;
+        let urls = Arc::new(links::content_urls(&self.config, opts, &indices));
+        links::resolve_internal(&urls, &mut indices)?;
+        apply_site_defaults(&self.config, &mut indices);
+        if let Some(typography) = &self.config.typography {
+            apply_typography(typography, &mut indices);
+        }
+        if let Some(short_links) = &self.config.short_links {
+            shortlink::export_short_links(&self.config, short_links, opts, &mut indices).await?;
+        }
+        series::assign_series(&self.config, opts, &mut indices);
 
         // Fill templating context
-        let mut ctx = template::Context::new();
-        ctx.insert("nav", build_navigation(&indices));
-        ctx.insert("articles", build_article_list(&indices, opts));
-        ctx.insert("site_title", self.config.site_info.title.to_string());
-        ctx.insert(
-            "site_description",
-            self.config.site_info.description.to_string(),
-        );
+        let usage = opts.report_unused.then(Arc::<Usage>::default);
+        if let Some(usage) = &usage {
+            ctx.track_usage(usage.clone());
+        }
+        fill_site_context(&self.config, opts, &mut ctx, &indices).await;
+        // Skipped renderings would be missing from the usage report
+        let render_cache = match self.config.incremental && usage.is_none() {
+            true => Some(Arc::new(
+                RenderCache::open(&self.config, &ctx, manifest.as_ref()).await?,
+            )),
+            false => None,
+        };
+        if let Some(render_cache) = &render_cache {
+            ctx.set_render_cache(render_cache.clone());
+        }
+        let protector = match indices
+            .iter()
+            .flat_map(|index| &index.pages)
+            .any(|page| page.metadata.protected && page.is_published(opts))
+        {
+            true => Some(Arc::new(protect::Protector::open(&self.config).await?)),
+            false => None,
+        };
+        if let Some(protector) = &protector {
+            ctx.set_protector(protector.clone());
+        }
+
+        taxonomy::export_taxonomies(&self.config, opts, &ctx, &indices).await?;
+        authors::export_authors(&self.config, opts, &ctx, &indices).await?;
+        series::export_series(&self.config, opts, &ctx, &indices).await?;
+        if self.config.search_index {
+            search::export_search_index(&self.config, opts, &indices).await?;
+        }
+        feed::export_feeds(&self.config, opts, &ctx, &indices, &urls).await?;
+        if let Some(sitemap) = &self.config.sitemap {
+            sitemap::export_sitemap(&self.config, sitemap, opts, &ctx, &indices).await?;
+        }
+        if let Some(archive) = &self.config.archive {
+            archive::export_archive(&self.config, archive, opts, &ctx, &indices).await?;
+        }
+        not_found::export_not_found(&self.config, &ctx, &urls).await?;
+        redirects::export_aliases(&self.config, opts, &indices).await?;
+        let header_rules = headers::page_headers(opts, &indices)?;
+        export_indices_to_html(&self.config, opts, ctx, indices, urls).await?;
+        if let Some(render_cache) = render_cache {
+            render_cache.prune().await?;
+        }
+        if let Some(protector) = protector {
+            protector.save().await?;
+        }
 
-        export_indices_to_html(&self.config, opts, ctx, indices).await?;
+        if let Some(usage) = usage {
+            let templates_dir = self.config.content_path.join("templates");
+            // Shortcode templates are used by the content instead
+            let templates = list_files(&templates_dir).await?;
+            let templates = templates
+                .into_iter()
+                .filter(|template| !template.starts_with(template::SHORTCODE_DIR));
+            for template in usage.unused_templates(templates) {
+                warn!("Template {} is never used", template.display());
+            }
+            for value in usage.unreferenced_values() {
+                warn!("Context value '{}' is never referenced", value);
+            }
+        }
 
-        mirror_assets_handle.await.map_err(Error::Join)??;
+        let manifest = match mirror_assets_handle {
+            Some(handle) => handle.await.map_err(Error::Join)??,
+            None => manifest.expect("assets were mirrored before rendering"),
+        };
+        // Appended once the assets are mirrored, which may contain rules
+        headers::write_headers(&self.config, &header_rules).await?;
+
+        // Post-process the complete output
+        if let (Some(cdn), true) = (&self.config.cdn_url, self.config.cdn_rewrite) {
+            let assets = manifest.into_values().collect();
+            let pages = cdn::rewrite_output(cdn, &assets, &self.config.output_path).await?;
+            debug!("Rewrote asset URLs of {} pages", pages);
+        }
+        if let Some(a11y) = &self.config.accessibility {
+            let pages = accessibility::check_output(a11y, &self.config.output_path).await?;
+            if pages > 0 {
+                warn!("{} pages have accessibility issues", pages);
+            }
+        }
 
         Ok(())
     }
 }
 
-/// Loads and parses all content in the `content_dir`.
+/// Loads and parses all content in the `content/` directory.
 ///
+/// Pages dated in the future are skipped unless enabled in the options.
 /// Returns the base index which contains all further pages.
-async fn load_and_parse_content(content_dir: PathBuf) -> Result<Vec<Index>> {
-    // Discovered indices
-    let mut indices = Vec::new();
+async fn load_and_parse_content(config: &Config, ctx: &Context, opts: &Cli) -> Result<Vec<Index>> {
+    let content_dir = config.content_path.join("content");
+    let now = OffsetDateTime::now_utc();
+    // Discovered indices by their directory relative to the content
+    let mut indices = BTreeMap::new();
+    // Discovered pages by their directory relative to the content
+    let mut pages_by_dir: BTreeMap<PathBuf, Vec<Page>> = BTreeMap::new();
+    let low_memory = opts.low_memory;
+    let jobs = opts.job_limit();
+    // Cascades of the sections which apply to a directory by the directory
+    // relative to the content
+    let mut cascades: BTreeMap<PathBuf, Arc<toml::Table>> = BTreeMap::new();
     // Stack storing the directories which remain to be processed
     let mut stack = vec![content_dir.clone()];
 
     while let Some(dir) = stack.pop() {
-        let mut index = None;
-        let mut pages_handles = Vec::new();
+        let mut index_file = None;
+        let mut page_files = Vec::new();
 
         // Iterate over the current directory entries
         let mut entries = tokio::fs::read_dir(&dir)
@@ -295,152 +1318,511 @@ async fn load_and_parse_content(content_dir: PathBuf) -> Result<Vec<Index>> {
             .await
             .map_err(|e| Error::ReadDirectory(dir.clone(), e))?
         {
-            let file = entry.path();
+            let mut file = entry.path();
             if file.is_dir() {
-                stack.push(file);
-            } else if file.is_file() {
+                // A directory with a page but no section is a page bundled
+                // with its assets
+                if !file.join(BUNDLE_PAGE).is_file() || file.join("_index.md").is_file() {
+                    stack.push(file);
+                    continue;
+                }
+                file.push(BUNDLE_PAGE);
+            }
+            // The not-found page is written on its own
+            if dir == content_dir && file.file_name() == Some(OsStr::new(not_found::NOT_FOUND_PAGE))
+            {
+                continue;
+            }
+            if file.is_file() && file.extension() == Some(OsStr::new("md")) {
+                let relpath = file
+                    .strip_prefix(&content_dir)
+                    .expect("starts with content directory")
+                    .to_path_buf();
                 if file.file_name() == Some(OsStr::new("_index.md")) {
-                    index = Some(file);
-                } else if file.extension() == Some(OsStr::new("md")) {
-                    let content_dir = content_dir.clone();
-                    let relpath = file
-                        .strip_prefix(&content_dir)
-                        .expect("starts with content directory")
-                        .to_path_buf();
-                    pages_handles.push(tokio::spawn(async move {
-                        Page::parse_md(content_dir, relpath).await
-                    }));
+                    index_file = Some(relpath);
+                } else {
+                    page_files.push(relpath);
                 }
             }
         }
 
+        let reldir = dir
+            .strip_prefix(&content_dir)
+            .expect("starts with content directory")
+            .to_path_buf();
+        // The index is needed first as its cascade applies to the pages
+        let index = match index_file {
+            Some(relpath) => Some(Index::parse_md(&content_dir, relpath, config, ctx).await?),
+            None => None,
+        };
+        let parent_cascade = reldir
+            .parent()
+            .and_then(|parent| cascades.get(parent))
+            .cloned()
+            .unwrap_or_default();
+        let cascade = match &index {
+            Some(index) if !index.metadata.cascade.is_empty() => {
+                let mut cascade = index.metadata.cascade.clone();
+                frontmatter::merge_defaults(&mut cascade, &parent_cascade);
+                Arc::new(cascade)
+            }
+            _ => parent_cascade,
+        };
+        cascades.insert(reldir.clone(), cascade.clone());
+
+        let mut pages_handles = Vec::with_capacity(page_files.len());
+        for relpath in page_files {
+            let content_dir = content_dir.clone();
+            let config = config.clone();
+            let ctx = ctx.clone();
+            let cascade = cascade.clone();
+            let permit = jobs.clone().acquire_owned().await.expect("never closed");
+            pages_handles.push(tokio::spawn(async move {
+                let _permit = permit;
+                Page::parse_md(content_dir, relpath, &config, &ctx, &cascade, low_memory).await
+            }));
+        }
         let mut pages = Vec::with_capacity(pages_handles.len());
         for handle in pages_handles {
-            pages.push(handle.await.map_err(Error::Join)??);
+            let page: Page = handle.await.map_err(Error::Join)??;
+            if !opts.future && page.metadata.date.is_some_and(|date| date > now) {
+                debug!("Skipping future page {:?}", page.metadata.filepath);
+                continue;
+            }
+            pages.push(page);
+        }
+        check_unique_ids(&pages)?;
+        if !pages.is_empty() {
+            pages_by_dir.insert(reldir.clone(), pages);
+        }
+        if let Some(index) = index {
+            indices.insert(reldir, index);
         }
+    }
 
-        // Read and process the index
-        if let Some(file) = index {
-            let content_dir = content_dir.clone();
-            let relpath = file
-                .strip_prefix(&content_dir)
-                .expect("starts with content directory")
-                .to_path_buf();
+    // Attach the pages to the section of their directory or its nearest
+    // ancestor, creating an implicit section at the top if there is none.
+    for (dir, pages) in pages_by_dir {
+        let section = match dir
+            .ancestors()
+            .find(|ancestor| indices.contains_key(*ancestor))
+        {
+            Some(section) => section.to_path_buf(),
+            None => {
+                debug!("Creating implicit section for {}", dir.display());
+                let top = dir.ancestors().last().expect("has a root").to_path_buf();
+                let section = dir
+                    .ancestors()
+                    .take_while(|ancestor| *ancestor != top)
+                    .last()
+                    .unwrap_or(&top)
+                    .to_path_buf();
+                indices.insert(section.clone(), Index::implicit(config, &section)?);
+                section
+            }
+        };
+        indices
+            .get_mut(&section)
+            .expect("section was found or inserted")
+            .pages
+            .extend(pages);
+    }
 
-            let mut index =
-                tokio::spawn(async move { Index::parse_md(content_dir, relpath).await })
-                    .await
-                    .map_err(Error::Join)??;
-            index.pages = pages;
-
-            // Sort pages
-            // We use unstable here since _I suppose_ pages are already in arbitrary order
-            // coming from the async tasks.
-            index.pages.sort_unstable_by(|p1, p2| {
-                match index.metadata.sort_by {
-                    SortOrder::Title => p1.metadata.title.cmp(&p2.metadata.title),
-                    SortOrder::Date => {
-                        // Sort pages based on their date descending.
-                        p2.metadata.date.cmp(&p1.metadata.date)
-                    }
-                    SortOrder::Weight => p1.metadata.weight.cmp(&p2.metadata.weight),
-                }
-            });
+    link_sections(&mut indices);
 
-            indices.push(index);
-        }
+    let mut indices: Vec<_> = indices.into_values().collect();
+    check_unique_urls(opts, &indices)?;
+    for index in &mut indices {
+        index.sort_pages();
     }
 
     Ok(indices)
 }
 
+/// Set the parent and subsections of every section by their directories.
+fn link_sections(indices: &mut BTreeMap<PathBuf, Index>) {
+    let dirs: Vec<_> = indices.keys().cloned().collect();
+    for dir in &dirs {
+        let parent = dir
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| indices.contains_key(*ancestor))
+            .map(Path::to_path_buf);
+        if let Some(parent) = &parent {
+            indices
+                .get_mut(parent)
+                .expect("parent is a section")
+                .subsections
+                .push(dir.clone());
+        }
+        indices.get_mut(dir).expect("section exists").parent = parent;
+    }
+}
+
+/// Ensure that no two pages of a directory share the same id and thereby URL.
+fn check_unique_ids(pages: &[Page]) -> Result<()> {
+    let mut ids: HashMap<&str, &Path> = HashMap::new();
+    for page in pages {
+        let metadata = &page.metadata;
+        if let Some(other) = ids.insert(&metadata.id, &metadata.filepath) {
+            return Err(Error::DuplicateId(
+                metadata.id.clone(),
+                other.to_path_buf(),
+                metadata.filepath.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Ensure that no two sections or published pages have the same URL after
+/// their ids and directories were normalized.
+fn check_unique_urls(opts: &Cli, indices: &[Index]) -> Result<()> {
+    let mut urls: HashMap<String, &Path> = HashMap::new();
+    for index in indices {
+        let pages = index
+            .pages
+            .iter()
+            .filter(|page| page.is_published(opts))
+            .map(|page| (page.url(), &page.metadata.filepath));
+        for (url, file) in std::iter::once((index.url(), &index.metadata.filepath)).chain(pages) {
+            if let Some(other) = urls.get(&url) {
+                return Err(Error::DuplicateUrl(url, other.to_path_buf(), file.clone()));
+            }
+            urls.insert(url, file);
+        }
+    }
+    Ok(())
+}
+
+/// Path of the content file relative to `content/`, or its file name if it
+/// is located elsewhere.
+fn content_relpath(config: &Config, file: &Path) -> PathBuf {
+    let content_dir = config.content_path.join("content");
+    match (file.canonicalize(), content_dir.canonicalize()) {
+        (Ok(file), Ok(dir)) if file.starts_with(&dir) => {
+            file.strip_prefix(&dir).expect("starts with dir").into()
+        }
+        _ => file.file_name().map(PathBuf::from).unwrap_or_default(),
+    }
+}
+
+/// Position of the section a page at the path relative to `content/` belongs
+/// to, which is the section of its directory or its nearest ancestor as in a
+/// build.
+fn section_of(indices: &[Index], relpath: &Path) -> Option<usize> {
+    let dir = relpath.parent().unwrap_or(Path::new(""));
+    dir.ancestors()
+        .find_map(|ancestor| indices.iter().position(|index| index.dir() == ancestor))
+}
+
+/// Cascade of the sections above a page at the path relative to `content/`,
+/// the nearest section taking precedence as in a build.
+fn cascade_of(indices: &[Index], relpath: &Path) -> toml::Table {
+    let dir = relpath.parent().unwrap_or(Path::new(""));
+    let mut cascade = toml::Table::new();
+    for ancestor in dir.ancestors() {
+        if let Some(index) = indices.iter().find(|index| index.dir() == ancestor) {
+            frontmatter::merge_defaults(&mut cascade, &index.metadata.cascade);
+        }
+    }
+    cascade
+}
+
+/// Ensure that the templates of all indices and published pages exist before
+/// anything is rendered.
+///
+/// Every content file with a missing template is logged.
+fn check_templates(config: &Config, opts: &Cli, indices: &[Index]) -> Result<()> {
+    let mut missing = 0;
+    for index in indices {
+        let pages = index
+            .pages
+            .iter()
+            .filter(|page| page.is_published(opts))
+            .map(|page| (&page.metadata.template, &page.metadata.filepath));
+        let files = std::iter::once((&index.metadata.template, &index.metadata.filepath));
+        for (template, file) in files.chain(pages) {
+            if !template::template_exists(config, template) {
+                error!(
+                    "Template {} of {} does not exist",
+                    template.display(),
+                    file.display()
+                );
+                missing += 1;
+            }
+        }
+    }
+    match missing {
+        0 => Ok(()),
+        _ => Err(Error::MissingTemplates(missing)),
+    }
+}
+
+/// Fill in metadata which defaults to the site configuration.
+fn apply_site_defaults(config: &Config, indices: &mut [Index]) {
+    let site = &config.site_info;
+    for index in indices {
+        let metadata = &mut index.metadata;
+        metadata.lang.get_or_insert_with(|| site.lang.clone());
+        metadata.dir.get_or_insert(site.dir);
+        for page in &mut index.pages {
+            let metadata = &mut page.metadata;
+            metadata.lang.get_or_insert_with(|| site.lang.clone());
+            metadata.dir.get_or_insert(site.dir);
+        }
+    }
+}
+
+/// Apply the typography pass to the content of all indices and pages in
+/// their language.
+fn apply_typography(config: &TypographyConfig, indices: &mut [Index]) {
+    for index in indices {
+        let lang = index.metadata.lang.as_deref().unwrap_or_default();
+        index.html = typography::apply(config, lang, &index.html);
+        for page in &mut index.pages {
+            let lang = page.metadata.lang.as_deref().unwrap_or_default();
+            page.html = typography::apply(config, lang, &page.html);
+        }
+    }
+}
+
+/// Render a template and write it to the `index.html` in the directory.
+///
+/// Further pages of a pagination are written to `page/<n>/` below it.
+/// Protected pages are encrypted before they are written and never cached.
+async fn write_page(
+    config: &Config,
+    ctx: &Context,
+    template: &Path,
+    dir: &Path,
+    protected: bool,
+) -> Result<()> {
+    let reldir = dir.strip_prefix(&config.output_path).unwrap_or(dir);
+    let url = match reldir.to_str().unwrap_or_default() {
+        "" => "/".to_string(),
+        reldir => format!("/{}/", reldir),
+    };
+    let mut ctx = ctx.clone();
+    nav::mark_active(config, &mut ctx, &url);
+    let pages = match protected {
+        true => template::template_pages(config, &ctx, template, &url).await?,
+        false => cache::template_pages(config, &ctx, template, &url).await?,
+    };
+    for (i, html) in pages.into_iter().enumerate() {
+        let dir = match i {
+            0 => dir.to_path_buf(),
+            _ => dir.join("page").join((i + 1).to_string()),
+        };
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.clone(), e))?;
+        let html = match protected {
+            true => {
+                let page_url = match i {
+                    0 => url.clone(),
+                    _ => format!("{}page/{}/", url, i + 1),
+                };
+                ctx.protector()
+                    .ok_or(Error::ProtectionNotConfigured)?
+                    .encrypt_page(&ctx, &page_url, &html)?
+            }
+            false => html,
+        };
+        let path = dir.join("index.html");
+        tokio::fs::write(&path, html)
+            .await
+            .map_err(|e| Error::WriteFile(path, e))?;
+    }
+    Ok(())
+}
+
+/// Insert the values shared by all templates of the site into the context.
+async fn fill_site_context(config: &Config, opts: &Cli, ctx: &mut Context, indices: &[Index]) {
+    ctx.insert(nav::NAV, nav::navigation(config, indices, opts));
+    if !config.menus.is_empty() {
+        ctx.insert(nav::MENUS, nav::menus(config));
+    }
+    ctx.insert("articles", build_article_list(config, indices, opts));
+    ctx.insert(
+        "featured_pages",
+        featured_pages(indices, opts)
+            .into_iter()
+            .map(|page| page.to_value(config))
+            .collect::<Vec<_>>(),
+    );
+    let today = OffsetDateTime::now_utc().date();
+    if let Some(page) = page_of_the_day(indices, opts, today) {
+        ctx.insert("page_of_the_day", page.to_value(config));
+    }
+    ctx.insert(
+        "site",
+        Value::from_iter([
+            ("title", config.site_info.title.as_str()),
+            ("description", config.site_info.description.as_str()),
+            ("lang", config.site_info.lang.as_str()),
+            ("dir", config.site_info.dir.as_str()),
+            ("base_url", config.base_url.as_deref().unwrap_or_default()),
+        ]),
+    );
+    ctx.insert("build", build_info(config, opts).await);
+    ctx.insert("site_title", config.site_info.title.to_string());
+    ctx.insert("site_description", config.site_info.description.to_string());
+}
+
+/// Insert the values of the page into the context of its template.
+fn fill_page_context(config: &Config, ctx: &mut Context, page: &Page) {
+    let mut page_value = page.to_map(config);
+    page_value.insert("content".to_string(), page.html.as_str().into());
+    // Deferred pages are restored before they are written, so the markdown
+    // and text are present for every page that is rendered
+    if let Some(markdown) = &page.raw_markdown {
+        page_value.insert("raw_markdown".to_string(), markdown.as_str().into());
+    }
+    if let Some(text) = &page.plain_text {
+        page_value.insert("plain_text".to_string(), text.as_str().into());
+    }
+    ctx.insert("page", Value::Map(page_value));
+    ctx.insert("extra", page.extra());
+    ctx.insert("lang", page.metadata.lang.clone().unwrap_or_default());
+    ctx.insert("dir", page.metadata.dir.unwrap_or_default().as_str());
+    ctx.insert("content", page.html.to_string());
+    ctx.insert("title", page.metadata.title.to_string());
+    ctx.insert("description", page.description());
+    ctx.insert("canonical", page.canonical(config));
+    ctx.insert("noindex", page.metadata.noindex);
+    ctx.insert("word_count", page.word_count as i64);
+    ctx.insert("reading_time", reading_time(page.word_count) as i64);
+    if let Some(excerpt) = &page.excerpt {
+        ctx.insert("excerpt", excerpt.as_str());
+    }
+    if let Some(short_url) = &page.short_url {
+        ctx.insert("short_url", config.absolute_url(short_url));
+    }
+    if let Some(social) = &config.social {
+        ctx.insert("social_meta", social::social_meta(config, social, page));
+    }
+    if config.structured_data {
+        ctx.insert("structured_data", structured_data::article(config, page));
+    }
+    if let Some(date) = page.metadata.date {
+        ctx.insert("date_iso8601", format_date_iso8601(&date));
+        ctx.insert("date", format_date_utc(config, &date));
+    }
+}
+
 /// Write all indices to disk.
+///
+/// In low-memory mode the pages are written one after another, rendering the
+/// content of each page only for writing it.
 async fn export_indices_to_html(
     config: &Config,
     opts: &Cli,
     mut ctx: Context,
     indices: Vec<Index>,
+    urls: Arc<links::ContentUrls>,
 ) -> Result<()> {
-    for index in indices {
+    let sections: Vec<_> = indices
+        .iter()
+        .map(|index| index.section_value(config, opts, &indices))
+        .collect();
+    let jobs = opts.job_limit();
+    for (index, section) in indices.into_iter().zip(sections) {
         debug!("Building index {:?}", index);
 
-        // Create filepath to store the index.html
-        let dir = config.output_path.join(
-            index
-                .metadata
-                .filepath
-                .parent()
-                .expect("index always has a parent"),
-        );
-        let file = dir.join("index.html");
-        tokio::fs::create_dir_all(&dir)
-            .await
-            .map_err(|e| Error::CreateDirectory(dir, e))?;
+        // Directory to store the index.html
+        let output_dir = config.output_path.join(&index.url_dir);
 
         // Build index context
+        let pages: Vec<_> = index
+            .pages
+            .iter()
+            .filter(|page| page.is_published(opts))
+            .map(|page| page.to_value(config))
+            .collect();
         ctx.insert("title", index.metadata.title.to_string());
         ctx.insert("content", index.html.to_string());
+        ctx.insert("pages", pages.clone());
+        ctx.insert("section", section);
+        let lang = index.metadata.lang.clone().unwrap_or_default();
+        let dir = index.metadata.dir.unwrap_or_default().as_str();
+        ctx.insert("extra", index.extra());
+        ctx.insert("lang", lang.as_str());
+        ctx.insert("dir", dir);
+        ctx.insert(
+            "index",
+            Value::from_iter([
+                ("title", Value::from(index.metadata.title.as_str())),
+                ("lang", lang.into()),
+                ("dir", dir.into()),
+                ("url", config.url_path(&index.url()).into()),
+                ("permalink", config.absolute_url(&index.url()).into()),
+                ("content", index.html.as_str().into()),
+                ("pages", pages.into()),
+                ("extra", index.extra()),
+            ]),
+        );
 
-        // Apply templating
-        let templates_dir = config.content_path.join("templates");
-        let template_path = templates_dir.join(&index.metadata.template);
-        let template = tokio::fs::read_to_string(&template_path)
-            .await
-            .map_err(|e| Error::ReadInput(template_path, e))?;
-        let html = template::template(config, &ctx, template).await?;
+        if config.structured_data {
+            let website = match index.dir() == Path::new("") {
+                true => structured_data::website(config),
+                false => String::new(),
+            };
+            ctx.insert("structured_data", website);
+        }
 
-        // Write index.html
-        tokio::fs::write(&file, html)
+        // Apply templating and write index.html
+        write_page(config, &ctx, &index.metadata.template, &output_dir, false)
             .await
-            .map_err(|e| Error::WriteFile(file, e))?;
+            .map_err(|e| Error::InContent(index.metadata.filepath.clone(), Box::new(e)))?;
 
         // Export pages
         let mut handles = Vec::new();
         let pages = index
             .pages
             .into_iter()
-            .filter(|page| !page.metadata.draft || opts.drafts);
-        for page in pages {
+            .filter(|page| page.is_published(opts));
+        for mut page in pages {
             let config = config.clone();
             let mut ctx = ctx.clone();
-            let templates_dir = templates_dir.clone();
+            let urls = opts.low_memory.then(|| urls.clone());
+            let permit = jobs.clone().acquire_owned().await.expect("never closed");
 
-            handles.push(tokio::spawn(async move {
+            let handle = tokio::spawn(async move {
+                let _permit = permit;
                 debug!("Building page '{:?}'", &page.metadata);
-
-                // Build page context
-                ctx.insert("content", page.html.to_string());
-                ctx.insert("title", page.metadata.title.to_string());
-                if let Some(excerpt) = page.metadata.excerpt {
-                    ctx.insert("excerpt", excerpt);
+                if let Some(urls) = urls {
+                    page.restore_deferred(&config, &ctx, &urls)
+                        .await
+                        .map_err(|e| {
+                            Error::InContent(page.metadata.filepath.clone(), Box::new(e))
+                        })?;
                 }
-                if let Some(date) = page.metadata.date {
-                    ctx.insert("date_iso8601", format_date_iso8601(&date));
-                    ctx.insert("date", format_date_utc(&date));
+
+                fill_page_context(&config, &mut ctx, &page);
+
+                // Apply templating and write page HTML to file
+                let dir = config.output_path.join(page.output_dir());
+                let protected = page.metadata.protected;
+                match page.parts.is_empty() {
+                    true => {
+                        write_page(&config, &ctx, &page.metadata.template, &dir, protected).await
+                    }
+                    false => split::write_parts(&config, &ctx, &page, &dir).await,
                 }
+                .map_err(|e| Error::InContent(page.metadata.filepath.clone(), Box::new(e)))?;
 
-                // Apply templating
-                let template_path = templates_dir.join(&page.metadata.template);
-                let template = tokio::fs::read_to_string(&template_path)
-                    .await
-                    .map_err(|e| Error::ReadInput(template_path, e))?;
-                let html = template::template(&config, &ctx, template).await?;
-
-                // Write page HTML to file
-                let dir = config
-                    .output_path
-                    .join(page.metadata.filepath.parent().unwrap())
-                    .join(page.metadata.id);
-                tokio::fs::create_dir_all(dir.clone())
-                    .await
-                    .map_err(|e| Error::CreateDirectory(dir.clone(), e))?;
-                let path = dir.join("index.html");
-                tokio::fs::write(&path, html)
-                    .await
-                    .map_err(|e| Error::WriteFile(path, e))?;
+                // Copy the assets of a bundle next to the page
+                if let Some(bundle) = &page.bundle {
+                    copy_bundle_assets(&config, bundle, &dir).await?;
+                }
 
                 Result::Ok(())
-            }))
+            });
+            match opts.low_memory {
+                true => handle.await.map_err(Error::Join)??,
+                false => handles.push(handle),
+            }
         }
 
         for handle in handles {
@@ -450,77 +1832,106 @@ async fn export_indices_to_html(
     Ok(())
 }
 
-/// Create the HTML for the navigation based on the indices and pages.
-fn build_navigation(indices: &[Index]) -> String {
-    let mut navs = Vec::new();
-
-    indices
-        .iter()
-        .flat_map(|index| index.metadata.display_in_nav.map(|i| (i, index)))
-        .for_each(|(i, index)| {
-            let path = PathBuf::from("/")
-                .join(index.metadata.filepath.parent().unwrap())
-                .display()
-                .to_string();
-            if path.len() > 1 {
-                navs.push((
-                    i,
-                    format!("<a href=\"{}/\">{}</a>\n", path, index.metadata.title),
-                ));
-            } else {
-                navs.push((i, format!("<a href=\"/\">{}</a>\n", index.metadata.title)));
-            }
-            index
-                .pages
-                .iter()
-                .flat_map(|page| page.metadata.display_in_nav.map(|i| (i, page)))
-                .for_each(|(i, page)| {
-                    let path = PathBuf::from("/")
-                        .join(index.metadata.filepath.parent().unwrap())
-                        .join(&page.metadata.id);
-                    navs.push((
-                        i,
-                        format!(
-                            "<a href=\"{}/\">{}</a>\n",
-                            path.display(),
-                            page.metadata.title
-                        ),
-                    ));
-                });
-        });
-
-    navs.sort_by_key(|(i, _nav)| *i);
-    navs.into_iter().map(|(_i, nav)| nav).collect()
-}
-
-/// Build an HTML list of articles.
-fn build_article_list(indices: &[Index], opts: &Cli) -> String {
+/// Build the list of articles.
+///
+/// Articles are all published pages with a date and an excerpt.
+fn build_article_list(config: &Config, indices: &[Index], opts: &Cli) -> Vec<Value> {
     indices
         .iter()
         .flat_map(|index| &index.pages)
         .filter(|page| {
-            page.metadata.date.is_some()
-                && page.metadata.excerpt.is_some()
-                && (!page.metadata.draft || opts.drafts)
+            page.metadata.date.is_some() && page.excerpt.is_some() && page.is_published(opts)
         })
-        .fold(String::new(), |mut output, page| {
-            // Append current metadata as HTML to post TOC
-            let path = PathBuf::from("/")
-                .join(page.metadata.filepath.parent().unwrap())
-                .join(&page.metadata.id);
-            let _ = write!(
-                output,
-                "<hgroup>\n<h3><a href=\"{path}/\">{title}</a></h3>\n<p><small><time \
-                 datetime=\"{date_iso}\">{date_utc}</time></small></p>\n</hgroup>\n<p>{excerpt}</\
-                 p>\n",
-                path = path.display(),
-                title = page.metadata.title,
-                date_iso = format_date_iso8601(&page.metadata.date.unwrap()),
-                date_utc = format_date_utc(&page.metadata.date.unwrap()),
-                excerpt = page.metadata.excerpt.as_ref().unwrap(),
-            );
-            output
+        .map(|page| {
+            let mut value = page.to_map(config);
+            value.insert("url".to_string(), config.link_url(&page.url()).into());
+            Value::Map(value)
         })
+        .collect()
+}
+
+/// Collect all featured pages in a deterministic order.
+fn featured_pages<'a>(indices: &'a [Index], opts: &Cli) -> Vec<&'a Page> {
+    let mut pages: Vec<_> = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.metadata.featured && page.is_published(opts))
+        .collect();
+    pages.sort_by(|p1, p2| p1.metadata.filepath.cmp(&p2.metadata.filepath));
+    pages
+}
+
+/// Pick one of the featured pages based on the given date.
+///
+/// The same date always picks the same page as long as the set of featured
+/// pages does not change.
+fn page_of_the_day<'a>(indices: &'a [Index], opts: &Cli, date: Date) -> Option<&'a Page> {
+    let pages = featured_pages(indices, opts);
+    if pages.is_empty() {
+        return None;
+    }
+
+    // Mix the day number (splitmix64) so consecutive days do not simply pick
+    // consecutive pages.
+    let mut seed = (date.to_julian_day() as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    seed ^= seed >> 31;
+
+    Some(pages[(seed % pages.len() as u64) as usize])
+}
+
+/// Information about the current build exposed to the templates as `build`.
+///
+/// The profile is `preview` when unpublished content is built and
+/// `production` otherwise. The commit is only set when the site is located in
+/// a git repository.
+async fn build_info(config: &Config, opts: &Cli) -> Value {
+    let profile = if opts.is_preview() {
+        "preview"
+    } else {
+        "production"
+    };
+    let mut info = HashMap::from([
+        ("profile".to_string(), Value::from(profile)),
+        (
+            "timestamp".to_string(),
+            format_date_iso8601(&OffsetDateTime::now_utc()).into(),
+        ),
+    ]);
+    if let Some(commit) = git_commit(&config.content_path).await {
+        info.insert("git_commit".to_string(), commit.into());
+    }
+    Value::Map(info)
+}
+
+/// Get the abbreviated hash of the commit checked out in the git repository
+/// containing `dir`, if any.
+///
+/// The command runs on the blocking thread pool to not stall the runtime.
+async fn git_commit(dir: &Path) -> Option<String> {
+    let dir = if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir.to_path_buf()
+    };
+    let command_dir = dir.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        std::process::Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .current_dir(command_dir)
+            .stderr(std::process::Stdio::null())
+            .output()
+    })
+    .await
+    .ok()?
+    .ok()?;
+    if !output.status.success() {
+        debug!("{} is not in a git repository", dir.display());
+        return None;
+    }
+    let commit = String::from_utf8(output.stdout).ok()?;
+    Some(commit.trim().to_string()).filter(|commit| !commit.is_empty())
 }
 
 fn format_date_iso8601(date: &OffsetDateTime) -> String {
@@ -528,23 +1939,45 @@ fn format_date_iso8601(date: &OffsetDateTime) -> String {
         .expect("date already validated")
 }
 
-fn format_date_utc(date: &OffsetDateTime) -> String {
-    date.to_offset(time::macros::offset!(UTC))
-        .format(&DATE_FORMAT)
-        .expect("date already validated")
+/// Format the date in UTC with the date format of the site.
+fn format_date_utc(config: &Config, date: &OffsetDateTime) -> String {
+    template::format_date(date, &config.date_format).expect("date format already validated")
 }
 
+/// Output paths of the assets by their path as referenced in templates, both
+/// relative to the output directory.
+///
+/// The paths only differ if assets are fingerprinted or compiled.
+type AssetManifest = BTreeMap<PathBuf, PathBuf>;
+
+/// Files of the assets configuring the host, which are found by their name
+/// and thus never fingerprinted.
+const HOST_FILES: &[&str] = &["_headers", "_redirects"];
+
 /// Mirror the assets fully.
-async fn mirror_assets(from: PathBuf, to: PathBuf) -> Result<()> {
+///
+/// Stylesheets are compiled instead of copied if Sass is configured, CSS and
+/// JavaScript files are minified if enabled. Returns the output paths of all
+/// assets.
+async fn mirror_assets(
+    from: PathBuf,
+    to: PathBuf,
+    sass: Option<&SassConfig>,
+    fingerprint: bool,
+    minify: bool,
+) -> Result<AssetManifest> {
+    let load_paths = [from.clone()];
+    let mut manifest = AssetManifest::new();
+
     // Ensure that the output base directory exists.
     tokio::fs::create_dir_all(&to)
         .await
         .map_err(|e| Error::CreateDirectory(to.clone(), e))?;
 
     // Stack storing the directories which remain to be processed
-    let mut stack = vec![(from, to)];
+    let mut stack = vec![(from, PathBuf::new())];
 
-    while let Some((from, to)) = stack.pop() {
+    while let Some((from, reldir)) = stack.pop() {
         // Iterate over the current directory entries
         let mut entries = tokio::fs::read_dir(&from)
             .await
@@ -555,65 +1988,441 @@ async fn mirror_assets(from: PathBuf, to: PathBuf) -> Result<()> {
             .map_err(|e| Error::ReadDirectory(from.clone(), e))?
         {
             let new_from = entry.path();
-            let new_to = to.join(entry.file_name());
+            let relpath = reldir.join(entry.file_name());
             if new_from.is_dir() {
                 // Replicate the found directory
+                let new_to = to.join(&relpath);
                 tokio::fs::create_dir_all(&new_to)
                     .await
                     .map_err(|e| Error::CreateDirectory(new_to.clone(), e))?;
                 // Add the directory to the stack to iterate later
-                stack.push((new_from, new_to));
+                stack.push((new_from, relpath));
+            } else if let Some(sass) = sass.filter(|_| sass::is_stylesheet(&new_from)) {
+                if let Some(css) = sass::compile(sass, &new_from, &load_paths).await? {
+                    let relpath = relpath.with_extension("css");
+                    let output = write_asset(&to, &relpath, css.as_bytes(), fingerprint).await?;
+                    manifest.insert(relpath, output);
+                }
+            } else if new_from.is_file() && (fingerprint || minify) {
+                let mut content = tokio::fs::read(&new_from)
+                    .await
+                    .map_err(|e| Error::ReadInput(new_from.clone(), e))?;
+                if minify {
+                    if let Some(minified) = minify::minify(&relpath, &content)? {
+                        content = minified;
+                    }
+                }
+                let host_file = HOST_FILES.iter().any(|name| relpath == Path::new(name));
+                let output =
+                    write_asset(&to, &relpath, &content, fingerprint && !host_file).await?;
+                manifest.insert(relpath, output);
             } else if new_from.is_file() {
                 // Copy the found file
+                let new_to = to.join(&relpath);
                 tokio::fs::copy(&new_from, &new_to)
                     .await
                     .map_err(|e| Error::Copy(new_from, new_to, e))?;
+                manifest.insert(relpath.clone(), relpath);
             }
         }
     }
 
+    Ok(manifest)
+}
+
+/// Write an asset to the output directory.
+///
+/// If fingerprinting, the content hash is added to the file name, e.g.
+/// `style.50a6bae2.css`. Returns the written path relative to `to`.
+async fn write_asset(
+    to: &Path,
+    relpath: &Path,
+    content: &[u8],
+    fingerprint: bool,
+) -> Result<PathBuf> {
+    let relpath = if fingerprint {
+        let hash = template::content_hash(content);
+        let name = match (relpath.file_stem(), relpath.extension()) {
+            (Some(stem), Some(ext)) => {
+                format!(
+                    "{}.{}.{}",
+                    stem.to_string_lossy(),
+                    hash,
+                    ext.to_string_lossy()
+                )
+            }
+            _ => format!("{}.{}", relpath.display(), hash),
+        };
+        relpath.with_file_name(name)
+    } else {
+        relpath.to_path_buf()
+    };
+    let path = to.join(&relpath);
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))?;
+    Ok(relpath)
+}
+
+/// Copy all files of a page bundle except for markdown to the directory.
+async fn copy_bundle_assets(config: &Config, bundle: &Path, dir: &Path) -> Result<()> {
+    let from = config.content_path.join("content").join(bundle);
+    let assets = list_files(&from)
+        .await?
+        .into_iter()
+        .filter(|file| file.extension() != Some(OsStr::new("md")));
+    for asset in assets {
+        let (from, to) = (from.join(&asset), dir.join(&asset));
+        if let Some(parent) = to.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Error::CreateDirectory(parent.to_path_buf(), e))?;
+        }
+        tokio::fs::copy(&from, &to)
+            .await
+            .map_err(|e| Error::Copy(from, to, e))?;
+    }
     Ok(())
 }
 
+/// List all files within the directory relative to it.
+async fn list_files(base: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    // Stack storing the directories which remain to be processed
+    let mut stack = vec![base.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(|e| Error::ReadDirectory(dir.clone(), e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| Error::ReadDirectory(dir.clone(), e))?
+        {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.is_file() {
+                let relpath = path
+                    .strip_prefix(base)
+                    .expect("starts with base directory")
+                    .to_path_buf();
+                files.insert(relpath);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 /// Extract frontmatter and markdown from a input file.
-fn parse_file(input: &str, filepath: impl AsRef<Path>) -> Result<(&str, &str)> {
-    let mut split = input.splitn(3, "+++");
-    // Empty before frontmatter
-    split.next();
-    let err = || Error::MalformedContent(filepath.as_ref().into());
-    let frontmatter = split.next().ok_or_else(err)?;
-    let markdown = split.next().ok_or_else(err)?.trim();
-    Ok((frontmatter, markdown))
+fn parse_file(
+    input: &str,
+    filepath: impl AsRef<Path>,
+) -> Result<(frontmatter::Frontmatter<'_>, &str)> {
+    frontmatter::split(input).ok_or_else(|| Error::MalformedContent(filepath.as_ref().into()))
 }
 
-fn convert_markdown(markdown: &str) -> String {
+/// Markdown extensions enabled for all content.
+///
+/// Quotes, dashes and ellipses are replaced by their typographic form if
+/// smart punctuation is enabled.
+fn markdown_options(smart_punctuation: bool) -> Options {
     let mut options = Options::empty();
+    options.set(Options::ENABLE_SMART_PUNCTUATION, smart_punctuation);
     options.insert(Options::ENABLE_FOOTNOTES);
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_TASKLISTS);
-    let parser = pulldown_cmark::Parser::new_ext(markdown, options);
+    options
+}
+
+/// Convert the markdown to HTML.
+///
+/// Relative images are resolved against the base URL if given. Attributes
+/// given after images and links are added to their HTML.
+async fn convert_markdown(
+    markdown: &str,
+    options: Options,
+    config: &Config,
+    ctx: &Context,
+    base_url: Option<&str>,
+) -> Result<String> {
+    let markdown = template::expand_shortcodes(config, ctx, markdown).await?;
+    let parser = pulldown_cmark::Parser::new_ext(&markdown, options);
+    let mut events: Vec<_> = match &config.markdown.math {
+        #[cfg(feature = "math")]
+        Some(math) => math::render(math, &markdown, parser.into_offset_iter()),
+        _ => parser.collect(),
+    };
+    events = attributes::mark(events);
+    if let Some(base_url) = base_url {
+        for event in &mut events {
+            if let Event::Start(Tag::Image(_, dest, _)) = event {
+                if !dest.contains(':') && !dest.starts_with(['/', '#', '?']) {
+                    *dest = format!("{}{}", base_url, dest).into();
+                }
+            }
+        }
+    }
+    if let Some(external) = &config.markdown.external_links {
+        events = links::rewrite_external(config, external, events);
+    }
+    #[cfg(feature = "images")]
+    if let Some(responsive) = &config.markdown.responsive_images {
+        events = images::responsive_images(config, ctx, responsive, events.into_iter()).await?;
+    }
 
     // Write to String buffer.
     let mut html = String::new();
-    pulldown_cmark::html::push_html(&mut html, parser);
+    match &config.markdown.footnotes {
+        Some(footnotes) => footnotes::push_html(footnotes, &mut html, events.into_iter()),
+        None => pulldown_cmark::html::push_html(&mut html, events.into_iter()),
+    }
 
-    html
+    Ok(attributes::apply(&html))
+}
+
+/// Shorten the text to at most `max` characters.
+///
+/// The text is cut after the last complete sentence if there is one, otherwise
+/// after the last complete word followed by an ellipsis.
+fn truncate_text(text: &str, max: usize) -> String {
+    let Some((end, _)) = text.char_indices().nth(max) else {
+        return text.to_string();
+    };
+    let prefix = &text[..end];
+    let sentence_end = prefix.char_indices().rfind(|(i, c)| {
+        matches!(c, '.' | '!' | '?') && text[i + 1..].chars().next().is_none_or(char::is_whitespace)
+    });
+    if let Some((i, _)) = sentence_end {
+        return text[..=i].to_string();
+    }
+    let word_end = match text[end..].starts_with(char::is_whitespace) {
+        true => end,
+        false => prefix.rfind(char::is_whitespace).unwrap_or(end),
+    };
+    format!("{}…", text[..word_end].trim_end())
+}
+
+/// Words read per minute to estimate the reading time.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Estimated reading time of a text in minutes, rounded up.
+fn reading_time(word_count: usize) -> usize {
+    word_count.div_ceil(WORDS_PER_MINUTE)
+}
+
+/// Marker in the content ending its excerpt.
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// Derive the excerpt from the text of the markdown up to the `<!-- more -->`
+/// marker or from its first paragraph.
+fn derive_excerpt(config: &Config, markdown: &str, options: Options) -> Option<String> {
+    let events = || pulldown_cmark::Parser::new_ext(markdown, options).into_offset_iter();
+    // Only an actual HTML comment ends the excerpt, not the marker in code
+    let marker = events().find(|(event, _)| match event {
+        Event::Html(html) => html.trim() == MORE_MARKER,
+        _ => false,
+    });
+    let excerpt = match marker {
+        Some((_, range)) => &markdown[..range.start],
+        None => {
+            let paragraph =
+                events().find(|(event, _)| matches!(event, Event::Start(Tag::Paragraph)));
+            &markdown[paragraph?.1]
+        }
+    };
+    let text = markdown_to_plain_text(config, excerpt, options);
+    (!text.is_empty()).then_some(text)
+}
+
+/// Extract the text of the markdown without any markup.
+///
+/// Blocks are separated by a single space. Shortcodes are left out, except
+/// for the bodies of blocks.
+fn markdown_to_plain_text(config: &Config, markdown: &str, options: Options) -> String {
+    let markdown = template::strip_shortcodes(config, markdown);
+    let mut text = String::new();
+    let events = pulldown_cmark::Parser::new_ext(&markdown, options).collect();
+    for event in attributes::mark(events) {
+        match event {
+            Event::Text(s) | Event::Code(s) => text.push_str(&s),
+            Event::End(
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..),
+            ) => {}
+            Event::SoftBreak | Event::HardBreak | Event::Rule | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 async fn try_main() -> Result<()> {
     let it = std::time::Instant::now();
 
     let cli = Cli::parse();
-    let config = Config::from_file(&cli.config_path).await?;
+    let configs = Config::load_all(&cli.config_path).await?;
 
     info!("Config read at {:?}", it.elapsed());
 
-    // Build website.
-    Website::new(config).build(&cli).await?;
+    if let Some(Command::Cache { command }) = &cli.command {
+        for config in &configs {
+            let state = state::StateDir::new(config);
+            match command {
+                CacheCommand::Clean => {
+                    state.clean().await?;
+                    info!("Removed caches in {}", state.path().display());
+                }
+                CacheCommand::Stats => {
+                    println!("{}", state.path().display());
+                    for (store, stats) in state.stats().await? {
+                        println!(
+                            "  {:<9} {:>6} files {:>10} bytes",
+                            store.dir_name(),
+                            stats.files,
+                            stats.bytes
+                        );
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Rollback) = &cli.command {
+        for config in &configs {
+            let manifest = snapshot::rollback(config).await?;
+            info!(
+                "Rolled {} back to the build of {}",
+                config.site_info.title, manifest.created
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Daemon {
+        interval,
+        error_overlay,
+    }) = &cli.command
+    {
+        return daemon::run(configs, &cli, *interval, *error_overlay).await;
+    }
+
+    if let (Some(Command::Verify { .. }), [_, _, ..]) = (&cli.command, configs.as_slice()) {
+        return Err(Error::VerifyWorkspace);
+    }
+    if let (Some(Command::Serve { .. }), [_, _, ..]) = (&cli.command, configs.as_slice()) {
+        return Err(Error::ServeWorkspace);
+    }
+
+    if let Some(Command::Render { file }) = &cli.command {
+        let [config] = configs.as_slice() else {
+            return Err(Error::RenderWorkspace);
+        };
+        let html = Website::new(config.clone())
+            .render(&cli, file.as_deref())
+            .await?;
+        print!("{}", html);
+        return Ok(());
+    }
+
+    if let Some(Command::Explain { file }) = &cli.command {
+        let [config] = configs.as_slice() else {
+            return Err(Error::ExplainWorkspace);
+        };
+        print!("{}", explain::explain(config, &cli, file).await?);
+        return Ok(());
+    }
+
+    if let Some(Command::Refactor { from, to, dry_run }) = &cli.command {
+        let [config] = configs.as_slice() else {
+            return Err(Error::RefactorWorkspace);
+        };
+        return refactor::refactor(config, from, to, *dry_run).await;
+    }
+
+    if let Some(Command::Rpc) = &cli.command {
+        let [config] = configs.as_slice() else {
+            return Err(Error::RpcWorkspace);
+        };
+        return rpc::serve(&Website::new(config.clone()), &cli).await;
+    }
+
+    // Build websites.
+    let mut output_path = PathBuf::new();
+    let mut diagnostics_path = PathBuf::new();
+    let mut broken_links = 0;
+    let mut exceeded_budgets = 0;
+    for config in configs {
+        info!("Building {}", config.site_info.title);
+        output_path = config.output_path.clone();
+        diagnostics_path = daemon::diagnostics_path(&config);
+        Website::new(config.clone()).build(&cli).await?;
+        // Only plain builds are published
+        if cli.command.is_none() && !cli.is_preview() {
+            snapshot::take_snapshot(&config).await?;
+        }
+
+        if let Some(Command::Check { external }) = &cli.command {
+            let broken = check::check_links(&config, *external).await?;
+            for link in &broken {
+                error!("{}", link);
+            }
+            broken_links += broken.len();
+
+            if config.budgets.is_some() || !config.taxonomies.is_empty() {
+                let ctx = template::Context::new();
+                let mut indices = load_and_parse_content(&config, &ctx, &cli).await?;
+                taxonomy::assign_terms(&config.taxonomies, &mut indices)?;
+                if let Some(budgets) = &config.budgets {
+                    let exceeded = check::check_budgets(budgets, &indices, &cli);
+                    for budget in &exceeded {
+                        error!("{}", budget);
+                    }
+                    exceeded_budgets += exceeded.len();
+                }
+                for taxonomy in &config.taxonomies {
+                    for (term, other) in taxonomy::similar_terms(taxonomy, &indices) {
+                        warn!(
+                            "Terms '{}' and '{}' of taxonomy '{}' are similar, consider an alias",
+                            term, other, taxonomy.name
+                        );
+                    }
+                }
+            }
+        }
+    }
+    if broken_links > 0 {
+        return Err(Error::BrokenLinks(broken_links));
+    }
+    if exceeded_budgets > 0 {
+        return Err(Error::ExceededBudgets(exceeded_budgets));
+    }
 
     info!("Website built at {:?}", it.elapsed());
 
+    if let Some(Command::Verify { expected_dir }) = &cli.command {
+        let differences = verify::compare_dirs(&output_path, expected_dir).await?;
+        for difference in &differences {
+            error!("{}", difference);
+        }
+        if !differences.is_empty() {
+            return Err(Error::Verify(differences.len()));
+        }
+        info!("Output matches {}", expected_dir.display());
+    }
+
+    if let Some(Command::Serve { addr, auth }) = &cli.command {
+        let (addr, auth) = (*addr, auth.clone());
+        return tokio::task::spawn_blocking(move || {
+            serve::run(&output_path, diagnostics_path, addr, auth)
+        })
+        .await
+        .map_err(Error::Join)?;
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,683 @@
+//! This module renders LaTeX math in the markdown content.
+//!
+//! Inline math is written as `$...$`, display math as `$$...$$` or in a
+//! fenced code block with the language `math`. Formulas are either wrapped in
+//! elements with KaTeX delimiters to be rendered in the browser or converted
+//! to MathML at build time.
+//!
+//! The MathML conversion covers the common subset of LaTeX: scripts,
+//! fractions, roots, greek letters, operators and text. Unsupported commands,
+//! unbalanced braces, missing arguments and groups nested too deeply are
+//! marked with `<merror>`.
+
+use std::ops::Range;
+
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+
+use crate::config::{MathConfig, MathMode};
+
+/// Render the math in the text of the events.
+///
+/// The source ranges of the events are used to tell escaped dollar signs
+/// apart, which do not start or end a formula.
+pub fn render<'a>(
+    config: &MathConfig,
+    markdown: &str,
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::new();
+    // Adjacent text with its original events, as the parser splits text at
+    // special characters and lines
+    let mut text = String::new();
+    let mut pending = Vec::new();
+    let mut math_block: Option<String> = None;
+    let mut in_code_block = false;
+
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) if &*lang == "math" => {
+                math_block = Some(String::new());
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) if &*lang == "math" => {
+                let tex = math_block.take().unwrap_or_default();
+                let html = match config.mode {
+                    MathMode::Katex => format!(
+                        "<div class=\"math math-display\">{}</div>\n",
+                        katex(&tex, true)
+                    ),
+                    MathMode::Mathml => format!("{}\n", mathml(&tex, true)),
+                };
+                output.push(Event::Html(html.into()));
+            }
+            Event::Text(tex) if math_block.is_some() => {
+                math_block.get_or_insert_with(String::new).push_str(&tex);
+            }
+            // The text following an escaped dollar sign starts with it
+            Event::Text(t)
+                if !in_code_block
+                    && t.starts_with('$')
+                    && markdown[..range.start].ends_with('\\') =>
+            {
+                flush(config, &mut text, &mut pending, &mut output);
+                output.push(Event::Text("$".into()));
+                text.push_str(&t[1..]);
+                pending.push(Event::Text(t[1..].to_string().into()));
+            }
+            Event::Text(ref t) if !in_code_block => {
+                text.push_str(t);
+                pending.push(event);
+            }
+            Event::SoftBreak => {
+                text.push('\n');
+                pending.push(event);
+            }
+            event => {
+                flush(config, &mut text, &mut pending, &mut output);
+                match event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                    Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                    _ => {}
+                }
+                output.push(event);
+            }
+        }
+    }
+    flush(config, &mut text, &mut pending, &mut output);
+    output
+}
+
+/// Replace the collected text by its formulas, or keep the original events if
+/// it contains none.
+fn flush<'a>(
+    config: &MathConfig,
+    text: &mut String,
+    pending: &mut Vec<Event<'a>>,
+    output: &mut Vec<Event<'a>>,
+) {
+    let segments = split_math(text);
+    if segments
+        .iter()
+        .all(|segment| matches!(segment, Segment::Text(_)))
+    {
+        output.append(pending);
+    } else {
+        pending.clear();
+        for segment in segments {
+            output.push(match segment {
+                Segment::Text(text) => Event::Text(text.to_string().into()),
+                Segment::Math(tex, display) => Event::Html(
+                    match config.mode {
+                        MathMode::Katex => {
+                            let class = if display {
+                                "math-display"
+                            } else {
+                                "math-inline"
+                            };
+                            format!(
+                                "<span class=\"math {}\">{}</span>",
+                                class,
+                                katex(tex, display)
+                            )
+                        }
+                        MathMode::Mathml => mathml(tex, display),
+                    }
+                    .into(),
+                ),
+            });
+        }
+    }
+    text.clear();
+}
+
+/// Part of a text which is either plain text or a formula.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Text(&'a str),
+    /// The formula and whether it is displayed as a block.
+    Math(&'a str, bool),
+}
+
+/// Split the text into plain text and formulas.
+///
+/// Like Pandoc, inline math must not start with a space, not end with a
+/// space and not be followed by a digit, so amounts like `$5 and $10` are
+/// kept as is.
+fn split_math(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while let Some(offset) = text[i..].find('$') {
+        let open = i + offset;
+        let math = if let Some(rest) = text[open..].strip_prefix("$$") {
+            rest.find("$$")
+                .filter(|&end| !rest[..end].trim().is_empty())
+                .map(|end| (&rest[..end], true, open + 2 + end + 2))
+        } else {
+            let rest = &text[open + 1..];
+            match rest.starts_with(|c: char| !c.is_whitespace()) {
+                true => rest
+                    .match_indices('$')
+                    .map(|(end, _)| end)
+                    .find(|&end| {
+                        !rest[..end].ends_with(char::is_whitespace)
+                            && !rest[end + 1..].starts_with(|c: char| c.is_ascii_digit())
+                    })
+                    .map(|end| (&rest[..end], false, open + 1 + end + 1)),
+                false => None,
+            }
+        };
+        match math {
+            Some((tex, display, end)) => {
+                if start < open {
+                    segments.push(Segment::Text(&text[start..open]));
+                }
+                segments.push(Segment::Math(tex.trim(), display));
+                start = end;
+                i = end;
+            }
+            None => i = open + 1,
+        }
+    }
+    if start < text.len() {
+        segments.push(Segment::Text(&text[start..]));
+    }
+    segments
+}
+
+/// Wrap the formula in the delimiters recognized by the KaTeX auto-render
+/// extension.
+fn katex(tex: &str, display: bool) -> String {
+    let (open, close) = if display {
+        ("\\[", "\\]")
+    } else {
+        ("\\(", "\\)")
+    };
+    let mut html = String::from(open);
+    escape_html(&mut html, tex.trim()).expect("writing to a string never fails");
+    html.push_str(close);
+    html
+}
+
+/// Convert the formula to MathML, keeping the LaTeX as annotation.
+fn mathml(tex: &str, display: bool) -> String {
+    let row = TexParser::new(tex.trim()).row(false);
+    format!(
+        "<math{}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { " display=\"block\"" } else { "" },
+        row,
+        escape(tex.trim())
+    )
+}
+
+/// Mark the text as an error in MathML.
+fn error(text: &str) -> String {
+    format!("<merror><mtext>{}</mtext></merror>", escape(text))
+}
+
+/// Escape text for use in MathML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).expect("writing to a string never fails");
+    escaped
+}
+
+/// Element and character of a LaTeX command for a symbol.
+fn symbol(name: &str) -> Option<(&'static str, &'static str)> {
+    let symbol = match name {
+        "alpha" => ("mi", "α"),
+        "beta" => ("mi", "β"),
+        "gamma" => ("mi", "γ"),
+        "delta" => ("mi", "δ"),
+        "epsilon" => ("mi", "ϵ"),
+        "varepsilon" => ("mi", "ε"),
+        "zeta" => ("mi", "ζ"),
+        "eta" => ("mi", "η"),
+        "theta" => ("mi", "θ"),
+        "vartheta" => ("mi", "ϑ"),
+        "iota" => ("mi", "ι"),
+        "kappa" => ("mi", "κ"),
+        "lambda" => ("mi", "λ"),
+        "mu" => ("mi", "μ"),
+        "nu" => ("mi", "ν"),
+        "xi" => ("mi", "ξ"),
+        "pi" => ("mi", "π"),
+        "rho" => ("mi", "ρ"),
+        "sigma" => ("mi", "σ"),
+        "tau" => ("mi", "τ"),
+        "upsilon" => ("mi", "υ"),
+        "phi" => ("mi", "ϕ"),
+        "varphi" => ("mi", "φ"),
+        "chi" => ("mi", "χ"),
+        "psi" => ("mi", "ψ"),
+        "omega" => ("mi", "ω"),
+        "Gamma" => ("mi", "Γ"),
+        "Delta" => ("mi", "Δ"),
+        "Theta" => ("mi", "Θ"),
+        "Lambda" => ("mi", "Λ"),
+        "Xi" => ("mi", "Ξ"),
+        "Pi" => ("mi", "Π"),
+        "Sigma" => ("mi", "Σ"),
+        "Upsilon" => ("mi", "Υ"),
+        "Phi" => ("mi", "Φ"),
+        "Psi" => ("mi", "Ψ"),
+        "Omega" => ("mi", "Ω"),
+        "infty" => ("mi", "∞"),
+        "partial" => ("mi", "∂"),
+        "nabla" => ("mi", "∇"),
+        "emptyset" => ("mi", "∅"),
+        "ell" => ("mi", "ℓ"),
+        "hbar" => ("mi", "ℏ"),
+        "pm" => ("mo", "±"),
+        "mp" => ("mo", "∓"),
+        "times" => ("mo", "×"),
+        "div" => ("mo", "÷"),
+        "cdot" => ("mo", "⋅"),
+        "ast" => ("mo", "∗"),
+        "circ" => ("mo", "∘"),
+        "leq" | "le" => ("mo", "≤"),
+        "geq" | "ge" => ("mo", "≥"),
+        "neq" | "ne" => ("mo", "≠"),
+        "ll" => ("mo", "≪"),
+        "gg" => ("mo", "≫"),
+        "approx" => ("mo", "≈"),
+        "equiv" => ("mo", "≡"),
+        "sim" => ("mo", "∼"),
+        "propto" => ("mo", "∝"),
+        "sum" => ("mo", "∑"),
+        "prod" => ("mo", "∏"),
+        "int" => ("mo", "∫"),
+        "oint" => ("mo", "∮"),
+        "in" => ("mo", "∈"),
+        "notin" => ("mo", "∉"),
+        "subset" => ("mo", "⊂"),
+        "subseteq" => ("mo", "⊆"),
+        "supset" => ("mo", "⊃"),
+        "supseteq" => ("mo", "⊇"),
+        "cup" => ("mo", "∪"),
+        "cap" => ("mo", "∩"),
+        "forall" => ("mo", "∀"),
+        "exists" => ("mo", "∃"),
+        "neg" => ("mo", "¬"),
+        "land" | "wedge" => ("mo", "∧"),
+        "lor" | "vee" => ("mo", "∨"),
+        "to" | "rightarrow" => ("mo", "→"),
+        "leftarrow" | "gets" => ("mo", "←"),
+        "leftrightarrow" => ("mo", "↔"),
+        "Rightarrow" | "implies" => ("mo", "⇒"),
+        "Leftarrow" => ("mo", "⇐"),
+        "Leftrightarrow" | "iff" => ("mo", "⇔"),
+        "mapsto" => ("mo", "↦"),
+        "ldots" | "dots" => ("mo", "…"),
+        "cdots" => ("mo", "⋯"),
+        "mid" => ("mo", "∣"),
+        "langle" => ("mo", "⟨"),
+        "rangle" => ("mo", "⟩"),
+        "lfloor" => ("mo", "⌊"),
+        "rfloor" => ("mo", "⌋"),
+        "lceil" => ("mo", "⌈"),
+        "rceil" => ("mo", "⌉"),
+        "vert" => ("mo", "|"),
+        "Vert" => ("mo", "‖"),
+        "{" => ("mo", "{"),
+        "}" => ("mo", "}"),
+        "|" => ("mo", "‖"),
+        "$" => ("mi", "$"),
+        "%" => ("mi", "%"),
+        "&" => ("mo", "&"),
+        "#" => ("mi", "#"),
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Functions which are set upright.
+const FUNCTIONS: &[&str] = &[
+    "arccos", "arcsin", "arctan", "cos", "cosh", "cot", "deg", "det", "dim", "exp", "gcd", "inf",
+    "lg", "lim", "ln", "log", "max", "min", "sec", "sin", "sinh", "sup", "tan", "tanh",
+];
+
+/// A token of LaTeX.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Command(String),
+}
+
+/// Maximum number of nested groups and arguments, which prevents deeply
+/// nested input from overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Converts LaTeX to MathML.
+struct TexParser {
+    chars: Vec<char>,
+    position: usize,
+    /// Number of atoms the current one is nested in.
+    depth: usize,
+    /// Whether the input was nested too deeply and the rest was skipped.
+    too_deep: bool,
+}
+
+impl TexParser {
+    fn new(tex: &str) -> Self {
+        TexParser {
+            chars: tex.chars().collect(),
+            position: 0,
+            depth: 0,
+            too_deep: false,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c != '\\' {
+            return Some(Token::Char(c));
+        }
+        let start = self.position;
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            self.position += 1;
+        }
+        if self.position == start && self.position < self.chars.len() {
+            self.position += 1;
+        }
+        Some(Token::Command(
+            self.chars[start..self.position].iter().collect(),
+        ))
+    }
+
+    /// Read the text of a group verbatim, e.g. the argument of `\text`.
+    fn raw_group(&mut self) -> String {
+        if self.peek() != Some('{') {
+            return match self.next() {
+                Some(Token::Char(c)) => c.to_string(),
+                _ => String::new(),
+            };
+        }
+        self.position += 1;
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.position) {
+            self.position += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Convert the formula until the end of the group or the input.
+    fn row(&mut self, in_group: bool) -> String {
+        let mut row = String::new();
+        while let Some(c) = self.peek() {
+            let atom = match c {
+                '}' => {
+                    self.position += 1;
+                    if in_group {
+                        return row;
+                    }
+                    row.push_str(&error("unexpected }"));
+                    continue;
+                }
+                // Scripts without a base, e.g. `^2`
+                '^' | '_' => "<mrow></mrow>".to_string(),
+                _ => self.atom(),
+            };
+            row.push_str(&self.scripts(atom));
+        }
+        if in_group && !self.too_deep {
+            row.push_str(&error("missing }"));
+        }
+        row
+    }
+
+    /// Attach sub- and superscripts to the base.
+    fn scripts(&mut self, base: String) -> String {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.position += 1;
+                    sub = Some(self.atom());
+                }
+                Some('^') if sup.is_none() => {
+                    self.position += 1;
+                    sup = Some(self.atom());
+                }
+                _ => break,
+            }
+        }
+        match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        }
+    }
+
+    /// Convert a single element, e.g. a letter, a command or a group.
+    ///
+    /// The element is missing at the end of the input or a group and before
+    /// scripts.
+    fn atom(&mut self) -> String {
+        if matches!(self.peek(), None | Some('^' | '_' | '}')) {
+            return error("missing argument");
+        }
+        if self.depth >= MAX_DEPTH {
+            self.position = self.chars.len();
+            self.too_deep = true;
+            return error("nested too deeply");
+        }
+        self.depth += 1;
+        let atom = self.nested_atom();
+        self.depth -= 1;
+        atom
+    }
+
+    /// Convert the next element, which is known to exist.
+    fn nested_atom(&mut self) -> String {
+        let Some(token) = self.next() else {
+            return String::new();
+        };
+        match token {
+            Token::Char('{') => format!("<mrow>{}</mrow>", self.row(true)),
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&c) = self.chars.get(self.position) {
+                    if !c.is_ascii_digit() && c != '.' {
+                        break;
+                    }
+                    number.push(c);
+                    self.position += 1;
+                }
+                format!("<mn>{}</mn>", number)
+            }
+            Token::Char(c) if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            Token::Char('-') => "<mo>−</mo>".to_string(),
+            Token::Char('*') => "<mo>∗</mo>".to_string(),
+            Token::Char('\'') => "<mo>′</mo>".to_string(),
+            Token::Char('&') => String::new(),
+            Token::Char(c) => format!("<mo>{}</mo>", escape(&c.to_string())),
+            Token::Command(name) => self.command(&name),
+        }
+    }
+
+    /// Convert a command with its arguments.
+    fn command(&mut self, name: &str) -> String {
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.atom();
+                let denominator = self.atom();
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "sqrt" => {
+                if self.peek() == Some('[') {
+                    self.position += 1;
+                    let mut index = String::new();
+                    loop {
+                        match self.peek() {
+                            Some(']') => {
+                                self.position += 1;
+                                break;
+                            }
+                            None | Some('}') => {
+                                index.push_str(&error("missing ]"));
+                                break;
+                            }
+                            Some(_) => {
+                                let atom = self.atom();
+                                index.push_str(&self.scripts(atom));
+                            }
+                        }
+                    }
+                    let radicand = self.atom();
+                    format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.atom())
+                }
+            }
+            "text" | "textrm" | "mbox" => format!("<mtext>{}</mtext>", escape(&self.raw_group())),
+            "mathrm" | "operatorname" => {
+                format!(
+                    "<mi mathvariant=\"normal\">{}</mi>",
+                    escape(&self.raw_group())
+                )
+            }
+            "mathbf" | "mathit" | "mathbb" | "mathcal" => {
+                let variant = match name {
+                    "mathbf" => "bold",
+                    "mathit" => "italic",
+                    "mathbb" => "double-struck",
+                    _ => "script",
+                };
+                format!(
+                    "<mi mathvariant=\"{}\">{}</mi>",
+                    variant,
+                    escape(&self.raw_group())
+                )
+            }
+            "left" | "right" => match self.next() {
+                Some(Token::Char('.')) | None => String::new(),
+                Some(Token::Char(c)) => format!("<mo>{}</mo>", escape(&c.to_string())),
+                Some(Token::Command(name)) => match symbol(&name) {
+                    Some((element, symbol)) => format!("<{0}>{1}</{0}>", element, escape(symbol)),
+                    None => error(&format!("\\{}", name)),
+                },
+            },
+            "," => "<mspace width=\"0.167em\"/>".to_string(),
+            ":" | ">" => "<mspace width=\"0.222em\"/>".to_string(),
+            ";" => "<mspace width=\"0.278em\"/>".to_string(),
+            "quad" => "<mspace width=\"1em\"/>".to_string(),
+            "qquad" => "<mspace width=\"2em\"/>".to_string(),
+            "!" | " " => String::new(),
+            "\\" => "<mspace linebreak=\"newline\"/>".to_string(),
+            name if FUNCTIONS.contains(&name) => format!("<mi>{}</mi>", name),
+            name => match symbol(name) {
+                Some((element, symbol)) => {
+                    format!("<{0}>{1}</{0}>", element, escape(symbol))
+                }
+                None => error(&format!("\\{}", name)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_math() {
+        assert_eq!(
+            vec![
+                Segment::Text("Let "),
+                Segment::Math("x^2", false),
+                Segment::Text(" be "),
+                Segment::Math("a + b", true),
+            ],
+            split_math("Let $x^2$ be $$ a + b $$")
+        );
+        assert_eq!(
+            vec![Segment::Text("Costs $5 and $10, or $ x $.")],
+            split_math("Costs $5 and $10, or $ x $.")
+        );
+    }
+
+    #[test]
+    fn test_mathml() {
+        let mut parser = TexParser::new(r"\frac{1}{2} x_i^2 \leq \sqrt[3]{\alpha} \text{if } \foo");
+        assert_eq!(
+            "<mfrac><mrow><mn>1</mn></mrow><mrow><mn>2</mn></mrow></mfrac>\
+             <msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup><mo>≤</mo>\
+             <mroot><mrow><mi>α</mi></mrow><mrow><mn>3</mn></mrow></mroot>\
+             <mtext>if </mtext><merror><mtext>\\foo</mtext></merror>",
+            parser.row(false)
+        );
+    }
+
+    #[test]
+    fn test_mathml_malformed() {
+        assert_eq!(
+            "<mfrac><mrow><merror><mtext>missing }</mtext></merror></mrow>\
+             <merror><mtext>missing argument</mtext></merror></mfrac>",
+            TexParser::new(r"\frac{").row(false)
+        );
+        assert_eq!(
+            "<msup><mi>x</mi><merror><mtext>missing argument</mtext></merror></msup>",
+            TexParser::new("x^").row(false)
+        );
+        assert_eq!(
+            "<merror><mtext>unexpected }</mtext></merror>\
+             <merror><mtext>unexpected }</mtext></merror>\
+             <mrow><merror><mtext>missing }</mtext></merror></mrow>",
+            TexParser::new("}}{").row(false)
+        );
+    }
+
+    #[test]
+    fn test_mathml_nesting() {
+        let tex = "{".repeat(20_000);
+        let row = TexParser::new(&tex).row(false);
+        assert_eq!(1, row.matches("<merror>").count());
+        assert!(row.contains("nested too deeply"));
+    }
+
+    #[test]
+    fn test_render() {
+        let markdown = "Price \\$5, area $\\pi r^2$.\n\n```math\nE = mc^2\n```\n";
+        let parser = pulldown_cmark::Parser::new(markdown).into_offset_iter();
+        let config = MathConfig {
+            mode: MathMode::Katex,
+        };
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, render(&config, markdown, parser).into_iter());
+        assert_eq!(
+            "<p>Price $5, area <span class=\"math math-inline\">\\(\\pi r^2\\)</span>.</p>\n\
+             <div class=\"math math-display\">\\[E = mc^2\\]</div>\n",
+            html
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! This module minifies stylesheets and scripts while mirroring the assets.
+
+use std::{ffi::OsStr, path::Path};
+
+use crate::error::{Error, Result};
+
+/// Minify the content of the asset if it is a stylesheet or script.
+///
+/// Returns `None` for other files and files which are already minified, e.g.
+/// `app.min.js`. Stylesheets are only minified with the `sass` feature.
+pub fn minify(path: &Path, content: &[u8]) -> Result<Option<Vec<u8>>> {
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    if stem.ends_with(".min") {
+        return Ok(None);
+    }
+    let err = |e: String| Error::Minify(path.to_path_buf(), e);
+    match path.extension().and_then(OsStr::to_str) {
+        #[cfg(feature = "sass")]
+        Some("css") => {
+            let css = String::from_utf8(content.to_vec()).map_err(|e| err(e.to_string()))?;
+            let options = grass::Options::default()
+                .style(grass::OutputStyle::Compressed)
+                .input_syntax(grass::InputSyntax::Css);
+            let minified = grass::from_string(css, &options).map_err(|e| err(e.to_string()))?;
+            Ok(Some(minified.into_bytes()))
+        }
+        Some("js") => {
+            let session = minify_js::Session::new();
+            let mut minified = Vec::new();
+            minify_js::minify(
+                &session,
+                minify_js::TopLevelMode::Global,
+                content,
+                &mut minified,
+            )
+            .map_err(|e| err(format!("{:?}", e)))?;
+            Ok(Some(minified))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minify() {
+        #[cfg(feature = "sass")]
+        {
+            let css = minify(Path::new("a.css"), b"a > b {\n  color: red;\n}\n").unwrap();
+            assert_eq!(b"a>b{color:red}".as_slice(), css.unwrap().trim_ascii_end());
+        }
+        let js = minify(Path::new("a.js"), b"const main = () => { let x = 1; };").unwrap();
+        assert_eq!(b"const main=()=>{let a=1}".as_slice(), js.unwrap());
+        assert_eq!(None, minify(Path::new("a.min.js"), b"let x").unwrap());
+        assert_eq!(None, minify(Path::new("a.png"), b"").unwrap());
+    }
+}
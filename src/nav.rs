@@ -0,0 +1,255 @@
+//! This module builds the navigation of the site and the menus of the config
+//! as lists of entries which templates iterate over.
+//!
+//! Every entry has a `title`, `url`, `weight`, the nested entries as
+//! `children` and an `active` flag, which is set while rendering a page for
+//! the entries linking to it and the entries they are nested in.
+
+use std::collections::HashMap;
+
+use crate::{
+    config::{Config, MenuItem},
+    template::{Context, Value},
+    Cli, Index,
+};
+
+/// Name of the navigation in the context.
+pub const NAV: &str = "nav";
+
+/// Name of the menus of the config in the context.
+pub const MENUS: &str = "menus";
+
+/// An entry of the navigation with the entries nested below it.
+struct NavItem {
+    /// Position given by `display_in_nav`.
+    position: usize,
+    title: String,
+    url: String,
+    children: Vec<NavItem>,
+}
+
+/// Value of an entry, which is not active until a page is rendered.
+fn entry_value(title: &str, url: &str, weight: i64, children: Vec<Value>) -> Value {
+    Value::Map(HashMap::from([
+        ("title".to_string(), title.into()),
+        ("url".to_string(), url.into()),
+        ("weight".to_string(), Value::Integer(weight)),
+        ("children".to_string(), Value::List(children)),
+        ("active".to_string(), Value::Bool(false)),
+    ]))
+}
+
+impl NavItem {
+    /// Value of the entry with its entries sorted by their position.
+    fn into_value(self) -> Value {
+        let mut children = self.children;
+        children.sort_by_key(|item| item.position);
+        entry_value(
+            &self.title,
+            &self.url,
+            self.position.try_into().unwrap_or(i64::MAX),
+            children.into_iter().map(NavItem::into_value).collect(),
+        )
+    }
+}
+
+/// Build the navigation from the indices and pages together with the links
+/// of the config.
+///
+/// Entries are nested below their section if enabled by `nav_groups`,
+/// otherwise all entries are listed in order of their position. Draft pages
+/// are only shown when building drafts.
+pub fn navigation(config: &Config, indices: &[Index], opts: &Cli) -> Value {
+    let mut items: Vec<_> = indices
+        .iter()
+        .filter(|index| index.parent.is_none())
+        .flat_map(|index| nav_items(config, opts, indices, index, None))
+        .collect();
+    items.extend(config.nav.iter().map(|link| NavItem {
+        position: link.position,
+        title: link.title.clone(),
+        url: link.url.clone(),
+        children: Vec::new(),
+    }));
+
+    if !config.nav_groups {
+        let mut stack: Vec<_> = items.into_iter().rev().collect();
+        items = Vec::new();
+        while let Some(mut item) = stack.pop() {
+            stack.extend(std::mem::take(&mut item.children).into_iter().rev());
+            items.push(item);
+        }
+    }
+    // Stable, so entries with the same position keep their order
+    items.sort_by_key(|item| item.position);
+    Value::List(items.into_iter().map(NavItem::into_value).collect())
+}
+
+/// Collect the navigation entries of the index and its subsections.
+///
+/// Pages are only shown together with their index. Subsections are nested
+/// below the index up to the depth, except for subsections of the top index
+/// which are shown next to it.
+fn nav_items(
+    config: &Config,
+    opts: &Cli,
+    indices: &[Index],
+    index: &Index,
+    depth: Option<usize>,
+) -> Vec<NavItem> {
+    let subsections = |depth: Option<usize>| {
+        index
+            .subsections
+            .iter()
+            .filter_map(|dir| indices.iter().find(|index| index.dir() == dir))
+            .flat_map(|subsection| nav_items(config, opts, indices, subsection, depth))
+            .collect::<Vec<_>>()
+    };
+    let Some(position) = index.metadata.display_in_nav else {
+        return subsections(depth);
+    };
+
+    let depth = match (depth, index.metadata.nav_depth) {
+        (Some(depth), Some(nav_depth)) => Some(depth.min(nav_depth)),
+        (depth, nav_depth) => depth.or(nav_depth),
+    };
+    let mut children = Vec::new();
+    if depth != Some(0) {
+        let pages = index
+            .pages
+            .iter()
+            .filter(|page| page.is_published(opts))
+            .flat_map(|page| page.metadata.display_in_nav.map(|i| (i, page)));
+        for (position, page) in pages {
+            children.push(NavItem {
+                position,
+                title: page.metadata.title.clone(),
+                url: config.link_url(&page.url()),
+                children: Vec::new(),
+            });
+        }
+        if index.parent.is_some() {
+            children.extend(subsections(depth.map(|depth| depth - 1)));
+        }
+    }
+
+    let mut items = vec![NavItem {
+        position,
+        title: index.metadata.title.clone(),
+        url: config.link_url(&index.url()),
+        children,
+    }];
+    if index.parent.is_none() {
+        items.extend(subsections(depth));
+    }
+    items
+}
+
+/// Value of the entries of a menu sorted by their weight.
+fn menu_value(config: &Config, items: &[MenuItem]) -> Value {
+    let mut items: Vec<_> = items.iter().collect();
+    items.sort_by_key(|item| item.weight);
+    let entries = items.into_iter().map(|item| {
+        // Paths of the site follow the link settings like the navigation
+        let url = match item.url.starts_with('/') {
+            true => config.link_url(&item.url),
+            false => item.url.clone(),
+        };
+        let children = match menu_value(config, &item.children) {
+            Value::List(children) => children,
+            _ => unreachable!("menus are lists"),
+        };
+        entry_value(&item.title, &url, item.weight, children)
+    });
+    Value::List(entries.collect())
+}
+
+/// Build the menus of the config by their name.
+pub fn menus(config: &Config) -> Value {
+    Value::Map(
+        config
+            .menus
+            .iter()
+            .map(|(name, items)| (name.clone(), menu_value(config, items)))
+            .collect(),
+    )
+}
+
+/// Set the `active` flag of the entries linking to the URL and of the
+/// entries they are nested in.
+///
+/// Returns whether any entry is active.
+fn set_active(entries: &mut Value, url: &str) -> bool {
+    let Value::List(entries) = entries else {
+        return false;
+    };
+    let mut any_active = false;
+    for entry in entries {
+        let Value::Map(entry) = entry else {
+            continue;
+        };
+        let children_active = entry
+            .get_mut("children")
+            .is_some_and(|children| set_active(children, url));
+        let active = children_active || entry.get("url") == Some(&Value::from(url));
+        entry.insert("active".to_string(), Value::Bool(active));
+        any_active |= active;
+    }
+    any_active
+}
+
+/// Mark the entries of the navigation and the menus in the context as active
+/// for the page at the URL path.
+pub fn mark_active(config: &Config, ctx: &mut Context, url: &str) {
+    let url = config.link_url(url);
+    let value = |ctx: &Context, name: &str| {
+        ctx.values()
+            .find(|(value_name, _)| *value_name == name)
+            .map(|(_, value)| value.clone())
+    };
+    if let Some(mut nav) = value(ctx, NAV) {
+        set_active(&mut nav, &url);
+        ctx.insert(NAV, nav);
+    }
+    if let Some(Value::Map(mut menus)) = value(ctx, MENUS) {
+        for menu in menus.values_mut() {
+            set_active(menu, &url);
+        }
+        ctx.insert(MENUS, Value::Map(menus));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_active() {
+        let child = entry_value("Post", "/blog/post/", 1, Vec::new());
+        let mut nav = Value::List(vec![
+            entry_value("Home", "/", 0, Vec::new()),
+            entry_value("Blog", "/blog/", 1, vec![child]),
+        ]);
+        assert!(set_active(&mut nav, "/blog/post/"));
+
+        fn flags(entries: &Value) -> Vec<Value> {
+            let Value::List(entries) = entries else {
+                panic!("entries are a list");
+            };
+            let mut active = Vec::new();
+            for entry in entries {
+                let Value::Map(entry) = entry else {
+                    panic!("entries are maps");
+                };
+                active.push(entry["active"].clone());
+                active.extend(flags(&entry["children"]));
+            }
+            active
+        }
+        // Home, blog and the post nested below it
+        assert_eq!(
+            vec![Value::Bool(false), Value::Bool(true), Value::Bool(true)],
+            flags(&nav)
+        );
+    }
+}
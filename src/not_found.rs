@@ -0,0 +1,83 @@
+//! This module generates the page shown for URLs which do not exist.
+//!
+//! The page is written from `content/404.md` with the `404.html` template to
+//! `404.html` in the output directory instead of a directory of its own, where
+//! static hosts like Netlify or GitHub Pages pick it up.
+
+use std::path::Path;
+
+use log::debug;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    fill_page_context, links,
+    template::{self, Context, Value},
+    Page,
+};
+
+/// Content file of the page, relative to `content/`.
+pub const NOT_FOUND_PAGE: &str = "404.md";
+
+/// Template the page is rendered with.
+pub const NOT_FOUND_TEMPLATE: &str = "404.html";
+
+/// File the page is written to, relative to the output directory.
+pub const NOT_FOUND_FILE: &str = "404.html";
+
+/// Write the not-found page if the content contains one.
+pub async fn export_not_found(
+    config: &Config,
+    ctx: &Context,
+    urls: &links::ContentUrls,
+) -> Result<()> {
+    let content_dir = config.content_path.join("content");
+    if !content_dir.join(NOT_FOUND_PAGE).is_file() {
+        return Ok(());
+    }
+    debug!("Building not-found page");
+
+    let in_content = |e| Error::InContent(NOT_FOUND_PAGE.into(), Box::new(e));
+    let mut page = Page::parse_md(
+        &content_dir,
+        NOT_FOUND_PAGE,
+        config,
+        ctx,
+        &toml::Table::new(),
+        false,
+    )
+    .await
+    .map_err(in_content)?;
+    let site = &config.site_info;
+    page.metadata.lang.get_or_insert_with(|| site.lang.clone());
+    page.metadata.dir.get_or_insert(site.dir);
+    page.html = links::resolve_content(Path::new(NOT_FOUND_PAGE), &page.html, urls)?;
+
+    let mut ctx = ctx.clone();
+    fill_page_context(config, &mut ctx, &page);
+    // The page is served for any URL, so it has none of its own
+    let url = format!("/{}", NOT_FOUND_FILE);
+    let mut page_value = page.to_map(config);
+    page_value.insert("content".to_string(), page.html.as_str().into());
+    page_value.insert("url".to_string(), url.as_str().into());
+    for key in ["permalink", "canonical"] {
+        page_value.insert(key.to_string(), config.absolute_url(&url).into());
+    }
+    ctx.insert("page", Value::Map(page_value));
+    ctx.insert("canonical", config.absolute_url(&url));
+
+    let template = Path::new(NOT_FOUND_TEMPLATE);
+    let html = template::template_pages(config, &ctx, template, &url)
+        .await
+        .map_err(in_content)?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    tokio::fs::create_dir_all(&config.output_path)
+        .await
+        .map_err(|e| Error::CreateDirectory(config.output_path.clone(), e))?;
+    let path = config.output_path.join(NOT_FOUND_FILE);
+    tokio::fs::write(&path, html)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))
+}
@@ -0,0 +1,308 @@
+//! This module encrypts the rendered HTML of protected pages, so they can be
+//! served by a static host and only read with the password.
+//!
+//! A protected page is replaced by a wrapper page asking for the password. It
+//! derives the key with PBKDF2 and decrypts the page with AES-GCM through the
+//! Web Crypto API of the browser.
+//!
+//! The salt is drawn once and kept in the registry store, so the key of a
+//! site stays the same between builds. Pages whose content did not change
+//! keep their previous ciphertext, so their output only changes with them.
+
+pub mod crypto;
+
+use std::{collections::BTreeMap, fmt, path::PathBuf, sync::Mutex};
+
+use log::debug;
+use pulldown_cmark::escape::escape_html;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    base64,
+    config::Config,
+    error::{Error, Result},
+    state::{StateDir, Store},
+    template::{Context, Value},
+};
+
+/// File name of the registry in the registry store.
+const REGISTRY_FILE: &str = "protection.toml";
+
+/// The salt of the site and the pages encrypted by the last build.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    /// Salt of the key in base64.
+    #[serde(default)]
+    salt: String,
+    /// Encrypted pages by their URL path.
+    #[serde(default)]
+    pages: BTreeMap<String, Encrypted>,
+}
+
+/// An encrypted page, with all bytes in base64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Encrypted {
+    /// HMAC of the page with the key, which tells whether the page changed
+    /// without revealing its content.
+    digest: String,
+    /// Nonce of the encryption.
+    iv: String,
+    /// Encrypted page followed by the tag.
+    ciphertext: String,
+}
+
+/// Script of the wrapper which decrypts the page and replaces the wrapper.
+const DECRYPT_SCRIPT: &str = r#"const bytes = (text) => Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+document.getElementById("fweb-protected").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const form = event.target;
+  const password = new TextEncoder().encode(form.password.value);
+  const material = await crypto.subtle.importKey("raw", password, "PBKDF2", false, ["deriveKey"]);
+  const key = await crypto.subtle.deriveKey(
+    { name: "PBKDF2", salt: bytes(protectedPage.salt), iterations: protectedPage.iterations, hash: "SHA-256" },
+    material,
+    { name: "AES-GCM", length: 256 },
+    false,
+    ["decrypt"],
+  );
+  try {
+    const page = await crypto.subtle.decrypt(
+      { name: "AES-GCM", iv: bytes(protectedPage.iv) },
+      key,
+      bytes(protectedPage.ciphertext),
+    );
+    document.open();
+    document.write(new TextDecoder().decode(page));
+    document.close();
+  } catch {
+    form.querySelector("[role=alert]").hidden = false;
+  }
+});"#;
+
+/// Draw random bytes.
+fn random<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| Error::Random(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Encrypts the protected pages of a build with the key of the site.
+pub struct Protector {
+    /// Path of the registry.
+    path: PathBuf,
+    iterations: u32,
+    salt: Vec<u8>,
+    key: [u8; 32],
+    /// Pages encrypted by the last build.
+    previous: BTreeMap<String, Encrypted>,
+    /// Pages encrypted by this build.
+    pages: Mutex<BTreeMap<String, Encrypted>>,
+}
+
+impl fmt::Debug for Protector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Protector")
+            .field("path", &self.path)
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Protector {
+    /// Derive the key of the site from the password in the environment and
+    /// read the pages encrypted by the last build.
+    pub async fn open(config: &Config) -> Result<Self> {
+        let protection = config
+            .protection
+            .as_ref()
+            .ok_or(Error::ProtectionNotConfigured)?;
+        let password = std::env::var(&protection.password_env)
+            .ok()
+            .filter(|password| !password.is_empty())
+            .ok_or_else(|| Error::MissingPassword(protection.password_env.clone()))?;
+
+        let dir = StateDir::new(config).store(Store::Registry).await?;
+        let path = dir.join(REGISTRY_FILE);
+        let registry: Registry = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|e| Error::ParseRegistry(path.clone(), e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+            Err(e) => return Err(Error::ReadInput(path, e)),
+        };
+        let salt = match base64::decode(&registry.salt) {
+            Some(salt) if !salt.is_empty() => salt,
+            _ => {
+                debug!("Drawing a new salt for protected pages");
+                random::<16>()?.to_vec()
+            }
+        };
+
+        // Deriving the key is slow on purpose, so it is only done once
+        let iterations = protection.iterations;
+        let key = {
+            let salt = salt.clone();
+            tokio::task::spawn_blocking(move || {
+                crypto::pbkdf2_sha256(password.as_bytes(), &salt, iterations)
+            })
+            .await
+            .map_err(Error::Join)?
+        };
+
+        Ok(Protector {
+            path,
+            iterations,
+            salt,
+            key,
+            previous: registry.pages,
+            pages: Default::default(),
+        })
+    }
+
+    /// Encrypt the rendered page at the URL path into a wrapper page which
+    /// decrypts it with the password.
+    ///
+    /// The title and language of the wrapper are taken from the context of
+    /// the page.
+    pub fn encrypt_page(&self, ctx: &Context, url: &str, html: &str) -> Result<String> {
+        let digest = base64::encode(&crypto::hmac_sha256(&self.key, html.as_bytes()));
+        let encrypted = match self.previous.get(url) {
+            Some(previous) if previous.digest == digest => previous.clone(),
+            _ => {
+                let nonce = random::<12>()?;
+                let ciphertext = crypto::aes256_gcm_encrypt(&self.key, &nonce, html.as_bytes());
+                Encrypted {
+                    digest,
+                    iv: base64::encode(&nonce),
+                    ciphertext: base64::encode(&ciphertext),
+                }
+            }
+        };
+
+        let text = |key| match ctx.get(key) {
+            Some(Value::String(text)) => text.as_str(),
+            _ => "",
+        };
+        let html = wrapper(
+            self.iterations,
+            text("title"),
+            text("lang"),
+            &self.salt,
+            &encrypted,
+        );
+        self.pages
+            .lock()
+            .expect("lock is never poisoned")
+            .insert(url.to_string(), encrypted);
+        Ok(html)
+    }
+
+    /// Write the salt and the pages encrypted by this build to the registry.
+    pub async fn save(&self) -> Result<()> {
+        let registry = Registry {
+            salt: base64::encode(&self.salt),
+            pages: std::mem::take(&mut *self.pages.lock().expect("lock is never poisoned")),
+        };
+        let content = toml::to_string(&registry).map_err(Error::SerializeRegistry)?;
+        tokio::fs::write(&self.path, content)
+            .await
+            .map_err(|e| Error::WriteFile(self.path.clone(), e))
+    }
+}
+
+/// Create the wrapper page containing the encrypted page.
+fn wrapper(iterations: u32, title: &str, lang: &str, salt: &[u8], encrypted: &Encrypted) -> String {
+    let mut escaped_title = String::new();
+    escape_html(&mut escaped_title, title).expect("writing to a string");
+    let mut escaped_lang = String::new();
+    escape_html(&mut escaped_lang, lang).expect("writing to a string");
+    let data = serde_json::json!({
+        "salt": base64::encode(salt),
+        "iv": encrypted.iv,
+        "iterations": iterations,
+        "ciphertext": encrypted.ciphertext,
+    });
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{escaped_lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{escaped_title}</title>
+</head>
+<body>
+<main>
+<form id="fweb-protected">
+<label>Password <input type="password" name="password" autocomplete="current-password" autofocus></label>
+<button>Open</button>
+<p role="alert" hidden>The password is not correct.</p>
+</form>
+</main>
+<script>
+const protectedPage = {data};
+{DECRYPT_SCRIPT}
+</script>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapper() {
+        let encrypted = Encrypted {
+            digest: String::new(),
+            iv: base64::encode(b"fo"),
+            ciphertext: base64::encode(b"foobar"),
+        };
+        let html = wrapper(1, "<Secret>", "en", b"f", &encrypted);
+        assert!(html.contains("<title>&lt;Secret&gt;</title>"));
+        assert!(
+            html.contains(r#"{"ciphertext":"Zm9vYmFy","iterations":1,"iv":"Zm8=","salt":"Zg=="}"#)
+        );
+    }
+
+    #[test]
+    fn test_encrypt_page_unchanged() {
+        let protector = |previous| Protector {
+            path: PathBuf::new(),
+            iterations: 1,
+            salt: b"salt".to_vec(),
+            key: crypto::pbkdf2_sha256(b"password", b"salt", 1),
+            previous,
+            pages: Default::default(),
+        };
+        let ctx = Context::new();
+        let first = protector(BTreeMap::new());
+        let html = first
+            .encrypt_page(&ctx, "/secret/", "<p>Secret</p>")
+            .unwrap();
+        // A random nonce is drawn for every new page
+        assert_ne!(
+            html,
+            protector(BTreeMap::new())
+                .encrypt_page(&ctx, "/secret/", "<p>Secret</p>")
+                .unwrap()
+        );
+
+        let second = protector(first.pages.into_inner().unwrap());
+        assert_eq!(
+            html,
+            second
+                .encrypt_page(&ctx, "/secret/", "<p>Secret</p>")
+                .unwrap()
+        );
+        assert_ne!(
+            html,
+            second
+                .encrypt_page(&ctx, "/secret/", "<p>Changed</p>")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Cryptographic primitives for encrypting pages in a form which browsers can
+//! decrypt with the Web Crypto API: PBKDF2 with HMAC-SHA-256 to derive a key
+//! from the password and AES-256-GCM to encrypt the content.
+//!
+//! The primitives are the ones of the RustCrypto crates, checked against the
+//! test vectors of RFC 4231, RFC 7914 and the GCM specification.
+
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Compute the HMAC-SHA-256 of the message with the key.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// Derive a 256 bit key from the password with PBKDF2-HMAC-SHA-256.
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password, salt, iterations)
+}
+
+/// Encrypt the plaintext with AES-256-GCM, returning the ciphertext followed
+/// by the 128 bit tag as expected by the Web Crypto API.
+pub fn aes256_gcm_encrypt(key: &[u8; 32], nonce: &[u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    Aes256Gcm::new(key.into())
+        .encrypt(nonce.into(), plaintext)
+        .expect("plaintext is within the length limit of GCM")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?"))
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_sha256() {
+        // RFC 7914, section 11, of which the key is the first half
+        assert_eq!(
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc",
+            hex(&pbkdf2_sha256(b"passwd", b"salt", 1))
+        );
+        // The RFC 6070 vector computed with SHA-256
+        assert_eq!(
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a",
+            hex(&pbkdf2_sha256(b"password", b"salt", 4096))
+        );
+    }
+
+    #[test]
+    fn test_aes256_gcm_encrypt() {
+        // GCM specification, test cases 13 and 14
+        assert_eq!(
+            "530f8afbc74536b9a963b4f1c4cb738b",
+            hex(&aes256_gcm_encrypt(&[0; 32], &[0; 12], &[]))
+        );
+        assert_eq!(
+            "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919",
+            hex(&aes256_gcm_encrypt(&[0; 32], &[0; 12], &[0; 16]))
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! This module generates the pages redirecting from the aliases of pages and
+//! sections, former URLs given in their frontmatter, to their current URL.
+//!
+//! Static hosts cannot be told to redirect in a portable way, so every alias
+//! is a page with a meta refresh and a canonical link to the target.
+
+use std::path::{Component, PathBuf};
+
+use log::debug;
+use pulldown_cmark::escape::escape_html;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    Cli, Index,
+};
+
+/// HTML of a page redirecting to the URL.
+pub fn redirect_html(target: &str) -> String {
+    let mut url = String::new();
+    escape_html(&mut url, target).expect("writing to a string never fails");
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Redirect</title>\
+         <link rel=\"canonical\" href=\"{url}\">\
+         <meta http-equiv=\"refresh\" content=\"0; url={url}\"></head>\
+         <body><a href=\"{url}\">{url}</a></body></html>\n"
+    )
+}
+
+/// File of the redirect page of an alias relative to the output directory,
+/// `None` if the alias leaves it.
+///
+/// Aliases naming an HTML file are written to the file, all others to the
+/// `index.html` of their directory.
+fn alias_file(alias: &str) -> Option<PathBuf> {
+    let mut file = PathBuf::new();
+    for component in PathBuf::from(alias).components() {
+        match component {
+            Component::Normal(name) => file.push(name),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    if file.extension().is_none_or(|ext| ext != "html") {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+/// Write the redirect pages of the aliases of all sections and published
+/// pages.
+pub async fn export_aliases(config: &Config, opts: &Cli, indices: &[Index]) -> Result<()> {
+    let mut aliases = Vec::new();
+    for index in indices {
+        let metadata = &index.metadata;
+        aliases.extend(metadata.aliases.iter().map(|alias| (alias, index.url())));
+        let pages = index.pages.iter().filter(|page| page.is_published(opts));
+        for page in pages {
+            let metadata = &page.metadata;
+            aliases.extend(metadata.aliases.iter().map(|alias| (alias, page.url())));
+        }
+    }
+
+    for (alias, url) in aliases {
+        let file = alias_file(alias).ok_or_else(|| Error::InvalidAlias(alias.clone()))?;
+        debug!("Redirecting {} to {}", alias, url);
+        let path = config.output_path.join(file);
+        let dir = path.parent().expect("alias file is in the output");
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.to_path_buf(), e))?;
+        let html = redirect_html(&config.absolute_url(&url));
+        tokio::fs::write(&path, html)
+            .await
+            .map_err(|e| Error::WriteFile(path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_file() {
+        assert_eq!(
+            Some(PathBuf::from("old/post/index.html")),
+            alias_file("/old/post/")
+        );
+        assert_eq!(
+            Some(PathBuf::from("2019/post.html")),
+            alias_file("/2019/post.html")
+        );
+        assert_eq!(None, alias_file("/old/../../etc/"));
+    }
+
+    #[test]
+    fn test_redirect_html() {
+        let html = redirect_html("https://example.com/?a=1&b=\"2\"");
+        assert!(html.contains("href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\""));
+        assert!(!html.contains("\"2\""));
+    }
+}
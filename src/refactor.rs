@@ -0,0 +1,457 @@
+//! This module moves a section of the site to another URL, e.g. to restructure
+//! the site without breaking links.
+//!
+//! The directory of the section is moved within the content and the links to
+//! the section in all content files are rewritten, both URL paths and links to
+//! content files. The former URLs of the moved pages and sections are added to
+//! their aliases, so redirects are generated for them. A dry run only prints
+//! the changes as a diff.
+
+use std::path::{Path, PathBuf};
+
+use log::info;
+use pulldown_cmark::{Event, Parser, Tag};
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    frontmatter::{self, Frontmatter},
+    list_files, slug,
+    template::Context,
+    Page,
+};
+
+/// URL path with a leading and a trailing slash.
+fn normalize(path: &str) -> String {
+    match path.trim_matches('/') {
+        "" => "/".to_string(),
+        path => format!("/{}/", path),
+    }
+}
+
+/// Rewrite the links to the URL path `from` and below it in the text to `to`.
+///
+/// Links are URL paths, absolute URLs of the site and links to content files
+/// starting with `@`, which are found after the delimiters of markdown links
+/// and HTML attributes.
+fn rewrite_links(text: &str, from: &str, to: &str, base_url: Option<&str>) -> String {
+    let (from, to) = (from.trim_end_matches('/'), to.trim_end_matches('/'));
+    let base_url = base_url.map(|base_url| base_url.trim_end_matches('/'));
+    let is_start = |c: char| matches!(c, '(' | '[' | '<' | '"' | '\'' | '=') || c.is_whitespace();
+    let is_end =
+        |c: char| matches!(c, '/' | '#' | '?' | ')' | '>' | '"' | '\'') || c.is_whitespace();
+
+    let mut rewritten = String::with_capacity(text.len());
+    let mut position = 0;
+    for (i, _) in text.match_indices(from) {
+        let before = &text[..i];
+        let before = match (before.strip_suffix('@'), base_url) {
+            (Some(before), _) => before,
+            (None, Some(base_url)) => before.strip_suffix(base_url).unwrap_or(before),
+            (None, None) => before,
+        };
+        let end = i + from.len();
+        if before.chars().next_back().is_none_or(is_start)
+            && text[end..].chars().next().is_none_or(is_end)
+        {
+            rewritten.push_str(&text[position..i]);
+            rewritten.push_str(to);
+            position = end;
+        }
+    }
+    rewritten.push_str(&text[position..]);
+    rewritten
+}
+
+/// Rewrite the links in the markdown outside of code, which shows links
+/// rather than linking to them.
+fn rewrite_markdown(markdown: &str, from: &str, to: &str, base_url: Option<&str>) -> String {
+    let code = Parser::new(markdown)
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| range);
+
+    let mut rewritten = String::with_capacity(markdown.len());
+    let mut position = 0;
+    for range in code {
+        rewritten.push_str(&rewrite_links(
+            &markdown[position..range.start],
+            from,
+            to,
+            base_url,
+        ));
+        rewritten.push_str(&markdown[range.clone()]);
+        position = range.end;
+    }
+    rewritten.push_str(&rewrite_links(&markdown[position..], from, to, base_url));
+    rewritten
+}
+
+/// Offset of the markdown after the frontmatter in the input.
+fn body_offset(input: &str, body: &str) -> usize {
+    body.as_ptr() as usize - input.as_ptr() as usize
+}
+
+/// Lines of the frontmatter belonging to the aliases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AliasLines {
+    /// Not the aliases.
+    None,
+    /// The aliases on a single line.
+    Single,
+    /// An array of aliases spanning several lines, up to the line with `]`.
+    Array,
+    /// A YAML list of aliases, up to the next line which is no item.
+    List,
+}
+
+/// Rewrite the links in the content file, leaving its aliases as they are
+/// since they are former URLs.
+fn rewrite_file(input: &str, from: &str, to: &str, base_url: Option<&str>) -> String {
+    let Some((_, body)) = frontmatter::split(input) else {
+        return rewrite_markdown(input, from, to, base_url);
+    };
+    let (head, body) = input.split_at(body_offset(input, body));
+
+    let mut rewritten = String::with_capacity(input.len());
+    let mut aliases = AliasLines::None;
+    for line in head.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let is_item = trimmed.starts_with('-') || line.starts_with(char::is_whitespace);
+        aliases = match aliases {
+            AliasLines::Array if line.contains(']') => AliasLines::Single,
+            AliasLines::Array => AliasLines::Array,
+            AliasLines::List if is_item => AliasLines::List,
+            _ if trimmed.trim_start_matches('"').starts_with("aliases") => {
+                match (line.contains('['), line.contains(']')) {
+                    (true, false) => AliasLines::Array,
+                    (false, _) => AliasLines::List,
+                    (true, true) => AliasLines::Single,
+                }
+            }
+            _ => AliasLines::None,
+        };
+        match aliases {
+            AliasLines::None => rewritten.push_str(&rewrite_links(line, from, to, base_url)),
+            _ => rewritten.push_str(line),
+        }
+        if aliases == AliasLines::Single {
+            aliases = AliasLines::None;
+        }
+    }
+    rewritten.push_str(&rewrite_markdown(body, from, to, base_url));
+    rewritten
+}
+
+/// Add the alias to the frontmatter of the content file.
+fn add_alias(input: &str, alias: &str) -> Option<String> {
+    let (frontmatter, body) = frontmatter::split(input)?;
+    let head = &input[..body_offset(input, body)];
+    let quoted = format!("\"{}\"", alias);
+
+    // Extend the aliases which are given
+    let mut offset = 0;
+    let mut lines = head.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let start = offset;
+        offset += line.len();
+        if !line
+            .trim_start()
+            .trim_start_matches('"')
+            .starts_with("aliases")
+        {
+            continue;
+        }
+        let mut output = input.to_string();
+        match line.find('[') {
+            Some(bracket) => {
+                let position = start + bracket + 1;
+                let separator = match input[position..].trim_start().starts_with(']') {
+                    true => "",
+                    false => ", ",
+                };
+                output.insert_str(position, &format!("{}{}", quoted, separator));
+            }
+            None => {
+                let indent = lines
+                    .peek()
+                    .map(|next| &next[..next.len() - next.trim_start().len()])
+                    .filter(|indent| !indent.is_empty())
+                    .unwrap_or("  ");
+                output.insert_str(offset, &format!("{}- {}\n", indent, quoted));
+            }
+        }
+        return Some(output);
+    }
+
+    // Otherwise add them at the start, before any tables
+    let (position, field) = match frontmatter {
+        Frontmatter::Toml(_) | Frontmatter::Yaml(_) => {
+            let position = head.find('\n')? + 1;
+            let field = match frontmatter {
+                Frontmatter::Toml(_) => format!("aliases = [{}]\n", quoted),
+                _ => format!("aliases: [{}]\n", quoted),
+            };
+            (position, field)
+        }
+        Frontmatter::Json(_) => {
+            let position = head.find('{')? + 1;
+            let separator = match input[position..].trim_start().starts_with('}') {
+                true => "",
+                false => ", ",
+            };
+            (position, format!("\"aliases\": [{}]{}", quoted, separator))
+        }
+    };
+    let mut output = input.to_string();
+    output.insert_str(position, &field);
+    Some(output)
+}
+
+/// Differences of the lines of the texts, removed lines start with `-` and
+/// added ones with `+`.
+///
+/// Every hunk of changes starts with the numbers of its first line in both
+/// texts.
+fn diff(before: &str, after: &str) -> String {
+    let (a, b): (Vec<_>, Vec<_>) = (before.lines().collect(), after.lines().collect());
+    // Lengths of the longest common subsequences of the remaining lines
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = match a[i] == b[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            in_hunk = false;
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if !in_hunk {
+            diff.push_str(&format!("@@ -{} +{} @@\n", i + 1, j + 1));
+            in_hunk = true;
+        }
+        if i < a.len() && (j == b.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// URL path of the page or section with the content at the path relative to
+/// `content/`.
+async fn content_url(config: &Config, relpath: &Path, content: &str) -> Result<String> {
+    if relpath.file_name().is_some_and(|name| name == "_index.md") {
+        let dir = relpath.parent().expect("index is a file");
+        let url_dir = slug::slugify_dir(&config.slugs, dir)
+            .ok_or_else(|| Error::EmptyDirSlug(dir.to_path_buf()))?;
+        return Ok(normalize(&url_dir.to_string_lossy()));
+    }
+    let ctx = Context::new();
+    let page = Page::parse(content, relpath, config, &ctx, &toml::Table::new(), true).await?;
+    Ok(page.url())
+}
+
+/// Move the section at the URL path `from` to the URL path `to`.
+///
+/// With a dry run the changes are printed instead.
+pub async fn refactor(config: &Config, from: &str, to: &str, dry_run: bool) -> Result<()> {
+    let (from, to) = (normalize(from), normalize(to));
+    let content_dir = config.content_path.join("content");
+    let from_dir = content_dir.join(from.trim_matches('/'));
+    let to_dir = content_dir.join(to.trim_matches('/'));
+    if from == "/" || !from_dir.is_dir() {
+        return Err(Error::UnknownSection(from));
+    }
+    if to == "/" || to_dir.exists() || to.starts_with(&from) {
+        return Err(Error::RefactorTarget(to));
+    }
+
+    let files = list_files(&content_dir).await?;
+    let moved_dir = PathBuf::from(from.trim_matches('/'));
+    for relpath in files
+        .iter()
+        .filter(|file| file.extension().is_some_and(|ext| ext == "md"))
+    {
+        let path = content_dir.join(relpath);
+        let input = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::ReadInput(path.clone(), e))?;
+        let mut output = rewrite_file(&input, &from, &to, config.base_url.as_deref());
+        let mut new_relpath = relpath.clone();
+        if let Ok(rest) = relpath.strip_prefix(&moved_dir) {
+            new_relpath = PathBuf::from(to.trim_matches('/')).join(rest);
+            // The URL stays the same if the slugs of both directories match
+            let alias = content_url(config, relpath, &input).await?;
+            if alias != content_url(config, &new_relpath, &output).await? {
+                output = add_alias(&output, &alias)
+                    .ok_or_else(|| Error::MalformedContent(path.clone()))?;
+            }
+        }
+        if output == input {
+            continue;
+        }
+
+        match dry_run {
+            true => {
+                println!("--- content/{}", relpath.display());
+                println!("+++ content/{}", new_relpath.display());
+                print!("{}", diff(&input, &output));
+            }
+            false => {
+                info!("Rewriting {}", relpath.display());
+                tokio::fs::write(&path, output)
+                    .await
+                    .map_err(|e| Error::WriteFile(path, e))?;
+            }
+        }
+    }
+
+    match dry_run {
+        true => println!("Moving {} to {}", from_dir.display(), to_dir.display()),
+        false => {
+            info!("Moving {} to {}", from_dir.display(), to_dir.display());
+            if let Some(parent) = to_dir.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| Error::CreateDirectory(parent.to_path_buf(), e))?;
+            }
+            tokio::fs::rename(&from_dir, &to_dir)
+                .await
+                .map_err(|e| Error::WriteFile(to_dir, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!("/old/", normalize("old"));
+        assert_eq!("/a/b/", normalize("/a/b"));
+        assert_eq!("/", normalize("/"));
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let base_url = Some("https://example.com/");
+        assert_eq!(
+            "[a](/new/a/) [b](@/new/b.md#x) <a href=\"https://example.com/new\">\
+             [c]: /new/?p=2 /older/ https://other.org/old/ (/old-posts/)",
+            rewrite_links(
+                "[a](/old/a/) [b](@/old/b.md#x) <a href=\"https://example.com/old\">\
+                 [c]: /old/?p=2 /older/ https://other.org/old/ (/old-posts/)",
+                "/old/",
+                "/new/",
+                base_url
+            )
+        );
+    }
+
+    #[test]
+    fn test_rewrite_file() {
+        assert_eq!(
+            "+++\ncover = \"/new/x.jpg\"\naliases = [\"/old/x/\"]\n+++\n[x](/new/x/)\n",
+            rewrite_file(
+                "+++\ncover = \"/old/x.jpg\"\naliases = [\"/old/x/\"]\n+++\n[x](/old/x/)\n",
+                "/old/",
+                "/new/",
+                None
+            )
+        );
+        assert_eq!(
+            "---\naliases:\n  - /old/x/\ntitle: X\n---\n[x](/new/x/)\n",
+            rewrite_file(
+                "---\naliases:\n  - /old/x/\ntitle: X\n---\n[x](/old/x/)\n",
+                "/old/",
+                "/new/",
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_rewrite_markdown() {
+        assert_eq!(
+            "[a](/new/a/) `/old/a/`\n\n```\n[a](/old/a/)\n```\n[b](/new/b/)\n",
+            rewrite_markdown(
+                "[a](/old/a/) `/old/a/`\n\n```\n[a](/old/a/)\n```\n[b](/old/b/)\n",
+                "/old/",
+                "/new/",
+                None
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_url() {
+        let config = dummy_config();
+        let page = "+++\ntitle = \"Hello\"\n+++\nText";
+        assert_eq!(
+            "/blog/hello/",
+            content_url(&config, Path::new("Blog/hello.md"), page)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            content_url(&config, Path::new("Blog/hello.md"), page)
+                .await
+                .unwrap(),
+            content_url(&config, Path::new("blog/hello.md"), page)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            "/blog/",
+            content_url(&config, Path::new("Blog/_index.md"), "")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_add_alias() {
+        assert_eq!(
+            Some("+++\naliases = [\"/old/x/\"]\ntitle = \"X\"\n+++\nText".to_string()),
+            add_alias("+++\ntitle = \"X\"\n+++\nText", "/old/x/")
+        );
+        assert_eq!(
+            Some("+++\naliases = [\"/old/x/\", \"/x/\"]\n+++\nText".to_string()),
+            add_alias("+++\naliases = [\"/x/\"]\n+++\nText", "/old/x/")
+        );
+        assert_eq!(
+            Some("---\ntitle: X\naliases:\n    - \"/old/x/\"\n    - /x/\n---\nText".to_string()),
+            add_alias("---\ntitle: X\naliases:\n    - /x/\n---\nText", "/old/x/")
+        );
+        assert_eq!(
+            Some("{\"aliases\": [\"/old/x/\"], \"title\": \"X\"}\nText".to_string()),
+            add_alias("{\"title\": \"X\"}\nText", "/old/x/")
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            "@@ -2 +2 @@\n-b\n+B\n@@ -4 +4 @@\n+d\n",
+            diff("a\nb\nc\n", "a\nB\nc\nd\n")
+        );
+        assert_eq!("", diff("a\n", "a\n"));
+    }
+}
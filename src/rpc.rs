@@ -0,0 +1,487 @@
+//! This module serves editor integrations with JSON-RPC 2.0 over stdin and
+//! stdout, one message per line.
+//!
+//! Every request is answered with the current content of the site, so
+//! editors can pass the unsaved text of a file along with its path. The
+//! content is parsed once per session, later only the pages changed on disk
+//! are parsed again. Changed sections and added or removed files reload the
+//! whole content.
+//!
+//! - `render` with `path` and optionally `text` returns the `html` of the
+//!   page.
+//! - `validate` with `path` and optionally `text` returns the `diagnostics`
+//!   of the frontmatter, the template and the internal links.
+//! - `complete_links` with an optional `prefix` returns the `items` which
+//!   internal links can point to, e.g. `@/blog/first.md`.
+//! - `exit` ends the session, as does the end of the input.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::debug;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::{
+    cascade_of,
+    config::Config,
+    error::{Error, Result},
+    links, list_files, load_and_parse_content, section_of, template, Cli, Index, Page, Website,
+};
+
+/// The message could not be parsed as JSON.
+const PARSE_ERROR: i64 = -32700;
+
+/// The method does not exist.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The parameters do not fit the method.
+const INVALID_PARAMS: i64 = -32602;
+
+/// Processing the request failed, e.g. as the site could not be loaded.
+const SERVER_ERROR: i64 = -32000;
+
+/// A request, or a notification if it has no id.
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Parameters of requests about a content file.
+#[derive(Debug, Deserialize)]
+struct FileParams {
+    /// Path of the content file.
+    path: PathBuf,
+    /// Text of the file if it differs from the one on disk.
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Parameters of link completion.
+#[derive(Debug, Default, Deserialize)]
+struct CompletionParams {
+    /// Start of the link typed so far.
+    #[serde(default)]
+    prefix: String,
+}
+
+/// An error answering a request.
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        RpcError::new(SERVER_ERROR, e)
+    }
+}
+
+/// State of a session with an editor.
+struct Session<'a> {
+    website: &'a Website,
+    opts: &'a Cli,
+    /// Content of the site, `None` until a request needs it.
+    content: Option<Content>,
+}
+
+/// Parsed content of the site along with the modification times of its
+/// markdown files by their path relative to `content/`.
+struct Content {
+    indices: Vec<Index>,
+    modified: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+/// Modification times of all markdown files of the content.
+async fn modification_times(config: &Config) -> Result<BTreeMap<PathBuf, Option<SystemTime>>> {
+    let content_dir = config.content_path.join("content");
+    let mut times = BTreeMap::new();
+    for relpath in list_files(&content_dir).await? {
+        if relpath.extension().is_some_and(|ext| ext == "md") {
+            let metadata = tokio::fs::metadata(content_dir.join(&relpath)).await.ok();
+            times.insert(relpath, metadata.and_then(|m| m.modified().ok()));
+        }
+    }
+    Ok(times)
+}
+
+impl<'a> Session<'a> {
+    fn new(website: &'a Website, opts: &'a Cli) -> Self {
+        Session {
+            website,
+            opts,
+            content: None,
+        }
+    }
+
+    /// The current content of the site, parsing only the files changed since
+    /// the last request.
+    async fn content(&mut self) -> Result<&[Index]> {
+        let config = &self.website.config;
+        let modified = modification_times(config).await?;
+        let changed: Option<Vec<_>> = self.content.as_ref().and_then(|content| {
+            let changed = modified
+                .iter()
+                .filter(|(file, time)| content.modified.get(*file) != Some(time))
+                .map(|(file, _)| file.clone())
+                .collect::<Vec<_>>();
+            let sections = changed
+                .iter()
+                .any(|file| file.file_name().is_some_and(|name| name == "_index.md"));
+            (content.modified.keys().eq(modified.keys()) && !sections).then_some(changed)
+        });
+
+        let ctx = template::Context::new();
+        let content = match (changed, self.content.take()) {
+            (Some(changed), Some(mut content)) => {
+                let content_dir = config.content_path.join("content");
+                let now = OffsetDateTime::now_utc();
+                for relpath in changed {
+                    debug!("Parsing {} again", relpath.display());
+                    let cascade = cascade_of(&content.indices, &relpath);
+                    let low_memory = self.opts.low_memory;
+                    let page =
+                        Page::parse_md(&content_dir, &relpath, config, &ctx, &cascade, low_memory)
+                            .await?;
+                    for index in &mut content.indices {
+                        index.pages.retain(|page| page.metadata.filepath != relpath);
+                    }
+                    let future = page.metadata.date.is_some_and(|date| date > now);
+                    if let (Some(position), false) = (
+                        section_of(&content.indices, &relpath),
+                        future && !self.opts.future,
+                    ) {
+                        let index = &mut content.indices[position];
+                        index.pages.push(page);
+                        index.sort_pages();
+                    }
+                }
+                content.modified = modified;
+                content
+            }
+            _ => Content {
+                indices: load_and_parse_content(config, &ctx, self.opts).await?,
+                modified,
+            },
+        };
+        Ok(&self.content.insert(content).indices)
+    }
+}
+
+/// Whether the session goes on after a message.
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Exit,
+}
+
+/// Answer the requests read from stdin until the input ends or `exit` is
+/// requested.
+pub async fn serve(website: &Website, opts: &Cli) -> Result<()> {
+    let mut session = Session::new(website, opts);
+    loop {
+        let line = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .map(|read| (read, line))
+        })
+        .await
+        .map_err(Error::Join)?;
+        let line = match line {
+            Ok((0, _)) => return Ok(()),
+            Ok((_, line)) => line,
+            Err(e) => return Err(Error::ReadInput("stdin".into(), e)),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, flow) = handle(&mut session, &line).await;
+        if let Some(response) = response {
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", response)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| Error::WriteFile("stdout".into(), e))?;
+        }
+        if flow == Flow::Exit {
+            return Ok(());
+        }
+    }
+}
+
+/// Answer a single message, notifications are not answered.
+async fn handle(session: &mut Session<'_>, message: &str) -> (Option<Value>, Flow) {
+    let request: Request = match serde_json::from_str(message) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, e);
+            return (Some(response(Value::Null, Err(error))), Flow::Continue);
+        }
+    };
+    debug!("Handling {}", request.method);
+
+    let flow = match request.method.as_str() {
+        "exit" => Flow::Exit,
+        _ => Flow::Continue,
+    };
+    let result = dispatch(session, &request.method, request.params).await;
+    (request.id.map(|id| response(id, result)), flow)
+}
+
+/// Call the method with the parameters.
+async fn dispatch(
+    session: &mut Session<'_>,
+    method: &str,
+    params: Value,
+) -> std::result::Result<Value, RpcError> {
+    match method {
+        "exit" => Ok(Value::Null),
+        "render" => render(session, parse_params(params)?).await,
+        "validate" => validate(session, parse_params(params)?).await,
+        "complete_links" => {
+            let params = match params {
+                Value::Null => CompletionParams::default(),
+                params => parse_params(params)?,
+            };
+            complete_links(session, params).await
+        }
+        method => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Method '{}' does not exist", method),
+        )),
+    }
+}
+
+/// Deserialize the parameters of a method.
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+/// The response to the request with the id.
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": error.code, "message": error.message},
+        }),
+    }
+}
+
+/// Text of the content file given by the parameters and its path relative
+/// to `content/`.
+async fn read_file(
+    website: &Website,
+    params: FileParams,
+) -> std::result::Result<(String, PathBuf), RpcError> {
+    let relpath = crate::content_relpath(&website.config, &params.path);
+    let text = match params.text {
+        Some(text) => text,
+        None => tokio::fs::read_to_string(&params.path)
+            .await
+            .map_err(|e| Error::ReadInput(params.path.clone(), e))?,
+    };
+    Ok((text, relpath))
+}
+
+/// Render the page of the content file.
+async fn render(
+    session: &mut Session<'_>,
+    params: FileParams,
+) -> std::result::Result<Value, RpcError> {
+    let (website, opts) = (session.website, session.opts);
+    let (text, relpath) = read_file(website, params).await?;
+    let indices = session.content().await?.to_vec();
+    let html = website
+        .render_content(opts, indices, &text, relpath)
+        .await?;
+    Ok(json!({ "html": html }))
+}
+
+/// Check the frontmatter, the template and the internal links of the content
+/// file.
+///
+/// Problems of the file are reported as diagnostics instead of an error.
+async fn validate(
+    session: &mut Session<'_>,
+    params: FileParams,
+) -> std::result::Result<Value, RpcError> {
+    let (website, opts) = (session.website, session.opts);
+    let config = &website.config;
+    let (text, relpath) = read_file(website, params).await?;
+    let ctx = template::Context::new();
+    let indices = session.content().await?;
+    let cascade = cascade_of(indices, &relpath);
+
+    let mut diagnostics = Vec::new();
+    match Page::parse(&text, &relpath, config, &ctx, &cascade, false).await {
+        Ok(page) => {
+            let template = &page.metadata.template;
+            if !template::template_exists(config, template) {
+                diagnostics.push(format!("Template {} does not exist", template.display()));
+            }
+            let urls = links::content_urls(config, opts, indices);
+            if let Err(e) = links::resolve_content(&relpath, &page.html, &urls) {
+                diagnostics.push(e.to_string());
+            }
+        }
+        Err(e) => diagnostics.push(e.to_string()),
+    }
+    let diagnostics: Vec<_> = diagnostics
+        .into_iter()
+        .map(|message| json!({ "message": message }))
+        .collect();
+    Ok(json!({ "diagnostics": diagnostics }))
+}
+
+/// List the content files internal links can point to which start with the
+/// prefix.
+async fn complete_links(
+    session: &mut Session<'_>,
+    params: CompletionParams,
+) -> std::result::Result<Value, RpcError> {
+    let (config, opts) = (&session.website.config, session.opts);
+    let indices = session.content().await?;
+    let urls = links::content_urls(config, opts, indices);
+    let titles = indices.iter().flat_map(|index| {
+        let pages = index
+            .pages
+            .iter()
+            .map(|page| (&page.metadata.filepath, &page.metadata.title));
+        std::iter::once((&index.metadata.filepath, &index.metadata.title)).chain(pages)
+    });
+    let mut items: Vec<_> = titles
+        .filter_map(|(file, title)| {
+            let label = link_target(file);
+            let url = urls.get(file)?;
+            label
+                .starts_with(&params.prefix)
+                .then(|| json!({ "label": label, "title": title, "url": url }))
+        })
+        .collect();
+    items.sort_by(|a, b| a["label"].as_str().cmp(&b["label"].as_str()));
+    Ok(json!({ "items": items }))
+}
+
+/// Target of an internal link to the content file, e.g. `@/blog/first.md`.
+fn link_target(file: &Path) -> String {
+    format!("{}{}", links::INTERNAL_PREFIX, file.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn website() -> Website {
+        let config: Config =
+            toml::from_str("[site_info]\ntitle = \"\"\ndescription = \"\"").unwrap();
+        Website::new(config)
+    }
+
+    fn opts() -> Cli {
+        Cli {
+            config_path: "config.toml".into(),
+            drafts: false,
+            future: false,
+            report_unused: false,
+            low_memory: false,
+            jobs: None,
+            command: Some(crate::Command::Rpc),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_errors() {
+        let (website, opts) = (website(), opts());
+        let mut session = Session::new(&website, &opts);
+        let (response, flow) = handle(&mut session, "{").await;
+        assert_eq!(PARSE_ERROR, response.unwrap()["error"]["code"]);
+        assert_eq!(Flow::Continue, flow);
+
+        let message = r#"{"jsonrpc": "2.0", "id": 1, "method": "nope"}"#;
+        let (response, _) = handle(&mut session, message).await;
+        let response = response.unwrap();
+        assert_eq!(1, response["id"]);
+        assert_eq!(METHOD_NOT_FOUND, response["error"]["code"]);
+
+        let message = r#"{"jsonrpc": "2.0", "id": 2, "method": "render", "params": {}}"#;
+        let (response, _) = handle(&mut session, message).await;
+        assert_eq!(INVALID_PARAMS, response.unwrap()["error"]["code"]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_exit() {
+        let message = r#"{"jsonrpc": "2.0", "method": "exit"}"#;
+        let (website, opts) = (website(), opts());
+        let (response, flow) = handle(&mut Session::new(&website, &opts), message).await;
+        assert_eq!(None, response);
+        assert_eq!(Flow::Exit, flow);
+    }
+
+    #[tokio::test]
+    async fn test_session_content() {
+        let site = std::env::temp_dir().join(format!("fweb-rpc-{}", std::process::id()));
+        let content_dir = site.join("content");
+        std::fs::create_dir_all(&content_dir).unwrap();
+        let config_file = site.join("config.toml");
+        std::fs::write(
+            &config_file,
+            "[site_info]\ntitle = \"\"\ndescription = \"\"\n",
+        )
+        .unwrap();
+        let page = content_dir.join("a.md");
+        std::fs::write(&page, "+++\ntitle = \"A\"\n+++\n").unwrap();
+        let website = Website::new(Config::from_file(&config_file).await.unwrap());
+        let opts = opts();
+        let mut session = Session::new(&website, &opts);
+        let titles = |indices: &[Index]| {
+            let pages = indices.iter().flat_map(|index| &index.pages);
+            pages
+                .map(|page| page.metadata.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["A"], titles(session.content().await.unwrap()));
+
+        // Changed pages are parsed again, added ones reload the content
+        std::fs::write(&page, "+++\ntitle = \"B\"\n+++\n").unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&page)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(vec!["B"], titles(session.content().await.unwrap()));
+        std::fs::write(content_dir.join("c.md"), "+++\ntitle = \"C\"\n+++\n").unwrap();
+        assert_eq!(vec!["B", "C"], titles(session.content().await.unwrap()));
+
+        std::fs::remove_dir_all(site).unwrap();
+    }
+
+    #[test]
+    fn test_link_target() {
+        assert_eq!("@/blog/first.md", link_target(Path::new("blog/first.md")));
+    }
+}
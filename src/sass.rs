@@ -0,0 +1,77 @@
+//! This module compiles Sass and SCSS stylesheets into CSS.
+//!
+//! Stylesheets are taken from `assets/`, where they replace the verbatim
+//! copy, and from the dedicated `sass/` directory. Partials, files starting
+//! with `_`, are only available to `@import` and `@use`.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "sass")]
+use log::debug;
+
+use crate::{
+    config::SassConfig,
+    error::{Error, Result},
+};
+
+/// Whether the file is a Sass or SCSS stylesheet.
+pub fn is_stylesheet(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(OsStr::to_str),
+        Some("scss" | "sass")
+    )
+}
+
+/// Whether the stylesheet is a partial which is not compiled on its own.
+#[cfg(feature = "sass")]
+fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| name.starts_with('_'))
+}
+
+/// Compile the stylesheet to CSS, returns `None` for partials.
+///
+/// Imports are resolved relative to the stylesheet and to the load paths.
+#[cfg(feature = "sass")]
+pub async fn compile(
+    config: &SassConfig,
+    file: &Path,
+    load_paths: &[PathBuf],
+) -> Result<Option<String>> {
+    if is_partial(file) {
+        return Ok(None);
+    }
+    debug!("Compiling stylesheet {}", file.display());
+
+    let style = if config.minify {
+        grass::OutputStyle::Compressed
+    } else {
+        grass::OutputStyle::Expanded
+    };
+    let input = file.to_path_buf();
+    let load_paths = load_paths.to_vec();
+    let css = tokio::task::spawn_blocking(move || {
+        let options = grass::Options::default()
+            .style(style)
+            .load_paths(&load_paths);
+        grass::from_path(&input, &options)
+    })
+    .await
+    .map_err(Error::Join)?
+    .map_err(|e| Error::Sass(file.to_path_buf(), e.to_string()))?;
+    Ok(Some(css))
+}
+
+/// Stylesheets cannot be compiled without the `sass` feature.
+#[cfg(not(feature = "sass"))]
+pub async fn compile(
+    _config: &SassConfig,
+    _file: &Path,
+    _load_paths: &[PathBuf],
+) -> Result<Option<String>> {
+    Err(Error::MissingFeature("sass"))
+}
@@ -0,0 +1,48 @@
+//! This module generates a search index of all pages which can be consumed
+//! by a client side search.
+
+use std::borrow::Cow;
+
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    Cli, Index,
+};
+
+/// File name of the search index in the output directory.
+const SEARCH_INDEX_FILE: &str = "search_index.json";
+
+/// A page in the search index.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    title: &'a str,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excerpt: Option<&'a str>,
+    body: Cow<'a, str>,
+}
+
+/// Write the search index of all published pages.
+pub async fn export_search_index(config: &Config, opts: &Cli, indices: &[Index]) -> Result<()> {
+    let mut entries = Vec::new();
+    let pages = indices.iter().flat_map(|index| &index.pages);
+    for page in pages.filter(|page| page.is_published(opts)) {
+        entries.push(Entry {
+            title: &page.metadata.title,
+            url: config.url_path(&page.url()),
+            excerpt: page.excerpt.as_deref(),
+            body: page.plain_text(config).await?,
+        });
+    }
+
+    let json = serde_json::to_string(&entries).map_err(Error::Json)?;
+    tokio::fs::create_dir_all(&config.output_path)
+        .await
+        .map_err(|e| Error::CreateDirectory(config.output_path.clone(), e))?;
+    let path = config.output_path.join(SEARCH_INDEX_FILE);
+    tokio::fs::write(&path, json)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))
+}
@@ -0,0 +1,133 @@
+//! This module links the parts of series of pages, e.g. posts written in
+//! several parts, and generates the optional landing pages of the series.
+//!
+//! The parts of a series are ordered by their date, undated parts last.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use log::debug;
+
+use crate::{
+    config::Config,
+    error::Result,
+    slug::slugify,
+    template::{Context, Value},
+    write_page, Cli, Index, Page,
+};
+
+/// Absolute URL path of the landing page of a series with a trailing slash.
+pub fn series_url(config: &Config, name: &str) -> String {
+    format!("/series/{}/", slugify(&config.slugs, name))
+}
+
+/// Collect all series with their published parts in order.
+fn collect_series<'a>(indices: &'a [Index], opts: &Cli) -> BTreeMap<&'a str, Vec<&'a Page>> {
+    let mut series: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let pages = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts));
+    for page in pages {
+        if let Some(name) = &page.metadata.series {
+            series.entry(name.as_str()).or_default().push(page);
+        }
+    }
+    for parts in series.values_mut() {
+        parts.sort_by(|p1, p2| {
+            let (m1, m2) = (&p1.metadata, &p2.metadata);
+            (m1.date.is_none(), m1.date, &m1.filepath).cmp(&(
+                m2.date.is_none(),
+                m2.date,
+                &m2.filepath,
+            ))
+        });
+    }
+    series
+}
+
+/// Title and URL of a part, all a part needs to know of the other parts.
+///
+/// The whole pages would be copied into every part of a series, which grows
+/// with the square of its length.
+fn part_link(config: &Config, page: &Page) -> Value {
+    Value::from_iter([
+        ("title", page.metadata.title.clone()),
+        ("url", config.url_path(&page.url())),
+    ])
+}
+
+/// Convert a series with the values of its parts into a map for templating.
+///
+/// The URL is only given if landing pages are generated.
+fn series_map(config: &Config, name: &str, parts: Vec<Value>) -> HashMap<String, Value> {
+    let mut series = HashMap::from([
+        ("name".to_string(), Value::from(name)),
+        ("slug".to_string(), slugify(&config.slugs, name).into()),
+        ("parts".to_string(), parts.into()),
+    ]);
+    if config.series_template.is_some() {
+        series.insert(
+            "url".to_string(),
+            config.url_path(&series_url(config, name)).into(),
+        );
+    }
+    series
+}
+
+/// Assign every part of a series its position within the series and the
+/// previous and next part.
+///
+/// The parts only carry the title and URL of the other parts, see
+/// [`part_link`].
+pub fn assign_series(config: &Config, opts: &Cli, indices: &mut [Index]) {
+    let mut values: HashMap<PathBuf, Value> = HashMap::new();
+    for (name, parts) in collect_series(indices, opts) {
+        let links: Vec<_> = parts.iter().map(|page| part_link(config, page)).collect();
+        let series = series_map(config, name, links.clone());
+        for (i, page) in parts.iter().enumerate() {
+            let mut value = series.clone();
+            value.insert("position".to_string(), (i as i64 + 1).into());
+            if let Some(previous) = i.checked_sub(1).map(|i| &links[i]) {
+                value.insert("previous".to_string(), previous.clone());
+            }
+            if let Some(next) = links.get(i + 1) {
+                value.insert("next".to_string(), next.clone());
+            }
+            values.insert(page.metadata.filepath.clone(), Value::Map(value));
+        }
+    }
+
+    for page in indices.iter_mut().flat_map(|index| &mut index.pages) {
+        page.series = values.remove(&page.metadata.filepath);
+    }
+}
+
+/// Write the landing pages of all series if a template is configured.
+pub async fn export_series(
+    config: &Config,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+) -> Result<()> {
+    let Some(template) = &config.series_template else {
+        return Ok(());
+    };
+    for (name, parts) in collect_series(indices, opts) {
+        debug!("Building series '{}'", name);
+
+        let mut series_ctx = ctx.clone();
+        series_ctx.insert("title", name);
+        let parts = parts.iter().map(|page| page.to_value(config)).collect();
+        series_ctx.insert("series", Value::Map(series_map(config, name, parts)));
+        let dir = config
+            .output_path
+            .join("series")
+            .join(slugify(&config.slugs, name));
+        write_page(config, &series_ctx, template, &dir, false).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,434 @@
+//! This module serves the built site over HTTP, e.g. to preview drafts.
+//!
+//! The server is meant for previews, not for production. It answers `GET`
+//! and `HEAD` requests for the files of the output, one thread per
+//! connection up to a limit, beyond which requests are refused. Connections
+//! which are too slow to send their request or read the response are
+//! closed. With credentials, every request must authenticate with basic
+//! auth, so a staging build can be shown to reviewers without publishing it.
+//!
+//! If the last build of `fweb daemon --error-overlay` failed, its errors are
+//! shown in a banner injected into the served pages. The files of the output
+//! stay as they were built.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use pulldown_cmark::escape::escape_html;
+
+use crate::{
+    base64,
+    error::{Error, Result},
+    links::percent_decode,
+};
+
+/// Maximum size of the head of a request.
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Maximum number of connections answered at the same time.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Time after which a connection waiting for a read or write is closed.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Realm of the basic auth challenge.
+const REALM: &str = "fweb preview";
+
+/// Credentials of basic auth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    user: String,
+    password: String,
+}
+
+/// Parse credentials given as `user:pass`.
+pub fn parse_credentials(input: &str) -> std::result::Result<Credentials, String> {
+    match input.split_once(':') {
+        Some((user, password)) if !user.is_empty() && !password.is_empty() => Ok(Credentials {
+            user: user.to_string(),
+            password: password.to_string(),
+        }),
+        _ => Err("expected credentials as 'user:pass'".to_string()),
+    }
+}
+
+/// Compare the bytes in a time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Whether the value of the `Authorization` header matches the credentials.
+fn is_authorized(credentials: &Credentials, header: Option<&str>) -> bool {
+    let Some(encoded) = header.and_then(|header| header.strip_prefix("Basic ")) else {
+        return false;
+    };
+    let Some(decoded) = base64::decode(encoded) else {
+        return false;
+    };
+    let expected = format!("{}:{}", credentials.user, credentials.password);
+    constant_time_eq(&decoded, expected.as_bytes())
+}
+
+/// File of the output for the URL path, `None` if the path leaves the
+/// output.
+fn resolve_file(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let path = url_path.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path)?;
+    let mut file = root.to_path_buf();
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(name) => file.push(name),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(file)
+}
+
+/// Media type of the file by its extension.
+fn content_type(file: &Path) -> &'static str {
+    match file
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+    {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Insert a banner with the diagnostics of a failed build into the page.
+///
+/// The banner is inserted before the end of the body or appended if the page
+/// has no body.
+fn with_overlay(html: &str, diagnostics: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, diagnostics).expect("writing to a string never fails");
+    let overlay = format!(
+        "<div style=\"position:fixed;inset:auto 0 0 0;z-index:2147483647;max-height:50vh;\
+         overflow:auto;margin:0;padding:1em;background:#300;color:#fdd;font:14px monospace\">\
+         <strong>The last build failed, this page is outdated.</strong>\
+         <pre style=\"white-space:pre-wrap\">{}</pre></div>",
+        escaped
+    );
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], overlay, &html[end..]),
+        None => format!("{}{}", html, overlay),
+    }
+}
+
+/// A response to a request.
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// A response with a plain text body.
+    fn text(status: &'static str, text: &str) -> Self {
+        Response {
+            status,
+            headers: vec![("Content-Type", "text/plain; charset=utf-8".to_string())],
+            body: text.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Answer the request for the URL path with the file of the output.
+///
+/// Directories are answered with their `index.html`, requests for
+/// directories without a trailing slash are redirected. Pages get the error
+/// overlay if the diagnostics file is not empty.
+fn respond(root: &Path, diagnostics: &Path, url_path: &str) -> Response {
+    let Some(mut file) = resolve_file(root, url_path) else {
+        return Response::text("400 Bad Request", "Bad request");
+    };
+    if file.is_dir() {
+        let (path, query) = url_path.split_at(url_path.find(['?', '#']).unwrap_or(url_path.len()));
+        if !path.ends_with('/') {
+            return Response {
+                status: "301 Moved Permanently",
+                headers: vec![("Location", format!("{}/{}", path, query))],
+                body: Vec::new(),
+            };
+        }
+        file.push("index.html");
+    }
+    match std::fs::read(&file) {
+        Ok(body) => {
+            let content_type = content_type(&file);
+            let body = match std::fs::read_to_string(diagnostics) {
+                Ok(diagnostics) if content_type.starts_with("text/html") => {
+                    match diagnostics.trim() {
+                        "" => body,
+                        diagnostics => {
+                            with_overlay(&String::from_utf8_lossy(&body), diagnostics).into_bytes()
+                        }
+                    }
+                }
+                _ => body,
+            };
+            Response {
+                status: "200 OK",
+                headers: vec![("Content-Type", content_type.to_string())],
+                body,
+            }
+        }
+        Err(_) => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+/// Read the request from the stream and write the response.
+fn handle(
+    root: &Path,
+    diagnostics: &Path,
+    credentials: Option<&Credentials>,
+    stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?).take(MAX_HEAD_SIZE as u64);
+    let mut head = Vec::new();
+    loop {
+        let read = reader.read_until(b'\n', &mut head)?;
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+        if read == 0 {
+            return write_response(
+                stream,
+                &Response::text("431 Request Header Fields Too Large", ""),
+                false,
+            );
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (method, url_path) = (
+        request_line.next().unwrap_or_default(),
+        request_line.next().unwrap_or_default(),
+    );
+    let authorization = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("authorization")
+            .then_some(value.trim())
+    });
+
+    let response = match method {
+        _ if credentials.is_some_and(|credentials| !is_authorized(credentials, authorization)) => {
+            Response {
+                headers: vec![
+                    ("WWW-Authenticate", format!("Basic realm=\"{}\"", REALM)),
+                    ("Content-Type", "text/plain; charset=utf-8".to_string()),
+                ],
+                ..Response::text("401 Unauthorized", "Unauthorized")
+            }
+        }
+        "GET" | "HEAD" => respond(root, diagnostics, url_path),
+        _ => Response::text("405 Method Not Allowed", "Method not allowed"),
+    };
+    debug!("{} {} {}", method, url_path, response.status);
+    write_response(stream, &response, method != "HEAD")
+}
+
+/// Write the response and close the connection.
+fn write_response(mut stream: TcpStream, response: &Response, body: bool) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    stream.write_all(head.as_bytes())?;
+    if body {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()
+}
+
+/// Serve the output directory on the address until the process ends.
+///
+/// The diagnostics file holds the errors of the last failed build, which are
+/// shown on all pages while it exists.
+pub fn run(
+    output_path: &Path,
+    diagnostics: PathBuf,
+    addr: SocketAddr,
+    credentials: Option<Credentials>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).map_err(|e| Error::Serve(addr, e))?;
+    let addr = listener.local_addr().map_err(|e| Error::Serve(addr, e))?;
+    match &credentials {
+        Some(credentials) => info!(
+            "Serving {} at http://{}/ for user {}",
+            output_path.display(),
+            addr,
+            credentials.user
+        ),
+        None => info!("Serving {} at http://{}/", output_path.display(), addr),
+    }
+
+    let root = Arc::new(output_path.to_path_buf());
+    let diagnostics = Arc::new(diagnostics);
+    let credentials = Arc::new(credentials);
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Accepting a connection failed: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+        {
+            debug!("Setting the timeouts of a connection failed: {}", e);
+            continue;
+        }
+        if connections.fetch_add(1, Ordering::AcqRel) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::AcqRel);
+            let response = Response::text("503 Service Unavailable", "Too many connections");
+            if let Err(e) = write_response(stream, &response, true) {
+                debug!("Refusing a connection failed: {}", e);
+            }
+            continue;
+        }
+        let (root, diagnostics, credentials, connections) = (
+            root.clone(),
+            diagnostics.clone(),
+            credentials.clone(),
+            connections.clone(),
+        );
+        std::thread::spawn(move || {
+            if let Err(e) = handle(&root, &diagnostics, credentials.as_ref().as_ref(), stream) {
+                debug!("Answering a request failed: {}", e);
+            }
+            connections.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_credentials() {
+        assert_eq!(
+            Ok(Credentials {
+                user: "alice".to_string(),
+                password: "se:cret".to_string()
+            }),
+            parse_credentials("alice:se:cret")
+        );
+        assert!(parse_credentials("alice").is_err());
+        assert!(parse_credentials(":secret").is_err());
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let credentials = parse_credentials("alice:secret").unwrap();
+        assert!(is_authorized(&credentials, Some("Basic YWxpY2U6c2VjcmV0")));
+        assert!(!is_authorized(&credentials, Some("Basic YWxpY2U6c2VjcmV1")));
+        assert!(!is_authorized(
+            &credentials,
+            Some("Bearer YWxpY2U6c2VjcmV0")
+        ));
+        assert!(!is_authorized(&credentials, None));
+    }
+
+    #[test]
+    fn test_resolve_file() {
+        let root = Path::new("_site");
+        assert_eq!(
+            Some(PathBuf::from("_site/blog/crème/")),
+            resolve_file(root, "/blog/cr%C3%A8me/?x=1")
+        );
+        assert_eq!(None, resolve_file(root, "/blog/../../etc/passwd"));
+        assert_eq!(None, resolve_file(root, "/%2e%2e/secret"));
+    }
+
+    #[test]
+    fn test_respond_redirect() {
+        let root = std::env::temp_dir().join(format!("fweb-redirect-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("blog")).unwrap();
+        let diagnostics = root.with_extension("txt");
+
+        for (url_path, location) in [
+            ("/blog", "/blog/"),
+            ("/blog?page=2", "/blog/?page=2"),
+            ("/blog#top", "/blog/#top"),
+        ] {
+            let response = respond(&root, &diagnostics, url_path);
+            assert_eq!("301 Moved Permanently", response.status);
+            assert_eq!(vec![("Location", location.to_string())], response.headers);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_with_overlay() {
+        let html = with_overlay("<body><p>Page</p></body>", "a < b");
+        assert!(html.starts_with("<body><p>Page</p><div"));
+        assert!(html.contains("<pre style=\"white-space:pre-wrap\">a &lt; b</pre>"));
+        assert!(html.ends_with("</div></body>"));
+        assert!(with_overlay("<p>Page</p>", "c").starts_with("<p>Page</p><div"));
+    }
+
+    #[test]
+    fn test_respond_overlay() {
+        let root = std::env::temp_dir().join(format!("fweb-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("index.html"), "<body></body>").unwrap();
+        std::fs::write(root.join("style.css"), "body {}").unwrap();
+        let diagnostics = root.with_extension("txt");
+
+        assert_eq!(
+            b"<body></body>",
+            &respond(&root, &diagnostics, "/").body[..]
+        );
+        std::fs::write(&diagnostics, "Build failed").unwrap();
+        let page = String::from_utf8(respond(&root, &diagnostics, "/").body).unwrap();
+        assert!(page.contains("<pre style=\"white-space:pre-wrap\">Build failed</pre>"));
+        // Other files and the output are left as they are
+        assert_eq!(
+            b"body {}",
+            &respond(&root, &diagnostics, "/style.css").body[..]
+        );
+        assert_eq!(
+            "<body></body>",
+            std::fs::read_to_string(root.join("index.html")).unwrap()
+        );
+
+        std::fs::remove_file(&diagnostics).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! This module keeps a registry of short ids of pages in the state directory
+//! and generates the pages redirecting from the short links to the pages.
+//!
+//! Once assigned, the id of a page never changes, so short links stay valid
+//! when the page is moved to another URL. The link of a page is found by its
+//! URL, by one of its aliases, which keep the URLs it was moved from, or by
+//! its source file, so neither moving the file nor changing the URL loses it.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, ShortLinksConfig},
+    error::{Error, Result},
+    redirects::redirect_html,
+    state::{StateDir, Store},
+    template::content_hash,
+    Cli, Index,
+};
+
+/// File name of the registry in the registry store.
+const REGISTRY_FILE: &str = "short_links.toml";
+
+/// Number of characters of a short id.
+const ID_LEN: usize = 6;
+
+/// The registry of all short links ever assigned.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    /// Links by their short id.
+    #[serde(default)]
+    links: BTreeMap<String, Link>,
+}
+
+/// The page a short link points to.
+#[derive(Debug, Serialize, Deserialize)]
+struct Link {
+    /// Path of the markdown file of the page relative to `content/`.
+    source: PathBuf,
+    /// URL path of the page at the last build.
+    url: String,
+}
+
+/// URL path of the short link with the id.
+fn short_url(short_links: &ShortLinksConfig, id: &str) -> String {
+    format!("/{}/{}/", short_links.path.display(), id)
+}
+
+/// Create a new id for the source which is not yet taken.
+fn new_id(registry: &Registry, source: &Path) -> String {
+    (0u32..)
+        .map(|attempt| {
+            let hash = content_hash(format!("{}#{}", source.display(), attempt).as_bytes());
+            hash[..ID_LEN].to_string()
+        })
+        .find(|id| !registry.links.contains_key(id))
+        .expect("there are enough ids")
+}
+
+/// Assign short links to all built pages and write the redirect pages.
+pub async fn export_short_links(
+    config: &Config,
+    short_links: &ShortLinksConfig,
+    opts: &Cli,
+    indices: &mut [Index],
+) -> Result<()> {
+    let (path, registry, unpublished) = assign(config, short_links, opts, indices).await?;
+    let content = toml::to_string(&registry).map_err(Error::SerializeRegistry)?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| Error::WriteFile(path, e))?;
+
+    // Links of removed pages keep pointing to their last URL
+    for (id, link) in &registry.links {
+        if unpublished.contains(id) {
+            continue;
+        }
+        let url = config.absolute_url(&link.url);
+        let html = redirect_html(&url);
+        let dir = config.output_path.join(&short_links.path).join(id.as_str());
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.clone(), e))?;
+        let path = dir.join("index.html");
+        tokio::fs::write(&path, html)
+            .await
+            .map_err(|e| Error::WriteFile(path, e))?;
+    }
+
+    Ok(())
+}
+
+/// Assign short links to all published pages without writing the registry
+/// or the redirect pages, e.g. to render a single page.
+pub async fn assign_short_links(
+    config: &Config,
+    short_links: &ShortLinksConfig,
+    opts: &Cli,
+    indices: &mut [Index],
+) -> Result<()> {
+    assign(config, short_links, opts, indices).await?;
+    Ok(())
+}
+
+/// URL path with a leading and a trailing slash, as aliases may be given
+/// without them.
+fn normalize_url(url: &str) -> String {
+    match url.trim_matches('/') {
+        "" => "/".to_string(),
+        path => format!("/{}/", path),
+    }
+}
+
+/// Read the registry and assign short links to all published pages.
+///
+/// Returns the path of the registry, the registry including the links of
+/// new pages and the ids of the links of unpublished pages.
+async fn assign(
+    config: &Config,
+    short_links: &ShortLinksConfig,
+    opts: &Cli,
+    indices: &mut [Index],
+) -> Result<(PathBuf, Registry, HashSet<String>)> {
+    let dir = StateDir::new(config).store(Store::Registry).await?;
+    let path = dir.join(REGISTRY_FILE);
+    let mut registry: Registry = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => {
+            toml::from_str(&content).map_err(|e| Error::ParseRegistry(path.clone(), e))?
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+        Err(e) => return Err(Error::ReadInput(path, e)),
+    };
+
+    // Assign ids and update the URLs of moved pages
+    let by_url: BTreeMap<String, String> = registry
+        .links
+        .iter()
+        .map(|(id, link)| (link.url.clone(), id.clone()))
+        .collect();
+    let by_source: BTreeMap<PathBuf, String> = registry
+        .links
+        .iter()
+        .map(|(id, link)| (link.source.clone(), id.clone()))
+        .collect();
+    let mut claimed = HashSet::new();
+    let mut unpublished = HashSet::new();
+    for page in indices.iter_mut().flat_map(|index| &mut index.pages) {
+        let source = page.metadata.filepath.clone();
+        let url = page.url();
+        let known = std::iter::once(url.clone())
+            .chain(
+                page.metadata
+                    .aliases
+                    .iter()
+                    .map(|alias| normalize_url(alias)),
+            )
+            .find_map(|url| by_url.get(&url))
+            .or_else(|| by_source.get(&source))
+            .filter(|id| !claimed.contains(*id))
+            .cloned();
+        if !page.is_published(opts) {
+            unpublished.extend(known);
+            continue;
+        }
+        let id = match known {
+            Some(id) => id,
+            None => {
+                let id = new_id(&registry, &source);
+                debug!("Assigning short id {} to {}", id, source.display());
+                id
+            }
+        };
+        claimed.insert(id.clone());
+        registry.links.insert(id.clone(), Link { source, url });
+        page.short_url = Some(short_url(short_links, &id));
+    }
+
+    Ok((path, registry, unpublished))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!("/old/post/", normalize_url("old/post"));
+        assert_eq!("/old/post/", normalize_url("/old/post/"));
+        assert_eq!("/", normalize_url("/"));
+    }
+}
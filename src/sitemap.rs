@@ -0,0 +1,106 @@
+//! This module generates a human-readable sitemap page listing all sections
+//! and their pages.
+
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::{
+    config::{Config, SitemapConfig},
+    error::Result,
+    template::{Context, Value},
+    write_page, Cli, Index,
+};
+
+/// Convert the sections into values for templating, ordered by their URL.
+///
+/// Every section has a `depth` of how deeply it is nested, the root section
+/// has a depth of zero. Cross-posted pages are left out since their original
+/// is located elsewhere, as are pages which should not be indexed.
+fn sections(config: &Config, opts: &Cli, indices: &[Index]) -> Vec<Value> {
+    let mut indices: Vec<_> = indices.iter().collect();
+    indices.sort_by_key(|index| index.url());
+    indices
+        .into_iter()
+        .map(|index| {
+            let url = index.url();
+            let depth = url.split('/').filter(|part| !part.is_empty()).count();
+            let pages: Vec<_> = index
+                .pages
+                .iter()
+                .filter(|page| page.is_published(opts))
+                .filter(|page| page.metadata.canonical.is_none() && !page.metadata.noindex)
+                .map(|page| page.to_value(config))
+                .collect();
+            Value::Map(HashMap::from([
+                ("title".to_string(), index.metadata.title.as_str().into()),
+                ("url".to_string(), config.url_path(&url).into()),
+                ("depth".to_string(), (depth as i64).into()),
+                ("pages".to_string(), pages.into()),
+            ]))
+        })
+        .collect()
+}
+
+/// Write the sitemap page.
+pub async fn export_sitemap(
+    config: &Config,
+    sitemap: &SitemapConfig,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+) -> Result<()> {
+    debug!("Building sitemap at /{}/", sitemap.path.display());
+
+    let mut ctx = ctx.clone();
+    ctx.insert("title", sitemap.title.as_str());
+    ctx.insert(
+        "sitemap",
+        Value::from_iter([
+            ("url", Value::from(format!("/{}/", sitemap.path.display()))),
+            ("sections", sections(config, opts, indices).into()),
+        ]),
+    );
+    let dir = config.output_path.join(&sitemap.path);
+    write_page(config, &ctx, &sitemap.template, &dir, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[tokio::test]
+    async fn test_sections() {
+        let config = Config {
+            content_path: std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/sites/basic"),
+            ..dummy_config()
+        };
+        let opts = <Cli as clap::Parser>::parse_from(["fweb", "--drafts"]);
+        let indices = crate::load_and_parse_content(&config, &Context::new(), &opts)
+            .await
+            .unwrap();
+
+        let sections = sections(&config, &opts, &indices);
+        assert_eq!(3, sections.len());
+        let Value::Map(blog) = &sections[1] else {
+            panic!("section is a map");
+        };
+        assert_eq!(Value::from("/blog/"), blog["url"]);
+        assert_eq!(Value::from(1), blog["depth"]);
+        let Value::List(pages) = &blog["pages"] else {
+            panic!("pages are a list");
+        };
+        let titles: Vec<_> = pages
+            .iter()
+            .filter_map(|page| match page {
+                Value::Map(page) => page.get("title"),
+                _ => None,
+            })
+            .collect();
+        // Drafts are listed when built, cross-posted pages never
+        assert!(titles.contains(&&Value::from("Unfinished")));
+        assert!(!titles.contains(&&Value::from("Old post")));
+    }
+}
@@ -0,0 +1,99 @@
+//! This module normalizes ids, section directories and taxonomy terms into
+//! the URL friendly form given by the slug policy of the config.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::SlugConfig;
+
+/// Create the URL friendly form of a text.
+///
+/// Runs of other characters than letters and digits are replaced by a single
+/// hyphen and removed at the start and end.
+pub fn slugify(config: &SlugConfig, text: &str) -> String {
+    let text = if config.unicode {
+        text.to_string()
+    } else {
+        deunicode::deunicode(text)
+    };
+
+    let mut slug = String::with_capacity(text.len());
+    let mut chars = 0;
+    for c in text.trim().chars() {
+        if config.max_length.is_some_and(|max| chars >= max) {
+            break;
+        }
+        if c.is_alphanumeric() {
+            if config.preserve_case {
+                slug.push(c);
+            } else {
+                slug.extend(c.to_lowercase());
+            }
+            chars += 1;
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+            chars += 1;
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// Normalize every directory of the path relative to `content/`, e.g. of a
+/// section, into the directory of its URL.
+///
+/// Returns `None` if a directory has no characters left.
+pub fn slugify_dir(config: &SlugConfig, dir: &Path) -> Option<PathBuf> {
+    let mut url_dir = PathBuf::new();
+    for component in dir.components() {
+        if let Component::Normal(name) = component {
+            let slug = slugify(config, &name.to_string_lossy());
+            if slug.is_empty() {
+                return None;
+            }
+            url_dir.push(slug);
+        }
+    }
+    Some(url_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        let config = SlugConfig::default();
+        assert_eq!("rust", slugify(&config, "Rust"));
+        assert_eq!(
+            "static-site-generators",
+            slugify(&config, " Static site  generators! ")
+        );
+        assert_eq!("c", slugify(&config, "C++"));
+        assert_eq!("creme-brulee", slugify(&config, "Crème brûlée"));
+    }
+
+    #[test]
+    fn test_slugify_policy() {
+        let mut config = SlugConfig {
+            unicode: true,
+            preserve_case: true,
+            max_length: None,
+        };
+        assert_eq!("Crème-brûlée", slugify(&config, "Crème brûlée!"));
+        config.max_length = Some(10);
+        assert_eq!("Crème-brûl", slugify(&config, "Crème brûlée"));
+        config.max_length = Some(6);
+        assert_eq!("Crème", slugify(&config, "Crème brûlée"));
+    }
+
+    #[test]
+    fn test_slugify_dir() {
+        let config = SlugConfig::default();
+        assert_eq!(
+            Some(PathBuf::from("blog/creme-brulee")),
+            slugify_dir(&config, Path::new("Blog/Crème brûlée"))
+        );
+        assert_eq!(Some(PathBuf::new()), slugify_dir(&config, Path::new("")));
+        assert_eq!(None, slugify_dir(&config, Path::new("blog/!!")));
+    }
+}
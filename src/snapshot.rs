@@ -0,0 +1,302 @@
+//! This module keeps snapshots of the outputs of the last builds in the state
+//! directory, so `fweb rollback` can restore the output of the previous build
+//! when a broken build was published.
+//!
+//! Every snapshot is a directory named by its sequence number, containing a
+//! copy of the output and a manifest with the content hashes of its files.
+//! The latest snapshot is the output currently published. Rolling back
+//! publishes the snapshot before it and drops the latest one, so rolling back
+//! again steps further back.
+//!
+//! Files unchanged since the previous snapshot are hard links to its files,
+//! so snapshots only take space for the changed files. Snapshots are never
+//! modified after they were taken, which keeps shared files intact.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config,
+    daemon,
+    error::{Error, Result},
+    format_date_iso8601, list_files,
+    state::{StateDir, Store},
+    template::content_hash,
+};
+
+/// Directory within a snapshot containing the copy of the output.
+const OUTPUT_DIR: &str = "output";
+
+/// File within a snapshot describing it.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Description of a snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Time the snapshot was taken in ISO 8601.
+    pub created: String,
+    /// Content hashes of the files by their path relative to the output.
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// Sequence number of the snapshot with the directory name, `None` for other
+/// entries, e.g. partially written snapshots.
+fn parse_id(name: &str) -> Option<u64> {
+    match name.bytes().all(|b| b.is_ascii_digit()) {
+        true => name.parse().ok(),
+        false => None,
+    }
+}
+
+/// Name of the directory of the snapshot with the sequence number.
+fn dir_name(id: u64) -> String {
+    format!("{:06}", id)
+}
+
+/// Sequence numbers of all snapshots in the directory, from the oldest one.
+async fn snapshot_ids(dir: &Path) -> Result<Vec<u64>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .map_err(|e| Error::ReadInput(dir.to_path_buf(), e))?;
+    let mut ids = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| Error::ReadInput(dir.to_path_buf(), e))?
+    {
+        if let Some(id) = entry.file_name().to_str().and_then(parse_id) {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Copy all files of the directory and return their content hashes.
+///
+/// Files with the same content hash in the previous snapshot, given by its
+/// directory and manifest, are hard linked to its files instead. If linking
+/// fails, e.g. on file systems without hard links, they are copied.
+async fn copy_files(
+    from: &Path,
+    to: &Path,
+    previous: Option<(&Path, &Manifest)>,
+) -> Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    for relpath in list_files(from).await? {
+        let (source, target) = (from.join(&relpath), to.join(&relpath));
+        let content = tokio::fs::read(&source)
+            .await
+            .map_err(|e| Error::ReadInput(source, e))?;
+        let dir = target.parent().expect("is a file");
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.to_path_buf(), e))?;
+        let hash = content_hash(&content);
+        let unchanged =
+            previous.filter(|(_, manifest)| manifest.files.get(&relpath) == Some(&hash));
+        let linked = match unchanged {
+            Some((snapshot, _)) => {
+                let original = snapshot.join(OUTPUT_DIR).join(&relpath);
+                tokio::fs::hard_link(&original, &target).await.is_ok()
+            }
+            None => false,
+        };
+        if !linked {
+            tokio::fs::write(&target, &content)
+                .await
+                .map_err(|e| Error::WriteFile(target, e))?;
+        }
+        files.insert(relpath, hash);
+    }
+    Ok(files)
+}
+
+/// Read the manifest of the snapshot, `None` if it is missing or unreadable.
+async fn read_manifest(snapshot: &Path) -> Option<Manifest> {
+    let json = tokio::fs::read_to_string(snapshot.join(MANIFEST_FILE))
+        .await
+        .ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Remove the directory if it exists.
+async fn remove_dir(path: &Path) -> Result<()> {
+    tokio::fs::remove_dir_all(path)
+        .await
+        .or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(Error::StateClean(path.to_path_buf(), e)),
+        })
+}
+
+/// Take a snapshot of the output if snapshots are enabled, removing the
+/// snapshots beyond the configured number.
+pub async fn take_snapshot(config: &Config) -> Result<()> {
+    if config.snapshots == 0 {
+        return Ok(());
+    }
+    let dir = StateDir::new(config).store(Store::Snapshots).await?;
+    let ids = snapshot_ids(&dir).await?;
+    let id = ids.last().map_or(1, |id| id + 1);
+
+    // Written next to the snapshots first, so a failed copy is never taken
+    // for a snapshot
+    let partial = dir.join(format!("{}.partial", dir_name(id)));
+    remove_dir(&partial).await?;
+    let previous = ids.last().map(|id| dir.join(dir_name(*id)));
+    let previous_manifest = match &previous {
+        Some(previous) => read_manifest(previous).await,
+        None => None,
+    };
+    let previous = previous.as_deref().zip(previous_manifest.as_ref());
+    let manifest = Manifest {
+        created: format_date_iso8601(&OffsetDateTime::now_utc()),
+        files: copy_files(&config.output_path, &partial.join(OUTPUT_DIR), previous).await?,
+    };
+    let manifest_path = partial.join(MANIFEST_FILE);
+    let json = serde_json::to_string_pretty(&manifest).expect("manifest is always serializable");
+    tokio::fs::write(&manifest_path, json)
+        .await
+        .map_err(|e| Error::WriteFile(manifest_path, e))?;
+    let snapshot = dir.join(dir_name(id));
+    tokio::fs::rename(&partial, &snapshot)
+        .await
+        .map_err(|e| Error::WriteFile(snapshot, e))?;
+    debug!("Took snapshot {} of {} files", id, manifest.files.len());
+
+    let stale = ids.len().saturating_sub(config.snapshots - 1);
+    for id in &ids[..stale] {
+        remove_dir(&dir.join(dir_name(*id))).await?;
+    }
+    Ok(())
+}
+
+/// Read the manifest of the snapshot and ensure its files are unchanged.
+async fn verify_snapshot(snapshot: &Path) -> Result<Manifest> {
+    let damaged = || Error::DamagedSnapshot(snapshot.to_path_buf());
+    let manifest_path = snapshot.join(MANIFEST_FILE);
+    let json = tokio::fs::read_to_string(&manifest_path)
+        .await
+        .map_err(|e| Error::ReadInput(manifest_path, e))?;
+    let manifest: Manifest = serde_json::from_str(&json).map_err(|_| damaged())?;
+
+    let output = snapshot.join(OUTPUT_DIR);
+    let files = list_files(&output).await?;
+    if !files.iter().eq(manifest.files.keys()) {
+        return Err(damaged());
+    }
+    for (relpath, hash) in &manifest.files {
+        let path = output.join(relpath);
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(|e| Error::ReadInput(path, e))?;
+        if content_hash(&content) != *hash {
+            return Err(damaged());
+        }
+    }
+    Ok(manifest)
+}
+
+/// Publish the output of the snapshot before the latest one and drop the
+/// latest one.
+///
+/// The output is replaced like a build of the daemon, see
+/// [`daemon::publish`]. The latest snapshot is only dropped once the output
+/// is published, so if rolling back is interrupted in between, it is kept
+/// and the next roll back publishes the same snapshot again.
+///
+/// Returns the manifest of the published snapshot.
+pub async fn rollback(config: &Config) -> Result<Manifest> {
+    let dir = StateDir::new(config).store(Store::Snapshots).await?;
+    let ids = snapshot_ids(&dir).await?;
+    let [.., previous, latest] = ids.as_slice() else {
+        return Err(Error::NoSnapshot(config.site_info.title.clone()));
+    };
+    let snapshot = dir.join(dir_name(*previous));
+    let manifest = verify_snapshot(&snapshot).await?;
+
+    // The snapshot is kept, as it is the latest one afterwards
+    let staging_path = daemon::sibling_path(&config.output_path, ".rollback");
+    remove_dir(&staging_path).await?;
+    tokio::fs::create_dir_all(&staging_path)
+        .await
+        .map_err(|e| Error::CreateDirectory(staging_path.clone(), e))?;
+    copy_files(&snapshot.join(OUTPUT_DIR), &staging_path, None).await?;
+    daemon::publish(&staging_path, &config.output_path).await?;
+    remove_dir(&dir.join(dir_name(*latest))).await?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(Some(12), parse_id(&dir_name(12)));
+        assert_eq!(None, parse_id("000012.partial"));
+        assert_eq!(None, parse_id(""));
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let output_path =
+            std::env::temp_dir().join(format!("fweb-snapshot-{}", std::process::id()));
+        let config = Config {
+            state_path: output_path.with_extension("state"),
+            output_path,
+            snapshots: 2,
+            ..dummy_config()
+        };
+        let index = config.output_path.join("index.html");
+        let build = |content: &'static str| {
+            let config = &config;
+            let index = &index;
+            async move {
+                tokio::fs::create_dir_all(&config.output_path)
+                    .await
+                    .unwrap();
+                tokio::fs::write(index, content).await.unwrap();
+                take_snapshot(config).await.unwrap();
+            }
+        };
+
+        build("first").await;
+        build("second").await;
+        build("third").await;
+        rollback(&config).await.unwrap();
+        assert_eq!("second", tokio::fs::read_to_string(&index).await.unwrap());
+        // Only two snapshots were kept
+        assert!(matches!(rollback(&config).await, Err(Error::NoSnapshot(_))));
+
+        // Unchanged files are shared with the previous snapshot
+        build("second").await;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let dir = StateDir::new(&config)
+                .store(Store::Snapshots)
+                .await
+                .unwrap();
+            let ids = snapshot_ids(&dir).await.unwrap();
+            let latest = dir.join(dir_name(*ids.last().unwrap()));
+            let file = latest.join(OUTPUT_DIR).join("index.html");
+            assert_eq!(2, tokio::fs::metadata(file).await.unwrap().nlink());
+        }
+        rollback(&config).await.unwrap();
+        assert_eq!("second", tokio::fs::read_to_string(&index).await.unwrap());
+
+        tokio::fs::remove_dir_all(&config.output_path)
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&config.state_path).await.unwrap();
+    }
+}
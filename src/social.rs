@@ -0,0 +1,117 @@
+//! This module generates Open Graph and Twitter Card meta tags of pages, so
+//! links shared on social networks show a preview.
+//!
+//! The tags are derived from the metadata of the page. Pages can override the
+//! title, description, image and card type in an `[extra.social]` table, e.g.
+//! to show a different image than on the page. Twitter falls back to the Open
+//! Graph tags, so only the card type and the handle of the site are given for
+//! it.
+
+use pulldown_cmark::escape::escape_html;
+
+use crate::{config::SocialConfig, links, Config, Page};
+
+/// Value of the `[extra.social]` table of the page.
+fn override_value<'a>(page: &'a Page, key: &str) -> Option<&'a str> {
+    page.metadata.extra.get("social")?.get(key)?.as_str()
+}
+
+/// Absolute URL of an image given as a URL, an absolute URL path or a path
+/// relative to the page.
+fn image_url(config: &Config, page: &Page, image: &str) -> String {
+    if links::has_scheme(image) || image.starts_with("//") {
+        image.to_string()
+    } else if image.starts_with('/') {
+        config.absolute_url(image)
+    } else {
+        config.absolute_url(&format!("{}{}", page.url(), image))
+    }
+}
+
+/// Append a meta tag with the attribute naming it and its content.
+fn push_meta(html: &mut String, attribute: &str, name: &str, content: &str) {
+    html.push_str(&format!("<meta {}=\"{}\" content=\"", attribute, name));
+    escape_html(&mut *html, content).expect("writing to a string never fails");
+    html.push_str("\">");
+}
+
+/// Meta tags of the page for social networks.
+pub fn social_meta(config: &Config, social: &SocialConfig, page: &Page) -> String {
+    let title = override_value(page, "title").unwrap_or(&page.metadata.title);
+    let description = override_value(page, "description")
+        .map(str::to_string)
+        .unwrap_or_else(|| page.description());
+    let image = override_value(page, "image").or(social.image.as_deref());
+
+    let mut html = String::new();
+    push_meta(&mut html, "property", "og:type", "article");
+    push_meta(
+        &mut html,
+        "property",
+        "og:site_name",
+        &config.site_info.title,
+    );
+    push_meta(&mut html, "property", "og:title", title);
+    push_meta(&mut html, "property", "og:description", &description);
+    push_meta(&mut html, "property", "og:url", &page.canonical(config));
+    if let Some(image) = image {
+        let url = image_url(config, page, image);
+        push_meta(&mut html, "property", "og:image", &url);
+    }
+    if let Some(lang) = &page.metadata.lang {
+        push_meta(&mut html, "property", "og:locale", &lang.replace('-', "_"));
+    }
+    if let Some(date) = &page.metadata.date {
+        let published = crate::format_date_iso8601(date);
+        push_meta(&mut html, "property", "article:published_time", &published);
+    }
+    if let Some(updated) = &page.metadata.updated {
+        let modified = crate::format_date_iso8601(updated);
+        push_meta(&mut html, "property", "article:modified_time", &modified);
+    }
+
+    let card = match image {
+        Some(_) => "summary_large_image",
+        None => "summary",
+    };
+    let card = override_value(page, "card").unwrap_or(card);
+    push_meta(&mut html, "name", "twitter:card", card);
+    if let Some(site) = &social.twitter_site {
+        push_meta(&mut html, "name", "twitter:site", site);
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[tokio::test]
+    async fn test_image_url() {
+        let config = Config {
+            base_url: Some("https://example.com/".to_string()),
+            ..dummy_config()
+        };
+        let page = Page::parse(
+            "+++\ntitle = \"Page\"\n+++\n",
+            std::path::Path::new("blog/page.md"),
+            &config,
+            &crate::template::Context::new(),
+            &toml::Table::new(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        for (image, url) in [
+            ("https://cdn.example/a.png", "https://cdn.example/a.png"),
+            ("//cdn.example/a.png", "//cdn.example/a.png"),
+            ("/logo.png", "https://example.com/logo.png"),
+            ("cover.png", "https://example.com/blog/page/cover.png"),
+            ("img/a:b.png", "https://example.com/blog/page/img/a:b.png"),
+        ] {
+            assert_eq!(url, image_url(&config, &page, image));
+        }
+    }
+}
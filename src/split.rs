@@ -0,0 +1,209 @@
+//! This module splits long pages into parts at their headings, e.g. to
+//! publish a book-length document with a page per chapter.
+//!
+//! Pages are split at the headings on the top level of the content up to
+//! the level given as `split` in the frontmatter. The markdown is marked
+//! before these headings and the rendered HTML is split at the marks when
+//! the page is written. The first part keeps the URL of the page, the others
+//! are written to `part/<n>/` below it.
+
+use std::{collections::HashMap, path::Path};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::{
+    config::Config,
+    error::Result,
+    fill_page_context,
+    template::{Context, Value},
+    write_page, Page,
+};
+
+/// Comment marking the start of a part in the HTML of a page.
+const PART_MARKER: &str = "<!--fweb:part-->";
+
+/// Directory of the further parts below the page.
+const PART_DIR: &str = "part";
+
+/// Headings on the top level of the markdown up to the level with their
+/// offset and text.
+fn split_headings(markdown: &str, level: u8, options: Options) -> Vec<(usize, String)> {
+    let mut headings = Vec::new();
+    let mut depth = 0usize;
+    // Heading whose text is collected
+    let mut heading: Option<(usize, String)> = None;
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(heading_level, ..))
+                if depth == 0 && heading_level as u8 <= level =>
+            {
+                heading = Some((range.start, String::new()));
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    headings.extend(heading.take());
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, title)) = &mut heading {
+                    title.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// Mark the start of every part but the first one in the markdown.
+pub fn mark_parts(markdown: &str, level: u8, options: Options) -> String {
+    let mut marked = String::with_capacity(markdown.len());
+    let mut position = 0;
+    for (offset, _) in split_headings(markdown, level, options) {
+        if markdown[..offset].trim().is_empty() {
+            continue;
+        }
+        marked.push_str(&markdown[position..offset]);
+        marked.push_str(PART_MARKER);
+        marked.push_str("\n\n");
+        position = offset;
+    }
+    marked.push_str(&markdown[position..]);
+    marked
+}
+
+/// Titles of the parts of the page, empty if the page is not split.
+///
+/// Parts are titled by the heading they start with, the text before the
+/// first heading by the title of the page.
+pub fn part_titles(markdown: &str, level: u8, options: Options, title: &str) -> Vec<String> {
+    let headings = split_headings(markdown, level, options);
+    let mut titles = Vec::with_capacity(headings.len() + 1);
+    match headings.first() {
+        None => return titles,
+        Some((offset, _)) if markdown[..*offset].trim().is_empty() => {}
+        Some(_) => titles.push(title.to_string()),
+    }
+    titles.extend(headings.into_iter().map(|(_, text)| text));
+    titles
+}
+
+/// Absolute URL path of the part with the number, counted from 1, of the
+/// page at the URL path.
+fn part_url(url: &str, number: usize) -> String {
+    match number {
+        1 => url.to_string(),
+        _ => format!("{}{}/{}/", url, PART_DIR, number),
+    }
+}
+
+/// Convert a part into a map for templating.
+fn part_map(config: &Config, page: &Page, number: usize) -> HashMap<String, Value> {
+    HashMap::from([
+        ("number".to_string(), Value::from(number as i64)),
+        ("title".to_string(), page.parts[number - 1].as_str().into()),
+        (
+            "url".to_string(),
+            config.url_path(&part_url(&page.url(), number)).into(),
+        ),
+    ])
+}
+
+/// Write every part of the split page to its own directory below the one of
+/// the page.
+///
+/// Templates get the current `part` with the previous and next one and all
+/// `parts` for a table of contents.
+pub async fn write_parts(config: &Config, ctx: &Context, page: &Page, dir: &Path) -> Result<()> {
+    let parts: Vec<_> = (1..=page.parts.len())
+        .map(|number| part_map(config, page, number))
+        .collect();
+    for (i, html) in page.html.split(PART_MARKER).enumerate() {
+        let number = i + 1;
+        if number > parts.len() {
+            break;
+        }
+        let mut part_page = page.clone();
+        part_page.html = html.trim_start().to_string();
+        let mut part_ctx = ctx.clone();
+        fill_page_context(config, &mut part_ctx, &part_page);
+
+        let mut part = parts[i].clone();
+        if let Some(previous) = i.checked_sub(1).map(|i| &parts[i]) {
+            part.insert("previous".to_string(), Value::Map(previous.clone()));
+        }
+        if let Some(next) = parts.get(i + 1) {
+            part.insert("next".to_string(), Value::Map(next.clone()));
+        }
+        part_ctx.insert("part", Value::Map(part));
+        part_ctx.insert(
+            "parts",
+            parts
+                .iter()
+                .enumerate()
+                .map(|(j, part)| {
+                    let mut part = part.clone();
+                    part.insert("current".to_string(), (i == j).into());
+                    Value::Map(part)
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let part_dir = match number {
+            1 => dir.to_path_buf(),
+            _ => dir.join(PART_DIR).join(number.to_string()),
+        };
+        let protected = page.metadata.protected;
+        write_page(
+            config,
+            &part_ctx,
+            &page.metadata.template,
+            &part_dir,
+            protected,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "Preface\n\n# One\n\nText\n\n## Section\n\n> # Quoted\n\n# Two `code`\n";
+
+    #[test]
+    fn test_mark_parts() {
+        assert_eq!(
+            "Preface\n\n<!--fweb:part-->\n\n# One\n\nText\n\n## Section\n\n> # Quoted\n\n\
+             <!--fweb:part-->\n\n# Two `code`\n",
+            mark_parts(BOOK, 1, Options::empty())
+        );
+        assert_eq!(
+            "# One\n\n<!--fweb:part-->\n\n# Two\n",
+            mark_parts("# One\n\n# Two\n", 1, Options::empty())
+        );
+    }
+
+    #[test]
+    fn test_part_titles() {
+        assert_eq!(
+            vec!["Book", "One", "Section", "Two code"],
+            part_titles(BOOK, 2, Options::empty(), "Book")
+        );
+        assert_eq!(
+            vec!["One", "Two"],
+            part_titles("# One\n\n# Two\n", 1, Options::empty(), "Book")
+        );
+        assert!(part_titles("Text\n\n## Section", 1, Options::empty(), "Book").is_empty());
+    }
+
+    #[test]
+    fn test_part_url() {
+        assert_eq!("/book/", part_url("/book/", 1));
+        assert_eq!("/book/part/3/", part_url("/book/", 3));
+    }
+}
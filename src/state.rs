@@ -0,0 +1,172 @@
+//! This module manages the state directory of a site, by default `.fweb/`
+//! next to the config.
+//!
+//! Features which cache data between builds keep it in one of the stores
+//! within the state directory, so it can be inspected and cleaned in one
+//! place.
+
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    list_files,
+};
+
+/// A store within the state directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    /// Results of previous builds.
+    Build,
+    /// Derived images, e.g. resized variants.
+    Images,
+    /// Registries which must survive cleaning, e.g. short links.
+    Registry,
+    /// Outputs of previous builds to roll back to.
+    Snapshots,
+}
+
+impl Store {
+    /// All stores.
+    pub const ALL: [Store; 4] = [
+        Store::Build,
+        Store::Images,
+        Store::Registry,
+        Store::Snapshots,
+    ];
+
+    /// Name of the directory of the store.
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Store::Build => "build",
+            Store::Images => "images",
+            Store::Registry => "registry",
+            Store::Snapshots => "snapshots",
+        }
+    }
+
+    /// Whether the store only holds data which can be recreated.
+    pub fn is_cache(&self) -> bool {
+        !matches!(self, Store::Registry | Store::Snapshots)
+    }
+}
+
+/// Size of a store.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Number of files.
+    pub files: usize,
+    /// Total size of the files in bytes.
+    pub bytes: u64,
+}
+
+/// The state directory of a site.
+#[derive(Debug, Clone)]
+pub struct StateDir {
+    path: PathBuf,
+}
+
+impl StateDir {
+    /// State directory of the site with the config.
+    pub fn new(config: &Config) -> Self {
+        StateDir {
+            path: config.state_path.clone(),
+        }
+    }
+
+    /// Directory of the store, created if it does not exist.
+    pub async fn store(&self, store: Store) -> Result<PathBuf> {
+        let dir = self.path.join(store.dir_name());
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| Error::CreateDirectory(dir.clone(), e))?;
+        Ok(dir)
+    }
+
+    /// Remove all stores which are caches.
+    pub async fn clean(&self) -> Result<()> {
+        for store in Store::ALL.into_iter().filter(Store::is_cache) {
+            self.clean_store(store).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a single store.
+    pub async fn clean_store(&self, store: Store) -> Result<()> {
+        let dir = self.path.join(store.dir_name());
+        debug!("Removing {}", dir.display());
+        tokio::fs::remove_dir_all(&dir)
+            .await
+            .or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(Error::StateClean(dir, e)),
+            })
+    }
+
+    /// Number of files and their size of every store.
+    pub async fn stats(&self) -> Result<Vec<(Store, StoreStats)>> {
+        let mut stats = Vec::with_capacity(Store::ALL.len());
+        for store in Store::ALL {
+            let dir = self.path.join(store.dir_name());
+            let mut store_stats = StoreStats::default();
+            if dir.is_dir() {
+                for file in list_files(&dir).await? {
+                    let path = dir.join(file);
+                    let metadata = tokio::fs::metadata(&path)
+                        .await
+                        .map_err(|e| Error::ReadInput(path, e))?;
+                    store_stats.files += 1;
+                    store_stats.bytes += metadata.len();
+                }
+            }
+            stats.push((store, store_stats));
+        }
+        Ok(stats)
+    }
+
+    /// Path of the state directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[tokio::test]
+    async fn test_stats_and_clean() {
+        let config = Config {
+            state_path: std::env::temp_dir().join(format!("fweb-state-{}", std::process::id())),
+            ..dummy_config()
+        };
+        let state = StateDir::new(&config);
+
+        let build = state.store(Store::Build).await.unwrap();
+        tokio::fs::write(build.join("a"), "abc").await.unwrap();
+        tokio::fs::create_dir_all(build.join("nested"))
+            .await
+            .unwrap();
+        tokio::fs::write(build.join("nested/b"), "de")
+            .await
+            .unwrap();
+        let registry = state.store(Store::Registry).await.unwrap();
+        tokio::fs::write(registry.join("links"), "x").await.unwrap();
+
+        let stats = state.stats().await.unwrap();
+        assert_eq!(Store::ALL.len(), stats.len());
+        assert_eq!((Store::Build, StoreStats { files: 2, bytes: 5 }), stats[0]);
+        assert_eq!((Store::Images, StoreStats::default()), stats[1]);
+
+        // Only caches are cleaned, missing stores are no error
+        state.clean().await.unwrap();
+        let build_exists = build.exists();
+        let registry_exists = registry.join("links").exists();
+        tokio::fs::remove_dir_all(state.path()).await.unwrap();
+        assert!(!build_exists);
+        assert!(registry_exists);
+    }
+}
@@ -0,0 +1,133 @@
+//! This module generates schema.org structured data of pages and the homepage
+//! as JSON-LD, so search engines can show e.g. the dates and authors of
+//! articles.
+
+use serde::Serialize;
+
+use crate::{authors::author_url, format_date_iso8601, Config, Page};
+
+/// URL of the schema.org vocabulary.
+const SCHEMA_ORG: &str = "https://schema.org";
+
+/// A person or organization as a schema.org value.
+#[derive(Debug, Serialize)]
+struct Agent<'a> {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+/// A schema.org `Article`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Article<'a> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    headline: &'a str,
+    description: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_modified: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    author: Vec<Agent<'a>>,
+    publisher: Agent<'a>,
+}
+
+/// A schema.org `WebSite`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebSite<'a> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    name: &'a str,
+    description: &'a str,
+    url: String,
+    in_language: &'a str,
+}
+
+/// Wrap the value in a script element.
+///
+/// Slashes of closing tags are escaped, so text of the site cannot end the
+/// script.
+fn script(value: &impl Serialize) -> String {
+    let json = serde_json::to_string(value)
+        .expect("structured data is always serializable")
+        .replace("</", "<\\/");
+    format!("<script type=\"application/ld+json\">{}</script>", json)
+}
+
+/// The site as its publisher.
+fn publisher(config: &Config) -> Agent<'_> {
+    Agent {
+        kind: "Organization",
+        name: &config.site_info.title,
+        url: Some(config.absolute_url("/")),
+    }
+}
+
+/// Structured data of the page as an `Article`.
+pub fn article(config: &Config, page: &Page) -> String {
+    let metadata = &page.metadata;
+    script(&Article {
+        context: SCHEMA_ORG,
+        kind: "Article",
+        headline: &metadata.title,
+        description: page.description(),
+        url: page.canonical(config),
+        in_language: metadata.lang.as_deref(),
+        date_published: metadata.date.as_ref().map(format_date_iso8601),
+        date_modified: metadata.updated.as_ref().map(format_date_iso8601),
+        author: metadata
+            .authors
+            .iter()
+            .map(|id| Agent {
+                kind: "Person",
+                name: config
+                    .authors
+                    .get(id)
+                    .map_or(id.as_str(), |author| author.name.as_str()),
+                url: config
+                    .author_template
+                    .as_ref()
+                    .map(|_| config.absolute_url(&author_url(config, id))),
+            })
+            .collect(),
+        publisher: publisher(config),
+    })
+}
+
+/// Structured data of the homepage as a `WebSite`.
+pub fn website(config: &Config) -> String {
+    let site_info = &config.site_info;
+    script(&WebSite {
+        context: SCHEMA_ORG,
+        kind: "WebSite",
+        name: &site_info.title,
+        description: &site_info.description,
+        url: config.absolute_url("/"),
+        in_language: &site_info.lang,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        assert_eq!(
+            "<script type=\"application/ld+json\">{\"headline\":\"<\\/script>\"}</script>",
+            script(&serde_json::json!({ "headline": "</script>" }))
+        );
+    }
+}
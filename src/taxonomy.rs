@@ -0,0 +1,325 @@
+//! This module classifies pages by the taxonomies declared in the config and
+//! generates the listing pages of their terms.
+
+use std::collections::{BTreeMap, HashMap};
+
+use log::debug;
+
+use crate::{
+    config::{Config, Taxonomy},
+    error::{Error, Result},
+    slug::slugify,
+    template::{Context, Value},
+    write_page, Cli, Index, Page,
+};
+
+/// Absolute URL path of a term with a trailing slash.
+pub fn term_url(config: &Config, taxonomy: &str, term: &str) -> String {
+    format!("/{}/{}/", taxonomy, slugify(&config.slugs, term))
+}
+
+/// Convert a term into a value for templating.
+pub fn term_value(config: &Config, taxonomy: &str, term: &str) -> Value {
+    Value::from_iter([
+        ("name", term.to_string()),
+        ("slug", slugify(&config.slugs, term)),
+        ("url", config.url_path(&term_url(config, taxonomy, term))),
+    ])
+}
+
+/// Replace the aliases among the terms by the terms they stand for.
+///
+/// Terms given several times, e.g. by a term and its alias, are kept once.
+fn resolve_aliases(taxonomy: &Taxonomy, terms: Vec<String>) -> Vec<String> {
+    let mut resolved: Vec<String> = Vec::with_capacity(terms.len());
+    for term in terms {
+        let term = taxonomy
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias.to_lowercase() == term.to_lowercase())
+            .map_or(term, |(_, target)| target.clone());
+        if !resolved.contains(&term) {
+            resolved.push(term);
+        }
+    }
+    resolved
+}
+
+/// Read the terms of all taxonomies from the frontmatter of every page.
+///
+/// Aliases of terms are replaced by the terms they stand for.
+pub fn assign_terms(taxonomies: &[Taxonomy], indices: &mut [Index]) -> Result<()> {
+    for page in indices.iter_mut().flat_map(|index| &mut index.pages) {
+        for taxonomy in taxonomies {
+            let terms = match page.metadata.fields.get(&taxonomy.name) {
+                Some(toml::Value::Array(terms)) => terms
+                    .iter()
+                    .map(|term| term.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>(),
+                Some(_) => None,
+                None => Some(Vec::new()),
+            };
+            let terms = terms.ok_or_else(|| {
+                Error::InvalidTerms(page.metadata.filepath.clone(), taxonomy.name.clone())
+            })?;
+            page.taxonomies
+                .insert(taxonomy.name.clone(), resolve_aliases(taxonomy, terms));
+        }
+    }
+
+    Ok(())
+}
+
+/// Collect all terms of the taxonomy with the published pages classified by
+/// them.
+fn collect_terms<'a>(
+    config: &Config,
+    taxonomy: &Taxonomy,
+    indices: &'a [Index],
+    opts: &Cli,
+) -> Result<BTreeMap<String, (String, Vec<&'a Page>)>> {
+    let classified = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .filter(|page| page.is_published(opts))
+        .flat_map(|page| {
+            let terms = page.taxonomies.get(&taxonomy.name).into_iter().flatten();
+            terms.map(move |term| (term.as_str(), page))
+        });
+    group_terms(config, &taxonomy.name, classified)
+}
+
+/// Group the items classified by terms of the taxonomy by the slug of the
+/// terms, ordered by it.
+///
+/// Terms which only differ in case share their listing, any other terms with
+/// the same slug collide. Terms without a slug would replace the listing of
+/// the taxonomy and are rejected.
+fn group_terms<'a, T>(
+    config: &Config,
+    taxonomy: &str,
+    classified: impl IntoIterator<Item = (&'a str, T)>,
+) -> Result<BTreeMap<String, (String, Vec<T>)>> {
+    let mut terms: BTreeMap<String, (String, Vec<T>)> = BTreeMap::new();
+    for (term, item) in classified {
+        let slug = slugify(&config.slugs, term);
+        if slug.is_empty() {
+            return Err(Error::EmptyTermSlug(term.to_string(), taxonomy.to_string()));
+        }
+        let (name, items) = terms
+            .entry(slug.clone())
+            .or_insert_with(|| (term.to_string(), Vec::new()));
+        if name.to_lowercase() != term.to_lowercase() {
+            return Err(Error::SlugCollision(
+                slug,
+                name.clone(),
+                term.to_string(),
+                taxonomy.to_string(),
+            ));
+        }
+        items.push(item);
+    }
+    Ok(terms)
+}
+
+/// Key of a term by which similar terms are found, its lowercase letters and
+/// digits without a plural `s`.
+fn similarity_key(term: &str) -> String {
+    let key: String = term
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match key.strip_suffix('s') {
+        Some(singular) if singular.chars().count() >= 3 => singular.to_string(),
+        _ => key,
+    }
+}
+
+/// Whether the keys differ in at most one inserted, removed or replaced
+/// character.
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<_>, Vec<_>) = (a.chars().collect(), b.chars().collect());
+    let (shorter, longer) = match a.len() <= b.len() {
+        true => (a, b),
+        false => (b, a),
+    };
+    if longer.len() - shorter.len() > 1 {
+        return false;
+    }
+    let prefix = shorter
+        .iter()
+        .zip(&longer)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == shorter.len() {
+        return true;
+    }
+    // Skip the differing character of both for a replacement, otherwise only
+    // the one inserted into the longer key
+    let rest = match shorter.len() == longer.len() {
+        true => prefix + 1,
+        false => prefix,
+    };
+    shorter[rest..] == longer[prefix + 1..]
+}
+
+/// Pairs of distinct terms of the taxonomy used by the pages which are
+/// likely the same, e.g. `rust-lang` and `rustlang` or `tag` and `tags`.
+///
+/// These are candidates for aliases. Terms are compared by their letters and
+/// digits regardless of case and a plural `s`. Terms of at least five
+/// characters are also similar if they differ in a single character, e.g. by
+/// a typo.
+pub fn similar_terms(taxonomy: &Taxonomy, indices: &[Index]) -> Vec<(String, String)> {
+    let mut terms: Vec<&String> = indices
+        .iter()
+        .flat_map(|index| &index.pages)
+        .flat_map(|page| page.taxonomies.get(&taxonomy.name).into_iter().flatten())
+        .collect();
+    terms.sort();
+    terms.dedup();
+
+    let keys: Vec<_> = terms.iter().map(|term| similarity_key(term)).collect();
+    let mut similar = Vec::new();
+    for (i, (term, key)) in terms.iter().zip(&keys).enumerate() {
+        for (other, other_key) in terms.iter().zip(&keys).skip(i + 1) {
+            let typo = key.chars().count() >= 5 && within_one_edit(key, other_key);
+            if key == other_key || typo {
+                similar.push((term.to_string(), other.to_string()));
+            }
+        }
+    }
+    similar
+}
+
+/// Write the listing pages of all taxonomies and their terms.
+pub async fn export_taxonomies(
+    config: &Config,
+    opts: &Cli,
+    ctx: &Context,
+    indices: &[Index],
+) -> Result<()> {
+    for taxonomy in &config.taxonomies {
+        debug!("Building taxonomy '{}'", taxonomy.name);
+
+        let terms = collect_terms(config, taxonomy, indices, opts)?;
+        let term_values: Vec<_> = terms
+            .values()
+            .map(|(term, pages)| {
+                let mut value = HashMap::from([(
+                    "pages".to_string(),
+                    pages
+                        .iter()
+                        .map(|page| page.to_value(config))
+                        .collect::<Vec<_>>()
+                        .into(),
+                )]);
+                if let Value::Map(map) = term_value(config, &taxonomy.name, term) {
+                    value.extend(map);
+                }
+                Value::Map(value)
+            })
+            .collect();
+
+        // Listing of all terms
+        let dir = config.output_path.join(&taxonomy.name);
+        let mut taxonomy_ctx = ctx.clone();
+        taxonomy_ctx.insert("title", taxonomy.name.as_str());
+        taxonomy_ctx.insert(
+            "taxonomy",
+            Value::from_iter([
+                ("name", Value::from(taxonomy.name.as_str())),
+                (
+                    "url",
+                    config.url_path(&format!("/{}/", taxonomy.name)).into(),
+                ),
+                ("terms", term_values.clone().into()),
+            ]),
+        );
+        write_page(config, &taxonomy_ctx, &taxonomy.template, &dir, false).await?;
+
+        // Listing of every term
+        for ((slug, (term, _)), term_value) in terms.iter().zip(term_values) {
+            let mut term_ctx = taxonomy_ctx.clone();
+            term_ctx.insert("title", term.as_str());
+            term_ctx.insert("term", term_value);
+            write_page(
+                config,
+                &term_ctx,
+                &taxonomy.term_template,
+                &dir.join(slug),
+                false,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    fn tags(aliases: &[(&str, &str)]) -> Taxonomy {
+        Taxonomy {
+            name: "tags".to_string(),
+            template: "taxonomy.html".into(),
+            term_template: "taxonomy_term.html".into(),
+            aliases: aliases
+                .iter()
+                .map(|(alias, term)| (alias.to_string(), term.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_aliases() {
+        let taxonomy = tags(&[("rust-lang", "rust"), ("JS", "javascript")]);
+        assert_eq!(
+            vec!["rust", "javascript", "web"],
+            resolve_aliases(
+                &taxonomy,
+                vec!["Rust-Lang".into(), "js".into(), "web".into(), "rust".into()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_similar() {
+        assert_eq!(similarity_key("Rust-Lang"), similarity_key("rustlang"));
+        assert_eq!(similarity_key("Tags"), similarity_key("tag"));
+        assert_eq!("css", similarity_key("CSS"));
+        assert!(within_one_edit("programming", "programing"));
+        assert!(within_one_edit("rustacean", "rustaceam"));
+        assert!(within_one_edit("rust", "rusty"));
+        assert!(!within_one_edit("rust", "trusty"));
+        assert!(!within_one_edit("linux", "unix"));
+    }
+
+    #[test]
+    fn test_group_terms() {
+        let config = dummy_config();
+        let terms = group_terms(&config, "tags", [("Rust", 1), ("web", 2), ("rust", 3)]).unwrap();
+        assert_eq!(
+            vec![
+                ("rust", &("Rust".to_string(), vec![1, 3])),
+                ("web", &("web".to_string(), vec![2])),
+            ],
+            terms
+                .iter()
+                .map(|(slug, v)| (slug.as_str(), v))
+                .collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            group_terms(&config, "tags", [("C++", 1), ("C", 2)]),
+            Err(Error::SlugCollision(slug, ..)) if slug == "c"
+        ));
+        assert!(matches!(
+            group_terms(&config, "tags", [("!!!", 1)]),
+            Err(Error::EmptyTermSlug(term, _)) if term == "!!!"
+        ));
+    }
+}
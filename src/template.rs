@@ -1,13 +1,36 @@
 //! This module is responsible for replacing shortcodes from input files with
 //! the appropriate data.
 
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use log::debug;
+use pulldown_cmark::escape::escape_html;
 
 use crate::{
+    cache::RenderCache,
     config::Config,
     error::{self, Error, Result},
+    protect::Protector,
+    AssetManifest,
+};
+
+mod filters;
+mod functions;
+mod shortcodes;
+
+pub use filters::format_date;
+pub use functions::{content_hash, Functions};
+pub use shortcodes::{
+    expand as expand_shortcodes, names as shortcode_names, strip as strip_shortcodes, SHORTCODE_DIR,
 };
 
 /// Start delimiter of a shortcode.
@@ -28,38 +51,912 @@ const TAG_START: &str = "{{";
 /// End delimiter of a tag.
 const TAG_END: &str = "}}";
 
+/// Names of the built-in commands, which cannot be used by block shortcodes.
+const KEYWORDS: &[&str] = &[
+    "include",
+    "include_if_exists",
+    "if",
+    "else",
+    "endif",
+    "for",
+    "endfor",
+    "require",
+    "end",
+    RAW,
+    END_RAW,
+];
+
+/// Command opening a block whose content is output verbatim.
+const RAW: &str = "raw";
+
+/// Command closing a raw block.
+const END_RAW: &str = "endraw";
+
+/// Maximum number of nested includes, which prevents endless recursion of
+/// templates including themselves.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Name of the value describing the current page of a pagination.
+const PAGINATOR: &str = "paginator";
+
+/// Escapes a following shortcode delimiter so it is output literally.
+const ESCAPE: char = '\\';
+
+/// Operators comparing two expressions in a condition, e.g.
+/// `{% if page.date > now() | date_add(years=-1) %}`.
+const COMPARISONS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+/// Separator to access values nested in a map, e.g. `page.title`.
+const PATH_SEPARATOR: char = '.';
+
+/// A value which can be referenced by tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// Text which is inserted as is.
+    String(String),
+
+    /// A whole number.
+    Integer(i64),
+
+    /// Either `true` or `false`.
+    Bool(bool),
+
+    /// A list of values which can be iterated with a for-block.
+    List(Vec<Value>),
+
+    /// Named values which are accessed with `name.key`.
+    Map(HashMap<String, Value>),
+}
+
+impl Value {
+    /// Get the value nested at the path of keys separated by dots, e.g.
+    /// `extra.category`.
+    fn get_path(&self, path: &str) -> Option<&Value> {
+        path.split(PATH_SEPARATOR)
+            .try_fold(self, |value, key| match value {
+                Value::Map(map) => map.get(key),
+                _ => None,
+            })
+    }
+
+    /// Whether the value is considered set in a conditional block.
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::String(s) => !s.is_empty(),
+            Value::Integer(n) => *n != 0,
+            Value::Bool(b) => *b,
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Integer(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<toml::Value> for Value {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Integer(n) => Value::Integer(n),
+            toml::Value::Float(f) => Value::String(f.to_string()),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(d) => Value::String(d.to_string()),
+            toml::Value::Array(a) => a.into(),
+            toml::Value::Table(t) => t.into_iter().collect(),
+        }
+    }
+}
+
+impl<V: Into<Value>> From<Vec<V>> for Value {
+    fn from(l: Vec<V>) -> Self {
+        Value::List(l.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Value {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Value::Map(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Records which templates and context values are used during a build.
+#[derive(Debug, Default)]
+pub struct Usage {
+    /// Templates which were rendered or included, relative to `templates/`.
+    templates: Mutex<HashSet<PathBuf>>,
+    /// Names of all values inserted into a context.
+    inserted: Mutex<HashSet<String>>,
+    /// Names of all values looked up from a context.
+    referenced: Mutex<HashSet<String>>,
+}
+
+impl Usage {
+    /// Mark a template as used.
+    pub fn record_template(&self, path: impl AsRef<Path>) {
+        let mut templates = self.templates.lock().expect("lock is never poisoned");
+        templates.insert(path.as_ref().to_path_buf());
+    }
+
+    /// Filter the given templates for the ones that were never used.
+    pub fn unused_templates(&self, templates: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+        let used = self.templates.lock().expect("lock is never poisoned");
+        let mut unused: Vec<_> = templates
+            .into_iter()
+            .filter(|template| !used.contains(template))
+            .collect();
+        unused.sort();
+        unused
+    }
+
+    /// Names of all values which were inserted but never referenced.
+    pub fn unreferenced_values(&self) -> Vec<String> {
+        let inserted = self.inserted.lock().expect("lock is never poisoned");
+        let referenced = self.referenced.lock().expect("lock is never poisoned");
+        let mut unreferenced: Vec<_> = inserted.difference(&referenced).cloned().collect();
+        unreferenced.sort();
+        unreferenced
+    }
+}
+
 /// Variable context for tags.
-pub type Context = HashMap<&'static str, String>;
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    /// Values by their name.
+    values: HashMap<String, Value>,
+    /// Usage tracking shared between all clones of the context.
+    usage: Option<Arc<Usage>>,
+    /// State of the template functions shared between all clones of the
+    /// context.
+    functions: Arc<Functions>,
+    /// Number of pages requested by `paginate` while rendering a page.
+    page_count: Arc<AtomicUsize>,
+    /// Templates parsed while rendering by their path relative to
+    /// `templates/`, `None` if they do not exist, shared between all clones
+    /// of the context.
+    templates: Arc<Mutex<HashMap<PathBuf, Option<Template>>>>,
+    /// Rendered pages of previous builds shared between all clones of the
+    /// context.
+    render_cache: Option<Arc<RenderCache>>,
+    /// Encryption of protected pages shared between all clones of the
+    /// context.
+    protector: Option<Arc<Protector>>,
+    /// Names of the values inserted since the render cache was set, which
+    /// are the ones differing between pages.
+    local: HashSet<String>,
+    /// Number of shortcodes the rendering is nested in.
+    shortcode_depth: usize,
+}
+
+impl Context {
+    /// Create an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Context for the shortcodes of content, sharing the parsed templates
+    /// and the state of the functions with this context but none of its
+    /// values.
+    pub fn shortcode_context(&self) -> Self {
+        Self {
+            functions: self.functions.clone(),
+            templates: self.templates.clone(),
+            shortcode_depth: self.shortcode_depth,
+            ..Self::default()
+        }
+    }
+
+    /// Record the usage of this context and everything cloned from it.
+    pub fn track_usage(&mut self, usage: Arc<Usage>) {
+        self.usage = Some(usage);
+    }
+
+    /// Resolve assets by the manifest of their output paths in this context
+    /// and everything cloned from it.
+    pub fn set_asset_manifest(&mut self, manifest: AssetManifest) {
+        self.functions = Arc::new(Functions::with_asset_manifest(manifest));
+    }
+
+    /// Output path of the asset with the path relative to `assets/`, its
+    /// fingerprinted one if assets are fingerprinted.
+    #[cfg(feature = "images")]
+    pub fn asset_output<'a>(&'a self, path: &'a Path) -> Option<&'a Path> {
+        self.functions.asset_output(path)
+    }
+
+    /// Reuse the pages rendered by previous builds in this context and
+    /// everything cloned from it.
+    pub fn set_render_cache(&mut self, cache: Arc<RenderCache>) {
+        self.render_cache = Some(cache);
+        self.local.clear();
+    }
+
+    /// Cache of rendered pages if builds are incremental.
+    pub fn render_cache(&self) -> Option<&RenderCache> {
+        self.render_cache.as_deref()
+    }
+
+    /// Encrypt protected pages with the protector in this context and
+    /// everything cloned from it.
+    pub fn set_protector(&mut self, protector: Arc<Protector>) {
+        self.protector = Some(protector);
+    }
+
+    /// Encryption of protected pages if the site has any.
+    pub fn protector(&self) -> Option<&Protector> {
+        self.protector.as_deref()
+    }
+
+    /// All values by their name, without marking them as referenced.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Values inserted since the render cache was set by their name.
+    pub fn local_values(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.local
+            .iter()
+            .filter_map(|name| Some((name.as_str(), self.values.get(name)?)))
+    }
+
+    /// Mark a template as used if usage is tracked.
+    pub fn record_template(&self, path: impl AsRef<Path>) {
+        if let Some(usage) = &self.usage {
+            usage.record_template(path);
+        }
+    }
+
+    /// Insert a value, replacing the previous value with the same name.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        let key = key.into();
+        if let Some(usage) = &self.usage {
+            let mut inserted = usage.inserted.lock().expect("lock is never poisoned");
+            inserted.insert(key.clone());
+        }
+        if self.render_cache.is_some() {
+            self.local.insert(key.clone());
+        }
+        self.values.insert(key, value.into());
+    }
+
+    /// Look up a value by its name.
+    ///
+    /// Values nested in maps are accessed with a dot separated path, e.g.
+    /// `page.title`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let mut keys = path.split(PATH_SEPARATOR);
+        let name = keys.next()?;
+        if let Some(usage) = &self.usage {
+            let mut referenced = usage.referenced.lock().expect("lock is never poisoned");
+            referenced.insert(name.to_string());
+        }
+        let mut value = self.values.get(name)?;
+        for key in keys {
+            match value {
+                Value::Map(map) => value = map.get(key)?,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Context {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Context {
+            values: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            usage: None,
+            functions: Arc::default(),
+            page_count: Arc::default(),
+            templates: Arc::default(),
+            render_cache: None,
+            protector: None,
+            local: HashSet::new(),
+            shortcode_depth: 0,
+        }
+    }
+}
+
+/// Separator between a value and its filters, e.g. `title | upper`.
+const FILTER_SEPARATOR: char = '|';
+
+/// A literal given in a template, e.g. as an argument to a filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Literal {
+    /// A double quoted string, e.g. `"text"`.
+    String(String),
+
+    /// An integer, e.g. `120`.
+    Integer(i64),
+
+    /// Either `true` or `false`.
+    Bool(bool),
+
+    /// The name of a variable which is looked up by the function it is
+    /// passed to, e.g. `pages`.
+    Variable(String),
+}
+
+impl FromStr for Literal {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if let Some(quoted) = input.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            // Resolve escaped characters
+            let mut s = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => s.extend(chars.next()),
+                    c => s.push(c),
+                }
+            }
+            Ok(Literal::String(s))
+        } else if let Ok(b) = input.parse() {
+            Ok(Literal::Bool(b))
+        } else if input.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && input
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == PATH_SEPARATOR)
+        {
+            Ok(Literal::Variable(input.to_string()))
+        } else {
+            input
+                .parse()
+                .map(Literal::Integer)
+                .map_err(|_| Error::ParseLiteral(input.to_string()))
+        }
+    }
+}
+
+/// Split the input at all separators which are not within a quoted string.
+fn split_unquoted(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(&input[part_start..i]);
+                part_start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[part_start..]);
+
+    parts
+}
+
+/// Find the first comparison operator outside of quotes.
+fn find_comparison(input: &str) -> Option<(usize, &'static str)> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            _ if in_quotes => {}
+            _ => {
+                if let Some(op) = COMPARISONS.iter().find(|op| input[i..].starts_with(*op)) {
+                    return Some((i, op));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Compare two values with the operator.
+///
+/// Text which can be read as dates on both sides is compared as dates,
+/// other text lexically and numbers by their value.
+fn compare(config: &Config, condition: &str, lhs: &Value, op: &str, rhs: &Value) -> Result<bool> {
+    let ordering = match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::String(a), Value::String(b)) => {
+            match (
+                filters::parse_date(config, a),
+                filters::parse_date(config, b),
+            ) {
+                (Ok(a), Ok(b)) => Some(a.cmp(&b)),
+                _ => Some(a.cmp(b)),
+            }
+        }
+        _ => None,
+    };
+    Ok(match (op, ordering) {
+        ("==", Some(ordering)) => ordering.is_eq(),
+        ("!=", Some(ordering)) => ordering.is_ne(),
+        ("==", None) => lhs == rhs,
+        ("!=", None) => lhs != rhs,
+        ("<", Some(ordering)) => ordering.is_lt(),
+        ("<=", Some(ordering)) => ordering.is_le(),
+        (">", Some(ordering)) => ordering.is_gt(),
+        (">=", Some(ordering)) => ordering.is_ge(),
+        _ => return Err(Error::Compare(condition.to_string())),
+    })
+}
+
+/// Evaluate the condition of a conditional block.
+///
+/// A single variable is true if it exists and is not empty. A comparison of
+/// two expressions is false if either side references a missing value.
+async fn evaluate_condition(config: &Config, ctx: &Context, condition: &str) -> Result<bool> {
+    let Some((i, op)) = find_comparison(condition) else {
+        return Ok(ctx.get(condition).is_some_and(Value::is_truthy));
+    };
+    let lhs = condition[..i].parse::<Expression>()?;
+    let rhs = condition[i + op.len()..].parse::<Expression>()?;
+    match (
+        lhs.evaluate(config, ctx).await,
+        rhs.evaluate(config, ctx).await,
+    ) {
+        (Ok(lhs), Ok(rhs)) => compare(config, condition, &lhs, op, &rhs),
+        (Err(Error::TagNotFound(_)), _) | (_, Err(Error::TagNotFound(_))) => Ok(false),
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    }
+}
+
+/// An argument of a filter or function, either positional or named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argument {
+    /// Name of the argument if given as `name=value`.
+    pub name: Option<String>,
+    /// Value of the argument.
+    pub value: Literal,
+}
+
+impl FromStr for Argument {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        match split_unquoted(input, '=').as_slice() {
+            [name, value] => {
+                let name = name.trim();
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(Error::ParseLiteral(input.to_string()));
+                }
+                Ok(Argument {
+                    name: Some(name.to_string()),
+                    value: value.parse()?,
+                })
+            }
+            _ => Ok(Argument {
+                name: None,
+                value: input.parse()?,
+            }),
+        }
+    }
+}
+
+/// Split arguments separated by whitespace into their tokens.
+///
+/// Tokens are quoted strings, `=` or words up to the next whitespace, `=` or
+/// quote.
+fn tokenize_args(input: &str) -> Result<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '=' => 1,
+            '"' => {
+                let mut escaped = false;
+                let close = rest[1..].char_indices().find(|&(_, c)| {
+                    let is_close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    is_close
+                });
+                match close {
+                    Some((i, _)) => i + 2,
+                    None => return Err(Error::ParseLiteral(rest.to_string())),
+                }
+            }
+            _ => rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '"')
+                .unwrap_or(rest.len()),
+        };
+        tokens.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse arguments of a command separated by whitespace, e.g.
+/// `"card.html" title="Hi" link = page.url`.
+fn parse_command_args(input: &str) -> Result<Vec<Argument>> {
+    let tokens = tokenize_args(input)?;
+    let mut args = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i..] {
+            [name, "=", value, ..] => {
+                if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(Error::ParseLiteral(name.to_string()));
+                }
+                args.push(Argument {
+                    name: Some(name.to_string()),
+                    value: value.parse()?,
+                });
+                i += 3;
+            }
+            ["=", ..] | [_, "="] => return Err(Error::ParseLiteral(input.to_string())),
+            [value, ..] => {
+                args.push(Argument {
+                    name: None,
+                    value: value.parse()?,
+                });
+                i += 1;
+            }
+            [] => break,
+        }
+    }
+    Ok(args)
+}
+
+/// Clone the context with the named arguments of a call inserted as values.
+///
+/// Variables given as arguments are looked up in the context.
+fn with_args(ctx: &Context, name: &str, args: &[Argument]) -> Result<Context> {
+    let mut args_ctx = ctx.clone();
+    for arg in args {
+        let arg_name = arg
+            .name
+            .as_deref()
+            .ok_or_else(|| Error::ShortcodeArgument(name.to_string()))?;
+        args_ctx.insert(arg_name, literal_value(ctx, &arg.value)?);
+    }
+    Ok(args_ctx)
+}
+
+/// Get the value of a literal, looking up variables in the context.
+fn literal_value(ctx: &Context, literal: &Literal) -> Result<Value> {
+    Ok(match literal {
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Integer(n) => Value::Integer(*n),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Variable(var) => ctx
+            .get(var)
+            .cloned()
+            .ok_or_else(|| Error::TagNotFound(var.clone()))?,
+    })
+}
+
+/// A call of a filter or function with its arguments.
+///
+/// Arguments are either given in parentheses, e.g. `truncate(120)`, or a
+/// single argument is separated by whitespace, e.g. `bust "style.css"`.
+#[derive(Debug, PartialEq, Eq)]
+struct Call {
+    /// Name of the filter or function.
+    name: String,
+    /// Arguments passed to the filter or function.
+    args: Vec<Argument>,
+}
+
+impl FromStr for Call {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let err = || Error::ParseShortcode(input.to_string());
+        let (name, args) = match input.split_once(|c: char| c == '(' || c.is_whitespace()) {
+            Some((name, args)) if input[name.len()..].starts_with('(') => {
+                let args = args.strip_suffix(')').ok_or_else(err)?;
+                let args = if args.trim().is_empty() {
+                    Vec::new()
+                } else {
+                    split_unquoted(args, ',')
+                        .into_iter()
+                        .map(str::parse)
+                        .collect::<Result<_>>()?
+                };
+                (name.trim(), args)
+            }
+            Some((name, arg)) => (name, vec![arg.parse()?]),
+            None => (input, Vec::new()),
+        };
+        if name.is_empty() {
+            return Err(err());
+        }
+        Ok(Call {
+            name: name.to_string(),
+            args,
+        })
+    }
+}
+
+/// The value an expression starts with.
+#[derive(Debug, PartialEq, Eq)]
+enum Operand {
+    /// A variable which is looked up in the context.
+    Variable(String),
+
+    /// A literal which is used as is, e.g. `"{{"` to output the delimiter.
+    Literal(Literal),
+
+    /// A call of a function, e.g. `bust "style.css"`.
+    Call(Call),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Variable(var) | Operand::Literal(Literal::Variable(var)) => {
+                write!(f, "{}", var)
+            }
+            Operand::Literal(Literal::String(s)) => write!(f, "{:?}", s),
+            Operand::Literal(Literal::Integer(n)) => write!(f, "{}", n),
+            Operand::Literal(Literal::Bool(b)) => write!(f, "{}", b),
+            Operand::Call(call) => write!(f, "{}(...)", call.name),
+        }
+    }
+}
+
+/// A variable, literal or function call together with the filters which are
+/// applied to it in order.
+#[derive(Debug, PartialEq, Eq)]
+struct Expression {
+    /// Value the filters are applied to.
+    operand: Operand,
+    /// Filters applied to the operand.
+    filters: Vec<Call>,
+}
+
+impl Expression {
+    /// Whether the value is inserted as is instead of escaped as HTML, which
+    /// holds for literals and values marked by the `safe` filter.
+    fn is_safe(&self) -> bool {
+        matches!(self.operand, Operand::Literal(Literal::String(_)))
+            || self
+                .filters
+                .iter()
+                .any(|filter| filter.name == filters::SAFE)
+    }
+
+    /// Evaluate the operand and apply all filters.
+    ///
+    /// A missing variable is replaced by the argument of the first `default`
+    /// filter, e.g. `{{ excerpt | default("") }}`, and only the filters after
+    /// it are applied.
+    async fn evaluate(&self, config: &Config, ctx: &Context) -> Result<Value> {
+        let mut filters = self.filters.as_slice();
+        let value = match &self.operand {
+            Operand::Variable(var) | Operand::Literal(Literal::Variable(var)) => {
+                match ctx.get(var) {
+                    Some(value) => value.clone(),
+                    None => {
+                        let i = filters
+                            .iter()
+                            .position(|filter| filter.name == filters::DEFAULT)
+                            .ok_or_else(|| Error::TagNotFound(var.to_string()))?;
+                        let value = match filters[i].args.as_slice() {
+                            [Argument { name: None, value }] => literal_value(ctx, value)?,
+                            _ => {
+                                return Err(Error::Filter(
+                                    filters::DEFAULT.to_string(),
+                                    "expects a single value".to_string(),
+                                ))
+                            }
+                        };
+                        filters = &filters[i + 1..];
+                        value
+                    }
+                }
+            }
+            Operand::Literal(Literal::String(s)) => Value::String(s.clone()),
+            Operand::Literal(Literal::Integer(n)) => Value::Integer(*n),
+            Operand::Literal(Literal::Bool(b)) => Value::Bool(*b),
+            Operand::Call(call) => functions::call(config, ctx, &call.name, &call.args).await?,
+        };
+        filters.iter().try_fold(value, |value, filter| {
+            filters::apply(config, &filter.name, value, &filter.args)
+        })
+    }
+}
+
+impl FromStr for Expression {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let mut parts = split_unquoted(input, FILTER_SEPARATOR).into_iter();
+        let operand = parts.next().unwrap_or_default().trim();
+        let operand = match operand.chars().next() {
+            None => return Err(Error::ParseShortcode(input.to_string())),
+            Some(c)
+                if c == '"'
+                    || c == '-'
+                    || c.is_ascii_digit()
+                    || operand.parse::<bool>().is_ok() =>
+            {
+                Operand::Literal(operand.parse()?)
+            }
+            Some(_) if operand.contains(|c: char| c == '(' || c.is_whitespace()) => {
+                Operand::Call(operand.parse()?)
+            }
+            Some(_) => Operand::Variable(operand.to_string()),
+        };
+        Ok(Expression {
+            operand,
+            filters: parts.map(str::parse).collect::<Result<_>>()?,
+        })
+    }
+}
 
 /// A information holder about a parsed shortcode.
 #[derive(Debug, PartialEq, Eq)]
 enum Shortcode {
     /// A shortcode with an include directive.
     ///
-    /// If applied, it will include the contents given in the path.
-    Include(PathBuf),
+    /// If applied, it will include the contents given in the path. Named
+    /// arguments are values only within the include, e.g.
+    /// `{% include "card.html" title="Hi" %}`.
+    Include(PathBuf, Vec<Argument>),
+
+    /// An include which is skipped if the file does not exist.
+    IncludeIfExists(PathBuf, Vec<Argument>),
 
     /// A shortcode to insert with the given variable.
-    Tag(String),
+    Tag(Expression),
+
+    /// Start of a conditional block.
+    ///
+    /// The block is rendered if the given variable exists and is not empty,
+    /// or if the comparison of two expressions holds, e.g.
+    /// `{% if page.date > now() | date_add(years=-1) %}`.
+    If(String),
+
+    /// Separates the two branches of a conditional block.
+    Else,
+
+    /// End of a conditional block.
+    EndIf,
+
+    /// Start of a loop block.
+    ///
+    /// The block is rendered for every item of the list with the item bound to
+    /// the given variable name.
+    For {
+        /// Name the current item is bound to.
+        var: String,
+        /// Expression of the list to iterate, e.g. `pages` or
+        /// `paginate(pages, 10)`.
+        list: String,
+    },
+
+    /// End of a loop block.
+    EndFor,
+
+    /// Values which must be given to the template.
+    ///
+    /// These are checked before the template is rendered.
+    Require(Vec<String>),
+
+    /// Start of a block shortcode, e.g. `{% note title="Hint" %}`.
+    ///
+    /// The body is rendered as markdown and passed as `body` to the template of
+    /// the same name in `templates/shortcodes/`, which inserts it with
+    /// `{{ body | safe }}`.
+    Block(Call),
+
+    /// End of a block shortcode.
+    End,
+
+    /// Content of a raw block, which is output as is, e.g.
+    /// `{% raw %}{{ page.title }}{% endraw %}`.
+    Raw(String),
+}
+
+/// Where the input of a template is rendered from.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// Directory of the template relative to `templates/`.
+    dir: PathBuf,
+    /// Number of includes the input is nested in.
+    include_depth: usize,
+    /// Number of tag values the input is nested in.
+    expansion_depth: usize,
+}
+
+impl Scope {
+    /// Scope of a template file at the path relative to `templates/`.
+    fn for_template(path: &Path) -> Self {
+        Scope {
+            dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            ..Default::default()
+        }
+    }
 }
 
 impl Shortcode {
     /// Applies the shortcode and converts it to HTML.
-    async fn to_html(&self, config: &Config, ctx: &Context) -> Result<String> {
+    async fn to_html(&self, config: &Config, ctx: &Context, scope: &Scope) -> Result<String> {
         match self {
-            Shortcode::Include(path) => {
-                let full_path = config.content_path.join("templates").join(path);
+            Shortcode::Include(path, args) | Shortcode::IncludeIfExists(path, args) => {
+                if scope.include_depth >= MAX_INCLUDE_DEPTH {
+                    return Err(Error::IncludeDepth(path.to_owned()));
+                }
+                let path = resolve_include(&scope.dir, path);
                 debug!("Including file '{}'", path.display());
-                tokio::fs::read_to_string(full_path)
+                let nodes = match cached_template(config, ctx, &path).await? {
+                    Ok(nodes) => nodes,
+                    Err(e)
+                        if e.kind() == std::io::ErrorKind::NotFound
+                            && matches!(self, Shortcode::IncludeIfExists(..)) =>
+                    {
+                        debug!("Skipping missing include '{}'", path.display());
+                        return Ok(String::new());
+                    }
+                    Err(e) => return Err(Error::IncludeShortcode(path.to_owned(), e)),
+                };
+                ctx.record_template(&path);
+                let include_ctx = with_args(ctx, "include", args)?;
+                check_required(&include_ctx, &path, &nodes)?;
+                let include_scope = Scope {
+                    include_depth: scope.include_depth + 1,
+                    ..Scope::for_template(&path)
+                };
+                Box::pin(render_nodes(config, &include_ctx, &nodes, &include_scope))
                     .await
-                    .map_err(|e| Error::IncludeShortcode(path.to_owned(), e))
+                    .map_err(|e| Error::InTemplate(path, Box::new(e)))
             }
-            Shortcode::Tag(var) => {
-                debug!("Replacing tag '{}'", var);
-                ctx.get(var.as_str())
-                    .cloned()
-                    .ok_or_else(|| Error::TagNotFound(var.to_string()))
+            Shortcode::Tag(expr) => {
+                debug!("Replacing tag '{}'", expr.operand);
+                match expr.evaluate(config, ctx).await? {
+                    Value::String(s) => Ok(s),
+                    Value::Integer(n) => Ok(n.to_string()),
+                    Value::Bool(b) => Ok(b.to_string()),
+                    _ => Err(Error::TagNotText(expr.operand.to_string())),
+                }
             }
+            Shortcode::Require(_) => Ok(String::new()),
+            Shortcode::Raw(content) => Ok(content.clone()),
+            Shortcode::If(_)
+            | Shortcode::Else
+            | Shortcode::EndIf
+            | Shortcode::For { .. }
+            | Shortcode::EndFor
+            | Shortcode::Block(_)
+            | Shortcode::End => Err(Error::UnexpectedBlock(format!("{:?}", self))),
         }
     }
 }
@@ -68,107 +965,797 @@ impl FromStr for Shortcode {
     type Err = Error;
 
     fn from_str(input: &str) -> Result<Self> {
+        if let Some(content) = raw_content(input) {
+            return Ok(Self::Raw(content.to_string()));
+        }
+        if command_at(input) == Some((RAW, input.len())) {
+            return Err(Error::UnclosedBlock(input.to_string()));
+        }
+
         let extract_command = |input: &str| -> Option<Self> {
             // {% include "stuff/head.html" %} -> include "stuff/head.html"
             let inner = input
                 .strip_prefix(COMMAND_START)?
                 .strip_suffix(COMMAND_END)?
                 .trim();
-            // include "stuff/head.html" -> "stuff/head.html"
-            let quoted_path = inner.strip_prefix("include")?.trim_start();
-            // stuff/head.html
-            let path: PathBuf = quoted_path
-                .strip_prefix('"')?
-                .strip_suffix('"')?
-                .parse()
-                .ok()?;
-            Some(Self::Include(path))
+            // include "stuff/head.html" -> ("include", "\"stuff/head.html\"")
+            let (keyword, args) = inner
+                .split_once(char::is_whitespace)
+                .map(|(keyword, args)| (keyword, args.trim()))
+                .unwrap_or((inner, ""));
+            match keyword {
+                "include" | "include_if_exists" => {
+                    // "card.html" title="Hi" -> card.html with its arguments
+                    let mut args = parse_command_args(args).ok()?.into_iter();
+                    let path: PathBuf = match args.next()? {
+                        Argument {
+                            name: None,
+                            value: Literal::String(path),
+                        } => path.into(),
+                        _ => return None,
+                    };
+                    let args: Vec<_> = args.collect();
+                    if args.iter().any(|arg| arg.name.is_none()) {
+                        return None;
+                    }
+                    match keyword {
+                        "include" => Some(Self::Include(path, args)),
+                        _ => Some(Self::IncludeIfExists(path, args)),
+                    }
+                }
+                "if" if !args.is_empty() => Some(Self::If(args.to_string())),
+                "else" if args.is_empty() => Some(Self::Else),
+                "endif" if args.is_empty() => Some(Self::EndIf),
+                "for" => {
+                    // page in pages -> ("page", "pages")
+                    let (var, list) = args.split_once(" in ")?;
+                    let (var, list) = (var.trim(), list.trim());
+                    if var.is_empty() || var.contains(PATH_SEPARATOR) || list.is_empty() {
+                        return None;
+                    }
+                    Some(Self::For {
+                        var: var.to_string(),
+                        list: list.to_string(),
+                    })
+                }
+                "endfor" if args.is_empty() => Some(Self::EndFor),
+                "require" => {
+                    // title, date -> ["title", "date"]
+                    let vars: Vec<_> = args.split(',').map(str::trim).collect();
+                    if vars.iter().any(|var| var.is_empty()) {
+                        return None;
+                    }
+                    Some(Self::Require(
+                        vars.into_iter().map(str::to_string).collect(),
+                    ))
+                }
+                "end" if args.is_empty() => Some(Self::End),
+                // note title="Hint" -> note with its arguments
+                name if !KEYWORDS.contains(&name)
+                    && name
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '_' || c == '-') =>
+                {
+                    Some(Self::Block(Call {
+                        name: name.to_string(),
+                        args: parse_command_args(args).ok()?,
+                    }))
+                }
+                _ => None,
+            }
         };
         let extract_tag = |input: &str| -> Option<Self> {
             let inner = input.strip_prefix(TAG_START)?.strip_suffix(TAG_END)?.trim();
-            Some(Self::Tag(inner.to_string()))
+            inner.parse().ok().map(Self::Tag)
+        };
+
+        extract_tag(input)
+            .or_else(|| extract_command(input))
+            .ok_or_else(|| error::Error::ParseShortcode(input.to_string()))
+    }
+}
+
+/// Resolve the path of an include relative to `templates/`.
+///
+/// Paths starting with `./` or `../` are relative to the directory of the
+/// including template, all other paths are relative to `templates/`.
+fn resolve_include(dir: &Path, path: &Path) -> PathBuf {
+    let path = if path.starts_with(".") || path.starts_with("..") {
+        dir.join(path)
+    } else {
+        path.to_path_buf()
+    };
+
+    // Normalize the path without touching the file system
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match resolved.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    resolved.pop();
+                }
+                _ => resolved.push(Component::ParentDir),
+            },
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Ensure that the path relative to the directory, e.g. `templates`, does not
+/// leave it.
+///
+/// Only the path itself is checked, symbolic links are refused when reading
+/// templates.
+pub(super) fn check_within(path: &Path, dir: &'static str) -> Result<()> {
+    let within = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    match within {
+        true => Ok(()),
+        false => Err(Error::OutsideDirectory(path.to_path_buf(), dir)),
+    }
+}
+
+/// Append the text to the output and remove the escapes of delimiters.
+fn push_unescaped(html: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(i) = rest.find(ESCAPE) {
+        let escaped = &rest[i + ESCAPE.len_utf8()..];
+        html.push_str(&rest[..i]);
+        if !(escaped.starts_with(TAG_START) || escaped.starts_with(COMMAND_START)) {
+            html.push(ESCAPE);
+        }
+        rest = escaped;
+    }
+    html.push_str(rest);
+}
+
+/// Get the trimmed inner text of a command at the start of the input and the
+/// index after its end.
+fn command_at(input: &str) -> Option<(&str, usize)> {
+    let inner = input.strip_prefix(COMMAND_START)?;
+    let end = inner.find(COMMAND_END)?;
+    Some((
+        inner[..end].trim(),
+        COMMAND_START.len() + end + COMMAND_END.len(),
+    ))
+}
+
+/// Find the `{% endraw %}` of a raw block whose opening command directly
+/// precedes the input.
+///
+/// Returns the range of the closing command relative to the input.
+fn find_raw_end(input: &str) -> Option<(usize, usize)> {
+    input
+        .match_indices(COMMAND_START)
+        .find_map(|(i, _)| match command_at(&input[i..]) {
+            Some((END_RAW, len)) => Some((i, i + len)),
+            _ => None,
+        })
+}
+
+/// Get the content of a whole raw block including its commands.
+fn raw_content(input: &str) -> Option<&str> {
+    let (RAW, open_end) = command_at(input)? else {
+        return None;
+    };
+    let (close_start, close_end) = find_raw_end(&input[open_end..])?;
+    (open_end + close_end == input.len()).then(|| &input[open_end..open_end + close_start])
+}
+
+/// Find a shortcode within the given input.
+///
+/// This returns the start and end indices including the delimiters.
+/// Essentially this is the range which gives the shortcut itself back from the
+/// input:
+///
+/// ```rust
+/// let (start, end) = find_shortcode(input);
+/// let shortcode = &input[start..end];
+/// ```
+fn find_shortcode(input: &str) -> Option<(usize, usize)> {
+    let mut search_start_idx = 0;
+
+    // Find the first '{' char
+    // This is a perf optimization as all shortcodes start with '{'
+    while let Some(start) = input[search_start_idx..].find(SHORTCODE_START) {
+        // Make start an absolute index
+        let start_abs = search_start_idx + start;
+
+        // Skip escaped delimiters, e.g. `\{{`
+        if input[..start_abs].ends_with(ESCAPE) {
+            search_start_idx = start_abs + 1;
+            continue;
+        }
+
+        // Check the next char to determine type and find the end if it exists
+        let end_abs = match &input[start_abs..] {
+            s if s.starts_with(TAG_START) => s[TAG_START.len()..]
+                .find(TAG_END)
+                .map(|i| start_abs + i + TAG_START.len() + TAG_END.len()),
+            s if s.starts_with(COMMAND_START) => match command_at(s) {
+                // Raw blocks are found as a whole so their content is never parsed
+                Some((RAW, len)) => {
+                    let close_end = find_raw_end(&s[len..]).map_or(0, |(_, end)| end);
+                    Some(start_abs + len + close_end)
+                }
+                Some((_, len)) => Some(start_abs + len),
+                None => None,
+            },
+            _ => None,
+        };
+
+        // Check if we found a valid end
+        match end_abs {
+            Some(end_abs) => return Some((start_abs, end_abs)),
+            None => search_start_idx = start_abs + 1,
+        }
+    }
+
+    None
+}
+
+/// Ranges of the optional `{% else %}` and the closing shortcode of a block.
+type BlockRanges = (Option<(usize, usize)>, (usize, usize));
+
+/// Find the end of a block whose opening shortcode directly precedes the input.
+///
+/// Returns the range of the optional `{% else %}` and the range of the closing
+/// shortcode, both relative to the input. Nested blocks are skipped.
+fn find_block_end(input: &str) -> Option<BlockRanges> {
+    let mut depth = 0usize;
+    let mut else_range = None;
+    let mut search_start_idx = 0;
+
+    while let Some((start, end)) = find_shortcode(&input[search_start_idx..]) {
+        // Make the range absolute
+        let (start, end) = (search_start_idx + start, search_start_idx + end);
+        match input[start..end].parse() {
+            Ok(Shortcode::If(_) | Shortcode::For { .. } | Shortcode::Block(_)) => depth += 1,
+            Ok(Shortcode::Else) if depth == 0 && else_range.is_none() => {
+                else_range = Some((start, end))
+            }
+            Ok(Shortcode::EndIf | Shortcode::EndFor | Shortcode::End) if depth == 0 => {
+                return Some((else_range, (start, end)))
+            }
+            Ok(Shortcode::EndIf | Shortcode::EndFor | Shortcode::End) => depth -= 1,
+            _ => {}
+        }
+        search_start_idx = end;
+    }
+
+    None
+}
+
+/// Whether the template at the path relative to `templates/` exists in the
+/// templates of the site or the theme.
+pub fn template_exists(config: &Config, path: &Path) -> bool {
+    template_path(config, path).is_some()
+}
+
+/// File of the template at the path relative to `templates/` in the
+/// templates of the site or the theme.
+pub fn template_path(config: &Config, path: &Path) -> Option<PathBuf> {
+    config
+        .template_dirs()
+        .map(|dir| dir.join(path))
+        .find(|file| file.is_file())
+}
+
+/// Names of the functions taking the path of an asset.
+const ASSET_FUNCTIONS: [&str; 4] = ["bust", "asset", "resize_image", "image_placeholder"];
+
+/// Templates and assets found to be used by templates without rendering
+/// them.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    /// Templates relative to `templates/`.
+    pub templates: BTreeSet<PathBuf>,
+    /// Templates relative to `templates/` which do not exist, but are used
+    /// once they are created, e.g. by `include_if_exists`.
+    pub missing: BTreeSet<PathBuf>,
+    /// Assets relative to `assets/` passed to asset functions, e.g. `bust`.
+    pub assets: BTreeSet<PathBuf>,
+    /// Whether an asset function is passed a variable, so the asset is only
+    /// known while rendering.
+    pub dynamic_assets: bool,
+}
+
+impl Dependencies {
+    /// Add the template at the path relative to `templates/` along with the
+    /// templates it includes and the assets it references.
+    ///
+    /// Includes are followed regardless of the conditions around them, so
+    /// these may be more than a single rendering uses. Templates which do
+    /// not exist are collected separately.
+    pub async fn add_template(&mut self, config: &Config, path: &Path) {
+        if self.templates.contains(path) || self.missing.contains(path) {
+            return;
+        }
+        let Ok(input) = read_template(config, path).await else {
+            self.missing.insert(path.to_path_buf());
+            return;
         };
+        self.templates.insert(path.to_path_buf());
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut rest = input.as_str();
+        while let Some((start, end)) = find_shortcode(rest) {
+            match rest[start..end].parse() {
+                Ok(Shortcode::Include(include, _) | Shortcode::IncludeIfExists(include, _)) => {
+                    let include = resolve_include(dir, &include);
+                    Box::pin(self.add_template(config, &include)).await;
+                }
+                Ok(Shortcode::Block(call)) => self.add_shortcode(config, &call.name).await,
+                Ok(Shortcode::Tag(Expression {
+                    operand: Operand::Call(call),
+                    ..
+                })) if ASSET_FUNCTIONS.contains(&call.name.as_str()) => {
+                    let path = call
+                        .args
+                        .iter()
+                        .find_map(|arg| match (&arg.name, &arg.value) {
+                            (None, Literal::String(path)) => Some(path),
+                            (Some(name), Literal::String(path)) if name == "path" => Some(path),
+                            _ => None,
+                        });
+                    match path {
+                        Some(path) => {
+                            self.assets
+                                .insert(PathBuf::from(path.trim_start_matches('/')));
+                        }
+                        None => self.dynamic_assets = true,
+                    }
+                }
+                _ => {}
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    /// Add the template of the shortcode with the name.
+    pub async fn add_shortcode(&mut self, config: &Config, name: &str) {
+        let path = Path::new(SHORTCODE_DIR).join(format!("{}.html", name));
+        Box::pin(self.add_template(config, &path)).await
+    }
+}
+
+/// Read the template at the path relative to `templates/`.
+///
+/// The templates of the site take precedence over the ones of the theme.
+/// Templates outside of the template directories, e.g. by `..` in the path or
+/// symbolic links, are refused.
+async fn read_template(config: &Config, path: &Path) -> std::io::Result<String> {
+    let mut result = Err(std::io::ErrorKind::NotFound.into());
+    for dir in config.template_dirs() {
+        let file = dir.join(path);
+        result = tokio::fs::read_to_string(&file).await;
+        match &result {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(_) => break,
+            Ok(_) => {
+                let root = tokio::fs::canonicalize(&dir).await?;
+                if !tokio::fs::canonicalize(&file).await?.starts_with(root) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::PermissionDenied,
+                        "outside of the template directory",
+                    ));
+                }
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// Read and parse the template at the path relative to `templates/` once for
+/// the context and everything cloned from it.
+///
+/// Errors of parsing the template are returned as the outer error, errors of
+/// reading it as the inner one.
+async fn cached_template(
+    config: &Config,
+    ctx: &Context,
+    path: &Path,
+) -> Result<std::io::Result<Template>> {
+    check_within(path, "templates")?;
+    let cached = ctx
+        .templates
+        .lock()
+        .expect("lock is never poisoned")
+        .get(path)
+        .cloned();
+    let nodes = match cached {
+        Some(nodes) => nodes,
+        None => {
+            let nodes = match read_template(config, path).await {
+                Ok(input) => {
+                    Some(Arc::from(parse(&input).map_err(|e| {
+                        Error::InTemplate(path.to_path_buf(), Box::new(e))
+                    })?))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Ok(Err(e)),
+            };
+            ctx.templates
+                .lock()
+                .expect("lock is never poisoned")
+                .insert(path.to_path_buf(), nodes.clone());
+            nodes
+        }
+    };
+    Ok(nodes.ok_or_else(|| std::io::ErrorKind::NotFound.into()))
+}
+
+/// Read the template file at the path relative to `templates/` and apply its
+/// shortcodes.
+pub async fn template_file(config: &Config, ctx: &Context, path: &Path) -> Result<String> {
+    let nodes = cached_template(config, ctx, path)
+        .await?
+        .map_err(|e| Error::ReadInput(config.content_path.join("templates").join(path), e))?;
+    ctx.record_template(path);
+    check_required(ctx, path, &nodes)?;
+    render_nodes(config, ctx, &nodes, &Scope::for_template(path))
+        .await
+        .map_err(|e| Error::InTemplate(path.to_path_buf(), Box::new(e)))
+}
+
+/// Ensure that all values required by the `{% require %}` commands of the
+/// template at the path exist before it is rendered.
+fn check_required(ctx: &Context, path: &Path, nodes: &[Node]) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Shortcode(Shortcode::Require(vars), _) => {
+                if let Some(var) = vars.iter().find(|var| ctx.get(var).is_none()) {
+                    return Err(Error::MissingRequired(path.to_path_buf(), var.clone()));
+                }
+            }
+            Node::If {
+                then_branch: body,
+                else_branch,
+                ..
+            }
+            | Node::For {
+                body, else_branch, ..
+            } => {
+                check_required(ctx, path, body)?;
+                check_required(ctx, path, else_branch)?;
+            }
+            Node::Block { body, .. } => check_required(ctx, path, body)?,
+            Node::Text(_) | Node::Shortcode(..) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Render the template file once for every page of its pagination.
+///
+/// Templates are rendered with a `paginator` describing the current page. If
+/// `paginate` splits a list into more than one page, the template is rendered
+/// again for every page, of which all but the first are located at
+/// `page/<n>/` below the URL.
+pub async fn template_pages(
+    config: &Config,
+    ctx: &Context,
+    path: &Path,
+    url: &str,
+) -> Result<Vec<String>> {
+    let mut ctx = ctx.clone();
+    ctx.page_count = Arc::default();
+    // Not inserted as value to keep it out of the usage report
+    ctx.values
+        .insert(PAGINATOR.to_string(), paginator_value(config, url, 1, 1));
+    let html = template_file(config, &ctx, path).await?;
+
+    let total = ctx.page_count.load(Ordering::Relaxed);
+    if total <= 1 {
+        return Ok(vec![html]);
+    }
+    let mut pages = Vec::with_capacity(total);
+    for current in 1..=total {
+        ctx.values.insert(
+            PAGINATOR.to_string(),
+            paginator_value(config, url, current, total),
+        );
+        pages.push(template_file(config, &ctx, path).await?);
+    }
+    Ok(pages)
+}
 
-        extract_tag(input)
-            .or_else(|| extract_command(input))
-            .ok_or_else(|| error::Error::ParseShortcode(input.to_string()))
+/// URL of the page of a pagination with the number starting at 1.
+pub fn paginated_url(url: &str, current: usize) -> String {
+    match current {
+        1 => url.to_string(),
+        _ => format!("{}page/{}/", url, current),
     }
 }
 
-/// Find a shortcode within the given input.
-///
-/// This returns the start and end indices including the delimiters.
-/// Essentially this is the range which gives the shortcut itself back from the
-/// input:
+/// Describe the current page of a pagination for templating.
+fn paginator_value(config: &Config, url: &str, current: usize, total: usize) -> Value {
+    let number = |n: usize| Value::Integer(n.try_into().unwrap_or(i64::MAX));
+    let page_url = |n: usize| Value::from(config.url_path(&paginated_url(url, n)));
+    let mut paginator = HashMap::from([
+        ("current".to_string(), number(current)),
+        ("total".to_string(), number(total)),
+        ("first".to_string(), page_url(1)),
+        ("last".to_string(), page_url(total)),
+    ]);
+    if current > 1 {
+        paginator.insert("previous".to_string(), page_url(current - 1));
+    }
+    if current < total {
+        paginator.insert("next".to_string(), page_url(current + 1));
+    }
+    Value::Map(paginator)
+}
+
+/// A part of a template, which is parsed once and rendered by walking the
+/// nodes.
+#[derive(Debug)]
+enum Node {
+    /// Text which is output as is, without the escapes of delimiters.
+    Text(String),
+
+    /// A shortcode outside of blocks, e.g. a tag or an include, along with
+    /// its source.
+    Shortcode(Shortcode, String),
+
+    /// A conditional block.
+    If {
+        condition: String,
+        then_branch: Vec<Node>,
+        else_branch: Vec<Node>,
+    },
+
+    /// A loop block, of which the else branch is rendered for empty lists.
+    For {
+        var: String,
+        list: String,
+        body: Vec<Node>,
+        else_branch: Vec<Node>,
+    },
+
+    /// A block shortcode along with the source of its opening shortcode.
+    Block {
+        call: Call,
+        body: Vec<Node>,
+        source: String,
+    },
+}
+
+/// Nodes of a parsed template file.
+type Template = Arc<[Node]>;
+
+/// Parse the input of a template into its nodes.
 ///
-/// ```rust
-/// let (start, end) = find_shortcode(input);
-/// let shortcode = &input[start..end];
-/// ```
-fn find_shortcode(input: &str) -> Option<(usize, usize)> {
-    let mut search_start_idx = 0;
+/// Blocks are parsed along with all of their branches, so errors are found
+/// regardless of the values the template is rendered with.
+fn parse(input: &str) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    let mut rest = input;
 
-    // Find the first '{' char
-    // This is a perf optimization as all shortcodes start with '{'
-    while let Some(start) = input[search_start_idx..].find(SHORTCODE_START) {
-        // Make start an absolute index
-        let start_abs = search_start_idx + start;
+    while let Some((start, end)) = find_shortcode(rest) {
+        let source = &rest[start..end];
+        let shortcode: Shortcode = source.parse()?;
+        if start > 0 {
+            let mut text = String::new();
+            push_unescaped(&mut text, &rest[..start]);
+            nodes.push(Node::Text(text));
+        }
 
-        // Check the next char to determine type and find the end if it exists
-        let end_abs = match &input[start_abs..] {
-            s if s.starts_with(TAG_START) => s[TAG_START.len()..]
-                .find(TAG_END)
-                .map(|i| start_abs + i + TAG_START.len() + TAG_END.len()),
-            s if s.starts_with(COMMAND_START) => s[COMMAND_START.len()..]
-                .find(COMMAND_END)
-                .map(|i| start_abs + i + COMMAND_START.len() + COMMAND_END.len()),
-            _ => None,
+        rest = match shortcode {
+            Shortcode::If(_) | Shortcode::For { .. } | Shortcode::Block(_) => {
+                // Blocks only consist of the part up to their closing shortcode
+                let body = &rest[end..];
+                let (else_range, (close_start, close_end)) =
+                    find_block_end(body).ok_or_else(|| Error::UnclosedBlock(source.to_string()))?;
+                let closed_by = body[close_start..close_end].parse();
+                match (&shortcode, closed_by) {
+                    (Shortcode::If(_), Ok(Shortcode::EndIf)) => {}
+                    (Shortcode::For { .. }, Ok(Shortcode::EndFor)) => {}
+                    (Shortcode::Block(_), Ok(Shortcode::End)) if else_range.is_none() => {}
+                    _ => return Err(Error::UnclosedBlock(source.to_string())),
+                }
+                let (then_branch, else_branch) = match else_range {
+                    Some((else_start, else_end)) => {
+                        (&body[..else_start], &body[else_end..close_start])
+                    }
+                    None => (&body[..close_start], ""),
+                };
+                let (then_branch, else_branch) = (parse(then_branch)?, parse(else_branch)?);
+                nodes.push(match shortcode {
+                    Shortcode::If(condition) => Node::If {
+                        condition,
+                        then_branch,
+                        else_branch,
+                    },
+                    Shortcode::For { var, list } => Node::For {
+                        var,
+                        list,
+                        body: then_branch,
+                        else_branch,
+                    },
+                    Shortcode::Block(call) => Node::Block {
+                        call,
+                        body: then_branch,
+                        source: source.to_string(),
+                    },
+                    _ => unreachable!("only blocks have a body"),
+                });
+                &body[close_end..]
+            }
+            Shortcode::Else | Shortcode::EndIf | Shortcode::EndFor | Shortcode::End => {
+                return Err(Error::UnexpectedBlock(source.to_string()))
+            }
+            shortcode => {
+                nodes.push(Node::Shortcode(shortcode, source.to_string()));
+                &rest[end..]
+            }
         };
+    }
 
-        // Check if we found a valid end
-        match end_abs {
-            Some(end_abs) => return Some((start_abs, end_abs)),
-            None => search_start_idx = start_abs + 1,
-        }
+    // Append the last part without a shortcode
+    if !rest.is_empty() {
+        let mut text = String::new();
+        push_unescaped(&mut text, rest);
+        nodes.push(Node::Text(text));
     }
 
-    None
+    Ok(nodes)
 }
 
-/// Apply shortcodes to the input template file.
-pub async fn template(config: &Config, ctx: &Context, mut input: String) -> error::Result<String> {
+/// Parse the input of a template and apply its shortcodes.
+async fn render(config: &Config, ctx: &Context, input: &str, scope: &Scope) -> Result<String> {
+    render_nodes(config, ctx, &parse(input)?, scope).await
+}
+
+/// Apply the shortcodes of the nodes of a template.
+///
+/// Values inserted by tags are only expanded again if enabled by
+/// [Config::tag_expansion_depth].
+async fn render_nodes(
+    config: &Config,
+    ctx: &Context,
+    nodes: &[Node],
+    scope: &Scope,
+) -> Result<String> {
     let mut html = String::new();
 
-    while let Some((start, end)) = find_shortcode(&input) {
-        // Parse shortcode
-        let shortcode_str = &input[start..end];
-        let shortcode: Shortcode = shortcode_str.parse()?;
-        // Push all content before the found shortcode to the output HTML
-        html.push_str(&input[..start]);
-        // Push handled shortcode and remaining input to as todo to the new input since
-        // there can be recursively nested shortcodes.
-        input = shortcode.to_html(config, ctx).await? + &input[end..];
+    for node in nodes {
+        match node {
+            Node::Text(text) => html.push_str(text),
+            Node::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let is_set = evaluate_condition(config, ctx, condition).await?;
+                // Only render the branch which was selected by the condition
+                let branch = if is_set { then_branch } else { else_branch };
+                html.push_str(&Box::pin(render_nodes(config, ctx, branch, scope)).await?);
+            }
+            Node::For {
+                var,
+                list,
+                body,
+                else_branch,
+            } => {
+                let items = match list.parse::<Expression>()?.evaluate(config, ctx).await? {
+                    Value::List(items) => items,
+                    _ => return Err(Error::NotAList(list.clone())),
+                };
+                if items.is_empty() {
+                    html.push_str(&Box::pin(render_nodes(config, ctx, else_branch, scope)).await?);
+                }
+                // Every item needs its own context
+                for item in items {
+                    let mut item_ctx = ctx.clone();
+                    item_ctx.insert(var.as_str(), item);
+                    html.push_str(&Box::pin(render_nodes(config, &item_ctx, body, scope)).await?);
+                }
+            }
+            Node::Block { call, body, source } => {
+                // Shortcodes within the body are applied before the markdown
+                let body = Box::pin(render_nodes(config, ctx, body, scope)).await?;
+                let body = shortcodes::markdown_body(config, ctx, &body).await?;
+                let rendered = shortcodes::render_shortcode(config, ctx, call, Some(body))
+                    .await
+                    .map_err(|e| Error::InTag(source.clone(), Box::new(e)))?;
+                html.push_str(&rendered);
+            }
+            Node::Shortcode(shortcode @ Shortcode::Tag(expr), source) => {
+                // Literals are never expanded so `{{ "{{" }}` stays as is
+                let is_literal = matches!(expr.operand, Operand::Literal(_));
+                let mut value = shortcode.to_html(config, ctx, scope).await?;
+                // Values may contain user supplied text, e.g. from the frontmatter
+                if !expr.is_safe() {
+                    let mut escaped = String::with_capacity(value.len());
+                    escape_html(&mut escaped, &value).expect("writing to a string never fails");
+                    // Single quotes are kept by pulldown-cmark but may delimit attributes
+                    value = escaped.replace('\'', "&#39;");
+                }
+                if !is_literal && scope.expansion_depth < config.tag_expansion_depth {
+                    let value_scope = Scope {
+                        expansion_depth: scope.expansion_depth + 1,
+                        ..scope.clone()
+                    };
+                    let expanded = Box::pin(render(config, ctx, &value, &value_scope))
+                        .await
+                        .map_err(|e| Error::InTag(source.clone(), Box::new(e)))?;
+                    html.push_str(&expanded);
+                } else {
+                    html.push_str(&value);
+                }
+            }
+            Node::Shortcode(shortcode, _) => {
+                // Includes are rendered on their own to resolve their relative
+                // includes against their own location
+                html.push_str(&shortcode.to_html(config, ctx, scope).await?);
+            }
+        }
     }
 
-    // Append the last part without a shortcode
-    html.push_str(&input);
-
     Ok(html)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::config;
 
-    fn dummy_config() -> Config {
+    /// Apply shortcodes to the input of a template located in `templates/`.
+    async fn template(config: &Config, ctx: &Context, input: String) -> Result<String> {
+        render(config, ctx, &input, &Scope::default()).await
+    }
+
+    /// Config with the defaults of all options.
+    pub(crate) fn dummy_config() -> Config {
         Config {
             site_info: config::SiteInfo {
                 title: "".to_string(),
                 description: "".to_string(),
+                lang: "en".to_string(),
+                dir: config::TextDirection::Ltr,
             },
+            config_file: "".into(),
             content_path: "".into(),
             output_path: "".into(),
+            state_path: "".into(),
+            base_url: None,
+            absolute_links: false,
+            cdn_url: None,
+            cdn_rewrite: false,
+            fingerprint_assets: false,
+            minify_assets: false,
+            theme_path: None,
+            taxonomies: Vec::new(),
+            authors: Default::default(),
+            author_template: None,
+            series_template: None,
+            slugs: Default::default(),
+            markdown: Default::default(),
+            nav: Vec::new(),
+            nav_groups: false,
+            menus: Default::default(),
+            description_length: 160,
+            date_format: "%Y-%m-%d".into(),
+            typography: None,
+            tag_expansion_depth: 0,
+            sass: None,
+            search_index: false,
+            short_links: None,
+            trailing_slash: config::TrailingSlash::default(),
+            feeds: Vec::new(),
+            sitemap: None,
+            archive: None,
+            accessibility: None,
+            protection: None,
+            social: None,
+            structured_data: false,
+            budgets: None,
+            incremental: false,
+            snapshots: 0,
         }
     }
 
@@ -223,21 +1810,704 @@ mod tests {
         assert!(find_shortcode(input).is_none());
     }
 
+    #[test]
+    fn test_escaped_shortcode() {
+        assert!(find_shortcode(r"\{{ x }}").is_none());
+        assert_eq!(Some((5, 9)), find_shortcode(r"\{%%}{%%}"));
+    }
+
+    #[tokio::test]
+    async fn test_escaped_delimiters() {
+        let ctx = Context::from_iter([("x", "1")]);
+        let input = r#"\{{ x }} \{% if %} {{ "{{" }} x }} \n {{ x }}"#.to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!(r"{{ x }} {% if %} {{ x }} \n 1", html);
+    }
+
     #[test]
     fn test_parse_include_shortcode() {
         let input = "{% include \"folder/head.html\" %}";
         let shortcode: Shortcode = input.parse().unwrap();
-        assert_eq!(Shortcode::Include("folder/head.html".into()), shortcode);
+        assert_eq!(
+            Shortcode::Include("folder/head.html".into(), Vec::new()),
+            shortcode
+        );
+    }
+
+    #[test]
+    fn test_parse_command_args() {
+        let args =
+            parse_command_args(r#""card.html" title = "Hi \"you\"" link=page.url n=3"#).unwrap();
+        let named = |name: &str, value| Argument {
+            name: Some(name.to_string()),
+            value,
+        };
+        assert_eq!(
+            vec![
+                Argument {
+                    name: None,
+                    value: Literal::String("card.html".to_string())
+                },
+                named("title", Literal::String("Hi \"you\"".to_string())),
+                named("link", Literal::Variable("page.url".to_string())),
+                named("n", Literal::Integer(3)),
+            ],
+            args
+        );
+        assert!(parse_command_args("title=").is_err());
+        assert!(parse_command_args(r#"title="open"#).is_err());
+    }
+
+    #[test]
+    fn test_resolve_include() {
+        let dir = Path::new("blog/layouts");
+        let resolve = |path: &str| resolve_include(dir, Path::new(path));
+        assert_eq!(PathBuf::from("head.html"), resolve("head.html"));
+        assert_eq!(PathBuf::from("blog/layouts/a.html"), resolve("./a.html"));
+        assert_eq!(PathBuf::from("blog/a.html"), resolve("../a.html"));
+        assert_eq!(PathBuf::from("../a.html"), resolve("../../../a.html"));
+    }
+
+    #[test]
+    fn test_parse_if_shortcodes() {
+        let shortcode: Shortcode = "{% if date %}".parse().unwrap();
+        assert_eq!(Shortcode::If("date".into()), shortcode);
+        let shortcode: Shortcode = "{% else %}".parse().unwrap();
+        assert_eq!(Shortcode::Else, shortcode);
+        let shortcode: Shortcode = "{%endif%}".parse().unwrap();
+        assert_eq!(Shortcode::EndIf, shortcode);
+        assert!("{% if %}".parse::<Shortcode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_if_block() {
+        let input = "a{% if x %}b{{ x }}{% else %}c{% endif %}d".to_string();
+        let ctx = Context::from_iter([("x", "1")]);
+        let html = template(&dummy_config(), &ctx, input.clone())
+            .await
+            .unwrap();
+        assert_eq!("ab1d", html);
+        let html = template(&dummy_config(), &Context::new(), input)
+            .await
+            .unwrap();
+        assert_eq!("acd", html);
+    }
+
+    #[tokio::test]
+    async fn test_if_comparison() {
+        let input = "{% if date > now() | date_add(years=-1) %}new{% else %}old{% endif %}";
+        let recent = crate::format_date_iso8601(&time::OffsetDateTime::now_utc());
+        let ctx = Context::from_iter([("date", recent.as_str())]);
+        let html = template(&dummy_config(), &ctx, input.to_string())
+            .await
+            .unwrap();
+        assert_eq!("new", html);
+        let ctx = Context::from_iter([("date", "2000-01-01")]);
+        let html = template(&dummy_config(), &ctx, input.to_string())
+            .await
+            .unwrap();
+        assert_eq!("old", html);
+
+        let input = r#"{% if n >= 2 %}a{% endif %}{% if s == "x" %}b{% endif %}{% if m != 1 %}c{% endif %}"#;
+        let ctx = Context::from_iter([("n", Value::Integer(2)), ("s", "x".into())]);
+        let html = template(&dummy_config(), &ctx, input.to_string())
+            .await
+            .unwrap();
+        assert_eq!("ab", html);
+    }
+
+    #[tokio::test]
+    async fn test_raw_block() {
+        let input = "{% if x %}{% raw %}{{ x }} {% endif %} \\{{{% endraw %}{% endif %}{{ x }}";
+        let ctx = Context::from_iter([("x", "1")]);
+        let html = template(&dummy_config(), &ctx, input.to_string())
+            .await
+            .unwrap();
+        assert_eq!("{{ x }} {% endif %} \\{{1", html);
+        assert!(
+            template(&dummy_config(), &ctx, "{% raw %}{{ x }}".to_string())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_if_block() {
+        let input = "{% if x %}{% if y %}y{% else %}n{% endif %}{% endif %}.".to_string();
+        let ctx = Context::from_iter([("x", "1")]);
+        let html = template(&dummy_config(), &ctx, input.clone())
+            .await
+            .unwrap();
+        assert_eq!("n.", html);
+        let html = template(&dummy_config(), &Context::new(), input)
+            .await
+            .unwrap();
+        assert_eq!(".", html);
+    }
+
+    #[test]
+    fn test_parse_for_shortcodes() {
+        let shortcode: Shortcode = "{% for page in pages %}".parse().unwrap();
+        assert_eq!(
+            Shortcode::For {
+                var: "page".into(),
+                list: "pages".into()
+            },
+            shortcode
+        );
+        let shortcode: Shortcode = "{% endfor %}".parse().unwrap();
+        assert_eq!(Shortcode::EndFor, shortcode);
+        assert!("{% for page %}".parse::<Shortcode>().is_err());
+    }
+
+    #[test]
+    fn test_context_nested_get() {
+        let page: Value = [("title", "Hello")].into_iter().collect();
+        let ctx = Context::from_iter([("page", page)]);
+        assert_eq!(Some(&Value::from("Hello")), ctx.get("page.title"));
+        assert_eq!(None, ctx.get("page.date"));
+        assert_eq!(None, ctx.get("page.title.length"));
+    }
+
+    #[tokio::test]
+    async fn test_for_block() {
+        let pages: Vec<Value> = ["a", "b"]
+            .into_iter()
+            .map(|title| [("title", title)].into_iter().collect())
+            .collect();
+        let ctx = Context::from_iter([("pages", pages)]);
+        let input = "{% for page in pages %}<{{ page.title }}>{% endfor %}".to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("<a><b>", html);
+    }
+
+    #[tokio::test]
+    async fn test_typed_values() {
+        let ctx = Context::from_iter([
+            ("n", Value::Integer(0)),
+            ("yes", Value::Bool(true)),
+            ("no", Value::Bool(false)),
+        ]);
+        let input =
+            "{{ n }}{% if n %}!{% endif %}{% if yes %}{{ yes }}{% endif %}{% if no %}!{% endif %}"
+                .to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("0true", html);
+    }
+
+    #[tokio::test]
+    async fn test_for_else_block() {
+        let ctx = Context::from_iter([("pages", Vec::<Value>::new())]);
+        let input = "{% for p in pages %}{{ p }}{% else %}none{% endfor %}".to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("none", html);
+    }
+
+    #[test]
+    fn test_check_required() {
+        let shortcode: Shortcode = "{% require title, page.date %}".parse().unwrap();
+        assert_eq!(
+            Shortcode::Require(vec!["title".into(), "page.date".into()]),
+            shortcode
+        );
+
+        let ctx = Context::from_iter([("title", "Title")]);
+        let input = "<h1>{{ title }}</h1>{% if x %}{% require title, page.date %}{% endif %}";
+        let err = check_required(&ctx, Path::new("post.html"), &parse(input).unwrap()).unwrap_err();
+        assert!(matches!(err, Error::MissingRequired(_, var) if var == "page.date"));
+        let nodes = parse("{% require title %}").unwrap();
+        assert!(check_required(&ctx, Path::new("post.html"), &nodes).is_ok());
+    }
+
+    #[test]
+    fn test_parse() {
+        let nodes =
+            parse("<ul>{% for p in pages %}<li>{{ p }}</li>{% else %}-{% endfor %}</ul>").unwrap();
+        let [Node::Text(start), Node::For {
+            body, else_branch, ..
+        }, Node::Text(end)] = nodes.as_slice()
+        else {
+            panic!("unexpected nodes {:?}", nodes);
+        };
+        assert_eq!(("<ul>", "</ul>"), (start.as_str(), end.as_str()));
+        assert_eq!(3, body.len());
+        assert!(matches!(else_branch.as_slice(), [Node::Text(text)] if text == "-"));
+
+        // Branches are parsed even if they are never rendered
+        let err = parse("{% if false %}{% endfor %}{% endif %}").unwrap_err();
+        assert!(matches!(err, Error::UnclosedBlock(_)));
+        let err = parse("{% if x %}{{ a | }}{% endif %}").unwrap_err();
+        assert!(matches!(err, Error::ParseShortcode(_)));
+    }
+
+    #[tokio::test]
+    async fn test_paginate() {
+        let numbers: Vec<Value> = (1..=5).map(Value::from).collect();
+        let mut ctx = Context::from_iter([("numbers", numbers)]);
+        let input = "{% for n in paginate(numbers, 2) %}{{ n }}{% endfor %}".to_string();
+        let html = template(&dummy_config(), &ctx, input.clone())
+            .await
+            .unwrap();
+        assert_eq!("12", html);
+        assert_eq!(3, ctx.page_count.load(Ordering::Relaxed));
+
+        ctx.insert(PAGINATOR, paginator_value(&dummy_config(), "/links/", 3, 3));
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("5", html);
+        assert_eq!(
+            Some(&Value::from("/links/page/2/")),
+            ctx.get("paginator.previous")
+        );
+        assert_eq!(None, ctx.get("paginator.next"));
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_block_end() {
+        let ctx = Context::from_iter([("x", "1")]);
+        let input = "{% if x %}{% endfor %}".to_string();
+        assert!(template(&dummy_config(), &ctx, input).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unbalanced_if_block() {
+        let ctx = Context::new();
+        let input = "{% if x %}".to_string();
+        assert!(template(&dummy_config(), &ctx, input).await.is_err());
+        let input = "{% endif %}".to_string();
+        assert!(template(&dummy_config(), &ctx, input).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_tag_with_filters() {
+        let shortcode: Shortcode = r#"{{ title | replace("|", ", ") | truncate(12) }}"#
+            .parse()
+            .unwrap();
+        let positional = |value| Argument { name: None, value };
+        let expected = Expression {
+            operand: Operand::Variable("title".into()),
+            filters: vec![
+                Call {
+                    name: "replace".into(),
+                    args: vec![
+                        positional(Literal::String("|".into())),
+                        positional(Literal::String(", ".into())),
+                    ],
+                },
+                Call {
+                    name: "truncate".into(),
+                    args: vec![positional(Literal::Integer(12))],
+                },
+            ],
+        };
+        assert_eq!(Shortcode::Tag(expected), shortcode);
+        assert!("{{ title | }}".parse::<Shortcode>().is_err());
+        assert!("{{ title | truncate(12 }}".parse::<Shortcode>().is_err());
+    }
+
+    #[test]
+    fn test_parse_function_call() {
+        let shortcode: Shortcode = r#"{{ bust "style.css" }}"#.parse().unwrap();
+        let expected = Call {
+            name: "bust".into(),
+            args: vec![Argument {
+                name: None,
+                value: Literal::String("style.css".into()),
+            }],
+        };
+        assert_eq!(
+            Shortcode::Tag(Expression {
+                operand: Operand::Call(expected),
+                filters: Vec::new()
+            }),
+            shortcode
+        );
+
+        let call: Call = r#"resize(path="a=b.jpg", width=800)"#.parse().unwrap();
+        assert_eq!(Some("path"), call.args[0].name.as_deref());
+        assert_eq!(Literal::String("a=b.jpg".into()), call.args[0].value);
+        assert_eq!(Literal::Integer(800), call.args[1].value);
+    }
+
+    #[tokio::test]
+    async fn test_tag_with_filters() {
+        let ctx = Context::from_iter([("title", "hello world")]);
+        let input = "{{ title | upper | truncate(8) }}".to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("HELLO W…", html);
+    }
+
+    #[tokio::test]
+    async fn test_default_filter() {
+        let ctx = Context::from_iter([("title", "hello")]);
+        let input = r#"{{ excerpt | upper | default("none") }}, {{ excerpt | default(title) | upper }}, {{ title | default("none") }}"#;
+        let html = template(&dummy_config(), &ctx, input.to_string())
+            .await
+            .unwrap();
+        assert_eq!("none, HELLO, hello", html);
+        assert!(
+            template(&dummy_config(), &ctx, "{{ excerpt | upper }}".to_string())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage_tracking() {
+        let usage = Arc::new(Usage::default());
+        let mut ctx = Context::new();
+        ctx.track_usage(usage.clone());
+        ctx.insert("used", "1");
+        ctx.insert("unused", "2");
+        let input = "{% if used %}{{ used }}{% endif %}".to_string();
+        template(&dummy_config(), &ctx.clone(), input)
+            .await
+            .unwrap();
+        assert_eq!(vec!["unused".to_string()], usage.unreferenced_values());
+    }
+
+    #[tokio::test]
+    async fn test_include_outside_templates() {
+        let dir = std::env::temp_dir().join(format!("fweb-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/inner.html"), "inner").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        let input = r#"{% include "inner.html" %}"#.to_string();
+        let html = template(&config, &Context::new(), input).await.unwrap();
+        assert_eq!("inner", html);
+        for path in ["../secret.txt", "./../secret.txt"] {
+            let input = format!(r#"{{% include "{}" %}}"#, path);
+            let err = template(&config, &Context::new(), input).await.unwrap_err();
+            assert!(
+                matches!(err, Error::OutsideDirectory(path, "templates") if path == Path::new("../secret.txt"))
+            );
+        }
+        // Links are resolved by the file system
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("templates/link.html"))
+                .unwrap();
+            let input = r#"{% include "link.html" %}"#.to_string();
+            let err = template(&config, &Context::new(), input).await.unwrap_err();
+            assert!(
+                matches!(err, Error::IncludeShortcode(_, e) if e.kind() == std::io::ErrorKind::PermissionDenied)
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_asset_outside_assets() {
+        for input in [
+            r#"{{ bust("../config.toml") }}"#,
+            r#"{{ asset("/../config.toml") }}"#,
+        ] {
+            let err = template(&dummy_config(), &Context::new(), input.to_string())
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, Error::OutsideDirectory(_, "assets")),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_template_exists() {
+        let dir = std::env::temp_dir().join(format!("fweb-exists-{}", std::process::id()));
+        let theme = dir.join("theme");
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::create_dir_all(theme.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "page").unwrap();
+        std::fs::write(theme.join("templates/index.html"), "index").unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            theme_path: Some(theme),
+            ..dummy_config()
+        };
+
+        assert!(template_exists(&config, Path::new("page.html")));
+        assert!(template_exists(&config, Path::new("index.html")));
+        assert!(!template_exists(&config, Path::new("post.html")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cached_template() {
+        let dir = std::env::temp_dir().join(format!("fweb-cached-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "first").unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        let ctx = Context::new();
+        let path = Path::new("page.html");
+        assert_eq!("first", template_file(&config, &ctx, path).await.unwrap());
+        std::fs::write(dir.join("templates/page.html"), "second").unwrap();
+        // Clones share the templates read before, a new context reads again
+        let page_ctx = ctx.clone();
+        assert_eq!(
+            "first",
+            template_file(&config, &page_ctx, path).await.unwrap()
+        );
+        // So do the contexts of shortcodes, without the values
+        let mut page_ctx = page_ctx;
+        page_ctx.insert("title", "Page");
+        let shortcode_ctx = page_ctx.shortcode_context();
+        assert_eq!(None, shortcode_ctx.get("title"));
+        assert_eq!(
+            "first",
+            template_file(&config, &shortcode_ctx, path).await.unwrap()
+        );
+        let new_ctx = Context::new();
+        assert_eq!(
+            "second",
+            template_file(&config, &new_ctx, path).await.unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recursive_shortcode() {
+        let dir = std::env::temp_dir().join(format!("fweb-recursive-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates/shortcodes")).unwrap();
+        std::fs::write(
+            dir.join("templates/shortcodes/note.html"),
+            "{% note %}x{% end %}",
+        )
+        .unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        /// Whether the error or one it wraps is the depth of the shortcode.
+        fn is_depth(err: &Error) -> bool {
+            match err {
+                Error::ShortcodeDepth(name) => name == "note",
+                Error::InTag(_, e) | Error::InTemplate(_, e) => is_depth(e),
+                _ => false,
+            }
+        }
+        let input = "{% note %}x{% end %}".to_string();
+        let err = template(&config, &Context::new(), input).await.unwrap_err();
+        assert!(is_depth(&err), "{err}");
+        let err = shortcodes::expand(&config, &Context::new(), "{% note %}x{% end %}")
+            .await
+            .unwrap_err();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(is_depth(&err), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_theme_templates() {
+        let dir = std::env::temp_dir().join(format!("fweb-theme-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("site/templates")).unwrap();
+        std::fs::create_dir_all(dir.join("theme/templates")).unwrap();
+        std::fs::write(dir.join("site/templates/nav.html"), "site nav").unwrap();
+        std::fs::write(dir.join("theme/templates/nav.html"), "theme nav").unwrap();
+        std::fs::write(dir.join("theme/templates/footer.html"), "theme footer").unwrap();
+        let config = Config {
+            content_path: dir.join("site"),
+            theme_path: Some(dir.join("theme")),
+            ..dummy_config()
+        };
+
+        let input = r#"{% include "nav.html" %}, {% include "footer.html" %}"#.to_string();
+        let html = template(&config, &Context::new(), input).await.unwrap();
+        assert_eq!("site nav, theme footer", html);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_include_with_args() {
+        let dir = std::env::temp_dir().join(format!("fweb-include-args-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/card.html"),
+            "{% require title %}<a href=\"{{ link }}\">{{ title }}</a>",
+        )
+        .unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        let ctx = Context::from_iter([("url", "/x/")]);
+        let input = r#"{% include "card.html" title="Hi" link=url %}{{ title }}"#.to_string();
+        let err = template(&config, &ctx, input).await.unwrap_err();
+        assert!(matches!(err, Error::TagNotFound(var) if var == "title"));
+        let input = r#"{% include "card.html" title="Hi" link=url %}"#.to_string();
+        let html = template(&config, &ctx, input).await.unwrap();
+        assert_eq!(r#"<a href="/x/">Hi</a>"#, html);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_template_dependencies() {
+        let dir = std::env::temp_dir().join(format!("fweb-dependencies-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates/shortcodes")).unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{% include \"head.html\" %}{% if x %}{% include_if_exists \"missing.html\" %}\
+             {% endif %}{% note %}a{% end %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/head.html"),
+            "{{ bust \"/style.css\" }}{{ resize_image(path=\"a.png\", width=10) }}\
+             {% include \"page.html\" %}",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/shortcodes/note.html"), "{{ body }}").unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        let mut dependencies = Dependencies::default();
+        dependencies
+            .add_template(&config, Path::new("page.html"))
+            .await;
+        assert_eq!(
+            Dependencies {
+                templates: BTreeSet::from([
+                    "head.html".into(),
+                    "page.html".into(),
+                    "shortcodes/note.html".into()
+                ]),
+                missing: BTreeSet::from(["missing.html".into()]),
+                assets: BTreeSet::from(["a.png".into(), "style.css".into()]),
+                dynamic_assets: false,
+            },
+            dependencies
+        );
+
+        // The asset passed as a variable is only known while rendering
+        std::fs::write(
+            dir.join("templates/cover.html"),
+            "{{ image_placeholder(path=page.cover) }}",
+        )
+        .unwrap();
+        let mut dependencies = Dependencies::default();
+        dependencies
+            .add_template(&config, Path::new("cover.html"))
+            .await;
+        assert!(dependencies.dynamic_assets);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_block_shortcode() {
+        let dir = std::env::temp_dir().join(format!("fweb-shortcodes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("templates/shortcodes")).unwrap();
+        std::fs::write(
+            dir.join("templates/shortcodes/note.html"),
+            "<aside class=\"{{ kind }}\">{{ body | safe }}</aside>\n",
+        )
+        .unwrap();
+        let config = Config {
+            content_path: dir.clone(),
+            ..dummy_config()
+        };
+
+        let input = "{% note kind=\"tip\" %}\n    Read **{{ x }}**\n{% end %}".to_string();
+        let ctx = Context::from_iter([("x", "this")]);
+        let html = template(&config, &ctx, input).await.unwrap();
+        assert_eq!(
+            "<aside class=\"tip\"><p>Read <strong>this</strong></p>\n</aside>",
+            html
+        );
+        let input = "{% note %}a{% endif %}".to_string();
+        let err = template(&config, &ctx, input).await.unwrap_err();
+        assert!(matches!(err, Error::UnclosedBlock(_)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_include_if_exists_missing() {
+        let input = r#"a{% include_if_exists "does/not/exist.html" %}b"#.to_string();
+        let html = template(&dummy_config(), &Context::new(), input)
+            .await
+            .unwrap();
+        assert_eq!("ab", html);
+
+        let input = r#"{% include "does/not/exist.html" %}"#.to_string();
+        assert!(template(&dummy_config(), &Context::new(), input)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tag_values_are_not_expanded() {
+        let ctx = Context::from_iter([("a", "{{ b }}"), ("b", "{ x")]);
+        let input = "{{ a }}".to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("{{ b }}", html);
+    }
+
+    #[tokio::test]
+    async fn test_recursive_tag_expansion() {
+        let mut config = dummy_config();
+        config.tag_expansion_depth = 1;
+        let ctx = Context::from_iter([("a", "{{ b }}"), ("b", "{{ a }}")]);
+        let input = "{{ a }}".to_string();
+        let html = template(&config, &ctx, input).await.unwrap();
+        assert_eq!("{{ a }}", html);
+
+        let ctx = Context::from_iter([("a", "{{ missing }}")]);
+        let input = "{{ a }}".to_string();
+        assert!(template(&config, &ctx, input).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_escaped_values() {
+        let ctx = Context::from_iter([
+            ("title", "Fish & <Chips>"),
+            ("content", "<p>Hi</p>"),
+            ("alt", "Ann's \"best\""),
+        ]);
+        let input = r#"<h1>{{ title }}</h1>{{ content | safe }}{{ "<hr>" }}<img alt='{{ alt }}'>"#
+            .to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!(
+            "<h1>Fish &amp; &lt;Chips&gt;</h1><p>Hi</p><hr><img alt='Ann&#39;s &quot;best&quot;'>",
+            html
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extra_values() {
+        let extra: toml::Table = toml::from_str(
+            "cover = \"a.png\"\nratio = 1.5\ntags = [\"x\", \"y\"]\n[social]\nimage = \"b.png\"",
+        )
+        .unwrap();
+        let ctx = Context::from_iter([("extra", toml::Value::Table(extra))]);
+        let input = "{{ extra.cover }} {{ extra.ratio }} {{ extra.social.image }}\
+                     {% for tag in extra.tags %} {{ tag }}{% endfor %}"
+            .to_string();
+        let html = template(&dummy_config(), &ctx, input).await.unwrap();
+        assert_eq!("a.png 1.5 b.png x y", html);
     }
 
     #[tokio::test]
     async fn test_existing_tag() {
         let input = "{{ test }}";
         let shortcode: Shortcode = input.parse().unwrap();
-        let ctx = Context::from_iter([("test", "value".to_string())]);
+        let ctx = Context::from_iter([("test", "value")]);
         assert_eq!(
             "value",
-            shortcode.to_html(&dummy_config(), &ctx).await.unwrap()
+            shortcode
+                .to_html(&dummy_config(), &ctx, &Scope::default())
+                .await
+                .unwrap()
         );
     }
 
@@ -246,7 +2516,7 @@ mod tests {
         let input = "{{ test }}";
         let shortcode: Shortcode = input.parse().unwrap();
         assert!(shortcode
-            .to_html(&dummy_config(), &Context::new())
+            .to_html(&dummy_config(), &Context::new(), &Scope::default())
             .await
             .is_err());
     }
@@ -0,0 +1,694 @@
+//! Built-in filters which transform tag values, e.g. `{{ title | upper }}`.
+
+use time::{
+    format_description::{self, well_known::Iso8601, OwnedFormatItem},
+    Date, Duration, Month, OffsetDateTime, PrimitiveDateTime,
+};
+
+use super::{Argument, Literal, Value};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+
+/// Signature of a filter.
+///
+/// A filter receives the value and its arguments and returns the transformed
+/// value or a reason why it could not be applied.
+type Filter = fn(Value, &[Argument]) -> std::result::Result<Value, String>;
+
+/// Signature of a filter depending on the config of the site.
+type SiteFilter = fn(&Config, Value, &[Argument]) -> std::result::Result<Value, String>;
+
+/// Name of the filter giving the value of missing variables.
+pub const DEFAULT: &str = "default";
+
+/// Name of the filter marking a value as trusted HTML, which is inserted
+/// without escaping.
+pub const SAFE: &str = "safe";
+
+/// All available filters by their name.
+const FILTERS: &[(&str, Filter)] = &[
+    ("upper", upper),
+    ("lower", lower),
+    ("capitalize", capitalize),
+    ("trim", trim),
+    ("truncate", truncate),
+    ("striptags", striptags),
+    ("replace", replace),
+    ("length", length),
+    ("first", first),
+    ("last", last),
+    ("join", join),
+    ("where", where_matches),
+    ("take", take),
+    (DEFAULT, default),
+    (SAFE, safe),
+];
+
+/// All available filters depending on the config by their name.
+const SITE_FILTERS: &[(&str, SiteFilter)] = &[
+    ("format", format),
+    ("date_add", date_add),
+    ("sort", sort),
+    ("date", date),
+];
+
+/// Apply the filter with the given name to the value.
+pub fn apply(config: &Config, name: &str, value: Value, args: &[Argument]) -> Result<Value> {
+    let result = match FILTERS.iter().find(|(filter_name, _)| *filter_name == name) {
+        Some((_, filter)) => filter(value, args),
+        None => {
+            let (_, filter) = SITE_FILTERS
+                .iter()
+                .find(|(filter_name, _)| *filter_name == name)
+                .ok_or_else(|| Error::UnknownFilter(name.to_string()))?;
+            filter(config, value, args)
+        }
+    };
+    result.map_err(|reason| Error::Filter(name.to_string(), reason))
+}
+
+/// Get the text of a value.
+fn text(value: Value) -> std::result::Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err("value is not text".to_string()),
+    }
+}
+
+/// Get the list of a value.
+fn list(value: Value) -> std::result::Result<Vec<Value>, String> {
+    match value {
+        Value::List(l) => Ok(l),
+        _ => Err("value is not a list".to_string()),
+    }
+}
+
+/// Get the positional argument at the given position.
+fn positional(args: &[Argument], i: usize) -> Option<&Literal> {
+    args.iter()
+        .filter(|arg| arg.name.is_none())
+        .nth(i)
+        .map(|arg| &arg.value)
+}
+
+/// Get the positional argument at the given position as text.
+fn text_arg(args: &[Argument], i: usize) -> std::result::Result<&str, String> {
+    match positional(args, i) {
+        Some(Literal::String(s)) => Ok(s),
+        Some(_) => Err(format!("argument {} must be a string", i + 1)),
+        None => Err(format!("missing argument {}", i + 1)),
+    }
+}
+
+/// Get the positional argument at the given position as a non-negative
+/// integer.
+fn usize_arg(args: &[Argument], i: usize) -> std::result::Result<usize, String> {
+    match positional(args, i) {
+        Some(Literal::Integer(n)) => {
+            usize::try_from(*n).map_err(|_| format!("argument {} must not be negative", i + 1))
+        }
+        Some(_) => Err(format!("argument {} must be an integer", i + 1)),
+        None => Err(format!("missing argument {}", i + 1)),
+    }
+}
+
+/// Get the value of a literal argument.
+fn literal_value(literal: &Literal) -> std::result::Result<Value, String> {
+    match literal {
+        Literal::String(s) => Ok(s.as_str().into()),
+        Literal::Integer(n) => Ok((*n).into()),
+        Literal::Bool(b) => Ok((*b).into()),
+        Literal::Variable(var) => Err(format!("'{}' must be a literal", var)),
+    }
+}
+
+/// Keep the value as is, as only its tag is affected by being safe, see
+/// `Expression::is_safe`.
+fn safe(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    match args {
+        [] => Ok(value),
+        _ => Err("expects no arguments".to_string()),
+    }
+}
+
+/// Keep the value as is, as only missing variables are replaced by the default,
+/// see `Expression::evaluate`.
+fn default(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    match args {
+        [Argument { name: None, .. }] => Ok(value),
+        _ => Err("expects a single value".to_string()),
+    }
+}
+
+fn upper(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    Ok(text(value)?.to_uppercase().into())
+}
+
+fn lower(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    Ok(text(value)?.to_lowercase().into())
+}
+
+/// Uppercase the first character.
+fn capitalize(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    let s = text(value)?;
+    let mut chars = s.chars();
+    Ok(match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>().into(),
+        None => s.into(),
+    })
+}
+
+fn trim(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    Ok(text(value)?.trim().into())
+}
+
+/// Shorten text to at most the given number of characters, ending with `…`.
+fn truncate(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let s = text(value)?;
+    let len = usize_arg(args, 0)?;
+    if s.chars().count() <= len {
+        return Ok(s.into());
+    }
+    let mut truncated: String = s.chars().take(len.saturating_sub(1)).collect();
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push('…');
+    Ok(truncated.into())
+}
+
+/// Remove all HTML tags.
+fn striptags(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    let s = text(value)?;
+    let mut stripped = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    Ok(stripped.into())
+}
+
+fn replace(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let s = text(value)?;
+    Ok(s.replace(text_arg(args, 0)?, text_arg(args, 1)?).into())
+}
+
+/// Number of characters of a text or items of a list.
+fn length(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    let len = match value {
+        Value::String(s) => s.chars().count(),
+        Value::List(l) => l.len(),
+        Value::Map(m) => m.len(),
+        _ => return Err("value has no length".to_string()),
+    };
+    Ok(Value::Integer(len as i64))
+}
+
+fn first(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    list(value)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "list is empty".to_string())
+}
+
+fn last(value: Value, _args: &[Argument]) -> std::result::Result<Value, String> {
+    list(value)?
+        .into_iter()
+        .last()
+        .ok_or_else(|| "list is empty".to_string())
+}
+
+/// Join a list of texts with the given separator.
+fn join(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let separator = text_arg(args, 0)?;
+    let items = list(value)?
+        .into_iter()
+        .map(text)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(items.join(separator).into())
+}
+
+/// Keep the items whose value at the key matches, e.g. `where(draft=false)` or
+/// `where("extra.category", "rust")`.
+///
+/// Lists match if they contain the value. Taxonomy terms are matched by their
+/// name, either at the key or within `taxonomies`, e.g. `where(tags="rust")`.
+fn where_matches(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let items = list(value)?;
+    let (key, expected) = match args {
+        [Argument {
+            name: Some(key),
+            value,
+        }] => (key.as_str(), literal_value(value)?),
+        [Argument {
+            name: None,
+            value: Literal::String(key),
+        }, Argument { name: None, value }] => (key.as_str(), literal_value(value)?),
+        _ => return Err("expects a key and the value to match".to_string()),
+    };
+
+    fn matches(value: &Value, expected: &Value) -> bool {
+        match value {
+            Value::List(items) => items.iter().any(|item| matches(item, expected)),
+            Value::Map(term) => term.get("name") == Some(expected),
+            value => value == expected,
+        }
+    }
+    let taxonomy_key = format!("taxonomies.{}", key);
+    Ok(items
+        .into_iter()
+        .filter(|item| {
+            item.get_path(key)
+                .or_else(|| item.get_path(&taxonomy_key))
+                .is_some_and(|value| matches(value, &expected))
+        })
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Key to sort values by, dates before other text.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Bool(bool),
+    Integer(i64),
+    Date(OffsetDateTime),
+    Text(String),
+}
+
+impl SortKey {
+    fn new(config: &Config, value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(SortKey::Bool(*b)),
+            Value::Integer(n) => Some(SortKey::Integer(*n)),
+            Value::String(s) => Some(match parse_date(config, s) {
+                Ok(date) => SortKey::Date(date),
+                Err(_) => SortKey::Text(s.clone()),
+            }),
+            Value::List(_) | Value::Map(_) => None,
+        }
+    }
+}
+
+/// Sort a list ascending, or descending with `desc=true`, optionally by the
+/// value at a key of every item, e.g. `sort(by="date", desc=true)`.
+///
+/// Items without text, a number or a bool at the key are sorted last.
+fn sort(config: &Config, value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let items = list(value)?;
+    let mut by = None;
+    let mut desc = false;
+    for arg in args {
+        match (arg.name.as_deref(), &arg.value) {
+            (Some("by"), Literal::String(key)) => by = Some(key.as_str()),
+            (Some("desc"), Literal::Bool(b)) => desc = *b,
+            _ => return Err("arguments must be by=\"key\" and desc=true".to_string()),
+        }
+    }
+
+    let mut keyed: Vec<_> = items
+        .into_iter()
+        .map(|item| {
+            let key = match by {
+                Some(by) => item
+                    .get_path(by)
+                    .and_then(|value| SortKey::new(config, value)),
+                None => SortKey::new(config, &item),
+            };
+            (key, item)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if desc => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    Ok(keyed
+        .into_iter()
+        .map(|(_, item)| item)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Keep the first items of a list.
+fn take(value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let mut items = list(value)?;
+    items.truncate(usize_arg(args, 0)?);
+    Ok(items.into())
+}
+
+/// Format description of a pattern as of `strftime`, e.g. `%B %d, %Y`.
+///
+/// Supported are `%Y`, `%y`, `%m`, `%d`, `%e`, `%B`, `%b`, `%A`, `%a`, `%H`,
+/// `%M`, `%S`, `%j` and `%%`, names are in English.
+fn strftime_description(pattern: &str) -> std::result::Result<OwnedFormatItem, String> {
+    let mut description = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let component = match c {
+            '%' => match chars.next() {
+                Some('Y') => "[year]",
+                Some('y') => "[year repr:last_two]",
+                Some('m') => "[month]",
+                Some('d') => "[day]",
+                Some('e') => "[day padding:none]",
+                Some('B') => "[month repr:long]",
+                Some('b') => "[month repr:short]",
+                Some('A') => "[weekday repr:long]",
+                Some('a') => "[weekday repr:short]",
+                Some('H') => "[hour]",
+                Some('M') => "[minute]",
+                Some('S') => "[second]",
+                Some('j') => "[ordinal]",
+                Some('%') => "%",
+                Some(c) => return Err(format!("unknown conversion '%{}'", c)),
+                None => return Err("pattern ends with '%'".to_string()),
+            },
+            '[' => "\\[",
+            '\\' => "\\\\",
+            c => {
+                description.push(c);
+                continue;
+            }
+        };
+        description.push_str(component);
+    }
+    format_description::parse_owned::<2>(&description).map_err(|e| e.to_string())
+}
+
+/// Format a date in UTC with a pattern as of `strftime`, e.g. `%B %d, %Y`.
+pub fn format_date(date: &OffsetDateTime, pattern: &str) -> std::result::Result<String, String> {
+    date.to_offset(time::UtcOffset::UTC)
+        .format(&strftime_description(pattern)?)
+        .map_err(|e| e.to_string())
+}
+
+/// Parse a date given in ISO 8601 or formatted by the date format of the
+/// site, in UTC.
+///
+/// Dates formatted without a time are at midnight.
+pub fn parse_date(config: &Config, s: &str) -> std::result::Result<OffsetDateTime, String> {
+    let err = || format!("'{}' is not a date", s);
+    if let Ok(date) = OffsetDateTime::parse(s, &Iso8601::DEFAULT) {
+        return Ok(date);
+    }
+    let description = strftime_description(&config.date_format).map_err(|_| err())?;
+    PrimitiveDateTime::parse(s, &description)
+        .or_else(|_| Date::parse(s, &description).map(Date::midnight))
+        .map(PrimitiveDateTime::assume_utc)
+        .map_err(|_| err())
+}
+
+/// Shift a date by the named amounts of `years`, `months`, `weeks`, `days`,
+/// `hours` and `minutes`, which may be negative.
+///
+/// Days beyond the end of a month are clamped, e.g. one month after January
+/// 31 is the last day of February. Dates out of the supported range are an
+/// error.
+fn date_add(
+    config: &Config,
+    value: Value,
+    args: &[Argument],
+) -> std::result::Result<Value, String> {
+    let date = parse_date(config, &text(value)?)?;
+    let mut amounts = [0i64; 6];
+    for arg in args {
+        let units = ["years", "months", "weeks", "days", "hours", "minutes"];
+        let i = arg
+            .name
+            .as_deref()
+            .and_then(|name| units.iter().position(|unit| *unit == name))
+            .ok_or_else(|| format!("arguments must be one of {}", units.join(", ")))?;
+        amounts[i] = match arg.value {
+            Literal::Integer(n) => n,
+            _ => return Err(format!("{} must be an integer", units[i])),
+        };
+    }
+    let [years, months, weeks, days, hours, minutes] = amounts;
+
+    let out_of_range = || "the date is out of range".to_string();
+
+    // Calendar units first, then fixed durations
+    let total_months = years
+        .checked_mul(12)
+        .and_then(|n| n.checked_add(months))
+        .and_then(|n| n.checked_add(i64::from(date.year()) * 12))
+        .map(|n| n + i64::from(u8::from(date.month()) - 1))
+        .ok_or_else(out_of_range)?;
+    let year = i32::try_from(total_months.div_euclid(12)).map_err(|_| out_of_range())?;
+    let month =
+        Month::try_from(total_months.rem_euclid(12) as u8 + 1).map_err(|e| e.to_string())?;
+    let day = date.day().min(month.length(year));
+    let shifted = Date::from_calendar_date(year, month, day).map_err(|_| out_of_range())?;
+    let durations = [
+        (weeks, Duration::WEEK),
+        (days, Duration::DAY),
+        (hours, Duration::HOUR),
+        (minutes, Duration::MINUTE),
+    ];
+    let shifted = durations
+        .into_iter()
+        .try_fold(date.replace_date(shifted), |date, (n, unit)| {
+            let n = i32::try_from(n).ok()?;
+            date.checked_add(unit.checked_mul(n)?)
+        })
+        .ok_or_else(out_of_range)?;
+    Ok(crate::format_date_iso8601(&shifted).into())
+}
+
+/// Format a date with a pattern where `Y`, `m`, `d`, `H`, `i` and `s` are
+/// replaced by the year, month, day, hour, minute and second.
+///
+/// All other characters are kept as is. Deprecated in favor of the `date`
+/// filter, to which the pattern is translated.
+fn format(config: &Config, value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let date = parse_date(config, &text(value)?)?;
+    let mut pattern = String::new();
+    for c in text_arg(args, 0)?.chars() {
+        match c {
+            'Y' => pattern.push_str("%Y"),
+            'm' => pattern.push_str("%m"),
+            'd' => pattern.push_str("%d"),
+            'H' => pattern.push_str("%H"),
+            'i' => pattern.push_str("%M"),
+            's' => pattern.push_str("%S"),
+            '%' => pattern.push_str("%%"),
+            c => pattern.push(c),
+        }
+    }
+    Ok(format_date(&date, &pattern)?.into())
+}
+
+/// Format a date in UTC with a pattern as of `strftime`, e.g. `%B %d, %Y`,
+/// or the date format of the site if none is given.
+///
+/// Names of months and weekdays are in English, there is no locale.
+fn date(config: &Config, value: Value, args: &[Argument]) -> std::result::Result<Value, String> {
+    let date = parse_date(config, &text(value)?)?;
+    let pattern = match positional(args, 0) {
+        Some(_) => text_arg(args, 0)?,
+        None => config.date_format.as_str(),
+    };
+    Ok(format_date(&date, pattern)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    fn apply_str(name: &str, value: &str, args: &[Literal]) -> Value {
+        let args: Vec<_> = args
+            .iter()
+            .map(|value| Argument {
+                name: None,
+                value: value.clone(),
+            })
+            .collect();
+        apply(&dummy_config(), name, value.into(), &args).unwrap()
+    }
+
+    #[test]
+    fn test_text_filters() {
+        assert_eq!(Value::from("ABC"), apply_str("upper", "abc", &[]));
+        assert_eq!(Value::from("Abc"), apply_str("capitalize", "abc", &[]));
+        assert_eq!(
+            Value::from("bold"),
+            apply_str("striptags", "<b>bold</b>", &[])
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let args = [Literal::Integer(6)];
+        assert_eq!(Value::from("short"), apply_str("truncate", "short", &args));
+        assert_eq!(
+            Value::from("a lot…"),
+            apply_str("truncate", "a lot of text", &args)
+        );
+    }
+
+    #[test]
+    fn test_format_date() {
+        let args = [Literal::String("d.m.Y".into())];
+        assert_eq!(
+            Value::from("02.01.2023"),
+            apply_str("format", "2023-01-02T10:00:00Z", &args)
+        );
+        assert_eq!(
+            Value::from("02.01.2023"),
+            apply_str("format", "2023-01-02", &args)
+        );
+        assert_eq!(
+            Value::from("100% 2023"),
+            apply_str("format", "2023-01-02", &[Literal::String("100% Y".into())])
+        );
+    }
+
+    #[test]
+    fn test_date() {
+        let args = [Literal::String("%A, %B %e, %Y %H:%M %j%%".into())];
+        assert_eq!(
+            Value::from("Monday, January 2, 2023 09:00 002%"),
+            apply_str("date", "2023-01-02T10:00:00+01:00", &args)
+        );
+        assert_eq!(
+            Value::from("Jan 02 23"),
+            apply_str("date", "2023-01-02", &[Literal::String("%b %d %y".into())])
+        );
+        assert_eq!(
+            Value::from("2023-01-02"),
+            apply_str("date", "2023-01-02", &[])
+        );
+        let args = [Argument {
+            name: None,
+            value: Literal::String("%Q".into()),
+        }];
+        assert!(apply(&dummy_config(), "date", "2023-01-02".into(), &args).is_err());
+    }
+
+    #[test]
+    fn test_parse_date_of_site() {
+        let config = Config {
+            date_format: "%B %e, %Y [%H:%M]".into(),
+            ..dummy_config()
+        };
+        let date = parse_date(&config, "2023-01-02T10:30:00Z").unwrap();
+        let formatted = format_date(&date, &config.date_format).unwrap();
+        assert_eq!("January 2, 2023 [10:30]", formatted);
+        assert_eq!(Ok(date), parse_date(&config, &formatted));
+        // Dates without a time are at midnight
+        let config = Config {
+            date_format: "%d.%m.%Y".into(),
+            ..dummy_config()
+        };
+        assert_eq!(
+            parse_date(&config, "2023-01-02T00:00:00Z"),
+            parse_date(&config, "02.01.2023")
+        );
+        assert!(parse_date(&config, "2023-01-02 10:00Z").is_err());
+    }
+
+    #[test]
+    fn test_date_add() {
+        let add = |date: &str, name: &str, n| {
+            let args = [Argument {
+                name: Some(name.to_string()),
+                value: Literal::Integer(n),
+            }];
+            apply(&dummy_config(), "date_add", date.into(), &args).unwrap()
+        };
+        assert_eq!(
+            Value::from("2022-01-02T00:00:00Z"),
+            add("2023-01-02", "years", -1)
+        );
+        assert_eq!(
+            Value::from("2023-02-28T10:00:00Z"),
+            add("2023-01-31T10:00:00Z", "months", 1)
+        );
+        assert_eq!(
+            Value::from("2022-12-26T10:00:00Z"),
+            add("2023-01-02T10:00:00Z", "weeks", -1)
+        );
+        for (name, n) in [("years", i64::MAX), ("years", 100_000), ("days", i64::MIN)] {
+            let args = [Argument {
+                name: Some(name.to_string()),
+                value: Literal::Integer(n),
+            }];
+            assert!(matches!(
+                apply(&dummy_config(), "date_add", "2023-01-02".into(), &args),
+                Err(Error::Filter(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_collection_filters() {
+        let page = |title: &str, date: &str, tags: &[&str]| {
+            let tags = tags
+                .iter()
+                .map(|tag| Value::Map(HashMap::from([("name".to_string(), (*tag).into())])))
+                .collect::<Vec<_>>();
+            Value::Map(HashMap::from([
+                ("title".to_string(), title.into()),
+                ("date".to_string(), date.into()),
+                (
+                    "taxonomies".to_string(),
+                    Value::Map(HashMap::from([("tags".to_string(), tags.into())])),
+                ),
+            ]))
+        };
+        let pages = Value::from(vec![
+            page("a", "2021-01-01", &["rust"]),
+            page("b", "2023-01-01", &["go"]),
+            page("c", "2022-01-01", &["rust", "go"]),
+        ]);
+        let named = |name: &str, value| Argument {
+            name: Some(name.to_string()),
+            value,
+        };
+        let titles = |value: Value| match value {
+            Value::List(items) => items
+                .iter()
+                .map(|item| item.get_path("title").cloned().unwrap())
+                .collect::<Vec<_>>(),
+            _ => panic!("not a list"),
+        };
+
+        let rust = apply(
+            &dummy_config(),
+            "where",
+            pages.clone(),
+            &[named("tags", Literal::String("rust".to_string()))],
+        )
+        .unwrap();
+        assert_eq!(vec![Value::from("a"), Value::from("c")], titles(rust));
+        let sorted = apply(
+            &dummy_config(),
+            "sort",
+            pages,
+            &[
+                named("by", Literal::String("date".to_string())),
+                named("desc", Literal::Bool(true)),
+            ],
+        )
+        .unwrap();
+        let take_two = [Argument {
+            name: None,
+            value: Literal::Integer(2),
+        }];
+        let newest = apply(&dummy_config(), "take", sorted, &take_two).unwrap();
+        assert_eq!(vec![Value::from("b"), Value::from("c")], titles(newest));
+    }
+
+    #[test]
+    fn test_unknown_filter() {
+        assert!(apply(&dummy_config(), "nope", "".into(), &[]).is_err());
+    }
+}
@@ -0,0 +1,473 @@
+//! Built-in functions which compute tag values, e.g. `{{ asset "style.css" }}`.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use time::OffsetDateTime;
+
+use super::{check_within, filters, Argument, Context, Literal, Value, PAGINATOR};
+#[cfg(feature = "images")]
+use crate::images;
+use crate::{
+    cdn,
+    config::Config,
+    error::{Error, Result},
+    AssetManifest,
+};
+
+/// State of the functions which is shared by all pages of a build.
+#[derive(Debug, Default)]
+pub struct Functions {
+    /// Content hashes of assets by their path relative to `assets/`.
+    asset_hashes: Mutex<HashMap<PathBuf, String>>,
+    /// Output paths of the assets if they are fingerprinted.
+    asset_manifest: Option<AssetManifest>,
+    /// Strings of `i18n/<lang>.toml` by the language, `None` if there is no
+    /// such file.
+    translations: Mutex<HashMap<String, Option<Arc<toml::Table>>>>,
+}
+
+impl Functions {
+    /// Output path of the asset with the path relative to `assets/`, its
+    /// fingerprinted one if assets are resolved by the manifest.
+    ///
+    /// Returns `None` for assets missing in the manifest.
+    pub fn asset_output<'a>(&'a self, path: &'a Path) -> Option<&'a Path> {
+        match &self.asset_manifest {
+            Some(manifest) => manifest.get(path).map(PathBuf::as_path),
+            None => Some(path),
+        }
+    }
+
+    /// Functions resolving assets by the manifest.
+    pub fn with_asset_manifest(manifest: AssetManifest) -> Self {
+        Functions {
+            asset_manifest: Some(manifest),
+            ..Default::default()
+        }
+    }
+}
+
+/// Get the single positional text argument.
+fn path_arg(args: &[Argument]) -> Option<&str> {
+    match args {
+        [Argument {
+            name: None,
+            value: Literal::String(path),
+        }] => Some(path),
+        _ => None,
+    }
+}
+
+/// Get the argument given by name or at the position.
+fn arg<'a>(args: &'a [Argument], name: &str, position: usize) -> Option<&'a Literal> {
+    args.iter()
+        .find(|arg| arg.name.as_deref() == Some(name))
+        .or_else(|| args.iter().filter(|arg| arg.name.is_none()).nth(position))
+        .map(|arg| &arg.value)
+}
+
+/// Get the optional argument given by name as a positive number.
+#[cfg(feature = "images")]
+fn size_arg(args: &[Argument], name: &str) -> std::result::Result<Option<u32>, String> {
+    match args.iter().find(|arg| arg.name.as_deref() == Some(name)) {
+        None => Ok(None),
+        Some(Argument {
+            value: Literal::Integer(n),
+            ..
+        }) if *n > 0 => u32::try_from(*n).map(Some).map_err(|e| e.to_string()),
+        Some(_) => Err(format!("{} must be a positive number", name)),
+    }
+}
+
+/// Prefix the URL path with the CDN origin if configured.
+fn asset_url(config: &Config, url: String) -> Value {
+    match &config.cdn_url {
+        Some(cdn) => cdn::cdn_url(cdn, &url).into(),
+        None => url.into(),
+    }
+}
+
+/// Call the function with the given name.
+pub async fn call(config: &Config, ctx: &Context, name: &str, args: &[Argument]) -> Result<Value> {
+    let err = |reason: &str| Error::Function(name.to_string(), reason.to_string());
+    match name {
+        "bust" => {
+            let path = path_arg(args).ok_or_else(|| err("expects the path of an asset"))?;
+            let hash = asset_hash(config, &ctx.functions, path).await?;
+            let url = format!("/{}?v={}", path.trim_start_matches('/'), hash);
+            Ok(asset_url(config, url))
+        }
+        "asset" => {
+            let path = path_arg(args).ok_or_else(|| err("expects the path of an asset"))?;
+            let path = Path::new(path.trim_start_matches('/'));
+            check_within(path, "assets")?;
+            let output = ctx
+                .functions
+                .asset_output(path)
+                .ok_or_else(|| err(&format!("asset {} does not exist", path.display())))?;
+            Ok(asset_url(config, format!("/{}", output.display())))
+        }
+        #[cfg(feature = "images")]
+        "resize_image" => {
+            let path = match arg(args, "path", 0) {
+                Some(Literal::String(path)) => Path::new(path.trim_start_matches('/')),
+                _ => return Err(err("expects the path of an image")),
+            };
+            check_within(path, "assets")?;
+            let width = size_arg(args, "width").map_err(|e| err(&e))?;
+            let height = size_arg(args, "height").map_err(|e| err(&e))?;
+            let url = images::resize(config, path, width, height).await?;
+            Ok(asset_url(config, url))
+        }
+        #[cfg(feature = "images")]
+        "image_placeholder" => {
+            let path = match arg(args, "path", 0) {
+                Some(Literal::String(path)) => Path::new(path.trim_start_matches('/')),
+                _ => return Err(err("expects the path of an image")),
+            };
+            check_within(path, "assets")?;
+            Ok(images::placeholder(config, path).await?.into())
+        }
+        #[cfg(not(feature = "images"))]
+        "resize_image" | "image_placeholder" => Err(Error::MissingFeature("images")),
+        "paginate" => {
+            let items = match arg(args, "list", 0) {
+                Some(Literal::Variable(var)) => match ctx.get(var) {
+                    Some(Value::List(items)) => items,
+                    Some(_) => return Err(Error::NotAList(var.to_string())),
+                    None => return Err(Error::TagNotFound(var.to_string())),
+                },
+                _ => return Err(err("expects the name of a list")),
+            };
+            let size = match arg(args, "size", 1) {
+                Some(Literal::Integer(size)) if *size > 0 => *size as usize,
+                _ => return Err(err("expects a positive page size")),
+            };
+            let current = match ctx.get(&format!("{}.current", PAGINATOR)) {
+                Some(Value::Integer(current)) if *current > 0 => *current as usize,
+                _ => 1,
+            };
+            ctx.page_count
+                .fetch_max(items.len().div_ceil(size), Ordering::Relaxed);
+            Ok(items
+                .iter()
+                .skip((current - 1) * size)
+                .take(size)
+                .cloned()
+                .collect::<Vec<_>>()
+                .into())
+        }
+        "group_by" => {
+            let items = match arg(args, "list", 0) {
+                Some(Literal::Variable(var)) => match ctx.get(var) {
+                    Some(Value::List(items)) => items,
+                    Some(_) => return Err(Error::NotAList(var.to_string())),
+                    None => return Err(Error::TagNotFound(var.to_string())),
+                },
+                _ => return Err(err("expects the name of a list")),
+            };
+            let key = match arg(args, "key", 1) {
+                Some(Literal::String(key)) => key,
+                _ => return Err(err("expects the key to group by as text")),
+            };
+            Ok(group_by(config, items, key).into())
+        }
+        "trans" => {
+            let key = match arg(args, "key", 0) {
+                Some(Literal::String(key)) => key,
+                _ => return Err(err("expects the key of a string")),
+            };
+            // The language of the page, which is empty for the one of the site
+            let lang = match arg(args, "lang", 1) {
+                Some(Literal::String(lang)) => lang.as_str(),
+                Some(_) => return Err(err("lang must be text")),
+                None => match ctx.get("lang") {
+                    Some(Value::String(lang)) => lang.as_str(),
+                    _ => "",
+                },
+            };
+            for lang in languages(lang, &config.site_info.lang) {
+                let Some(strings) = translations(config, &ctx.functions, lang).await? else {
+                    continue;
+                };
+                // Keys of nested tables are separated by dots
+                let (mut table, mut value) = (Some(strings.as_ref()), None);
+                for key in key.split('.') {
+                    value = table.and_then(|table| table.get(key));
+                    table = value.and_then(toml::Value::as_table);
+                }
+                match value {
+                    Some(toml::Value::String(text)) => return Ok(text.as_str().into()),
+                    Some(_) => return Err(err(&format!("'{}' in {} is not text", key, lang))),
+                    None => continue,
+                }
+            }
+            Err(err(&format!("no translation of '{}' for '{}'", key, lang)))
+        }
+        "now" => Ok(crate::format_date_iso8601(&OffsetDateTime::now_utc()).into()),
+        "date_diff" => {
+            // Dates are given as text or by the name of a value
+            let date = |position, name| match arg(args, name, position) {
+                Some(Literal::String(s)) => filters::parse_date(config, s).map_err(|e| err(&e)),
+                Some(Literal::Variable(var)) => match ctx.get(var) {
+                    Some(Value::String(s)) => filters::parse_date(config, s).map_err(|e| err(&e)),
+                    Some(_) => Err(err(&format!("'{}' is not a date", var))),
+                    None => Err(Error::TagNotFound(var.to_string())),
+                },
+                _ => Err(err("expects two dates")),
+            };
+            // Positive if `to` is after `from`
+            let difference = date(1, "to")? - date(0, "from")?;
+            let n = match arg(args, "unit", 2) {
+                None => difference.whole_days(),
+                Some(Literal::String(unit)) => match unit.as_str() {
+                    "weeks" => difference.whole_weeks(),
+                    "days" => difference.whole_days(),
+                    "hours" => difference.whole_hours(),
+                    "minutes" => difference.whole_minutes(),
+                    _ => return Err(err("unit must be weeks, days, hours or minutes")),
+                },
+                Some(_) => return Err(err("unit must be text")),
+            };
+            Ok(Value::Integer(n))
+        }
+        _ => Err(Error::UnknownFunction(name.to_string())),
+    }
+}
+
+/// Get the value at the path of keys separated by dots, e.g. `extra.category`.
+///
+/// The `year` and `month` of an item are taken from its `date` unless set.
+fn lookup<'a>(config: &Config, item: &'a Value, path: &str) -> Option<Cow<'a, Value>> {
+    if let Some(value) = item.get_path(path) {
+        return Some(Cow::Borrowed(value));
+    }
+    let date = match item.get_path("date")? {
+        Value::String(date) => filters::parse_date(config, date).ok()?,
+        _ => return None,
+    };
+    match path {
+        "year" => Some(Cow::Owned(i64::from(date.year()).into())),
+        "month" => Some(Cow::Owned(
+            format!("{}-{:02}", date.year(), u8::from(date.month())).into(),
+        )),
+        _ => None,
+    }
+}
+
+/// Group the items by the value at the key.
+///
+/// Every group is a map of the `key` and its `items`, in the order the keys
+/// first appear in the list. Items with a list at the key are part of the group
+/// of every element, items without the key are left out.
+fn group_by(config: &Config, items: &[Value], key: &str) -> Vec<Value> {
+    let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+    for item in items {
+        let keys = match lookup(config, item, key) {
+            Some(value) => match value.into_owned() {
+                Value::List(keys) => keys,
+                value => vec![value],
+            },
+            None => continue,
+        };
+        for group_key in keys {
+            match groups.iter_mut().find(|(k, _)| *k == group_key) {
+                Some((_, group)) => group.push(item.clone()),
+                None => groups.push((group_key, vec![item.clone()])),
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(key, items)| {
+            Value::Map(HashMap::from([
+                ("key".to_string(), key),
+                ("items".to_string(), items.into()),
+            ]))
+        })
+        .collect()
+}
+
+/// Get the content hash of the asset with the path relative to `assets/`.
+///
+/// Every asset is hashed once per build so all pages use the same hash.
+async fn asset_hash(config: &Config, functions: &Functions, path: &str) -> Result<String> {
+    let path = PathBuf::from(path.trim_start_matches('/'));
+    check_within(&path, "assets")?;
+    if let Some(hash) = functions
+        .asset_hashes
+        .lock()
+        .expect("lock is never poisoned")
+        .get(&path)
+    {
+        return Ok(hash.clone());
+    }
+
+    let full_path = config.content_path.join("assets").join(&path);
+    let content = tokio::fs::read(&full_path)
+        .await
+        .map_err(|e| Error::ReadInput(full_path, e))?;
+    let hash = content_hash(&content);
+    functions
+        .asset_hashes
+        .lock()
+        .expect("lock is never poisoned")
+        .insert(path, hash.clone());
+    Ok(hash)
+}
+
+/// Languages to look up translations in, from the most specific one.
+///
+/// Regional variants fall back to their language and all languages to the
+/// one of the site, e.g. `de-AT`, `de` and `en`.
+fn languages<'a>(lang: &'a str, site_lang: &'a str) -> Vec<&'a str> {
+    let mut languages = Vec::new();
+    for lang in [lang, site_lang] {
+        let primary = lang.split_once('-').map_or(lang, |(primary, _)| primary);
+        for lang in [lang, primary] {
+            if !lang.is_empty() && !languages.contains(&lang) {
+                languages.push(lang);
+            }
+        }
+    }
+    languages
+}
+
+/// Get the strings of `i18n/<lang>.toml`, `None` if there is no such file.
+///
+/// Every file is read once per build.
+async fn translations(
+    config: &Config,
+    functions: &Functions,
+    lang: &str,
+) -> Result<Option<Arc<toml::Table>>> {
+    if let Some(strings) = functions
+        .translations
+        .lock()
+        .expect("lock is never poisoned")
+        .get(lang)
+    {
+        return Ok(strings.clone());
+    }
+
+    let path = config
+        .content_path
+        .join("i18n")
+        .join(format!("{}.toml", lang));
+    let strings = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => Some(Arc::new(
+            toml::from_str(&content).map_err(|e| Error::ParseTranslations(path, e))?,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(Error::ReadInput(path, e)),
+    };
+    functions
+        .translations
+        .lock()
+        .expect("lock is never poisoned")
+        .insert(lang.to_string(), strings.clone());
+    Ok(strings)
+}
+
+/// Compute a short hash of the content which is stable between builds.
+///
+/// This uses the 64 bit FNV-1a hash, truncated to 8 hex digits.
+pub fn content_hash(content: &[u8]) -> String {
+    let hash = content.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)[..8].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::tests::dummy_config;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!("cbf29ce4", content_hash(b""));
+        assert_eq!(content_hash(b"body {}"), content_hash(b"body {}"));
+        assert_ne!(content_hash(b"body {}"), content_hash(b"body { }"));
+    }
+
+    #[tokio::test]
+    async fn test_date_diff() {
+        let args = |from: &str, to: &str, unit: &str| {
+            [from, to, unit].map(|s| Argument {
+                name: None,
+                value: Literal::String(s.to_string()),
+            })
+        };
+        let diff = |from, to, unit| async move {
+            call(
+                &dummy_config(),
+                &Context::new(),
+                "date_diff",
+                &args(from, to, unit),
+            )
+            .await
+        };
+        assert_eq!(
+            Value::Integer(2),
+            diff("2023-01-01", "2023-01-15", "weeks").await.unwrap()
+        );
+        assert_eq!(
+            Value::Integer(-36),
+            diff("2023-01-02T12:00:00Z", "2023-01-01", "hours")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_languages() {
+        assert_eq!(vec!["de-AT", "de", "en"], languages("de-AT", "en"));
+        assert_eq!(vec!["en-GB", "en"], languages("", "en-GB"));
+        assert_eq!(vec!["de", "en"], languages("de", "en"));
+    }
+
+    #[test]
+    fn test_group_by() {
+        let page = |date: &str, category: &str| {
+            Value::Map(HashMap::from([
+                ("date".to_string(), date.into()),
+                (
+                    "extra".to_string(),
+                    Value::Map(HashMap::from([("category".to_string(), category.into())])),
+                ),
+            ]))
+        };
+        let pages = [
+            page("2023-05-01", "a"),
+            page("2022-03-01", "b"),
+            page("2022-01-01", "a"),
+        ];
+        let keys = |groups: Vec<Value>| {
+            groups
+                .into_iter()
+                .map(|group| match group {
+                    Value::Map(mut map) => match map.remove("items") {
+                        Some(Value::List(items)) => (map.remove("key").unwrap(), items.len()),
+                        _ => panic!("no items"),
+                    },
+                    _ => panic!("not a map"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(Value::Integer(2023), 1), (Value::Integer(2022), 2)],
+            keys(group_by(&dummy_config(), &pages, "year"))
+        );
+        assert_eq!(
+            vec![(Value::from("a"), 2), (Value::from("b"), 1)],
+            keys(group_by(&dummy_config(), &pages, "extra.category"))
+        );
+        assert!(group_by(&dummy_config(), &pages, "missing").is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+//! Shortcodes within the markdown content, e.g. `{{ youtube(id="abc") }}`.
+//!
+//! A shortcode renders the template of the same name in
+//! `templates/shortcodes/` with its arguments as values. Block shortcodes
+//! enclose a body up to `{% end %}`, which is rendered as markdown and passed
+//! as `body`. Templates can use block shortcodes as well:
+//!
+//! ```text
+//! {% figure src="x.jpg" caption="A caption" %}
+//! The body
+//! {% end %}
+//! ```
+//!
+//! Shortcodes within code are kept as is, as is the content of
+//! `{% raw %}...{% endraw %}` blocks. Text in delimiters which does not call
+//! a shortcode template, e.g. `{{ mustache }}` in prose, is kept as well.
+
+use std::{collections::BTreeSet, ops::Range, path::Path};
+
+use super::{
+    find_shortcode, parse_command_args, raw_content, template_file, template_path, with_args, Call,
+    Context, COMMAND_END, COMMAND_START, TAG_END, TAG_START,
+};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// Directory of the shortcode templates relative to `templates/`.
+pub const SHORTCODE_DIR: &str = "shortcodes";
+
+/// Name of the command closing a block shortcode.
+const BLOCK_END: &str = "end";
+
+/// Name of the value containing the body of a block shortcode.
+const BODY: &str = "body";
+
+/// A shortcode found in the content.
+#[derive(Debug, PartialEq, Eq)]
+struct ContentShortcode {
+    /// Call of the shortcode template with its arguments.
+    call: Call,
+    /// Whether the shortcode encloses a body up to `{% end %}`.
+    block: bool,
+}
+
+impl ContentShortcode {
+    /// Parse a shortcode including its delimiters.
+    ///
+    /// Arguments of commands are separated by whitespace, e.g.
+    /// `{% figure src="x.jpg" %}`, the ones of tags are given in parentheses,
+    /// e.g. `{{ youtube(id="abc") }}`.
+    fn parse(input: &str) -> Result<Self> {
+        let err = || Error::ParseShortcode(input.to_string());
+        if let Some(inner) = input.strip_prefix(TAG_START) {
+            let call = inner.strip_suffix(TAG_END).ok_or_else(err)?.parse()?;
+            return Ok(ContentShortcode { call, block: false });
+        }
+
+        let inner = input
+            .strip_prefix(COMMAND_START)
+            .and_then(|s| s.strip_suffix(COMMAND_END))
+            .ok_or_else(err)?
+            .trim();
+        let (name, args) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+        Ok(ContentShortcode {
+            call: Call {
+                name: name.to_string(),
+                args: parse_command_args(args)?,
+            },
+            block: true,
+        })
+    }
+
+    /// Whether this closes a block shortcode.
+    fn is_block_end(&self) -> bool {
+        self.block && self.call.name == BLOCK_END && self.call.args.is_empty()
+    }
+}
+
+/// Parse the shortcode if it closes a block or its template exists in
+/// `templates/shortcodes/`, `None` for any other text in delimiters.
+fn parse_known(config: &Config, input: &str) -> Option<ContentShortcode> {
+    let shortcode = ContentShortcode::parse(input).ok()?;
+    let path = Path::new(SHORTCODE_DIR).join(format!("{}.html", shortcode.call.name));
+    (shortcode.is_block_end() || template_path(config, &path).is_some()).then_some(shortcode)
+}
+
+/// Remove the indentation common to all lines which are not blank, so
+/// indented bodies in templates are not taken as code blocks.
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render the body of a block shortcode from markdown to HTML.
+pub(super) async fn markdown_body(config: &Config, ctx: &Context, body: &str) -> Result<String> {
+    let options = crate::markdown_options(config.markdown.smart_punctuation);
+    Box::pin(crate::convert_markdown(
+        &dedent(body),
+        options,
+        config,
+        ctx,
+        None,
+    ))
+    .await
+}
+
+/// Maximum number of nested shortcodes, which prevents endless recursion of
+/// shortcode templates using themselves.
+const MAX_SHORTCODE_DEPTH: usize = 32;
+
+/// Render the template of the shortcode with its arguments and body.
+///
+/// Variables given as arguments are looked up in the context.
+pub(super) async fn render_shortcode(
+    config: &Config,
+    ctx: &Context,
+    call: &Call,
+    body: Option<String>,
+) -> Result<String> {
+    if ctx.shortcode_depth >= MAX_SHORTCODE_DEPTH {
+        return Err(Error::ShortcodeDepth(call.name.clone()));
+    }
+    let mut shortcode_ctx = with_args(ctx, &call.name, &call.args)?;
+    shortcode_ctx.shortcode_depth += 1;
+    if let Some(body) = body {
+        shortcode_ctx.insert(BODY, body);
+    }
+    let path = Path::new(SHORTCODE_DIR).join(format!("{}.html", call.name));
+    let html = Box::pin(template_file(config, &shortcode_ctx, &path)).await?;
+    Ok(html.trim_end().to_string())
+}
+
+/// Ranges of code spans and blocks in the markdown.
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new(markdown)
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| range)
+        .collect()
+}
+
+/// Find the next shortcode outside of code starting at the index.
+///
+/// Returns the absolute range of the shortcode.
+fn next_shortcode(
+    markdown: &str,
+    mut from: usize,
+    code: &[Range<usize>],
+) -> Option<(usize, usize)> {
+    while let Some((start, end)) = find_shortcode(&markdown[from..]) {
+        let (start, end) = (from + start, from + end);
+        match code.iter().find(|range| range.contains(&start)) {
+            Some(range) => from = range.end.max(start + 1),
+            None => return Some((start, end)),
+        }
+    }
+    None
+}
+
+/// Expand all shortcodes in the markdown by rendering their templates.
+///
+/// Templates are shared with the context, but shortcodes see none of its
+/// values, only their arguments.
+pub async fn expand(config: &Config, ctx: &Context, markdown: &str) -> Result<String> {
+    if find_shortcode(markdown).is_none() {
+        return Ok(markdown.to_string());
+    }
+    let ctx = ctx.shortcode_context();
+    let code = code_ranges(markdown);
+
+    let mut output = String::with_capacity(markdown.len());
+    let mut position = 0;
+    while let Some((start, end)) = next_shortcode(markdown, position, &code) {
+        output.push_str(&markdown[position..start]);
+        if let Some(content) = raw_content(&markdown[start..end]) {
+            output.push_str(content);
+            position = end;
+            continue;
+        }
+        let Some(shortcode) = parse_known(config, &markdown[start..end]) else {
+            // Only the opening brace is kept, as shortcodes may follow within
+            output.push('{');
+            position = start + 1;
+            continue;
+        };
+        if shortcode.is_block_end() {
+            return Err(Error::UnexpectedBlock(markdown[start..end].to_string()));
+        }
+        if !shortcode.block {
+            output.push_str(&render_shortcode(config, &ctx, &shortcode.call, None).await?);
+            position = end;
+            continue;
+        }
+
+        // Find the matching end of the block, skipping nested blocks
+        let mut depth = 0usize;
+        let mut search = end;
+        let (body_end, block_end) = loop {
+            let (inner_start, inner_end) = next_shortcode(markdown, search, &code)
+                .ok_or_else(|| Error::UnclosedBlock(markdown[start..end].to_string()))?;
+            if raw_content(&markdown[inner_start..inner_end]).is_some() {
+                search = inner_end;
+                continue;
+            }
+            match parse_known(config, &markdown[inner_start..inner_end]) {
+                Some(inner) if inner.is_block_end() && depth == 0 => {
+                    break (inner_start, inner_end)
+                }
+                Some(inner) if inner.is_block_end() => depth -= 1,
+                Some(inner) if inner.block => depth += 1,
+                Some(_) => {}
+                None => {
+                    search = inner_start + 1;
+                    continue;
+                }
+            }
+            search = inner_end;
+        };
+        // Shortcodes within the body are expanded along with the markdown
+        let body = markdown_body(config, &ctx, &markdown[end..body_end]).await?;
+        let html = render_shortcode(config, &ctx, &shortcode.call, Some(body)).await?;
+        output.push_str(&html);
+        position = block_end;
+    }
+    output.push_str(&markdown[position..]);
+
+    Ok(output)
+}
+
+/// Names of all shortcodes used outside of code in the markdown.
+pub fn names(config: &Config, markdown: &str) -> BTreeSet<String> {
+    let code = code_ranges(markdown);
+    let mut names = BTreeSet::new();
+    let mut position = 0;
+    while let Some((start, end)) = next_shortcode(markdown, position, &code) {
+        if raw_content(&markdown[start..end]).is_none() {
+            match parse_known(config, &markdown[start..end]) {
+                Some(shortcode) if !shortcode.is_block_end() => {
+                    names.insert(shortcode.call.name);
+                }
+                Some(_) => {}
+                None => {
+                    position = start + 1;
+                    continue;
+                }
+            }
+        }
+        position = end;
+    }
+    names
+}
+
+/// Remove all shortcodes outside of code from the markdown.
+///
+/// The bodies of block shortcodes and the content of raw blocks are kept, e.g.
+/// for the plain text of a page.
+pub fn strip(config: &Config, markdown: &str) -> String {
+    let code = code_ranges(markdown);
+    let mut output = String::with_capacity(markdown.len());
+    let mut position = 0;
+    while let Some((start, end)) = next_shortcode(markdown, position, &code) {
+        output.push_str(&markdown[position..start]);
+        let shortcode = &markdown[start..end];
+        match raw_content(shortcode) {
+            Some(content) => output.push_str(content),
+            None if parse_known(config, shortcode).is_none() => {
+                output.push('{');
+                position = start + 1;
+                continue;
+            }
+            None => {}
+        }
+        position = end;
+    }
+    output.push_str(&markdown[position..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::Literal;
+
+    #[test]
+    fn test_parse_content_shortcode() {
+        let shortcode = ContentShortcode::parse(r#"{{ youtube(id="abc") }}"#).unwrap();
+        assert!(!shortcode.block);
+        assert_eq!("youtube", shortcode.call.name);
+        assert_eq!(Some("id"), shortcode.call.args[0].name.as_deref());
+
+        let shortcode =
+            ContentShortcode::parse(r#"{% figure src="x.jpg" caption="A caption" %}"#).unwrap();
+        assert!(shortcode.block);
+        assert_eq!("figure", shortcode.call.name);
+        assert_eq!(
+            Literal::String("A caption".to_string()),
+            shortcode.call.args[1].value
+        );
+        assert!(ContentShortcode::parse("{% end %}").unwrap().is_block_end());
+    }
+
+    #[test]
+    fn test_dedent() {
+        assert_eq!("a\n  b\n\nc", dedent("    a\n      b\n\n    c"));
+    }
+
+    /// Config of the basic test site, which has the shortcodes `abbr` and
+    /// `figure`.
+    fn site_config() -> Config {
+        Config {
+            content_path: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sites/basic"),
+            ..crate::template::tests::dummy_config()
+        }
+    }
+
+    #[test]
+    fn test_skip_shortcodes_in_code() {
+        let markdown = "`{{ a() }}`\n\n```\n{{ b() }}\n```\n\n{{ c() }}";
+        let code = code_ranges(markdown);
+        let (start, end) = next_shortcode(markdown, 0, &code).unwrap();
+        assert_eq!("{{ c() }}", &markdown[start..end]);
+        let markdown = "`{{ abbr() }}`\n\n```\n{{ abbr() }}\n```\n\n{{ abbr() }}";
+        assert_eq!(
+            "`{{ abbr() }}`\n\n```\n{{ abbr() }}\n```\n\n",
+            strip(&site_config(), markdown)
+        );
+    }
+
+    #[test]
+    fn test_shortcode_names() {
+        let markdown = "{{ abbr() }} `{{ b() }}` {% figure %}{{ abbr() }}{% end %}\
+             {% raw %}{{ d() }}{% endraw %}{{ mustache }}";
+        assert_eq!(
+            BTreeSet::from(["abbr".to_string(), "figure".to_string()]),
+            names(&site_config(), markdown)
+        );
+    }
+
+    #[test]
+    fn test_strip_keeps_raw_content() {
+        let markdown = "a{{ abbr() }}{% raw %}{{ c() }}{% endraw %}";
+        assert_eq!("a{{ c() }}", strip(&site_config(), markdown));
+    }
+
+    #[tokio::test]
+    async fn test_keep_unknown_shortcodes() {
+        let markdown = "{{ mustache }}, {% if x %} and {{{ abbr(text=\"a\", title=\"b\") }}";
+        assert_eq!(
+            "{{ mustache }}, {% if x %} and {<abbr title=\"b\">a</abbr>",
+            expand(&site_config(), &Context::new(), markdown)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            "{{ mustache }}, {% if x %} and {",
+            strip(&site_config(), markdown)
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! This module improves the typography of the converted content.
+//!
+//! Straight quotes are replaced by the quotes of the page language, spaces
+//! before punctuation are made non-breaking where the language requires it,
+//! and the last two words of paragraphs, headings and list items are joined
+//! to prevent widows. Code, preformatted text, scripts and styles are left
+//! untouched.
+
+use crate::config::{LanguageTypography, TypographyConfig};
+
+/// Elements whose text is never changed.
+const RAW_ELEMENTS: &[&str] = &["code", "kbd", "math", "pre", "samp", "script", "style"];
+
+/// Elements whose last two words are joined to prevent widows.
+const WIDOW_ELEMENTS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6", "li", "p"];
+
+/// Punctuation of which a preceding space is made non-breaking if configured.
+const SPACED_PUNCTUATION: &[char] = &[';', ':', '!', '?', '»'];
+
+/// Quotes used if the language does not configure them.
+const DEFAULT_QUOTES: [&str; 4] = ["“", "”", "‘", "’"];
+
+/// Find the typography of a language, falling back to its primary subtag,
+/// e.g. `fr` for `fr-CA`.
+fn language<'a>(config: &'a TypographyConfig, lang: &str) -> Option<&'a LanguageTypography> {
+    config.languages.get(lang).or_else(|| {
+        lang.split(['-', '_'])
+            .next()
+            .and_then(|primary| config.languages.get(primary))
+    })
+}
+
+/// Whether a quote after the character opens a quotation.
+fn opens_quote(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{-–—/\u{a0}".contains(c),
+    }
+}
+
+/// State of the pass while walking the HTML.
+struct Typographer<'a> {
+    quotes: [&'a str; 4],
+    spaced_punctuation: bool,
+    widows: bool,
+    output: String,
+    /// Last character of the text, used to decide about quotes.
+    previous: Option<char>,
+    /// Nesting depth of raw elements.
+    raw_depth: usize,
+    /// Byte indices of the last two spaces in the current widow element.
+    spaces: [Option<usize>; 2],
+}
+
+impl Typographer<'_> {
+    /// Handle an HTML tag.
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with("</");
+        let name: String = tag
+            .trim_start_matches(['<', '/'])
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if RAW_ELEMENTS.contains(&name.as_str()) {
+            if closing {
+                self.raw_depth = self.raw_depth.saturating_sub(1);
+            } else if !tag.ends_with("/>") {
+                self.raw_depth += 1;
+            }
+            // Code is treated as a word for the quotes around it
+            self.previous = Some('x');
+        }
+        if WIDOW_ELEMENTS.contains(&name.as_str()) {
+            if let ([Some(_), Some(last)], true, true) = (self.spaces, closing, self.widows) {
+                self.output.replace_range(last..last + 1, "&nbsp;");
+            }
+            self.spaces = [None, None];
+            self.previous = None;
+        }
+        self.output.push_str(tag);
+    }
+
+    /// Handle a character of text which is not within a raw element.
+    fn text(&mut self, c: char) {
+        match c {
+            '"' | '\'' => {
+                let single = c == '\'';
+                let next_quote = if opens_quote(self.previous) {
+                    self.quotes[usize::from(single) * 2]
+                } else {
+                    self.quotes[usize::from(single) * 2 + 1]
+                };
+                // Apostrophes within words always use the closing quote
+                let quote = if single && self.previous.is_some_and(char::is_alphanumeric) {
+                    DEFAULT_QUOTES[3]
+                } else {
+                    next_quote
+                };
+                self.output.push_str(quote);
+            }
+            // Only existing spaces are replaced, so URLs and times keep their colons
+            c if self.spaced_punctuation
+                && SPACED_PUNCTUATION.contains(&c)
+                && self.previous.is_some_and(char::is_whitespace) =>
+            {
+                let trimmed = self.output.trim_end().len();
+                self.output.truncate(trimmed);
+                if self.spaces[1].is_some_and(|space| space >= trimmed) {
+                    self.spaces = [None, self.spaces[0]];
+                }
+                self.output.push_str("&nbsp;");
+                self.output.push(c);
+            }
+            ' ' => {
+                self.spaces = [self.spaces[1], Some(self.output.len())];
+                self.output.push(c);
+            }
+            c => self.output.push(c),
+        }
+        self.previous = Some(c);
+    }
+}
+
+/// Apply the typography of the language to the HTML.
+pub fn apply(config: &TypographyConfig, lang: &str, html: &str) -> String {
+    let language = language(config, lang);
+    let quotes = match language.and_then(|language| language.quotes.as_ref()) {
+        Some([a, b, c, d]) => [a.as_str(), b.as_str(), c.as_str(), d.as_str()],
+        None => DEFAULT_QUOTES,
+    };
+    let mut typographer = Typographer {
+        quotes,
+        spaced_punctuation: language.is_some_and(|language| language.space_before_punctuation),
+        widows: config.widows,
+        output: String::with_capacity(html.len()),
+        previous: None,
+        raw_depth: 0,
+        spaces: [None, None],
+    };
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+            typographer.tag(&rest[..end]);
+            rest = &rest[end..];
+        } else if typographer.raw_depth > 0 {
+            let end = rest.find('<').unwrap_or(rest.len());
+            typographer.output.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("&quot;") {
+            typographer.text('"');
+            rest = after;
+        } else if rest.starts_with('&') {
+            // Other entities are kept and treated as a word character
+            let end = rest.find(';').map_or(1, |i| i + 1);
+            typographer.output.push_str(&rest[..end]);
+            typographer.previous = Some('x');
+            rest = &rest[end..];
+        } else {
+            let c = rest.chars().next().expect("rest is not empty");
+            typographer.text(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    typographer.output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn config() -> TypographyConfig {
+        TypographyConfig {
+            widows: true,
+            languages: BTreeMap::from([(
+                "fr".to_string(),
+                LanguageTypography {
+                    quotes: Some(["«&nbsp;", "&nbsp;»", "‹&nbsp;", "&nbsp;›"].map(String::from)),
+                    space_before_punctuation: true,
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_quotes_and_widows() {
+        assert_eq!(
+            "<p>She said “it’s <code>\"raw\"</code>” twice&nbsp;today</p>",
+            apply(
+                &config(),
+                "en",
+                "<p>She said &quot;it's <code>\"raw\"</code>&quot; twice today</p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_french_punctuation() {
+        assert_eq!(
+            "<h1>Vraiment&nbsp;? «&nbsp;Oui&nbsp;»</h1>",
+            apply(&config(), "fr-CA", "<h1>Vraiment ? &quot;Oui&quot;</h1>")
+        );
+        // A narrow no-break space is replaced as well
+        assert_eq!(
+            "<li>Voilà&nbsp;!</li>",
+            apply(&config(), "fr", "<li>Voilà\u{202f}!</li>")
+        );
+    }
+
+    #[test]
+    fn test_french_punctuation_without_space() {
+        assert_eq!(
+            "<p>Ouvert à 12:30 sur https://example.org,&nbsp;lu?</p>",
+            apply(
+                &config(),
+                "fr",
+                "<p>Ouvert à 12:30 sur https://example.org, lu?</p>"
+            )
+        );
+        assert_eq!(
+            "<p>Vraiment&nbsp;?</p>",
+            apply(&config(), "fr", "<p>Vraiment&nbsp;?</p>")
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! This module compares a built site with an expected output tree.
+//!
+//! It is used by the `verify` command as well as the golden-site tests.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, Result},
+    list_files,
+};
+
+/// A difference between the built and the expected output.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// The file is expected but was not built.
+    Missing(PathBuf),
+
+    /// The file was built but is not expected.
+    Unexpected(PathBuf),
+
+    /// The file exists in both trees but with a different content.
+    Changed(PathBuf),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Missing(path) => write!(f, "Missing file {}", path.display()),
+            Difference::Unexpected(path) => write!(f, "Unexpected file {}", path.display()),
+            Difference::Changed(path) => write!(f, "Changed file {}", path.display()),
+        }
+    }
+}
+
+/// Compare the files of the built directory with the expected directory.
+///
+/// Returns all differences ordered by their path.
+pub async fn compare_dirs(
+    built: impl AsRef<Path>,
+    expected: impl AsRef<Path>,
+) -> Result<Vec<Difference>> {
+    let (built, expected) = (built.as_ref(), expected.as_ref());
+    let built_files = list_files(built).await?;
+    let expected_files = list_files(expected).await?;
+
+    let mut differences = Vec::new();
+    for file in expected_files.union(&built_files) {
+        match (expected_files.contains(file), built_files.contains(file)) {
+            (true, false) => differences.push(Difference::Missing(file.clone())),
+            (false, true) => differences.push(Difference::Unexpected(file.clone())),
+            _ => {
+                let read = |path: PathBuf| async move {
+                    tokio::fs::read(&path)
+                        .await
+                        .map_err(|e| Error::ReadInput(path, e))
+                };
+                if read(built.join(file)).await? != read(expected.join(file)).await? {
+                    differences.push(Difference::Changed(file.clone()));
+                }
+            }
+        }
+    }
+
+    Ok(differences)
+}
+
+// The example sites use all optional features
+#[cfg(all(test, feature = "images", feature = "math", feature = "sass"))]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Config, TextDirection},
+        template::Value,
+        Cli, Website,
+    };
+
+    /// Build the example site `tests/sites/<name>` and compare it with its
+    /// `expected/` directory.
+    async fn golden_site(name: &str, low_memory: bool) -> Vec<Difference> {
+        let site = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/sites")
+            .join(name);
+        let mut config = Config::from_file(site.join("config.toml")).await.unwrap();
+        config.output_path = std::env::temp_dir().join(format!(
+            "fweb-golden-{}-{}-{}",
+            name,
+            low_memory,
+            std::process::id()
+        ));
+        config.state_path = config.output_path.with_extension("state");
+        let output_path = config.output_path.clone();
+        let state_path = config.state_path.clone();
+
+        let opts = Cli {
+            config_path: site.join("config.toml"),
+            drafts: false,
+            future: false,
+            report_unused: false,
+            low_memory,
+            jobs: None,
+            command: None,
+        };
+        Website::new(config).build(&opts).await.unwrap();
+        let differences = compare_dirs(&output_path, site.join("expected"))
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&output_path).await.unwrap();
+        tokio::fs::remove_dir_all(&state_path).await.ok();
+
+        differences
+    }
+
+    /// Write a site to a temporary directory with the given site info and
+    /// content files given by their frontmatter.
+    async fn temp_site(
+        name: &str,
+        site_info: &str,
+        files: &[(&str, &str)],
+    ) -> (std::path::PathBuf, Config) {
+        let site = std::env::temp_dir().join(format!("fweb-{}-{}", name, std::process::id()));
+        for (file, frontmatter) in files {
+            let path = site.join("content").join(file);
+            tokio::fs::create_dir_all(path.parent().unwrap())
+                .await
+                .unwrap();
+            let content = format!("+++\n{}\n+++\n", frontmatter);
+            tokio::fs::write(path, content).await.unwrap();
+        }
+        let config_file = site.join("config.toml");
+        let config = format!(
+            "[site_info]\ntitle = \"Test\"\ndescription = \"\"\n{}\n",
+            site_info
+        );
+        tokio::fs::write(&config_file, config).await.unwrap();
+        let config = Config::from_file(&config_file).await.unwrap();
+        (site, config)
+    }
+
+    #[tokio::test]
+    async fn test_golden_basic_site() {
+        assert_eq!(Vec::<Difference>::new(), golden_site("basic", false).await);
+    }
+
+    #[tokio::test]
+    async fn test_golden_basic_site_low_memory() {
+        assert_eq!(Vec::<Difference>::new(), golden_site("basic", true).await);
+    }
+
+    #[tokio::test]
+    async fn test_golden_fingerprint_site() {
+        assert_eq!(
+            Vec::<Difference>::new(),
+            golden_site("fingerprint", false).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_golden_trailing_slash_site() {
+        assert_eq!(
+            Vec::<Difference>::new(),
+            golden_site("trailing-slash", false).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_url_after_normalization() {
+        let site = std::env::temp_dir().join(format!("fweb-duplicate-{}", std::process::id()));
+        for dir in ["content/Été", "content/ete"] {
+            tokio::fs::create_dir_all(site.join(dir)).await.unwrap();
+            let page = site.join(dir).join("page.md");
+            tokio::fs::write(page, "+++\ntitle = \"Page\"\n+++\n")
+                .await
+                .unwrap();
+        }
+        let config_file = site.join("config.toml");
+        let config = "[site_info]\ntitle = \"Test\"\ndescription = \"\"\n";
+        tokio::fs::write(&config_file, config).await.unwrap();
+        let config = Config::from_file(&config_file).await.unwrap();
+
+        let err = crate::load_and_parse_content(
+            &config,
+            &crate::template::Context::new(),
+            &<Cli as clap::Parser>::parse_from(["fweb"]),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, crate::error::Error::DuplicateUrl(url, ..) if url == "/ete/"),
+            "{}",
+            err
+        );
+        tokio::fs::remove_dir_all(site).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incremental_build() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sites/basic");
+        let site = std::env::temp_dir().join(format!("fweb-incremental-{}", std::process::id()));
+        for file in crate::list_files(&source).await.unwrap() {
+            if file.starts_with("expected") {
+                continue;
+            }
+            let target = site.join(&file);
+            tokio::fs::create_dir_all(target.parent().unwrap())
+                .await
+                .unwrap();
+            tokio::fs::copy(source.join(&file), target).await.unwrap();
+        }
+        let opts = Cli {
+            config_path: site.join("config.toml"),
+            drafts: false,
+            future: false,
+            report_unused: false,
+            low_memory: false,
+            jobs: None,
+            command: None,
+        };
+        let build = |incremental: bool| {
+            let (site, opts) = (&site, &opts);
+            async move {
+                let mut config = Config::from_file(site.join("config.toml")).await.unwrap();
+                config.incremental = incremental;
+                config.output_path = site.with_extension(format!("{}", incremental));
+                config.state_path = site.with_extension("state");
+                let output_path = config.output_path.clone();
+                Website::new(config).build(opts).await.unwrap();
+                output_path
+            }
+        };
+        // The incremental build reproduces a clean build of the current site
+        let check = || async {
+            let incremental = build(true).await;
+            let clean = build(false).await;
+            assert_eq!(
+                Vec::<Difference>::new(),
+                compare_dirs(&incremental, &clean).await.unwrap()
+            );
+        };
+        let append = |file: &'static str, text: &'static str| {
+            let path = site.join(file);
+            async move {
+                let mut content = tokio::fs::read_to_string(&path).await.unwrap();
+                content.push_str(text);
+                tokio::fs::write(&path, content).await.unwrap();
+            }
+        };
+
+        check().await;
+        // The second build reuses the cached pages
+        check().await;
+        append("content/blog/first.md", "\nAppended paragraph.\n").await;
+        check().await;
+        append("templates/page.html", "<!-- changed -->").await;
+        check().await;
+        tokio::fs::write(
+            site.join("i18n/en.toml"),
+            "share = \"Tell others\"\n\n[reading]\nminutes = \"min\"\n",
+        )
+        .await
+        .unwrap();
+        check().await;
+        // Creating an optional include changes the pages using it
+        append(
+            "templates/page.html",
+            "{% include_if_exists \"optional.html\" %}",
+        )
+        .await;
+        check().await;
+        tokio::fs::write(site.join("templates/optional.html"), "Optional")
+            .await
+            .unwrap();
+        check().await;
+
+        for path in [
+            site.with_extension("true"),
+            site.with_extension("false"),
+            site.with_extension("state"),
+            site,
+        ] {
+            tokio::fs::remove_dir_all(path).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_low_memory_drops_text() {
+        let site = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sites/basic");
+        let config = Config::from_file(site.join("config.toml")).await.unwrap();
+        let content = "+++\ntitle = \"Page\"\n+++\nSome *text*.\n";
+        let cascade = toml::Table::new();
+        let ctx = crate::template::Context::new();
+        let parse = |defer_html| {
+            crate::Page::parse(
+                content,
+                Path::new("page.md"),
+                &config,
+                &ctx,
+                &cascade,
+                defer_html,
+            )
+        };
+
+        let page = parse(false).await.unwrap();
+        assert_eq!(Some("Some text."), page.plain_text.as_deref());
+        // Only the current page carries its text, not listings of it
+        let Value::Map(map) = page.to_value(&config) else {
+            panic!("page is a map");
+        };
+        assert!(!map.contains_key("plain_text"));
+        assert!(!map.contains_key("raw_markdown"));
+        let page = parse(true).await.unwrap();
+        assert_eq!(None, page.raw_markdown);
+        assert_eq!(None, page.plain_text);
+        // The description does not need the text
+        assert_eq!("Some text.", page.description());
+    }
+
+    #[tokio::test]
+    async fn test_derived_excerpt() {
+        let site = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sites/basic");
+        let config = Config::from_file(site.join("config.toml")).await.unwrap();
+        let cascade = toml::Table::new();
+        let ctx = crate::template::Context::new();
+        let parse = |content: &'static str| {
+            crate::Page::parse(
+                content,
+                Path::new("page.md"),
+                &config,
+                &ctx,
+                &cascade,
+                false,
+            )
+        };
+
+        let content = "+++\ntitle = \"Page\"\n+++\nFirst.\n\nSecond.\n\n<!-- more -->\n\nThird.\n";
+        let page = parse(content).await.unwrap();
+        assert_eq!(Some("First. Second."), page.excerpt.as_deref());
+        assert_eq!("First. Second.", page.description());
+
+        // The marker in code is not the end of the excerpt
+        let content = "+++\ntitle = \"Page\"\n+++\nUse `<!-- more -->`.\n\nSecond.\n";
+        let page = parse(content).await.unwrap();
+        assert_eq!(Some("Use <!-- more -->."), page.excerpt.as_deref());
+    }
+
+    #[tokio::test]
+    async fn test_git_commit() {
+        let repo = Path::new(env!("CARGO_MANIFEST_DIR"));
+        if repo.join(".git").exists() {
+            let commit = crate::git_commit(repo).await.unwrap();
+            assert!(commit.chars().all(|c| c.is_ascii_hexdigit()), "{commit}");
+        }
+
+        let dir = std::env::temp_dir().join(format!("fweb-git-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let commit = crate::git_commit(&dir).await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        assert_eq!(None, commit);
+    }
+
+    #[tokio::test]
+    async fn test_featured_pages() {
+        let (site, config) = temp_site(
+            "featured",
+            "",
+            &[
+                ("_index.md", "title = \"Home\"\nsort_by = \"title\""),
+                ("a.md", "title = \"A\"\nfeatured = true"),
+                ("b.md", "title = \"B\"\nfeatured = true\ndraft = true"),
+                ("c.md", "title = \"C\""),
+            ],
+        )
+        .await;
+        let ctx = crate::template::Context::new();
+
+        let titles = |pages: Vec<&crate::Page>| {
+            pages
+                .iter()
+                .map(|page| page.metadata.title.clone())
+                .collect::<Vec<_>>()
+        };
+        let opts = <Cli as clap::Parser>::parse_from(["fweb"]);
+        let indices = crate::load_and_parse_content(&config, &ctx, &opts)
+            .await
+            .unwrap();
+        assert_eq!(vec!["A"], titles(crate::featured_pages(&indices, &opts)));
+
+        let opts = <Cli as clap::Parser>::parse_from(["fweb", "--drafts"]);
+        let indices = crate::load_and_parse_content(&config, &ctx, &opts)
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&site).await.unwrap();
+        assert_eq!(
+            vec!["A", "B"],
+            titles(crate::featured_pages(&indices, &opts))
+        );
+
+        // The same day always picks the same page, but not always the same one
+        let start = time::macros::date!(2023 - 01 - 01);
+        let picks: Vec<_> = (0..30)
+            .map(|day| {
+                let date = start + time::Duration::days(day);
+                let page = crate::page_of_the_day(&indices, &opts, date).unwrap();
+                assert_eq!(
+                    page.metadata.title,
+                    crate::page_of_the_day(&indices, &opts, date)
+                        .unwrap()
+                        .metadata
+                        .title
+                );
+                page.metadata.title.as_str()
+            })
+            .collect();
+        assert!(picks.contains(&"A") && picks.contains(&"B"));
+    }
+
+    #[tokio::test]
+    async fn test_site_defaults() {
+        let (site, config) = temp_site(
+            "defaults",
+            "lang = \"ar\"\ndir = \"rtl\"",
+            &[
+                ("_index.md", "title = \"Home\"\nsort_by = \"title\""),
+                ("a.md", "title = \"A\""),
+                ("b.md", "title = \"B\"\nlang = \"en\"\ndir = \"ltr\""),
+            ],
+        )
+        .await;
+        let opts = <Cli as clap::Parser>::parse_from(["fweb"]);
+        let mut indices =
+            crate::load_and_parse_content(&config, &crate::template::Context::new(), &opts)
+                .await
+                .unwrap();
+        tokio::fs::remove_dir_all(&site).await.unwrap();
+        crate::apply_site_defaults(&config, &mut indices);
+
+        let index = &indices[0].metadata;
+        assert_eq!(
+            (Some("ar"), Some(TextDirection::Rtl)),
+            (index.lang.as_deref(), index.dir)
+        );
+        let pages: Vec<_> = indices[0]
+            .pages
+            .iter()
+            .map(|page| (page.metadata.lang.as_deref(), page.metadata.dir))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("ar"), Some(TextDirection::Rtl)),
+                (Some("en"), Some(TextDirection::Ltr))
+            ],
+            pages
+        );
+    }
+}
@@ -0,0 +1,5 @@
+function greet(name) {
+  const message = "Hello " + name;
+  console.log(message);
+}
+greet("you");
@@ -0,0 +1 @@
+var greet=(a=>{const b=`Hello `+ a;console.log(b)});greet(`you`)
\ No newline at end of file